@@ -0,0 +1,5 @@
+-- Input: user_id, token name, hashed token, array of token scopes
+-- Returns: token_id, token_created
+INSERT INTO api_tokens (token_user, token_name, token_hash, token_scopes)
+VALUES ($1::INT4, $2::VARCHAR, $3::VARCHAR, $4::VARCHAR[])
+RETURNING token_id, token_created;
\ No newline at end of file
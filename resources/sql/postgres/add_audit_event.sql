@@ -0,0 +1,4 @@
+-- Input: action, actor user_id, target user_id, client ip, details (all except the action can be NULL)
+-- Returns: nothing
+INSERT INTO audit_events (event_action, event_actor, event_target, event_ip, event_details)
+VALUES ($1::VARCHAR, $2::INT4, $3::INT4, $4::VARCHAR, $5::VARCHAR);
\ No newline at end of file
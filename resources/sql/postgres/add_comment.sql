@@ -1,4 +1,7 @@
 -- Input: comment poster userid, upload id, comment content
--- Returns: None
+-- Returns: comment_id (nothing if the upload doesn't exist or isn't ready)
 INSERT INTO comments (comment_poster, comment_upload, comment_text)
-VALUES ($1::INT4, $2::INT4, $3::TEXT);
\ No newline at end of file
+SELECT $1::INT4, u.upload_id, $3::TEXT
+FROM uploads u
+WHERE u.upload_id = $2::INT4 AND u.upload_status = 'Ready'
+RETURNING comment_id;
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- Input: invite code, invite creator userid, invite lifetime (in hours), invite quota (NULL = unlimited)
+-- Returns: invite_id, invite_created, invite_expire (no row if the quota of the creator is exhausted)
+-- The quota counts the invites which are used or not yet expired, the caller has to lock the row of the creator (see lock_user.sql)
+INSERT INTO invites (invite_code, invite_creator, invite_expire)
+SELECT $1::VARCHAR, $2::INT4, Now() + $3::INT4 * INTERVAL '1 hour'
+WHERE $4::INT8 IS NULL OR (
+    SELECT COUNT(*)
+    FROM invites i
+    WHERE i.invite_creator = $2::INT4
+    AND (i.invite_used_at IS NOT NULL OR i.invite_expire > Now())
+) < $4::INT8
+RETURNING invite_id, invite_created, invite_expire;
\ No newline at end of file
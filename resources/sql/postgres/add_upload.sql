@@ -1,5 +1,6 @@
--- Input: upload filename, upload is sfw, upload is nsfw, upload type, uploader userid
+-- Input: upload filename, original filename, upload is sfw, upload is nsfw, uploader userid, SHA-256 hash of the file
 -- Returns: upload_id
-INSERT INTO uploads (upload_filename, upload_is_sfw, upload_is_nsfw, upload_type, uploader)
-VALUES ($1::VARCHAR, $2::BOOL, $3::BOOL, $4::ANYENUM, $5::INT4)
+-- The upload type and the perceptual hash are set after the processing (set_upload_ready.sql)
+INSERT INTO uploads (upload_filename, upload_original_filename, upload_is_sfw, upload_is_nsfw, uploader, upload_hash, upload_status)
+VALUES ($1::VARCHAR, $2::VARCHAR, $3::BOOL, $4::BOOL, $5::INT4, $6::VARCHAR, 'Queued')
 RETURNING upload_id;
\ No newline at end of file
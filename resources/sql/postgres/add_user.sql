@@ -1,5 +1,5 @@
--- Input: username, hashed password, user is mod
+-- Input: username, hashed password, user is mod, user is admin, username skeleton
 -- Returns: user id
-INSERT INTO public.users (user_name, user_pass, user_is_mod)
-VALUES ($1::VARCHAR, $2::VARCHAR, $3::BOOL)
+INSERT INTO public.users (user_name, user_pass, user_is_mod, user_is_admin, user_name_skeleton)
+VALUES ($1::VARCHAR, $2::VARCHAR, $3::BOOL, $4::BOOL, $5::VARCHAR)
 RETURNING user_id;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Input: banned user id, ban reason, ban duration (in hours)
+-- Returns: ban_id
+INSERT INTO user_banns (ban_user, ban_reason, ban_duration)
+VALUES ($1::INT4, $2::TEXT, $3::INT4)
+RETURNING ban_id;
\ No newline at end of file
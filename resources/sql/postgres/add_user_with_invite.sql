@@ -0,0 +1,12 @@
+-- Input: username, hashed password, invite code, username skeleton
+-- Returns: user id (nothing if the invite code is unknown, expired or already used)
+WITH used_invite AS (
+    UPDATE invites
+    SET invite_used_at = Now()
+    WHERE invite_code = $3::VARCHAR AND invite_used_at IS NULL AND invite_expire > Now()
+    RETURNING invite_id
+)
+INSERT INTO users (user_name, user_pass, user_invite, user_name_skeleton)
+SELECT $1::VARCHAR, $2::VARCHAR, ui.invite_id, $4::VARCHAR
+FROM used_invite ui
+RETURNING user_id;
\ No newline at end of file
@@ -0,0 +1,19 @@
+-- Input: user_id, anonymized username
+-- Returns: user_id (nothing if the user doesn't exist)
+-- Uploads, comments, tags and votes stay and are shown under the anonymized username
+WITH removed_tokens AS (
+    DELETE FROM api_tokens
+    WHERE token_user = $1::INT4
+), removed_codes AS (
+    DELETE FROM recovery_codes
+    WHERE code_user = $1::INT4
+), removed_invites AS (
+    DELETE FROM invites
+    WHERE invite_creator = $1::INT4 AND invite_used_at IS NULL
+)
+UPDATE users
+SET user_name = $2::VARCHAR, user_pass = '', user_is_mod = false, user_is_admin = false,
+    user_invite = NULL, user_totp_secret = NULL, user_totp_enabled = false,
+    user_name_skeleton = NULL
+WHERE user_id = $1::INT4
+RETURNING user_id;
@@ -0,0 +1,7 @@
+-- Input: username, username skeleton
+-- Returns: true if the username is taken or looks like an existing username
+SELECT EXISTS (
+    SELECT 1
+    FROM users us
+    WHERE LOWER(us.user_name) = LOWER($1::VARCHAR) OR us.user_name_skeleton = $2::VARCHAR
+);
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- Input: none
+-- Returns: upload_id, upload_filename of the oldest queued upload (nothing if the queue is empty)
+-- SKIP LOCKED lets every worker claim another upload
+UPDATE uploads
+SET upload_status = 'Processing'
+WHERE upload_id = (
+    SELECT u.upload_id
+    FROM uploads u
+    WHERE u.upload_status = 'Queued'
+    ORDER BY u.upload_id ASC
+    LIMIT 1
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING upload_id, upload_filename;
\ No newline at end of file
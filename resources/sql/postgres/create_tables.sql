@@ -29,6 +29,12 @@ CREATE TYPE p0nygramm."UploadType" AS
  ENUM ('Image','AnimatedImage','Video');
 -- ddl-end --
 
+-- object: p0nygramm."UploadStatus" | type: TYPE --
+-- DROP TYPE IF EXISTS p0nygramm."UploadStatus" CASCADE;
+CREATE TYPE p0nygramm."UploadStatus" AS
+ ENUM ('Queued','Processing','Ready','Failed');
+-- ddl-end --
+
 -- object: p0nygramm.comments | type: TABLE --
 -- DROP TABLE IF EXISTS p0nygramm.comments CASCADE;
 CREATE TABLE p0nygramm.comments (
@@ -92,7 +98,7 @@ CREATE TABLE p0nygramm.project_kvconfig (
 );
 -- ddl-end --
 
-INSERT INTO p0nygramm.project_kvconfig (kv_key) VALUES (E'schema_version');
+INSERT INTO p0nygramm.project_kvconfig (kv_key, kv_value_str) VALUES (E'schema_version', E'2');
 -- ddl-end --
 
 -- object: p0nygramm.votes_tum | type: TABLE --
@@ -102,7 +108,8 @@ CREATE TABLE p0nygramm.votes_tum (
 	vote_tagmap integer NOT NULL,
 	vote_number integer NOT NULL DEFAULT 0,
 	vote_user integer NOT NULL,
-	CONSTRAINT votes_tum_pk PRIMARY KEY (vote_id)
+	CONSTRAINT votes_tum_pk PRIMARY KEY (vote_id),
+	CONSTRAINT vote_tagmap_user_unique UNIQUE (vote_tagmap, vote_user)
 
 );
 -- ddl-end --
@@ -114,7 +121,8 @@ CREATE TABLE p0nygramm.votes_uploads (
 	vote_upload integer NOT NULL,
 	vote_number integer NOT NULL DEFAULT 0,
 	vote_user integer NOT NULL,
-	CONSTRAINT votes_uploads_pk PRIMARY KEY (vote_id)
+	CONSTRAINT votes_uploads_pk PRIMARY KEY (vote_id),
+	CONSTRAINT vote_upload_user_unique UNIQUE (vote_upload, vote_user)
 
 );
 -- ddl-end --
@@ -126,7 +134,8 @@ CREATE TABLE p0nygramm.votes_comments (
 	vote_comment integer NOT NULL,
 	vote_number integer NOT NULL DEFAULT 0,
 	vote_user integer NOT NULL,
-	CONSTRAINT votes_comments_pk PRIMARY KEY (vote_id)
+	CONSTRAINT votes_comments_pk PRIMARY KEY (vote_id),
+	CONSTRAINT vote_comment_user_unique UNIQUE (vote_comment, vote_user)
 
 );
 -- ddl-end --
@@ -142,11 +151,33 @@ CREATE TABLE p0nygramm.uploads (
 	upload_type p0nygramm."UploadType" NOT NULL DEFAULT Image,
 	upload_upvotes integer NOT NULL DEFAULT 0,
 	uploader integer NOT NULL,
+	upload_hash varchar(64),
+	upload_phash bigint,
+	upload_original_filename varchar(32),
+	upload_status p0nygramm."UploadStatus" NOT NULL DEFAULT 'Ready',
+	upload_error varchar(128),
+	upload_prv_variants varchar(512),
+	upload_has_prv_clip bool NOT NULL DEFAULT false,
 	CONSTRAINT uploads_pk PRIMARY KEY (upload_id),
-	CONSTRAINT upload_filename_unique UNIQUE (upload_filename)
+	CONSTRAINT upload_filename_unique UNIQUE (upload_filename),
+	CONSTRAINT upload_hash_unique UNIQUE (upload_hash)
 
 );
 -- ddl-end --
+COMMENT ON COLUMN p0nygramm.uploads.upload_hash IS E'SHA-256 hash of the uploaded file, prevents that the same file is uploaded twice';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.uploads.upload_phash IS E'Perceptual hash (dHash) of the image or of the first video keyframe, similar uploads have a small hamming distance (NULL if it could not be calculated)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.uploads.upload_original_filename IS E'Filename sent by the client, only metadata (the file is stored under the server generated upload_filename)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.uploads.upload_status IS E'Processing state of the upload, only ready uploads are listed (new uploads are queued until a worker has processed the file)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.uploads.upload_error IS E'Reason why the processing failed (NULL if it did not fail)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.uploads.upload_prv_variants IS E'Preview variants which were generated for the upload (in the format of the preview_variants config entry), NULL for uploads with a single 100x100 jpg preview from before the variants existed';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.uploads.upload_has_prv_clip IS E'True if a preview clip was generated for the upload (the clip generation can fail or be disabled)';
+-- ddl-end --
 
 -- object: p0nygramm.users | type: TABLE --
 -- DROP TABLE IF EXISTS p0nygramm.users CASCADE;
@@ -157,13 +188,176 @@ CREATE TABLE p0nygramm.users (
 	user_upvotes integer NOT NULL DEFAULT 0,
 	user_is_mod bool NOT NULL DEFAULT false,
 	user_is_admin bool NOT NULL DEFAULT false,
+	user_invite integer,
+	user_totp_secret varchar(32),
+	user_totp_enabled bool NOT NULL DEFAULT false,
+	user_totp_last_step bigint,
+	user_name_skeleton varchar(160),
+	user_show_sfw bool NOT NULL DEFAULT true,
+	user_show_nsfw bool NOT NULL DEFAULT false,
 	CONSTRAINT users_pk PRIMARY KEY (user_id),
-	CONSTRAINT user_name_unique UNIQUE (user_name)
+	CONSTRAINT user_name_unique UNIQUE (user_name),
+	CONSTRAINT user_name_skeleton_unique UNIQUE (user_name_skeleton)
 
 );
 -- ddl-end --
 COMMENT ON COLUMN p0nygramm.users.user_pass IS E'Hashed password';
 -- ddl-end --
+COMMENT ON COLUMN p0nygramm.users.user_invite IS E'Invite used for the registration (NULL if registered with the master invite key)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.users.user_totp_secret IS E'Base32 encoded TOTP secret (NULL if 2FA was never enrolled)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.users.user_totp_last_step IS E'Time step of the last accepted TOTP code, prevents the reuse of codes';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.users.user_show_sfw IS E'Last content filter set by the user, used for requests with api tokens (they have no session)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.users.user_name_skeleton IS E'Lowercased confusable skeleton (UTS #39) of the username, prevents lookalike usernames (NULL for anonymized users)';
+-- ddl-end --
+
+-- object: p0nygramm.invites | type: TABLE --
+-- DROP TABLE IF EXISTS p0nygramm.invites CASCADE;
+CREATE TABLE p0nygramm.invites (
+	invite_id serial NOT NULL,
+	invite_code varchar(32) NOT NULL,
+	invite_creator integer NOT NULL,
+	invite_created timestamp with time zone NOT NULL DEFAULT Now(),
+	invite_expire timestamp with time zone NOT NULL,
+	invite_used_at timestamp with time zone,
+	CONSTRAINT invites_pk PRIMARY KEY (invite_id),
+	CONSTRAINT invite_code_unique UNIQUE (invite_code)
+
+);
+-- ddl-end --
+
+-- object: p0nygramm.recovery_codes | type: TABLE --
+-- DROP TABLE IF EXISTS p0nygramm.recovery_codes CASCADE;
+CREATE TABLE p0nygramm.recovery_codes (
+	code_id serial NOT NULL,
+	code_user integer NOT NULL,
+	code_hash varchar(64) NOT NULL,
+	CONSTRAINT recovery_codes_pk PRIMARY KEY (code_id)
+
+);
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.recovery_codes.code_hash IS E'SHA-256 hash of the 2FA recovery code';
+-- ddl-end --
+
+-- object: p0nygramm.api_tokens | type: TABLE --
+-- DROP TABLE IF EXISTS p0nygramm.api_tokens CASCADE;
+CREATE TABLE p0nygramm.api_tokens (
+	token_id serial NOT NULL,
+	token_user integer NOT NULL,
+	token_name varchar(64) NOT NULL,
+	token_hash varchar(64) NOT NULL,
+	token_scopes varchar(16)[] NOT NULL,
+	token_created timestamp with time zone NOT NULL DEFAULT Now(),
+	token_last_used timestamp with time zone,
+	CONSTRAINT api_tokens_pk PRIMARY KEY (token_id),
+	CONSTRAINT token_hash_unique UNIQUE (token_hash)
+
+);
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.api_tokens.token_hash IS E'SHA-256 hash of the api token';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.api_tokens.token_scopes IS E'Allowed actions (read, upload, vote, comment)';
+-- ddl-end --
+
+-- object: p0nygramm.audit_events | type: TABLE --
+-- DROP TABLE IF EXISTS p0nygramm.audit_events CASCADE;
+CREATE TABLE p0nygramm.audit_events (
+	event_id serial NOT NULL,
+	event_action varchar(32) NOT NULL,
+	event_actor integer,
+	event_target integer,
+	event_ip varchar(45),
+	event_details varchar(128),
+	event_time timestamp with time zone NOT NULL DEFAULT Now(),
+	CONSTRAINT audit_events_pk PRIMARY KEY (event_id)
+
+);
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.audit_events.event_actor IS E'User who did the action (NULL for cli actions, unknown or deleted users)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.audit_events.event_target IS E'User affected by the action (NULL if the action has no target or the user was deleted)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.audit_events.event_ip IS E'IP address of the client (NULL for cli actions)';
+-- ddl-end --
+COMMENT ON COLUMN p0nygramm.audit_events.event_details IS E'Additional information (like the new role or the username of a failed login)';
+-- ddl-end --
+
+-- The perceptual hash is indexed in 8 parts of one byte: two hashes with a hamming distance below 8 have at least one equal byte,
+-- so get_similar_uploads only has to compare the uploads which were found by one of the indexes
+-- object: upload_phash_byte0_idx | type: INDEX --
+-- DROP INDEX IF EXISTS p0nygramm.upload_phash_byte0_idx CASCADE;
+CREATE INDEX upload_phash_byte0_idx ON p0nygramm.uploads
+	USING btree
+	(
+	  ((upload_phash >> 0) & 255)
+	);
+-- ddl-end --
+
+-- object: upload_phash_byte1_idx | type: INDEX --
+-- DROP INDEX IF EXISTS p0nygramm.upload_phash_byte1_idx CASCADE;
+CREATE INDEX upload_phash_byte1_idx ON p0nygramm.uploads
+	USING btree
+	(
+	  ((upload_phash >> 8) & 255)
+	);
+-- ddl-end --
+
+-- object: upload_phash_byte2_idx | type: INDEX --
+-- DROP INDEX IF EXISTS p0nygramm.upload_phash_byte2_idx CASCADE;
+CREATE INDEX upload_phash_byte2_idx ON p0nygramm.uploads
+	USING btree
+	(
+	  ((upload_phash >> 16) & 255)
+	);
+-- ddl-end --
+
+-- object: upload_phash_byte3_idx | type: INDEX --
+-- DROP INDEX IF EXISTS p0nygramm.upload_phash_byte3_idx CASCADE;
+CREATE INDEX upload_phash_byte3_idx ON p0nygramm.uploads
+	USING btree
+	(
+	  ((upload_phash >> 24) & 255)
+	);
+-- ddl-end --
+
+-- object: upload_phash_byte4_idx | type: INDEX --
+-- DROP INDEX IF EXISTS p0nygramm.upload_phash_byte4_idx CASCADE;
+CREATE INDEX upload_phash_byte4_idx ON p0nygramm.uploads
+	USING btree
+	(
+	  ((upload_phash >> 32) & 255)
+	);
+-- ddl-end --
+
+-- object: upload_phash_byte5_idx | type: INDEX --
+-- DROP INDEX IF EXISTS p0nygramm.upload_phash_byte5_idx CASCADE;
+CREATE INDEX upload_phash_byte5_idx ON p0nygramm.uploads
+	USING btree
+	(
+	  ((upload_phash >> 40) & 255)
+	);
+-- ddl-end --
+
+-- object: upload_phash_byte6_idx | type: INDEX --
+-- DROP INDEX IF EXISTS p0nygramm.upload_phash_byte6_idx CASCADE;
+CREATE INDEX upload_phash_byte6_idx ON p0nygramm.uploads
+	USING btree
+	(
+	  ((upload_phash >> 48) & 255)
+	);
+-- ddl-end --
+
+-- object: upload_phash_byte7_idx | type: INDEX --
+-- DROP INDEX IF EXISTS p0nygramm.upload_phash_byte7_idx CASCADE;
+CREATE INDEX upload_phash_byte7_idx ON p0nygramm.uploads
+	USING btree
+	(
+	  ((upload_phash >> 56) & 255)
+	);
+-- ddl-end --
 
 -- object: username_uq | type: INDEX --
 -- DROP INDEX IF EXISTS p0nygramm.username_uq CASCADE;
@@ -265,4 +459,44 @@ REFERENCES p0nygramm.users (user_id) MATCH FULL
 ON DELETE CASCADE ON UPDATE CASCADE;
 -- ddl-end --
 
+-- object: invite_creator_fk | type: CONSTRAINT --
+-- ALTER TABLE p0nygramm.invites DROP CONSTRAINT IF EXISTS invite_creator_fk CASCADE;
+ALTER TABLE p0nygramm.invites ADD CONSTRAINT invite_creator_fk FOREIGN KEY (invite_creator)
+REFERENCES p0nygramm.users (user_id) MATCH FULL
+ON DELETE CASCADE ON UPDATE CASCADE;
+-- ddl-end --
+
+-- object: user_invite_fk | type: CONSTRAINT --
+-- ALTER TABLE p0nygramm.users DROP CONSTRAINT IF EXISTS user_invite_fk CASCADE;
+ALTER TABLE p0nygramm.users ADD CONSTRAINT user_invite_fk FOREIGN KEY (user_invite)
+REFERENCES p0nygramm.invites (invite_id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE CASCADE;
+-- ddl-end --
 
+-- object: code_user_fk | type: CONSTRAINT --
+-- ALTER TABLE p0nygramm.recovery_codes DROP CONSTRAINT IF EXISTS code_user_fk CASCADE;
+ALTER TABLE p0nygramm.recovery_codes ADD CONSTRAINT code_user_fk FOREIGN KEY (code_user)
+REFERENCES p0nygramm.users (user_id) MATCH FULL
+ON DELETE CASCADE ON UPDATE CASCADE;
+-- ddl-end --
+
+-- object: token_user_fk | type: CONSTRAINT --
+-- ALTER TABLE p0nygramm.api_tokens DROP CONSTRAINT IF EXISTS token_user_fk CASCADE;
+ALTER TABLE p0nygramm.api_tokens ADD CONSTRAINT token_user_fk FOREIGN KEY (token_user)
+REFERENCES p0nygramm.users (user_id) MATCH FULL
+ON DELETE CASCADE ON UPDATE CASCADE;
+-- ddl-end --
+
+-- object: event_actor_fk | type: CONSTRAINT --
+-- ALTER TABLE p0nygramm.audit_events DROP CONSTRAINT IF EXISTS event_actor_fk CASCADE;
+ALTER TABLE p0nygramm.audit_events ADD CONSTRAINT event_actor_fk FOREIGN KEY (event_actor)
+REFERENCES p0nygramm.users (user_id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE CASCADE;
+-- ddl-end --
+
+-- object: event_target_fk | type: CONSTRAINT --
+-- ALTER TABLE p0nygramm.audit_events DROP CONSTRAINT IF EXISTS event_target_fk CASCADE;
+ALTER TABLE p0nygramm.audit_events ADD CONSTRAINT event_target_fk FOREIGN KEY (event_target)
+REFERENCES p0nygramm.users (user_id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE CASCADE;
+-- ddl-end --
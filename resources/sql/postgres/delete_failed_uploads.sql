@@ -0,0 +1,6 @@
+-- Input: lifetime of failed uploads in hours
+-- Returns: upload_id of every removed upload
+-- Failed uploads have no files on the server, the tags and comments are removed by the foreign keys
+DELETE FROM uploads
+WHERE upload_status = 'Failed' AND upload_timestamp < Now() - MAKE_INTERVAL(hours => $1::INT4)
+RETURNING upload_id;
\ No newline at end of file
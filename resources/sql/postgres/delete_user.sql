@@ -0,0 +1,17 @@
+-- Input: user_id
+-- Returns: upload_id, upload_filename, upload_prv_variants, upload_has_prv_clip of every upload of the user (the files have to be removed by the caller)
+-- Tag and upload votes of the user are removed explicitly, because their foreign keys don't cascade
+WITH removed_tum_votes AS (
+    DELETE FROM votes_tum
+    WHERE vote_user = $1::INT4
+), removed_upload_votes AS (
+    DELETE FROM votes_uploads
+    WHERE vote_user = $1::INT4
+), removed_user AS (
+    DELETE FROM users
+    WHERE user_id = $1::INT4
+    RETURNING user_id
+)
+SELECT up.upload_id, up.upload_filename, up.upload_prv_variants, up.upload_has_prv_clip
+FROM uploads up
+INNER JOIN removed_user ru ON up.uploader = ru.user_id;
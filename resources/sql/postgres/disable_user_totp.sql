@@ -0,0 +1,9 @@
+-- Input: user_id
+-- Removes the totp secret and all recovery codes of the user
+WITH removed_codes AS (
+    DELETE FROM recovery_codes
+    WHERE code_user = $1::INT4
+)
+UPDATE users
+SET user_totp_secret = NULL, user_totp_enabled = false
+WHERE user_id = $1::INT4;
\ No newline at end of file
@@ -0,0 +1,16 @@
+-- Input: user_id, array of hashed recovery codes
+-- Returns: user_id (nothing if the user has no pending totp secret)
+-- Replaces all existing recovery codes of the user (only if 2FA could be enabled)
+WITH enabled_user AS (
+    UPDATE users
+    SET user_totp_enabled = true
+    WHERE user_id = $1::INT4 AND user_totp_secret IS NOT NULL
+    RETURNING user_id
+), removed_codes AS (
+    DELETE FROM recovery_codes
+    WHERE code_user IN (SELECT eu.user_id FROM enabled_user eu)
+)
+INSERT INTO recovery_codes (code_user, code_hash)
+SELECT eu.user_id, unnest($2::VARCHAR[])
+FROM enabled_user eu
+RETURNING code_user;
\ No newline at end of file
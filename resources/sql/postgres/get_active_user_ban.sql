@@ -0,0 +1,8 @@
+-- Input: user_id
+-- Returns: ban_id, ban_reason, ban_start, ban_duration of the longest running active ban (if the user is banned)
+SELECT ub.ban_id, ub.ban_reason, ub.ban_start, ub.ban_duration
+FROM user_banns ub
+WHERE ub.ban_user = $1::INT4
+AND (ub.ban_start + ub.ban_duration * INTERVAL '1 hour') > Now()
+ORDER BY (ub.ban_start + ub.ban_duration * INTERVAL '1 hour') DESC
+LIMIT 1;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Input: user_id
+-- Returns: token_id, token_name, token_scopes, token_created, token_last_used of all tokens of the user
+SELECT token_id, token_name, token_scopes, token_created, token_last_used
+FROM api_tokens
+WHERE token_user = $1::INT4
+ORDER BY token_id;
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- Input: event_id (start id), max_count (how many entries will be returned), action, actor user_id, target user_id (the filters are ignored if NULL)
+-- Returns: event_id, event_action, event_actor, actor username, event_target, target username, event_ip, event_details, event_time
+SELECT ae.event_id, ae.event_action, ae.event_actor, ua.user_name, ae.event_target, ut.user_name, ae.event_ip, ae.event_details, ae.event_time
+FROM audit_events ae
+LEFT JOIN users ua ON ua.user_id = ae.event_actor
+LEFT JOIN users ut ON ut.user_id = ae.event_target
+WHERE (ae.event_id <= $1::INT4)
+AND ($3::VARCHAR IS NULL OR ae.event_action = $3::VARCHAR)
+AND ($4::INT4 IS NULL OR ae.event_actor = $4::INT4)
+AND ($5::INT4 IS NULL OR ae.event_target = $5::INT4)
+ORDER BY ae.event_id DESC
+LIMIT $2::INT2;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Input: none
+-- Returns: upload_filename of every upload which is queued or processed (their tmp files are still needed)
+SELECT u.upload_filename
+FROM uploads u
+WHERE u.upload_status IN ('Queued', 'Processing');
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Returns: upload_id, upload_filename, upload_prv_variants, upload_has_prv_clip of every processed upload
+SELECT u.upload_id, u.upload_filename, u.upload_prv_variants, u.upload_has_prv_clip
+FROM uploads u
+WHERE u.upload_status = 'Ready'
+ORDER BY u.upload_id;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Input: none
+-- Returns: schema version of the tables ('1' if it was never stored)
+SELECT COALESCE(
+    (SELECT kv.kv_value_str FROM project_kvconfig kv WHERE kv.kv_key = 'schema_version'),
+    '1'
+);
\ No newline at end of file
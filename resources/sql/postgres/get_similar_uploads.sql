@@ -0,0 +1,21 @@
+-- Input: perceptual hash, max hamming distance, excluded upload_id (0 = none), max_count (how many entries will be returned), allow sfw, allow nsfw
+-- Returns: upload_id, upload_filename, upload_is_nsfw, upload_type, upload_prv_variants, upload_has_prv_clip (the most similar uploads first)
+-- The hamming distance is the number of set bits after a XOR of both hashes
+-- Up to a distance of 7 one of the hash bytes is equal, so only the uploads found by the byte indexes are compared (no full table scan)
+SELECT u.upload_id, u.upload_filename, u.upload_is_nsfw, u.upload_type, u.upload_prv_variants, u.upload_has_prv_clip
+FROM uploads u
+WHERE u.upload_phash IS NOT NULL AND u.upload_id <> $3::INT4 AND u.upload_status = 'Ready'
+AND ($2::INT4 > 7 OR (
+    ((u.upload_phash >> 0) & 255) = (($1::INT8 >> 0) & 255)
+    OR ((u.upload_phash >> 8) & 255) = (($1::INT8 >> 8) & 255)
+    OR ((u.upload_phash >> 16) & 255) = (($1::INT8 >> 16) & 255)
+    OR ((u.upload_phash >> 24) & 255) = (($1::INT8 >> 24) & 255)
+    OR ((u.upload_phash >> 32) & 255) = (($1::INT8 >> 32) & 255)
+    OR ((u.upload_phash >> 40) & 255) = (($1::INT8 >> 40) & 255)
+    OR ((u.upload_phash >> 48) & 255) = (($1::INT8 >> 48) & 255)
+    OR ((u.upload_phash >> 56) & 255) = (($1::INT8 >> 56) & 255)
+))
+AND LENGTH(REPLACE(((u.upload_phash # $1::INT8)::BIT(64))::TEXT, '0', '')) <= $2::INT4
+AND ( ($5::BOOL = true AND u.upload_is_sfw = $5::BOOL) OR ($6::BOOL = true AND u.upload_is_nsfw = $6::BOOL) )
+ORDER BY LENGTH(REPLACE(((u.upload_phash # $1::INT8)::BIT(64))::TEXT, '0', '')), u.upload_id DESC
+LIMIT $4::INT2;
\ No newline at end of file
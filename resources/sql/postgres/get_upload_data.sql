@@ -1,6 +1,6 @@
 -- Input: upload_id
--- Returns: upload_filename; upload_timestamp; is_nsfw, upload_type (image, gif or video), uploader_id, uploader_username, upvotes
-SELECT up.upload_filename, up.upload_timestamp, up.upload_is_nsfw, up.upload_type, up.uploader, us.user_name, up.upload_upvotes
+-- Returns: upload_filename; upload_timestamp; is_nsfw, upload_type (image, gif or video), uploader_id, uploader_username, upvotes, original filename
+SELECT up.upload_filename, up.upload_timestamp, up.upload_is_nsfw, up.upload_type, up.uploader, us.user_name, up.upload_upvotes, up.upload_original_filename
 FROM uploads up
 INNER JOIN users us ON up.uploader = us.user_id
-WHERE up.uploader = $1::INT4;
\ No newline at end of file
+WHERE up.upload_id = $1::INT4 AND up.upload_status = 'Ready';
\ No newline at end of file
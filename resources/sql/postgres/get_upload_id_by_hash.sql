@@ -0,0 +1,5 @@
+-- Input: SHA-256 hash of the file
+-- Returns: upload_id (nothing if the file wasn't uploaded yet)
+SELECT u.upload_id
+FROM uploads u
+WHERE u.upload_hash = $1::VARCHAR;
\ No newline at end of file
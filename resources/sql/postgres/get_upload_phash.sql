@@ -0,0 +1,5 @@
+-- Input: upload_id
+-- Returns: upload_phash (NULL if the hash could not be calculated, nothing if the upload doesn't exist)
+SELECT u.upload_phash
+FROM uploads u
+WHERE u.upload_id = $1::INT4;
\ No newline at end of file
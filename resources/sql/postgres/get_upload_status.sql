@@ -0,0 +1,5 @@
+-- Input: upload_id
+-- Returns: upload_status, upload_error, uploader, upload_phash (nothing if the upload doesn't exist)
+SELECT u.upload_status, u.upload_error, u.uploader, u.upload_phash
+FROM uploads u
+WHERE u.upload_id = $1::INT4;
\ No newline at end of file
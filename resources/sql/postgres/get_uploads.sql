@@ -1,8 +1,8 @@
 -- Input: upload_id (start id), max_count (how many entries will be returned), allow sfw, allow nsfw
--- Returns: upload_id, upload_filename, upload_is_nsfw, upload_type
-SELECT u.upload_id, u.upload_filename, u.upload_is_nsfw, u.upload_type
+-- Returns: upload_id, upload_filename, upload_is_nsfw, upload_type, upload_prv_variants, upload_has_prv_clip
+SELECT u.upload_id, u.upload_filename, u.upload_is_nsfw, u.upload_type, u.upload_prv_variants, u.upload_has_prv_clip
 FROM uploads u
-WHERE (u.upload_id <= $1::INT4)
+WHERE (u.upload_id <= $1::INT4) AND u.upload_status = 'Ready'
 AND ( ($3::BOOL = true AND u.upload_is_sfw = $3::BOOL) OR ($4::BOOL = true AND u.upload_is_nsfw = $4::BOOL) )
 ORDER BY u.upload_id DESC
 LIMIT $2::INT2;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Input: user_id
+-- Returns: comment_id, upload_id, comment timestamp, comment content and upvotes of all comments of the user
+SELECT c.comment_id, c.comment_upload, c.comment_timestamp, c.comment_text, c.comment_upvotes
+FROM comments c
+WHERE c.comment_poster = $1::INT4
+ORDER BY c.comment_timestamp ASC;
@@ -0,0 +1,7 @@
+-- Input: user_id
+-- Returns: tum_id, upload_id, tag_text and upvotes of all tags added by the user
+SELECT tum.tum_id, tum.upload_id, ta.tag_text, tum.tag_upvotes
+FROM tag_upload_map tum
+INNER JOIN tags ta ON tum.tag_id = ta.tag_id
+WHERE tum.tag_poster = $1::INT4
+ORDER BY tum.tum_id ASC;
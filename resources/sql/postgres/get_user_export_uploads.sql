@@ -0,0 +1,6 @@
+-- Input: user_id
+-- Returns: upload_id, upload_filename, upload_timestamp, is_nsfw, upload_type and upvotes of all uploads of the user
+SELECT up.upload_id, up.upload_filename, up.upload_timestamp, up.upload_is_nsfw, up.upload_type, up.upload_upvotes
+FROM uploads up
+WHERE up.uploader = $1::INT4 AND up.upload_status = 'Ready'
+ORDER BY up.upload_id ASC;
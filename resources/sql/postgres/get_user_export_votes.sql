@@ -0,0 +1,13 @@
+-- Input: user_id
+-- Returns: vote target (upload, comment or tag), id of the voted object and vote value of all votes of the user
+SELECT 'upload', vu.vote_upload, vu.vote_number
+FROM votes_uploads vu
+WHERE vu.vote_user = $1::INT4
+UNION ALL
+SELECT 'comment', vc.vote_comment, vc.vote_number
+FROM votes_comments vc
+WHERE vc.vote_user = $1::INT4
+UNION ALL
+SELECT 'tag', vt.vote_tagmap, vt.vote_number
+FROM votes_tum vt
+WHERE vt.vote_user = $1::INT4;
@@ -0,0 +1,5 @@
+-- Input: user_id
+-- Returns: show sfw, show nsfw
+SELECT user_show_sfw, user_show_nsfw
+FROM users
+WHERE user_id = $1::INT4;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Input: invite creator userid
+-- Returns: invite_code, invite_created, invite_expire, invite_used_at, invited user (id), invited user (username) ordered by date/time
+SELECT i.invite_code, i.invite_created, i.invite_expire, i.invite_used_at, us.user_id, us.user_name
+FROM invites i
+LEFT JOIN users us ON us.user_invite = i.invite_id
+WHERE i.invite_creator = $1::INT4
+ORDER BY i.invite_created DESC;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Input: user_id
+-- Returns: totp secret (NULL if not enrolled), totp enabled, last used time step (NULL if no code was used yet)
+SELECT user_totp_secret, user_totp_enabled, user_totp_last_step
+FROM users
+WHERE user_id = $1::INT4;
\ No newline at end of file
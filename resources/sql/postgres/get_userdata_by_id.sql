@@ -1,5 +1,5 @@
 -- Input: user_id
--- Returns: user_id, username, hashed password, is mod
-SELECT us.user_id, us.user_name, us.user_pass, us.user_is_mod
+-- Returns: user_id, username, hashed password, is mod, is admin
+SELECT us.user_id, us.user_name, us.user_pass, us.user_is_mod, us.user_is_admin
 FROM users us
 WHERE us.user_id = $1::INT4;
\ No newline at end of file
@@ -1,5 +1,5 @@
 -- Input: username
--- Returns: user_id, username, hashed password, is mod
-SELECT us.user_id, us.user_name, us.user_pass, us.user_is_mod
+-- Returns: user_id, username, hashed password, is mod, is admin
+SELECT us.user_id, us.user_name, us.user_pass, us.user_is_mod, us.user_is_admin
 FROM users us
 WHERE LOWER(us.user_name) = LOWER($1::VARCHAR);
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Returns: user_id, username of all users which were registered before the username skeletons existed
+-- Anonymized users have no skeleton on purpose (they have no password)
+SELECT user_id, user_name
+FROM users
+WHERE user_name_skeleton IS NULL AND user_pass <> ''
+ORDER BY user_id;
\ No newline at end of file
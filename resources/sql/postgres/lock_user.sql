@@ -0,0 +1,7 @@
+-- Input: userid
+-- Returns: userid
+-- Locks the row of the user until the end of the transaction (serializes actions which check a per-user quota)
+SELECT u.user_id
+FROM users u
+WHERE u.user_id = $1::INT4
+FOR UPDATE;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Input: token_id, user_id
+-- Returns: token_id (nothing if the token doesn't exist or belongs to another user)
+DELETE FROM api_tokens
+WHERE token_id = $1::INT4 AND token_user = $2::INT4
+RETURNING token_id;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Input: user_id
+-- Returns: None
+DELETE FROM user_banns
+WHERE ban_user = $1::INT4;
\ No newline at end of file
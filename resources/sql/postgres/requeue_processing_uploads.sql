@@ -0,0 +1,6 @@
+-- Input: none
+-- Returns: upload_id of every upload which was in processing (the processing was interrupted by a server restart)
+UPDATE uploads
+SET upload_status = 'Queued'
+WHERE upload_status = 'Processing'
+RETURNING upload_id;
\ No newline at end of file
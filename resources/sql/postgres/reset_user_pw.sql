@@ -0,0 +1,11 @@
+-- Input: New hashed user password, user id
+-- Returns: user_id (nothing if the user doesn't exist)
+-- The api tokens are removed too, they could have been created by someone who knew the old password
+WITH removed_tokens AS (
+    DELETE FROM api_tokens
+    WHERE token_user = $2::INT4
+)
+UPDATE users
+SET user_pass = $1::VARCHAR
+WHERE user_id = $2::INT4
+RETURNING user_id;
\ No newline at end of file
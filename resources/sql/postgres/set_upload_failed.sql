@@ -0,0 +1,7 @@
+-- Input: upload_id, error message
+-- Returns: upload_id (nothing if the upload doesn't exist)
+-- The hash is removed, so that the file can be uploaded again
+UPDATE uploads
+SET upload_status = 'Failed', upload_error = $2::VARCHAR, upload_hash = NULL
+WHERE upload_id = $1::INT4
+RETURNING upload_id;
\ No newline at end of file
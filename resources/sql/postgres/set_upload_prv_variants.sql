@@ -0,0 +1,6 @@
+-- Input: upload_id, preview variants
+-- Returns: upload_id (nothing if the upload doesn't exist)
+UPDATE uploads
+SET upload_prv_variants = $2::VARCHAR
+WHERE upload_id = $1::INT4
+RETURNING upload_id;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Input: upload_id, upload filename, upload type, perceptual hash, preview variants, has preview clip
+-- Returns: upload_id (nothing if the upload doesn't exist)
+UPDATE uploads
+SET upload_status = 'Ready', upload_filename = $2::VARCHAR, upload_type = $3::ANYENUM, upload_phash = $4::INT8, upload_prv_variants = $5::VARCHAR, upload_has_prv_clip = $6::BOOL, upload_error = NULL
+WHERE upload_id = $1::INT4
+RETURNING upload_id;
\ No newline at end of file
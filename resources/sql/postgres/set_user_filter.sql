@@ -0,0 +1,6 @@
+-- Input: user_id, show sfw, show nsfw
+-- Returns: user_id (nothing if the user doesn't exist)
+UPDATE users
+SET user_show_sfw = $2::BOOL, user_show_nsfw = $3::BOOL
+WHERE user_id = $1::INT4
+RETURNING user_id;
\ No newline at end of file
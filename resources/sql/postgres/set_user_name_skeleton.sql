@@ -0,0 +1,6 @@
+-- Input: user_id, username skeleton
+-- Returns: user_id (nothing if the user doesn't exist or another user has the same skeleton)
+UPDATE users
+SET user_name_skeleton = $2::VARCHAR
+WHERE user_id = $1::INT4 AND NOT EXISTS (SELECT 1 FROM users u WHERE u.user_name_skeleton = $2::VARCHAR)
+RETURNING user_id;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Input: user is mod, user is admin, user_id
+-- Returns: user_id (nothing if the user doesn't exist)
+UPDATE users
+SET user_is_mod = $1::BOOL, user_is_admin = $2::BOOL
+WHERE user_id = $3::INT4
+RETURNING user_id;
\ No newline at end of file
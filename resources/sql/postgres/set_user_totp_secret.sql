@@ -0,0 +1,6 @@
+-- Input: user_id, base32 encoded totp secret
+-- Returns: user_id (nothing if the user doesn't exist or 2FA is already enabled)
+UPDATE users
+SET user_totp_secret = $2::VARCHAR
+WHERE user_id = $1::INT4 AND user_totp_enabled = false
+RETURNING user_id;
\ No newline at end of file
@@ -0,0 +1,155 @@
+-- Input: none
+-- Returns: none
+-- Upgrades the tables of an existing installation to schema version 2 (the tables of new installations are created by create_tables.sql)
+-- Nothing is changed if the schema is already up to date, so the upgrade can be run more than once
+DO $$
+BEGIN
+    IF COALESCE((SELECT kv_value_str FROM p0nygramm.project_kvconfig WHERE kv_key = 'schema_version'), '1')::INT4 >= 2 THEN
+        RETURN;
+    END IF;
+
+    CREATE TYPE p0nygramm."UploadStatus" AS ENUM ('Queued','Processing','Ready','Failed');
+
+    -- Invite codes replace the shared master invite key
+    CREATE TABLE p0nygramm.invites (
+        invite_id serial NOT NULL,
+        invite_code varchar(32) NOT NULL,
+        invite_creator integer NOT NULL,
+        invite_created timestamp with time zone NOT NULL DEFAULT Now(),
+        invite_expire timestamp with time zone NOT NULL,
+        invite_used_at timestamp with time zone,
+        CONSTRAINT invites_pk PRIMARY KEY (invite_id),
+        CONSTRAINT invite_code_unique UNIQUE (invite_code)
+    );
+
+    CREATE TABLE p0nygramm.recovery_codes (
+        code_id serial NOT NULL,
+        code_user integer NOT NULL,
+        code_hash varchar(64) NOT NULL,
+        CONSTRAINT recovery_codes_pk PRIMARY KEY (code_id)
+    );
+
+    COMMENT ON COLUMN p0nygramm.recovery_codes.code_hash IS E'SHA-256 hash of the 2FA recovery code';
+
+    CREATE TABLE p0nygramm.api_tokens (
+        token_id serial NOT NULL,
+        token_user integer NOT NULL,
+        token_name varchar(64) NOT NULL,
+        token_hash varchar(64) NOT NULL,
+        token_scopes varchar(16)[] NOT NULL,
+        token_created timestamp with time zone NOT NULL DEFAULT Now(),
+        token_last_used timestamp with time zone,
+        CONSTRAINT api_tokens_pk PRIMARY KEY (token_id),
+        CONSTRAINT token_hash_unique UNIQUE (token_hash)
+    );
+
+    COMMENT ON COLUMN p0nygramm.api_tokens.token_hash IS E'SHA-256 hash of the api token';
+    COMMENT ON COLUMN p0nygramm.api_tokens.token_scopes IS E'Allowed actions (read, upload, vote, comment)';
+
+    CREATE TABLE p0nygramm.audit_events (
+        event_id serial NOT NULL,
+        event_action varchar(32) NOT NULL,
+        event_actor integer,
+        event_target integer,
+        event_ip varchar(45),
+        event_details varchar(128),
+        event_time timestamp with time zone NOT NULL DEFAULT Now(),
+        CONSTRAINT audit_events_pk PRIMARY KEY (event_id)
+    );
+
+    COMMENT ON COLUMN p0nygramm.audit_events.event_actor IS E'User who did the action (NULL for cli actions, unknown or deleted users)';
+    COMMENT ON COLUMN p0nygramm.audit_events.event_target IS E'User affected by the action (NULL if the action has no target or the user was deleted)';
+    COMMENT ON COLUMN p0nygramm.audit_events.event_ip IS E'IP address of the client (NULL for cli actions)';
+    COMMENT ON COLUMN p0nygramm.audit_events.event_details IS E'Additional information (like the new role or the username of a failed login)';
+
+    -- Existing uploads were processed before the upload queue existed, so they are ready
+    ALTER TABLE p0nygramm.uploads
+        ADD COLUMN upload_hash varchar(64),
+        ADD COLUMN upload_phash bigint,
+        ADD COLUMN upload_original_filename varchar(32),
+        ADD COLUMN upload_status p0nygramm."UploadStatus" NOT NULL DEFAULT 'Ready',
+        ADD COLUMN upload_error varchar(128),
+        ADD COLUMN upload_prv_variants varchar(512),
+        ADD COLUMN upload_has_prv_clip bool NOT NULL DEFAULT false,
+        ADD CONSTRAINT upload_hash_unique UNIQUE (upload_hash);
+
+    COMMENT ON COLUMN p0nygramm.uploads.upload_hash IS E'SHA-256 hash of the uploaded file, prevents that the same file is uploaded twice';
+    COMMENT ON COLUMN p0nygramm.uploads.upload_phash IS E'Perceptual hash (dHash) of the image or of the first video keyframe, similar uploads have a small hamming distance (NULL if it could not be calculated)';
+    COMMENT ON COLUMN p0nygramm.uploads.upload_original_filename IS E'Filename sent by the client, only metadata (the file is stored under the server generated upload_filename)';
+    COMMENT ON COLUMN p0nygramm.uploads.upload_status IS E'Processing state of the upload, only ready uploads are listed (new uploads are queued until a worker has processed the file)';
+    COMMENT ON COLUMN p0nygramm.uploads.upload_error IS E'Reason why the processing failed (NULL if it did not fail)';
+    COMMENT ON COLUMN p0nygramm.uploads.upload_prv_variants IS E'Preview variants which were generated for the upload (in the format of the preview_variants config entry), NULL for uploads with a single 100x100 jpg preview from before the variants existed';
+    COMMENT ON COLUMN p0nygramm.uploads.upload_has_prv_clip IS E'True if a preview clip was generated for the upload (the clip generation can fail or be disabled)';
+
+    -- The username skeletons of existing users are stored with "maintenance --update-username-skeletons"
+    ALTER TABLE p0nygramm.users
+        ADD COLUMN user_invite integer,
+        ADD COLUMN user_totp_secret varchar(32),
+        ADD COLUMN user_totp_enabled bool NOT NULL DEFAULT false,
+        ADD COLUMN user_totp_last_step bigint,
+        ADD COLUMN user_name_skeleton varchar(160),
+        ADD COLUMN user_show_sfw bool NOT NULL DEFAULT true,
+        ADD COLUMN user_show_nsfw bool NOT NULL DEFAULT false,
+        ADD CONSTRAINT user_name_skeleton_unique UNIQUE (user_name_skeleton);
+
+    COMMENT ON COLUMN p0nygramm.users.user_invite IS E'Invite used for the registration (NULL if registered with the master invite key)';
+    COMMENT ON COLUMN p0nygramm.users.user_totp_secret IS E'Base32 encoded TOTP secret (NULL if 2FA was never enrolled)';
+    COMMENT ON COLUMN p0nygramm.users.user_totp_last_step IS E'Time step of the last accepted TOTP code, prevents the reuse of codes';
+    COMMENT ON COLUMN p0nygramm.users.user_show_sfw IS E'Last content filter set by the user, used for requests with api tokens (they have no session)';
+    COMMENT ON COLUMN p0nygramm.users.user_name_skeleton IS E'Lowercased confusable skeleton (UTS #39) of the username, prevents lookalike usernames (NULL for anonymized users)';
+
+    -- Only the newest vote of a user is kept, the votes are updated in place since the constraints exist
+    DELETE FROM p0nygramm.votes_tum v USING p0nygramm.votes_tum nv
+    WHERE v.vote_tagmap = nv.vote_tagmap AND v.vote_user = nv.vote_user AND v.vote_id < nv.vote_id;
+
+    DELETE FROM p0nygramm.votes_uploads v USING p0nygramm.votes_uploads nv
+    WHERE v.vote_upload = nv.vote_upload AND v.vote_user = nv.vote_user AND v.vote_id < nv.vote_id;
+
+    DELETE FROM p0nygramm.votes_comments v USING p0nygramm.votes_comments nv
+    WHERE v.vote_comment = nv.vote_comment AND v.vote_user = nv.vote_user AND v.vote_id < nv.vote_id;
+
+    ALTER TABLE p0nygramm.votes_tum ADD CONSTRAINT vote_tagmap_user_unique UNIQUE (vote_tagmap, vote_user);
+    ALTER TABLE p0nygramm.votes_uploads ADD CONSTRAINT vote_upload_user_unique UNIQUE (vote_upload, vote_user);
+    ALTER TABLE p0nygramm.votes_comments ADD CONSTRAINT vote_comment_user_unique UNIQUE (vote_comment, vote_user);
+
+    -- See create_tables.sql for the byte indexes of the perceptual hash
+    CREATE INDEX upload_phash_byte0_idx ON p0nygramm.uploads USING btree (((upload_phash >> 0) & 255));
+    CREATE INDEX upload_phash_byte1_idx ON p0nygramm.uploads USING btree (((upload_phash >> 8) & 255));
+    CREATE INDEX upload_phash_byte2_idx ON p0nygramm.uploads USING btree (((upload_phash >> 16) & 255));
+    CREATE INDEX upload_phash_byte3_idx ON p0nygramm.uploads USING btree (((upload_phash >> 24) & 255));
+    CREATE INDEX upload_phash_byte4_idx ON p0nygramm.uploads USING btree (((upload_phash >> 32) & 255));
+    CREATE INDEX upload_phash_byte5_idx ON p0nygramm.uploads USING btree (((upload_phash >> 40) & 255));
+    CREATE INDEX upload_phash_byte6_idx ON p0nygramm.uploads USING btree (((upload_phash >> 48) & 255));
+    CREATE INDEX upload_phash_byte7_idx ON p0nygramm.uploads USING btree (((upload_phash >> 56) & 255));
+
+    ALTER TABLE p0nygramm.invites ADD CONSTRAINT invite_creator_fk FOREIGN KEY (invite_creator)
+    REFERENCES p0nygramm.users (user_id) MATCH FULL
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+    ALTER TABLE p0nygramm.users ADD CONSTRAINT user_invite_fk FOREIGN KEY (user_invite)
+    REFERENCES p0nygramm.invites (invite_id) MATCH SIMPLE
+    ON DELETE SET NULL ON UPDATE CASCADE;
+
+    ALTER TABLE p0nygramm.recovery_codes ADD CONSTRAINT code_user_fk FOREIGN KEY (code_user)
+    REFERENCES p0nygramm.users (user_id) MATCH FULL
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+    ALTER TABLE p0nygramm.api_tokens ADD CONSTRAINT token_user_fk FOREIGN KEY (token_user)
+    REFERENCES p0nygramm.users (user_id) MATCH FULL
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+    ALTER TABLE p0nygramm.audit_events ADD CONSTRAINT event_actor_fk FOREIGN KEY (event_actor)
+    REFERENCES p0nygramm.users (user_id) MATCH SIMPLE
+    ON DELETE SET NULL ON UPDATE CASCADE;
+
+    ALTER TABLE p0nygramm.audit_events ADD CONSTRAINT event_target_fk FOREIGN KEY (event_target)
+    REFERENCES p0nygramm.users (user_id) MATCH SIMPLE
+    ON DELETE SET NULL ON UPDATE CASCADE;
+
+    INSERT INTO p0nygramm.project_kvconfig (kv_key, kv_value_str)
+    VALUES ('schema_version', '2')
+    ON CONFLICT (kv_key)
+    DO UPDATE
+    SET kv_value_str = EXCLUDED.kv_value_str;
+END
+$$;
\ No newline at end of file
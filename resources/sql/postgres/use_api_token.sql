@@ -0,0 +1,7 @@
+-- Input: hashed token
+-- Returns: token_id, token_user, token_name, token_scopes, token_created, token_last_used (nothing if the token is unknown)
+-- Updates the last usage of the token
+UPDATE api_tokens
+SET token_last_used = Now()
+WHERE token_hash = $1::VARCHAR
+RETURNING token_id, token_user, token_name, token_scopes, token_created, token_last_used;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Input: user_id, hashed recovery code
+-- Returns: code_id (nothing if the recovery code is unknown)
+-- A recovery code can only be used once
+DELETE FROM recovery_codes
+WHERE code_user = $1::INT4 AND code_hash = $2::VARCHAR
+RETURNING code_id;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Input: user_id, time step of the verified totp code
+-- Returns: user_id (nothing if the time step or a later one was already used)
+-- Every totp code can only be used once, otherwise an intercepted code could be replayed within its validity
+UPDATE users
+SET user_totp_last_step = $2::INT8
+WHERE user_id = $1::INT4 AND (user_totp_last_step IS NULL OR user_totp_last_step < $2::INT8)
+RETURNING user_id;
\ No newline at end of file
@@ -1,14 +1,20 @@
 -- Input: comment_id, user_id (voter), vote_value
-BEGIN TRANSACTION;
-
-INSERT INTO votes_comments (vote_comment , vote_user, vote_number)
-VALUES ($1::INT4, $2::INT4, $3::INT4)
-ON CONFLICT (vote_comment, vote_user)
-DO UPDATE
-SET vote_number = EXCLUDED.vote_number;
-
+-- Returns: comment_id (nothing if the comment doesn't exist or its upload isn't ready)
+-- A single statement, so that the vote and the counter are changed together
+WITH ready_comment AS (
+    SELECT c.comment_id
+    FROM comments c
+    INNER JOIN uploads u ON c.comment_upload = u.upload_id
+    WHERE c.comment_id = $1::INT4 AND u.upload_status = 'Ready'
+), comment_vote AS (
+    INSERT INTO votes_comments (vote_comment, vote_user, vote_number)
+    SELECT rc.comment_id, $2::INT4, $3::INT4
+    FROM ready_comment rc
+    ON CONFLICT (vote_comment, vote_user)
+    DO UPDATE
+    SET vote_number = EXCLUDED.vote_number
+)
 UPDATE comments
 SET comment_upvotes = comment_upvotes + $3::INT4
-WHERE comment_id = $1::INT4;
-
-COMMIT TRANSACTION;
\ No newline at end of file
+WHERE comment_id IN (SELECT comment_id FROM ready_comment)
+RETURNING comment_id;
\ No newline at end of file
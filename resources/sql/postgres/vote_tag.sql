@@ -1,17 +1,20 @@
--- Input: upload_id, tag_id, user_id (voter), vote_value
-BEGIN TRANSACTION;
-
-INSERT INTO votes_tum (vote_tagmap, vote_user, vote_number)
-SELECT tum.tum_id, $3::INT4, $4::INT4
-FROM tag_upload_map AS tum
-INNER JOIN votes_tum AS v_tum ON tum.tum_id = vote_tagmap
-WHERE tum.tag_id = $2::INT4 AND tum.upload_id = $1::INT4
-ON CONFLICT (vote_tagmap, vote_user)
-DO UPDATE
-SET vote_number = EXCLUDED.vote_number;
-
+-- Input: tum_id, user_id (voter), vote_value
+-- Returns: tum_id (nothing if the tag isn't assigned or its upload isn't ready)
+-- A single statement, so that the vote and the counter are changed together
+WITH ready_tum AS (
+    SELECT tum.tum_id
+    FROM tag_upload_map tum
+    INNER JOIN uploads u ON tum.upload_id = u.upload_id
+    WHERE tum.tum_id = $1::INT4 AND u.upload_status = 'Ready'
+), tum_vote AS (
+    INSERT INTO votes_tum (vote_tagmap, vote_user, vote_number)
+    SELECT rt.tum_id, $2::INT4, $3::INT4
+    FROM ready_tum rt
+    ON CONFLICT (vote_tagmap, vote_user)
+    DO UPDATE
+    SET vote_number = EXCLUDED.vote_number
+)
 UPDATE tag_upload_map
 SET tag_upvotes = tag_upvotes + $3::INT4
-WHERE upload_id = $1::INT4 AND tag_id = $2::INT4;
-
-COMMIT TRANSACTION;
\ No newline at end of file
+WHERE tum_id IN (SELECT tum_id FROM ready_tum)
+RETURNING tum_id;
\ No newline at end of file
@@ -1,14 +1,19 @@
 -- Input: upload_id, user_id (voter), vote_value
-BEGIN TRANSACTION;
-
-INSERT INTO votes_uploads (vote_upload, vote_user, vote_number)
-VALUES ($1::INT4, $2::INT4, $3::INT4)
-ON CONFLICT (vote_upload, vote_user)
-DO UPDATE
-SET vote_number = EXCLUDED.vote_number;
-
+-- Returns: upload_id (nothing if the upload doesn't exist or isn't ready)
+-- A single statement, so that the vote and the counter are changed together
+WITH ready_upload AS (
+    SELECT u.upload_id
+    FROM uploads u
+    WHERE u.upload_id = $1::INT4 AND u.upload_status = 'Ready'
+), upload_vote AS (
+    INSERT INTO votes_uploads (vote_upload, vote_user, vote_number)
+    SELECT ru.upload_id, $2::INT4, $3::INT4
+    FROM ready_upload ru
+    ON CONFLICT (vote_upload, vote_user)
+    DO UPDATE
+    SET vote_number = EXCLUDED.vote_number
+)
 UPDATE uploads
 SET upload_upvotes = upload_upvotes + $3::INT4
-WHERE upload_id = $1::INT4;
-
-COMMIT TRANSACTION;
\ No newline at end of file
+WHERE upload_id IN (SELECT upload_id FROM ready_upload)
+RETURNING upload_id;
\ No newline at end of file
@@ -43,6 +43,24 @@ function js_register() {
     api_register(register_data.username, register_data.password, register_data.invite_key, js_register_callback);
 }
 
+function js_reset_password() {
+    // Read input data
+    var reset_password_data = ui_get_reset_password_data();
+
+    // Check password
+    if (reset_password_data.password !== reset_password_data.rpassword) {
+        ui_report_msg("Die eingegebenen Passwörter stimmen nicht überein!", ui_message_output_channel.reset_password, ui_message_type.error);
+
+        return;
+    }
+
+    // Disable submit button
+    ui_set_reset_password_state(ui_btn_state.deactivate);
+
+    // Send new password
+    api_reset_password(reset_password_data.reset_token, reset_password_data.password, js_reset_password_callback);
+}
+
 // Callback functions
 function js_display_uploads_prv_callback(response_code, response_content, callback_param) {
     // Handle backend errors
@@ -87,6 +105,29 @@ function js_logout_callback(response_code, response_content, callback_param) {
     ui_page_reload();
 }
 
+function js_reset_password_callback(response_code, response_content, callback_param) {
+    // Handle backend errors
+    if (response_code != 200) {
+        var error_msg = response_content.error_msg;
+
+        ui_report_msg(error_msg, ui_message_output_channel.reset_password, ui_message_type.error);
+        ui_set_reset_password_state(ui_btn_state.activate);
+
+        // The backend sends a new link, if the used one was used up by the failed reset
+        if (response_content.reset_url) {
+            ui_set_reset_url(response_content.reset_url);
+        }
+
+        return;
+    }
+
+    // Report reset success
+    ui_report_msg("Das Passwort wurde geändert. Sie werden in Kürze zur Anmeldung weitergeleitet.", ui_message_output_channel.reset_password, ui_message_type.success);
+
+    // Go to the login page after two seconds
+    window.setTimeout(function() { location.href = "/"; }, 2000);
+}
+
 function js_register_callback(response_code, response_content, callback_param) {
     // Handle backend errors
     if (response_code != 200) {
@@ -6,7 +6,10 @@
 // Parameter 3: Caller parameter
 
 const httpTimeout = 10000 // 10 seconds
-const httpTimeoutFileUpload = 120000 // 2 minutes
+const httpTimeoutFileUpload = 120000 // 2 minutes (per chunk)
+const uploadChunkSize = 4194304 // 4 MB, an interrupted upload only has to repeat the current chunk
+const uploadMaxRetries = 5 // Number of attempts to resume an interrupted upload in a row
+const uploadRetryDelay = 2000 // 2 seconds (multiplied with the number of the attempt)
 
 // POST data formats:
 const post_urlencoded = 1;
@@ -31,7 +34,7 @@ function api_login(username, password, keep_logged_in, callback, callback_param)
 }
 
 function api_logout(callback, callback_param) {
-    api_send_http_request("GET", null, "/js-api/logout", null, callback, null, callback_param);
+    api_send_http_request("POST", null, "/js-api/logout", null, callback, null, callback_param);
 }
 
 function api_register(username, password, invite_key, callback, callback_param) {
@@ -43,24 +46,55 @@ function api_register(username, password, invite_key, callback, callback_param)
     api_send_http_request("POST", post_urlencoded, "/js-api/register", url_encoded_form_data, callback, null, callback_param);
 }
 
+function api_reset_password(reset_token, new_password, callback, callback_param) {
+    var url_path = "/js-api/reset_password/" + reset_token;
+    var url_encoded_form_data = "new_password=" + encodeURIComponent(new_password);
+
+    api_send_http_request("POST", post_urlencoded, url_path, url_encoded_form_data, callback, null, callback_param);
+}
+
 function api_set_filter(show_sfw, show_nsfw, callback, callback_param) {
     var url_path = "/js-api/set_filter/" + show_sfw + "/" + show_nsfw;
 
-    api_send_http_request("GET", null, url_path, null, callback, null, callback_param);
+    api_send_http_request("PUT", null, url_path, null, callback, null, callback_param);
+}
+
+// Uploads the file in chunks, interrupted chunks are resumed from the offset the server has received
+// The response of the last chunk contains the upload result (the file is processed in the background afterwards)
+function api_upload_file(file, classification, taglist, callback, progress_callback, callback_param) {
+    var url_encoded_form_data = "filename=" + encodeURIComponent(file.name);
+    url_encoded_form_data += "&upload_size=" + file.size;
+    url_encoded_form_data += "&classification=" + encodeURIComponent(classification);
+    url_encoded_form_data += "&taglist=" + encodeURIComponent(taglist);
+
+    api_send_http_request("POST", post_urlencoded, "/js-api/create_upload_session", url_encoded_form_data, function(status, response) {
+        if (status != 200) {
+            callback(status, response, callback_param);
+            return;
+        }
+
+        var upload_session = JSON.parse(response);
+        api_upload_file_chunks(upload_session, file, 0, callback, progress_callback, callback_param);
+    }, null, null);
 }
 
 // Helper functions
+// The csrf token is embedded into the index page and has to be send with every state changing request
+function api_get_csrf_token() {
+    var csrf_meta = document.querySelector("meta[name='csrf-token']");
+
+    if (csrf_meta === null) {
+        return "";
+    }
+
+    return csrf_meta.getAttribute("content");
+}
+
 function api_send_http_request(method, post_data_format, path, content, callback, progress_callback, callback_param) {
     var xhttp = new XMLHttpRequest();
     xhttp.timeout = httpTimeout;
     xhttp_ref = xhttp;
 
-    // Set options for post content type
-    // In case of multipart form data we don't set that field here (the FormData object sets that)
-    if (post_data_format == post_urlencoded) {
-        xhttp.setRequestHeader("Content-Type", "application/x-www-form-urlencoded");
-    }
-
     // Request finished handler
     xhttp.onreadystatechange = function() {
         if (this.readyState == 3) {
@@ -98,10 +132,120 @@ function api_send_http_request(method, post_data_format, path, content, callback
 
     xhttp.open(method, path, true);
 
+    // Request headers can only be set after open()
+    if (method != "GET") {
+        xhttp.setRequestHeader("X-CSRF-Token", api_get_csrf_token());
+    }
+
+    // Set options for post content type
+    // In case of multipart form data we don't set that field here (the FormData object sets that)
+    if (post_data_format == post_urlencoded) {
+        xhttp.setRequestHeader("Content-Type", "application/x-www-form-urlencoded");
+    }
+
     if (content === null) {
         xhttp.send();
     }
     else {
         xhttp.send(content);
     }
+}
+
+// Asks the server how much of the file was received and continues the upload from there
+function api_resume_upload(upload_session, file, retry_count, callback, progress_callback, callback_param) {
+    var url_path = "/js-api/get_upload_session/" + upload_session.upload_session_id;
+
+    api_send_http_request("GET", null, url_path, null, function(status, response) {
+        if (status == 200) {
+            api_upload_file_chunks(JSON.parse(response), file, retry_count, callback, progress_callback, callback_param);
+        }
+        else if (status == 503 && retry_count < uploadMaxRetries) {
+            setTimeout(function() {
+                api_resume_upload(upload_session, file, retry_count + 1, callback, progress_callback, callback_param);
+            }, uploadRetryDelay * (retry_count + 1));
+        }
+        else {
+            callback(status, response, callback_param);
+        }
+    }, null, null);
+}
+
+// The chunk is send as raw body, the Upload-Offset header tells the server where the chunk starts
+function api_send_upload_chunk(upload_session_id, upload_offset, chunk, callback, progress_callback) {
+    var xhttp = new XMLHttpRequest();
+    xhttp.timeout = httpTimeoutFileUpload;
+    xhttp_ref = xhttp;
+
+    xhttp.onreadystatechange = function() {
+        if (this.readyState == 4) {
+            xhttp_ref = null;
+
+            // Connection errors have no status code
+            if (this.status == 0) {
+                callback(503, null);
+            }
+            else {
+                callback(this.status, this.response);
+            }
+        }
+    };
+
+    xhttp.ontimeout = function() {
+        callback(503, null);
+    };
+
+    if (progress_callback != null) {
+        xhttp.upload.onprogress = function(event) {
+            progress_callback(event.loaded);
+        };
+    }
+
+    xhttp.open("PATCH", "/js-api/append_upload_chunk/" + upload_session_id, true);
+    xhttp.setRequestHeader("X-CSRF-Token", api_get_csrf_token());
+    xhttp.setRequestHeader("Content-Type", "application/offset+octet-stream");
+    xhttp.setRequestHeader("Upload-Offset", upload_offset);
+    xhttp.send(chunk);
+}
+
+function api_upload_file_chunks(upload_session, file, retry_count, callback, progress_callback, callback_param) {
+    var chunk_start = upload_session.upload_offset;
+    var chunk_end = Math.min(chunk_start + uploadChunkSize, upload_session.upload_size);
+    var chunk_progress_callback = null;
+
+    // The progress is reported for the whole file
+    if (progress_callback != null) {
+        chunk_progress_callback = function(chunk_loaded) {
+            var progress = Math.round(100 / upload_session.upload_size * (chunk_start + chunk_loaded));
+
+            if (progress > last_send_progress) {
+                last_send_progress = progress;
+                progress_callback(progress);
+            }
+        };
+    }
+
+    api_send_upload_chunk(upload_session.upload_session_id, chunk_start, file.slice(chunk_start, chunk_end), function(status, response) {
+        if (status == 200) {
+            var new_upload_session = JSON.parse(response);
+
+            if (new_upload_session.upload_offset < new_upload_session.upload_size) {
+                api_upload_file_chunks(new_upload_session, file, 0, callback, progress_callback, callback_param);
+            }
+            else {
+                last_send_progress = -1;
+                callback(status, response, callback_param);
+            }
+        }
+        // Timeouts, server errors and offset conflicts are resumed with the offset the server has stored
+        // Duplicate uploads are reported with 409 too, but can't be resumed
+        else if ((status >= 500 || (status == 409 && JSON.parse(response).error_code != "DuplicateUpload")) && retry_count < uploadMaxRetries) {
+            setTimeout(function() {
+                api_resume_upload(upload_session, file, retry_count + 1, callback, progress_callback, callback_param);
+            }, uploadRetryDelay * (retry_count + 1));
+        }
+        else {
+            last_send_progress = -1;
+            callback(status, response, callback_param);
+        }
+    }, chunk_progress_callback);
 }
\ No newline at end of file
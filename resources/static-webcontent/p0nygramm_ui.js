@@ -18,6 +18,13 @@ const ui_log_password = "log_password";
 const ui_keep_logged_in = "log_keep_logged_in";
 const ui_login_output_element = "div_login_error";
 
+// Password reset elements
+const ui_reset_token = "reset_token";
+const ui_reset_password = "reset_password";
+const ui_reset_rpassword = "reset_rpassword";
+const ui_reset_btn = "reset_submit";
+const ui_reset_output_element = "div_reset_error";
+
 // Overlay elements
 const ui_overlay_container_id = "overlay_container";
 const ui_overlay_msg_id = "overlay_msg_txt";
@@ -41,7 +48,8 @@ const ui_message_output_channel = {
     login: 0,
     register: 1,
     overlay: 2,
-    content_section: 3
+    content_section: 3,
+    reset_password: 4
 };
 
 const ui_message_type = {
@@ -167,6 +175,16 @@ function ui_get_register_data() {
     return register_data;
 }
 
+function ui_get_reset_password_data() {
+    var reset_password_data = {
+        reset_token: document.getElementById(ui_reset_token).value,
+        password: document.getElementById(ui_reset_password).value,
+        rpassword: document.getElementById(ui_reset_rpassword).value
+    };
+
+    return reset_password_data;
+}
+
 // Hide message
 function ui_hide_msg(output_channel) {
     var output_element = null;
@@ -178,6 +196,9 @@ function ui_hide_msg(output_channel) {
     else if (output_channel === ui_message_output_channel.register) {
         output_element = document.getElementById(ui_register_output_element);
     }
+    else if (output_channel === ui_message_output_channel.reset_password) {
+        output_element = document.getElementById(ui_reset_output_element);
+    }
     else if (output_channel === ui_message_output_channel.overlay) {
         ui_close_overlay();
 
@@ -201,6 +222,17 @@ function ui_set_auth_state(btn_state) {
     }
 }
 
+// Enable or disable the submit button of the password reset page
+function ui_set_reset_password_state(btn_state) {
+    document.getElementById(ui_reset_btn).disabled = (btn_state === ui_btn_state.deactivate);
+}
+
+// Use the new reset link for the next try (the used link isn't valid anymore)
+function ui_set_reset_url(reset_url) {
+    document.getElementById(ui_reset_token).value = reset_url.substring(reset_url.lastIndexOf("/") + 1);
+    window.history.replaceState(null, "", reset_url);
+}
+
 function ui_set_uploads_prv(uploads) {
     active_upload_prv_objects = uploads;
 }
@@ -221,6 +253,9 @@ function ui_report_msg(message, output_channel, message_type) {
     else if (output_channel === ui_message_output_channel.register) {
         output_element = document.getElementById(ui_register_output_element);
     }
+    else if (output_channel === ui_message_output_channel.reset_password) {
+        output_element = document.getElementById(ui_reset_output_element);
+    }
     else if (output_channel === ui_message_output_channel.overlay) {
         output_element = document.getElementById(ui_overlay_msg_id);
     }
@@ -1,25 +1,71 @@
 pub mod request_data;
 pub mod response_result;
 
-use actix_web::{web};
+use actix_web::{web, HttpRequest};
 use crate::config::ProjectConfig;
 use actix_session::Session;
 use crate::db_api::DbConnection;
-use crate::security::{get_user_session, check_username, check_password, verify_password, check_invite_key, hash_password, check_filename};
-use crate::db_api::db_result::{DbApiErrorType, UploadPrvList, UploadData};
+use crate::security::{get_user_session, check_and_normalize_username, check_password, verify_password, check_invite_key, hash_password, sanitize_filename, generate_invite_key, get_client_ip, get_client_user_agent, check_totp_code, check_recovery_code, check_reset_token, generate_api_token, get_user_session_or_api_token, hash_random_token, generate_recovery_codes, generate_totp_secret, get_totp_uri, get_username_skeleton, purge_session, verify_totp_code, PasswordVerification};
+use crate::config::{AccountDeletionMode, SimilarUploadMode};
+use crate::db_api::db_result::{ApiTokenList, ApiTokenScope, AuditAction, AuditEventList, DbApiErrorType, UploadPrvList, UploadData, UploadSessionData, UploadStatus, UploadStatusData, UserRole, InviteData, InviteList, SessionInfoList, UserDataExport};
+use crate::db_api::db_result;
+use crate::db_api::db_result::SessionErrorType;
 use crate::db_api::db_result::SessionErrorType::DbError;
-use crate::backend_api::response_result::ErrorCode::{DatabaseError, Unauthorized, UserInputError, NoResult, Ignored, UnknownError, CookieError, InternalError};
+use crate::backend_api::response_result::ErrorCode::{DatabaseError, Unauthorized, UserInputError, NoResult, Ignored, UnknownError, CookieError, InternalError, UserBanned, Forbidden, TooManyRequests, TotpRequired, DuplicateUpload, PayloadTooLarge};
 use actix_multipart::{Multipart, Field};
 use futures::{StreamExt, TryStreamExt};
-use std::collections::HashMap;
-use log::{error};
+use sha2::{Digest, Sha256};
+use log::{error, warn};
 use tokio::io::AsyncWriteExt;
-use crate::file_api::{get_upload_path_tmp, process_file, delete_upload_srv};
-use crate::file_api::FileProcessErrorType::FormatError;
+use crate::file_api::{append_upload_chunks, generate_file_hash, generate_upload_filename, get_upload_path_tmp, create_upload_archive, delete_upload_srv, delete_upload_tmp, is_image_file, is_video_file};
 use crate::db_api::db_result::DbApiErrorType::PartFail;
-use crate::backend_api::request_data::{CommentData, TagData, LoginData, RegisterData, check_file_mime, check_form_content_mime,};
-use crate::backend_api::response_result::{BackendError, SuccessReport, AddUploadSuccess, UserExists, Filter, UserData};
+use crate::backend_api::request_data::{AuditEventFilter, BanUserData, ChangePasswordData, CommentData, CreateApiTokenData, CreateUploadSessionData, DeleteAccountData, TagData, LoginData, MultipartFile, MultipartFormData, RegisterData, ResetPasswordData, TotpCodeData, TotpDisableData, check_file_mime, check_form_content_mime,};
+use crate::backend_api::response_result::{ApiTokenCreated, BackendError, PasswordResetCreated, SuccessReport, AddUploadSuccess, UserExists, Filter, UserData, TotpEnrollment, TotpRecoveryCodes, UploadSessionStatus};
 use actix_web::http::StatusCode;
+use std::convert::TryFrom;
+use actix_files::NamedFile;
+use chrono::Local;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+
+const MAX_FORM_FIELD_SIZE : usize = 64 * 1024; // Maximal size of a single text field of multipart form data in bytes
+const MAX_FORM_FIELDS_SIZE : usize = 256 * 1024; // Text fields can add this many bytes to the file size limit of a multipart request
+const PENDING_LOGIN_MAX_ATTEMPTS : u32 = 5; // Wrong 2FA codes after which a pending login becomes invalid
+
+// Returns with a TooManyRequests error if the rate limit counter is locked
+macro_rules! check_rate_limit {
+    ($db_connection:ident, $counter_name:expr) => {
+        let lock_duration = $db_connection.get_rate_limit_lock($counter_name).await;
+
+        if lock_duration.is_err() {
+            let error = lock_duration.err().unwrap();
+            let error_txt = error.error_msg;
+
+            handle_error_str!(DatabaseError, error_txt.as_str(), INTERNAL_SERVER_ERROR);
+        }
+
+        let lock_duration = lock_duration.ok().unwrap();
+
+        if lock_duration > 0 {
+            handle_rate_limit!(lock_duration);
+        }
+    };
+}
+
+// Counts an attempt for all given rate limit counters and returns with a TooManyRequests error if one of them got locked
+macro_rules! count_rate_limit_attempt {
+    ($config:ident, $db_connection:ident, $max_attempts:expr, $( $counter_name:expr ),+) => {
+        let mut lock_duration : u32 = 0;
+
+        $(
+            let counter_lock_duration = register_rate_limit_attempt(&$config, &$db_connection, $counter_name, $max_attempts).await;
+            lock_duration = lock_duration.max(counter_lock_duration);
+        )+
+
+        if lock_duration > 0 {
+            handle_rate_limit!(lock_duration);
+        }
+    };
+}
 
 macro_rules! get_db_connection {
     ($config:ident, $req_postgres:expr, $req_redis:expr) => {
@@ -35,6 +81,7 @@ macro_rules! get_db_connection {
     };
 }
 
+// The variant with request and token scope also accepts api tokens (Authorization: Bearer) instead of the session cookie
 macro_rules! get_user_session_data {
     ($db_connection:ident, $session:ident, $force_session_renew:expr) => {
         {
@@ -44,6 +91,18 @@ macro_rules! get_user_session_data {
                 handle_session_error!(user_session);
             }
 
+            user_session.ok().unwrap()
+        }
+    };
+    ($db_connection:ident, $session:ident, $request:ident, $force_session_renew:expr, $token_scope:expr) => {
+        {
+            let user_session = get_user_session_or_api_token(&$db_connection, &$session, &$request,
+                                                             $force_session_renew, $token_scope).await;
+
+            if user_session.is_err() {
+                handle_session_error!(user_session);
+            }
+
             user_session.ok().unwrap()
         }
     };
@@ -66,6 +125,16 @@ macro_rules! handle_error_str {
     };
 }
 
+macro_rules! handle_rate_limit {
+    ($lock_duration:expr) => {
+        let error_msg = format!("Zu viele Versuche, bitte in {} Sekunden erneut versuchen", $lock_duration);
+        let backend_error = BackendError::new_with_retry_after(StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                                                               TooManyRequests, error_msg.as_str(), $lock_duration);
+
+        return Err(backend_error);
+    };
+}
+
 macro_rules! handle_session_error {
     ($user_session:ident) => {
         let error = $user_session.err().unwrap();
@@ -73,6 +142,12 @@ macro_rules! handle_session_error {
         let error_code = if error.error_type == DbError {
             DatabaseError
         }
+        else if error.error_type == SessionErrorType::UserBanned {
+            UserBanned
+        }
+        else if error.error_type == SessionErrorType::MissingScope {
+            Forbidden
+        }
         else {
             Unauthorized
         };
@@ -86,9 +161,43 @@ macro_rules! handle_session_error {
     };
 }
 
-pub async fn add_comment(config: &web::Data<ProjectConfig>, session: &Session, comment_data: &web::Form<CommentData>) -> Result<SuccessReport, BackendError> {
+// Returns the userdata of the logged in user, if the user has at least the required role
+macro_rules! require_user_role {
+    ($db_connection:ident, $session_data:ident, $required_role:expr) => {
+        {
+            let own_user_data = $db_connection.get_userdata_by_id($session_data.user_id).await;
+
+            if own_user_data.is_err() {
+                let error = own_user_data.err().unwrap();
+                let error_txt = error.error_msg;
+
+                handle_error_str!(DatabaseError, error_txt.as_str(), INTERNAL_SERVER_ERROR);
+            }
+
+            let own_user_data = own_user_data.ok().unwrap();
+
+            if own_user_data.get_user_role() < $required_role {
+                handle_error_str!(Forbidden, "Für diese Aktion fehlt die nötige Berechtigung", FORBIDDEN);
+            }
+
+            own_user_data
+        }
+    };
+}
+
+// Writes the action to the audit log, errors are only logged because the action itself was already successful
+async fn add_audit_event(db_connection: &DbConnection, audit_action: AuditAction, actor_id: Option<i32>, target_id: Option<i32>,
+                         client_ip: Option<&str>, event_details: Option<&str>) {
+    let add_result = db_connection.add_audit_event(audit_action, actor_id, target_id, client_ip, event_details).await;
+
+    if add_result.is_err() {
+        error!("add_audit_event: Failed to write {} event to the audit log", audit_action.as_str());
+    }
+}
+
+pub async fn add_comment(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, comment_data: &web::Form<CommentData>) -> Result<SuccessReport, BackendError> {
     let db_connection = get_db_connection!(config, true, true);
-    let session_data = get_user_session_data!(db_connection, session, false);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Comment);
 
     let validated_comment_data = comment_data.validate_data(&db_connection).await;
 
@@ -107,6 +216,11 @@ pub async fn add_comment(config: &web::Data<ProjectConfig>, session: &Session, c
             let error = post_result.err().unwrap();
             let error_msg = error.error_msg;
 
+            // Uploads can't be commented while they are processed or after the processing failed
+            if error.error_type == DbApiErrorType::NoResult {
+                handle_error_str!(NoResult, error_msg.as_str(), NOT_FOUND);
+            }
+
             handle_error_str!(InternalError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
         }
     }
@@ -115,13 +229,19 @@ pub async fn add_comment(config: &web::Data<ProjectConfig>, session: &Session, c
     }
 }
 
-pub async fn add_upload(config: &web::Data<ProjectConfig>, session: &Session, payload: &mut Multipart) -> Result<AddUploadSuccess, BackendError> {
+pub async fn add_upload(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, payload: &mut Multipart) -> Result<AddUploadSuccess, BackendError> {
     let db_connection = get_db_connection!(config, true, true);
-    let session_data = get_user_session_data!(db_connection, session, false);
-    let multipart_data = parse_multipart_form_data(payload, false).await;
-    let taglist_str = multipart_data.get("taglist");
-    let filename = multipart_data.get("file");
-    let upload_classification = multipart_data.get("classification");
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Upload);
+    let multipart_data = parse_multipart_form_data(&config, payload, false).await;
+
+    if multipart_data.is_err() {
+        return Err(multipart_data.err().unwrap());
+    }
+
+    let multipart_data = multipart_data.ok().unwrap();
+    let taglist_str = multipart_data.form_fields.get("taglist");
+    let upload_file = multipart_data.files.get("file");
+    let upload_classification = multipart_data.form_fields.get("classification");
     let mut upload_is_nsfw = false;
 
     if upload_classification.is_some() {
@@ -138,187 +258,248 @@ pub async fn add_upload(config: &web::Data<ProjectConfig>, session: &Session, pa
         handle_error_str!(UserInputError, "Upload muss entweder als SFW oder als NSFW gekennzeichnet sein", BAD_REQUEST);
     }
 
-    if filename.is_some() {
-        let filename = filename.unwrap();
-        let filename_is_ok = check_filename(filename.as_str());
+    if upload_file.is_some() {
+        let upload_file = upload_file.unwrap();
+        let taglist_str = taglist_str.map(|taglist_str| taglist_str.as_str());
+
+        return queue_upload(&db_connection, session_data.user_id, upload_file, upload_is_nsfw, taglist_str).await;
+    }
+    else {
+        handle_error_str!(UnknownError, "Es ist ein Fehler beim Speichern der Datei auf dem Server aufgetreten", INTERNAL_SERVER_ERROR);
+    }
+}
+
+// Appends the request body to a chunked upload, the Upload-Offset header has to contain the number of already received bytes
+// Interrupted requests keep the received part, the client can ask for the current offset with get_upload_session
+// The upload is added to the upload queue after the last chunk
+pub async fn append_upload_chunk(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<String>,
+                                 payload: &mut web::Payload) -> Result<UploadSessionStatus, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Upload);
+    let upload_session_id = url_data.as_str();
+    let upload_session = get_own_upload_session(&db_connection, upload_session_id, session_data.user_id).await;
+
+    if upload_session.is_err() {
+        return Err(upload_session.err().unwrap());
+    }
 
-        if filename_is_ok {
-            let file_process_success = process_file(&config, filename).await;
+    let chunk_offset = request.headers().get("Upload-Offset")
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|header_value| header_value.parse::<u64>().ok());
 
-            if file_process_success.is_ok() {
-                let file_process_success = file_process_success.ok().unwrap();
-                let uploader_id = session_data.user_id;
-                let upload_type = file_process_success.upload_type;
-                let db_success = db_connection.add_upload(filename, upload_is_nsfw, upload_type, uploader_id).await;
+    if chunk_offset.is_none() {
+        handle_error_str!(UserInputError, "Upload-Offset Header fehlt oder ist ungültig", BAD_REQUEST);
+    }
 
-                if db_success.is_ok() {
-                    let upload_id = db_success.ok().unwrap();
+    let lock_result = db_connection.lock_upload_session(upload_session_id).await;
 
-                    if taglist_str.is_some() {
-                        let taglist_str = taglist_str.unwrap();
-                        let taglist_data = TagData::from_str(taglist_str);
-                        let taglist_full_success = taglist_data.full_success;
-                        let taglist_vec = taglist_data.as_str_ref_vec();
+    if lock_result.is_err() {
+        handle_error_str!(DatabaseError, lock_result.err().unwrap().error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+    else if lock_result.as_ref().ok().unwrap().is_none() {
+        handle_error_str!(UserInputError, "Der Upload wird bereits von einer anderen Anfrage übertragen", CONFLICT);
+    }
 
-                        let db_success = db_connection.add_tags(taglist_vec, uploader_id, upload_id).await;
+    let lock_token = lock_result.ok().unwrap().unwrap();
 
-                        if db_success.is_ok() {
-                            let ret_val = AddUploadSuccess::new(true, upload_id, true, taglist_full_success);
+    // The offset could have changed while another request was holding the lock
+    let upload_session = get_own_upload_session(&db_connection, upload_session_id, session_data.user_id).await;
+    let write_result = match upload_session {
+        Ok(upload_session) => write_upload_chunk(&db_connection, upload_session, chunk_offset.unwrap(), payload).await,
+        Err(error) => Err(error),
+    };
 
-                            return Ok(ret_val);
-                        }
-                        else {
-                            let error = db_success.err().unwrap();
-                            let error_type = error.error_type;
+    let _unlock_result = db_connection.unlock_upload_session(upload_session_id, lock_token.as_str()).await;
 
-                            if error_type == PartFail {
-                                let ret_val = AddUploadSuccess::new(true, upload_id, true, false);
+    if write_result.is_err() {
+        return Err(write_result.err().unwrap());
+    }
 
-                                return Ok(ret_val);
-                            }
-                        }
-                    }
+    let upload_session = write_result.ok().unwrap();
+    let mut upload_session_status = UploadSessionStatus::new(&upload_session);
 
-                    let ret_val = AddUploadSuccess::new(true, upload_id, false, false);
+    if upload_session.upload_offset < upload_session.upload_size {
+        return Ok(upload_session_status);
+    }
 
-                    return Ok(ret_val);
-                }
-                else {
-                    delete_upload_srv(&config, filename).await;
+    // The tmp file is either queued or deleted now, so the upload can't be resumed anymore
+    let _destroy_result = db_connection.destroy_upload_session(upload_session_id).await;
+    let filename = upload_session.storage_filename.as_str();
+    let file_hash = generate_file_hash(filename).await;
 
-                    let error = db_success.err().unwrap();
-                    let error_msg = error.error_msg;
-                    handle_error_str!(InternalError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
-                }
-            }
-            else {
-                let error = file_process_success.err().unwrap();
-                let error_type = error.error_code;
-                let error_msg = error.error_msg;
+    if file_hash.is_none() {
+        delete_upload_tmp(filename).await;
 
-                if error_type == FormatError {
-                    handle_error_str!(UserInputError, error_msg.as_str(), BAD_REQUEST);
-                }
-                else {
-                    handle_error_str!(InternalError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
-                }
-            }
-        }
-        else {
-            handle_error_str!(UserInputError, "Dateiname enthält ungültige Zeichen", BAD_REQUEST);
-        }
+        handle_error_str!(InternalError, "Es ist ein Fehler beim Lesen der Datei auf dem Server aufgetreten", INTERNAL_SERVER_ERROR);
     }
-    else {
-        handle_error_str!(UnknownError, "Es ist ein Fehler beim Speichern der Datei auf dem Server aufgetreten", INTERNAL_SERVER_ERROR);
+
+    let taglist_str = Some(upload_session.upload_taglist.as_str()).filter(|taglist_str| !taglist_str.is_empty());
+    let upload_file = MultipartFile::new(filename, upload_session.original_filename.as_str(), file_hash.unwrap().as_str());
+    let queue_result = queue_upload(&db_connection, session_data.user_id, &upload_file, upload_session.upload_is_nsfw, taglist_str).await;
+
+    if queue_result.is_err() {
+        return Err(queue_result.err().unwrap());
     }
+
+    upload_session_status.set_upload_result(queue_result.ok().unwrap());
+
+    return Ok(upload_session_status);
 }
 
-pub async fn check_username_exists(config: &web::Data<ProjectConfig>, url_data: &web::Path<String>) -> Result<UserExists, BackendError> {
-    let db_connection = get_db_connection!(config, true, false);
+pub async fn ban_user(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, ban_data: &web::Form<BanUserData>) -> Result<SuccessReport, BackendError> {
+    let validated_ban_data = ban_data.validate_data();
 
-    let username = url_data.as_str();
-    let username_is_ok = check_username(username);
+    if validated_ban_data.is_none() {
+        handle_error_str!(UserInputError, "Übergebene Daten konnten nicht validiert werden", BAD_REQUEST);
+    }
 
-    if !username_is_ok {
-        handle_error_str!(UserInputError, "Benutzername entspricht nicht den Richtlinien", BAD_REQUEST);
+    let validated_ban_data = validated_ban_data.unwrap();
+    let target_user_id = validated_ban_data.user_id;
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let own_user_data = require_user_role!(db_connection, session_data, UserRole::Moderator);
+
+    if target_user_id == session_data.user_id {
+        handle_error_str!(UserInputError, "Man kann sich nicht selbst sperren", BAD_REQUEST);
     }
 
-    let user_exists = db_connection.check_user_exists(username).await;
+    let target_user_data = db_connection.get_userdata_by_id(target_user_id).await;
 
-    if user_exists.is_ok() {
-        let user_exists = user_exists.ok().unwrap();
-        let user_exists_obj = UserExists::new(user_exists);
+    if target_user_data.is_err() {
+        let error = target_user_data.err().unwrap();
 
-        return Ok(user_exists_obj);
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
     }
-    else {
-        let error = user_exists.err().unwrap();
-        let error_msg = error.error_msg;
 
-        handle_error_str!(InternalError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    if target_user_data.ok().unwrap().get_user_role() >= own_user_data.get_user_role() {
+        handle_error_str!(Forbidden, "Benutzer mit gleicher oder höherer Rolle können nicht gesperrt werden", FORBIDDEN);
     }
-}
 
-pub async fn get_filter(config: &web::Data<ProjectConfig>, session: &Session) -> Result<Filter, BackendError> {
-    let db_connection = get_db_connection!(config, true, true);
-    let _session_data = get_user_session_data!(db_connection, session, false);
+    let ban_reason = validated_ban_data.ban_reason.as_str();
+    let ban_duration = validated_ban_data.ban_duration;
+    let ban_result = db_connection.add_user_ban(target_user_id, ban_reason, ban_duration).await;
 
-    let show_sfw = session.get::<bool>("show_sfw");
-    let show_nsfw = session.get::<bool>("show_nsfw");
+    if ban_result.is_err() {
+        handle_error_str!(DatabaseError, "Fehler beim Speichern der Sperre", INTERNAL_SERVER_ERROR);
+    }
 
-    if show_sfw.is_ok() && show_nsfw.is_ok() {
-        let show_sfw = show_sfw.unwrap();
-        let show_nsfw = show_nsfw.unwrap();
+    let client_ip = get_client_ip(&config, request);
+    let ban_details = format!("{} Stunden", ban_duration);
+    add_audit_event(&db_connection, AuditAction::UserBan, Some(session_data.user_id), Some(target_user_id),
+                    Some(client_ip.as_str()), Some(ban_details.as_str())).await;
 
-        if show_sfw.is_some() && show_nsfw.is_some() {
-            let show_sfw = show_sfw.unwrap();
-            let show_nsfw = show_nsfw.unwrap();
+    // Kick the banned user out of all active sessions
+    let destroy_result = db_connection.destroy_user_sessions(target_user_id, None).await;
 
-            let filter_obj = Filter::new(show_sfw, show_nsfw);
+    if destroy_result.is_err() {
+        handle_error_str!(DatabaseError, "Die Sperre wurde gespeichert, aber die Sessions des Benutzers konnten nicht beendet werden", INTERNAL_SERVER_ERROR);
+    }
 
-            return Ok(filter_obj);
-        }
+    return Ok(SuccessReport::new(true));
+}
+
+// Aborts a chunked upload and deletes the already received part
+pub async fn cancel_upload_session(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<String>) -> Result<SuccessReport, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Upload);
+    let upload_session_id = url_data.as_str();
+    let upload_session = get_own_upload_session(&db_connection, upload_session_id, session_data.user_id).await;
+
+    if upload_session.is_err() {
+        return Err(upload_session.err().unwrap());
     }
 
-    // If filter is not set: Set show_sfw = true and show_nsfw to false
-    let set_result_1 = session.set("show_sfw", true);
-    let set_result_2 = session.set("show_nsfw", false);
+    let lock_result = db_connection.lock_upload_session(upload_session_id).await;
 
-    if set_result_1.is_err() || set_result_2.is_err() {
-        handle_error_str!(CookieError, "Fehler beim Speichern der Default Einstellung", INTERNAL_SERVER_ERROR);
+    if lock_result.is_err() {
+        handle_error_str!(DatabaseError, lock_result.err().unwrap().error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+    else if lock_result.as_ref().ok().unwrap().is_none() {
+        handle_error_str!(UserInputError, "Der Upload wird gerade übertragen und kann nicht abgebrochen werden", CONFLICT);
     }
-    else {
-        let filter_obj = Filter::new(true, false);
 
-        return Ok(filter_obj);
+    let lock_token = lock_result.ok().unwrap().unwrap();
+
+    let destroy_result = db_connection.destroy_upload_session(upload_session_id).await;
+
+    if destroy_result.is_err() {
+        let _unlock_result = db_connection.unlock_upload_session(upload_session_id, lock_token.as_str()).await;
+
+        handle_error_str!(DatabaseError, destroy_result.err().unwrap().error_msg.as_str(), INTERNAL_SERVER_ERROR);
     }
+
+    delete_upload_tmp(upload_session.ok().unwrap().storage_filename.as_str()).await;
+
+    return Ok(SuccessReport::new(true));
 }
 
-pub async fn get_own_userdata(config: &web::Data<ProjectConfig>, session: &Session) -> Result<UserData, BackendError> {
+pub async fn change_password(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, password_data: &web::Form<ChangePasswordData>) -> Result<SuccessReport, BackendError> {
+    let old_password = password_data.old_password.as_str();
+    let new_password = password_data.new_password.as_str();
+
+    if !check_password(old_password) || !check_password(new_password) {
+        handle_error_str!(UserInputError, "Das Passwort entspricht nicht den Richtlinien", BAD_REQUEST);
+    }
+
     let db_connection = get_db_connection!(config, true, true);
     let session_data = get_user_session_data!(db_connection, session, false);
-    let target_user_id = session_data.user_id;
-    let url_data = web::Path::from(target_user_id);
+    let own_user_data = require_user_role!(db_connection, session_data, UserRole::User);
 
-    get_userdata_by_id(config, session, &url_data).await
-}
+    let verification_result = verify_password_rate_limited(config, &db_connection, &own_user_data, old_password, "Das alte Passwort ist falsch").await;
 
-//pub async fn get_session_data(config: &web::Data<ProjectConfig>, session: &Session) -> Result<SessionData, BackendError> {
-//    let db_connection = get_db_connection!(config, true, true);
-//    let session_data = get_user_session_data!(db_connection, session, false);
-//
-//    return Ok(session_data);
-//}
+    if verification_result.is_err() {
+        return Err(verification_result.err().unwrap());
+    }
 
-pub async fn get_uploads(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<(i32, i16)>) -> Result<UploadPrvList, BackendError> {
-    let db_connection = get_db_connection!(config, true, true);
-    let _session_data = get_user_session_data!(db_connection, session, false);
-    let filter_settings = get_filter(config, session).await;
-    let filter_settings = filter_settings.unwrap_or(Filter::new(true, false));
+    let new_password_hash = hash_password(new_password, &config.security_config);
 
-    let (start_id, amount) = url_data.as_ref().clone();
-    let show_sfw = filter_settings.show_sfw;
-    let show_nsfw = filter_settings.show_nsfw;
+    if new_password_hash.is_none() {
+        handle_error_str!(InternalError, "Fehler beim Hashen des Passwortes", INTERNAL_SERVER_ERROR);
+    }
 
-    if start_id < 1 {
-        handle_error_str!(UserInputError, "Die Start ID kann nicht kleiner als 1 sein", BAD_REQUEST);
+    let new_password_hash = new_password_hash.unwrap();
+    let change_result = db_connection.change_user_pw_by_id(session_data.user_id, new_password_hash.as_str()).await;
+
+    if change_result.is_err() {
+        handle_error_str!(DatabaseError, "Fehler beim Speichern des neuen Passwortes", INTERNAL_SERVER_ERROR);
     }
 
-    if amount < 1 || amount > 500 {
-        handle_error_str!(UserInputError, "Die Anzahl der auszugebenden Uploads muss im Bereich von 1 bis 500 liegen", BAD_REQUEST);
+    let client_ip = get_client_ip(&config, request);
+    add_audit_event(&db_connection, AuditAction::PasswordChange, Some(session_data.user_id), Some(session_data.user_id),
+                    Some(client_ip.as_str()), None).await;
+
+    // Log out all other devices (the current session stays active)
+    let session_id = session_data.session_id.as_str();
+    let destroy_result = db_connection.destroy_user_sessions(session_data.user_id, Some(session_id)).await;
+
+    if destroy_result.is_err() {
+        handle_error_str!(DatabaseError, "Das Passwort wurde geändert, aber die anderen Sessions konnten nicht beendet werden", INTERNAL_SERVER_ERROR);
     }
 
-    let uploads = db_connection.get_uploads(start_id, amount, show_sfw, show_nsfw).await;
+    return Ok(SuccessReport::new(true));
+}
 
-    if uploads.is_ok() {
-        let uploads = uploads.ok().unwrap();
+// Checks if the password reset token is valid without using it (used by the reset page)
+pub async fn check_password_reset(config: &web::Data<ProjectConfig>, reset_token: &str) -> Result<SuccessReport, BackendError> {
+    if !check_reset_token(reset_token) {
+        handle_error_str!(UserInputError, "Der Link zum Zurücksetzen des Passwortes ist ungültig", BAD_REQUEST);
+    }
 
-        return Ok(uploads);
+    let db_connection = get_db_connection!(config, false, true);
+    let reset_user_id = db_connection.get_password_reset(reset_token).await;
+
+    if reset_user_id.is_ok() {
+        return Ok(SuccessReport::new(true));
     }
     else {
-        let error = uploads.err().unwrap();
+        let error = reset_user_id.err().unwrap();
 
-        if error.error_type == DbApiErrorType::NoResult {
+        if error.error_type == SessionErrorType::SessionInvalid {
             handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
         }
         else {
@@ -327,381 +508,1834 @@ pub async fn get_uploads(config: &web::Data<ProjectConfig>, session: &Session, u
     }
 }
 
-pub async fn get_uploads_range(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<(i32, i32)>) -> Result<UploadPrvList, BackendError> {
+pub async fn check_username_exists(config: &web::Data<ProjectConfig>, request: &HttpRequest, url_data: &web::Path<String>) -> Result<UserExists, BackendError> {
     let db_connection = get_db_connection!(config, true, true);
-    let _session_data = get_user_session_data!(db_connection, session, false);
-    let filter_settings = get_filter(config, session).await;
-    let filter_settings = filter_settings.unwrap_or(Filter::new(true, false));
 
-    let (start_id, end_id) = url_data.as_ref().clone();
-    let show_sfw = filter_settings.show_sfw;
-    let show_nsfw = filter_settings.show_nsfw;
+    let username = check_and_normalize_username(url_data.as_str());
 
-    if start_id < 1 {
-        handle_error_str!(UserInputError, "Die Start ID kann nicht kleiner als 1 sein", BAD_REQUEST);
+    if username.is_none() {
+        handle_error_str!(UserInputError, "Benutzername entspricht nicht den Richtlinien", BAD_REQUEST);
     }
 
-    if end_id < 1 {
-        handle_error_str!(UserInputError, "Die End ID kann nicht kleiner als 1 sein", BAD_REQUEST);
-    }
+    let username = username.unwrap();
+    let username_skeleton = get_username_skeleton(username.as_str());
 
-    let uploads = db_connection.get_uploads_range(start_id, end_id, show_sfw, show_nsfw).await;
+    // Every lookup counts to prevent username enumeration
+    let client_ip = get_client_ip(&config, request);
+    let counter_ip = format!("username_check.ip.{}", client_ip);
+    let max_requests = config.security_config.username_check_max_requests.get_value();
 
-    if uploads.is_ok() {
-        let uploads = uploads.ok().unwrap();
+    check_rate_limit!(db_connection, counter_ip.as_str());
+    count_rate_limit_attempt!(config, db_connection, max_requests, counter_ip.as_str());
 
-        return Ok(uploads);
+    let user_exists = db_connection.check_user_exists(username.as_str(), username_skeleton.as_str()).await;
+
+    if user_exists.is_ok() {
+        let user_exists = user_exists.ok().unwrap();
+        let user_exists_obj = UserExists::new(user_exists);
+
+        return Ok(user_exists_obj);
     }
     else {
-        let error = uploads.err().unwrap();
+        let error = user_exists.err().unwrap();
+        let error_msg = error.error_msg;
 
-        if error.error_type == DbApiErrorType::NoResult {
-            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
-        }
-        else {
-            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
-        }
+        handle_error_str!(InternalError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
     }
 }
 
-pub async fn get_upload_data(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<i32>) -> Result<UploadData, BackendError> {
-    let target_upload_id = url_data.as_ref().clone();
+pub async fn create_api_token(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, token_data: &web::Form<CreateApiTokenData>) -> Result<ApiTokenCreated, BackendError> {
+    let validated_data = token_data.validate_data();
 
-    // Input checks
-    if target_upload_id < 1 {
-        handle_error_str!(UserInputError, "Die Upload-ID muss 1 oder größer sein", BAD_REQUEST);
+    if validated_data.is_none() {
+        handle_error_str!(UserInputError, "Ungültiger Name oder ungültige Berechtigungen für den API Token", BAD_REQUEST);
     }
 
+    let (token_name, token_scopes) = validated_data.unwrap();
     let db_connection = get_db_connection!(config, true, true);
-    let _session_data = get_user_session_data!(db_connection, session, false);
-    let upload_data = db_connection.get_upload_data(target_upload_id).await;
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let token_list = db_connection.get_api_tokens(session_data.user_id).await;
 
-    if upload_data.is_ok() {
-        let upload_data = upload_data.ok().unwrap();
+    if token_list.is_err() {
+        let error = token_list.err().unwrap();
 
-        return Ok(upload_data);
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+
+    let token_quota = config.security_config.api_token_quota.get_value() as usize;
+
+    if token_list.ok().unwrap().token_list.len() >= token_quota {
+        handle_error_str!(Forbidden, "Die maximale Anzahl an API Tokens ist erreicht", FORBIDDEN);
+    }
+
+    let api_token = generate_api_token();
+    let token_hash = hash_random_token(api_token.as_str());
+    let add_result = db_connection.add_api_token(session_data.user_id, token_name.as_str(), token_hash.as_str(), &token_scopes).await;
+
+    if add_result.is_ok() {
+        let token_data = add_result.ok().unwrap();
+        let client_ip = get_client_ip(&config, request);
+        let token_details = format!("Token {} ({})", token_data.token_id, token_data.token_name);
+        add_audit_event(&db_connection, AuditAction::ApiTokenCreate, Some(session_data.user_id), Some(session_data.user_id),
+                        Some(client_ip.as_str()), Some(token_details.as_str())).await;
+
+        return Ok(ApiTokenCreated::new(api_token, token_data));
     }
     else {
-        let error = upload_data.err().unwrap();
+        let error = add_result.err().unwrap();
 
-        if error.error_type == DbApiErrorType::NoResult {
-            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
-        }
-        else {
-            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
-        }
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
     }
 }
 
-pub async fn get_userdata_by_id(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<i32>) -> Result<UserData, BackendError> {
-    let target_user_id = url_data.as_ref().clone();
+pub async fn create_invite(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest) -> Result<InviteData, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let own_user_data = require_user_role!(db_connection, session_data, UserRole::User);
+    let user_id = session_data.user_id;
 
-    if target_user_id > 0 {
-        let db_connection = get_db_connection!(config, true, true);
-        let _session_data = get_user_session_data!(db_connection, session, false);
+    // Admins can create as many invites as they want
+    let mut invite_quota = None;
 
-        let user_data = db_connection.get_userdata_by_id(target_user_id).await;
+    if own_user_data.get_user_role() < UserRole::Admin {
+        invite_quota = Some(config.security_config.invite_quota.get_value() as i64);
+    }
 
-        if user_data.is_ok() {
-            let user_data = UserData::new(&user_data.ok().unwrap());
+    let invite_code = generate_invite_key();
+    let invite_lifetime = config.security_config.invite_lifetime.get_value() as i32;
+    let invite_data = db_connection.add_invite(invite_code.as_str(), user_id, invite_lifetime, invite_quota).await;
+
+    if invite_data.is_err() && invite_data.as_ref().err().unwrap().error_type == DbApiErrorType::NoResult {
+        handle_error_str!(Forbidden, "Das Kontingent an Invitecodes ist aufgebraucht", FORBIDDEN);
+    }
+
+    if invite_data.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        add_audit_event(&db_connection, AuditAction::InviteCreate, Some(user_id), Some(user_id),
+                        Some(client_ip.as_str()), None).await;
+
+        return Ok(invite_data.ok().unwrap());
+    }
+    else {
+        handle_error_str!(DatabaseError, "Fehler beim Speichern des Invitecodes", INTERNAL_SERVER_ERROR);
+    }
+}
+
+// Creates a one-time link for the user to set a new password (there is no email to send it automatically)
+pub async fn create_password_reset(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<i32>) -> Result<PasswordResetCreated, BackendError> {
+    let target_user_id = url_data.as_ref().clone();
+
+    if target_user_id < 1 {
+        handle_error_str!(UserInputError, "Benutzer ID ist ungültig", BAD_REQUEST);
+    }
+
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let _own_user_data = require_user_role!(db_connection, session_data, UserRole::Admin);
+    let target_user_data = db_connection.get_userdata_by_id(target_user_id).await;
+
+    if target_user_data.is_err() {
+        let error = target_user_data.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // Anonymized accounts have no password and mustn't be reactivated
+    if target_user_data.ok().unwrap().password_hash.is_empty() {
+        handle_error_str!(UserInputError, "Der Account wurde gelöscht", BAD_REQUEST);
+    }
+
+    let reset_lifetime = config.security_config.password_reset_lifetime.get_value();
+    let reset_token = db_connection.create_password_reset(target_user_id, reset_lifetime).await;
+
+    if reset_token.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        add_audit_event(&db_connection, AuditAction::PasswordResetCreate, Some(session_data.user_id), Some(target_user_id),
+                        Some(client_ip.as_str()), None).await;
+
+        return Ok(PasswordResetCreated::new(reset_token.ok().unwrap(), reset_lifetime));
+    }
+    else {
+        let redis_error = reset_token.err().unwrap();
+
+        handle_error_str!(DatabaseError, redis_error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+// Starts a chunked upload, the file content is sent afterwards with append_upload_chunk
+// Unfinished uploads can be resumed for a day after the last received chunk
+pub async fn create_upload_session(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, upload_data: &web::Form<CreateUploadSessionData>) -> Result<UploadSessionStatus, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Upload);
+    let upload_is_nsfw = upload_data.validate_data();
+
+    if upload_is_nsfw.is_none() {
+        handle_error_str!(UserInputError, "Übergebene Daten konnten nicht validiert werden", BAD_REQUEST);
+    }
+
+    let max_upload_size = config.application_config.max_upload_size.get_value();
+
+    if upload_data.upload_size > max_upload_size as u64 * 1024 * 1024 {
+        let error_msg = format!("Die Datei ist zu groß (maximal {} MB)", max_upload_size);
+
+        handle_error_str!(PayloadTooLarge, error_msg.as_str(), PAYLOAD_TOO_LARGE);
+    }
+
+    let original_filename = sanitize_filename(upload_data.filename.as_str());
+    let original_filename = original_filename.as_str();
+    let storage_filename = generate_upload_filename(original_filename);
+
+    if storage_filename.is_none() || !(is_image_file(original_filename) || is_video_file(original_filename)) {
+        handle_error_str!(UserInputError, "Der Dateityp wird nicht unterstützt", BAD_REQUEST);
+    }
+
+    let storage_filename = storage_filename.unwrap();
+    let filepath = get_upload_path_tmp(storage_filename.as_str());
+    let file : tokio::io::Result<tokio::fs::File> = tokio::fs::File::create(filepath.as_str()).await;
+
+    if file.is_err() {
+        error!("Can't create file: {}", filepath.as_str());
+
+        handle_error_str!(UnknownError, "Es ist ein Fehler beim Speichern der Datei auf dem Server aufgetreten", INTERNAL_SERVER_ERROR);
+    }
+
+    let taglist_str = upload_data.taglist.clone().unwrap_or_default();
+    let mut upload_session = UploadSessionData::new(session_data.user_id, storage_filename.as_str(), original_filename,
+                                                    upload_data.upload_size, upload_is_nsfw.unwrap(), taglist_str.as_str());
+    let upload_session_id = db_connection.create_upload_session(&upload_session).await;
+
+    if upload_session_id.is_err() {
+        delete_upload_tmp(storage_filename.as_str()).await;
+
+        handle_error_str!(DatabaseError, upload_session_id.err().unwrap().error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+
+    upload_session.upload_session_id = upload_session_id.ok().unwrap();
+
+    return Ok(UploadSessionStatus::new(&upload_session));
+}
+
+// Deletes the own account, the content of the user is anonymized or deleted depending on the configured account deletion mode
+pub async fn delete_account(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, delete_data: &web::Form<DeleteAccountData>) -> Result<SuccessReport, BackendError> {
+    let password = delete_data.password.as_str();
+
+    if !check_password(password) {
+        handle_error_str!(UserInputError, "Das Passwort entspricht nicht den Richtlinien", BAD_REQUEST);
+    }
+
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let own_user_data = require_user_role!(db_connection, session_data, UserRole::User);
+
+    let verification_result = verify_password_rate_limited(config, &db_connection, &own_user_data, password, "Das Passwort ist falsch").await;
+
+    if verification_result.is_err() {
+        return Err(verification_result.err().unwrap());
+    }
+
+    let user_id = session_data.user_id;
+    let account_deletion_mode = config.application_config.account_deletion_mode.get_value();
+
+    if account_deletion_mode == AccountDeletionMode::Cascade {
+        let delete_result = db_connection.delete_user(user_id).await;
+
+        if delete_result.is_err() {
+            let error = delete_result.err().unwrap();
+
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+
+        for upload_file in delete_result.ok().unwrap() {
+            delete_upload_srv(&config, upload_file.upload_filename.as_str(), upload_file.upload_prv_variants.as_deref(),
+                              upload_file.upload_has_prv_clip).await;
+        }
+    }
+    else {
+        // The username can't be registered again, because check_and_normalize_username doesn't allow underscores
+        let anonymized_username = format!("deleted_user_{}", user_id);
+        let anonymize_result = db_connection.anonymize_user(user_id, anonymized_username.as_str()).await;
+
+        if anonymize_result.is_err() {
+            let error = anonymize_result.err().unwrap();
+
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // A deleted user can't be referenced anymore, so the user id is only kept in the details
+    let client_ip = get_client_ip(&config, request);
+    let audit_user_id = if account_deletion_mode == AccountDeletionMode::Cascade { None } else { Some(user_id) };
+    let delete_details = format!("Benutzer ID {}", user_id);
+    add_audit_event(&db_connection, AuditAction::AccountDelete, audit_user_id, audit_user_id,
+                    Some(client_ip.as_str()), Some(delete_details.as_str())).await;
+
+    let destroy_result = db_connection.destroy_user_sessions(user_id, None).await;
+    purge_session(session);
+
+    if destroy_result.is_err() {
+        handle_error_str!(DatabaseError, "Der Account wurde gelöscht, aber die Sessions konnten nicht beendet werden", INTERNAL_SERVER_ERROR);
+    }
+
+    return Ok(SuccessReport::new(true));
+}
+
+// Deletes the already saved files of an aborted multipart request
+async fn delete_multipart_files(multipart_data: &MultipartFormData) {
+    for multipart_file in multipart_data.files.values() {
+        delete_upload_tmp(multipart_file.storage_filename.as_str()).await;
+    }
+}
+
+// Returns everything stored about the own account (the uploaded files are exported by export_user_files)
+pub async fn export_user_data(config: &web::Data<ProjectConfig>, session: &Session) -> Result<UserDataExport, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let own_user_data = require_user_role!(db_connection, session_data, UserRole::User);
+    let user_export = db_connection.get_user_export(&own_user_data).await;
+
+    if user_export.is_ok() {
+        return Ok(user_export.ok().unwrap());
+    }
+    else {
+        let error = user_export.err().unwrap();
+
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+// Returns a zip archive with the original files of all own uploads
+pub async fn export_user_files(config: &web::Data<ProjectConfig>, session: &Session) -> Result<NamedFile, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let own_user_data = require_user_role!(db_connection, session_data, UserRole::User);
+    let user_export = db_connection.get_user_export(&own_user_data).await;
+
+    if user_export.is_err() {
+        let error = user_export.err().unwrap();
+
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+
+    let upload_filenames : Vec<String> = user_export.ok().unwrap().upload_list.iter()
+        .map(|upload| upload.upload_filename.clone())
+        .collect();
+    let archive_name = format!("p0nygramm_export_{}", own_user_data.username);
+    let tmp_archive_name = format!("{}_{}", own_user_data.user_id, Local::now().format("%Y%m%d%H%M%S%f"));
+    let archive_filepath = create_upload_archive(&config, tmp_archive_name.as_str(), &upload_filenames).await;
+
+    if archive_filepath.is_none() {
+        handle_error_str!(InternalError, "Fehler beim Erstellen des Archivs", INTERNAL_SERVER_ERROR);
+    }
+
+    let archive_filepath = archive_filepath.unwrap();
+    let archive_file = NamedFile::open(archive_filepath.as_str());
+
+    // The open file handle stays valid, so the archive can already be removed from the tmp directory
+    let rm_archive_success : tokio::io::Result<()> = tokio::fs::remove_file(archive_filepath.as_str()).await;
+
+    if rm_archive_success.is_err() {
+        warn!("export_user_files: Failed to remove the archive {}", archive_filepath.as_str());
+    }
+
+    if archive_file.is_err() {
+        handle_error_str!(InternalError, "Fehler beim Lesen des Archivs", INTERNAL_SERVER_ERROR);
+    }
+
+    let content_disposition = ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(format!("{}.zip", archive_name))],
+    };
+    let archive_file = archive_file.unwrap().set_content_disposition(content_disposition);
+
+    return Ok(archive_file);
+}
+
+// Returns the ids of existing uploads which look like the new upload (empty if the check is disabled or failed)
+pub async fn find_similar_uploads(config: &ProjectConfig, db_connection: &DbConnection, upload_id: i32, upload_phash: Option<i64>) -> Vec<i32> {
+    let similar_upload_mode = config.application_config.similar_upload_mode.get_value();
+
+    if upload_phash.is_none() || similar_upload_mode == SimilarUploadMode::Off {
+        return Vec::new();
+    }
+
+    let max_distance = config.application_config.similar_upload_distance.get_value() as i32;
+    let similar_uploads = db_connection.get_similar_uploads(upload_phash.unwrap(), max_distance, upload_id, 10, true, true).await;
+
+    if similar_uploads.is_err() {
+        warn!("find_similar_uploads: Failed to search for similar uploads");
+
+        return Vec::new();
+    }
+
+    return similar_uploads.ok().unwrap().uploads.iter().map(|upload_preview| upload_preview.upload_id).collect();
+}
+
+pub async fn get_api_tokens(config: &web::Data<ProjectConfig>, session: &Session) -> Result<ApiTokenList, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let token_list = db_connection.get_api_tokens(session_data.user_id).await;
+
+    if token_list.is_ok() {
+        return Ok(token_list.ok().unwrap());
+    }
+    else {
+        let error = token_list.err().unwrap();
+
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+// Returns the audit log from the start id downwards (only for admins)
+pub async fn get_audit_events(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<(i32, i16)>, filter_data: &web::Query<AuditEventFilter>) -> Result<AuditEventList, BackendError> {
+    let (start_id, amount) = url_data.as_ref().clone();
+
+    if start_id < 1 {
+        handle_error_str!(UserInputError, "Die Start ID kann nicht kleiner als 1 sein", BAD_REQUEST);
+    }
+
+    if amount < 1 || amount > 500 {
+        handle_error_str!(UserInputError, "Die Anzahl der auszugebenden Einträge muss im Bereich von 1 bis 500 liegen", BAD_REQUEST);
+    }
+
+    let validated_filter = filter_data.validate_data();
+
+    if validated_filter.is_none() {
+        handle_error_str!(UserInputError, "Ungültige Aktion oder Benutzer ID im Filter", BAD_REQUEST);
+    }
+
+    let (audit_action, actor_id, target_id) = validated_filter.unwrap();
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let _own_user_data = require_user_role!(db_connection, session_data, UserRole::Admin);
+    let audit_events = db_connection.get_audit_events(start_id, amount, audit_action, actor_id, target_id).await;
+
+    if audit_events.is_ok() {
+        return Ok(audit_events.ok().unwrap());
+    }
+    else {
+        let error = audit_events.err().unwrap();
+
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+pub async fn get_filter(config: &web::Data<ProjectConfig>, session: &Session) -> Result<Filter, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let _session_data = get_user_session_data!(db_connection, session, false);
+
+    let show_sfw = session.get::<bool>("show_sfw");
+    let show_nsfw = session.get::<bool>("show_nsfw");
+
+    if show_sfw.is_ok() && show_nsfw.is_ok() {
+        let show_sfw = show_sfw.unwrap();
+        let show_nsfw = show_nsfw.unwrap();
+
+        if show_sfw.is_some() && show_nsfw.is_some() {
+            let show_sfw = show_sfw.unwrap();
+            let show_nsfw = show_nsfw.unwrap();
+
+            let filter_obj = Filter::new(show_sfw, show_nsfw);
+
+            return Ok(filter_obj);
+        }
+    }
+
+    // If filter is not set: Set show_sfw = true and show_nsfw to false
+    let set_result_1 = session.set("show_sfw", true);
+    let set_result_2 = session.set("show_nsfw", false);
+
+    if set_result_1.is_err() || set_result_2.is_err() {
+        handle_error_str!(CookieError, "Fehler beim Speichern der Default Einstellung", INTERNAL_SERVER_ERROR);
+    }
+    else {
+        let filter_obj = Filter::new(true, false);
+
+        return Ok(filter_obj);
+    }
+}
+
+pub async fn get_invites(config: &web::Data<ProjectConfig>, session: &Session) -> Result<InviteList, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let invite_list = db_connection.get_user_invites(session_data.user_id).await;
+
+    if invite_list.is_ok() {
+        return Ok(invite_list.ok().unwrap());
+    }
+    else {
+        let error = invite_list.err().unwrap();
+
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+// Returns the upload session, if it belongs to the given user (other users get the same error as for expired sessions)
+async fn get_own_upload_session(db_connection: &DbConnection, upload_session_id: &str, user_id: i32) -> Result<UploadSessionData, BackendError> {
+    let upload_session = db_connection.get_upload_session(upload_session_id).await;
+
+    if upload_session.is_err() {
+        let error = upload_session.err().unwrap();
+
+        if error.error_type == DbError {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+
+        handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+    }
+
+    let upload_session = upload_session.ok().unwrap();
+
+    if upload_session.user_id != user_id {
+        handle_error_str!(NoResult, "Der Upload ist abgelaufen oder existiert nicht", NOT_FOUND);
+    }
+
+    return Ok(upload_session);
+}
+
+pub async fn get_own_userdata(config: &web::Data<ProjectConfig>, session: &Session) -> Result<UserData, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let user_data = db_connection.get_userdata_by_id(session_data.user_id).await;
+
+    if user_data.is_ok() {
+        let user_data = UserData::new(&user_data.ok().unwrap());
+
+        return Ok(user_data);
+    }
+    else {
+        let error = user_data.err().unwrap();
+
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+//pub async fn get_session_data(config: &web::Data<ProjectConfig>, session: &Session) -> Result<SessionData, BackendError> {
+//    let db_connection = get_db_connection!(config, true, true);
+//    let session_data = get_user_session_data!(db_connection, session, false);
+//
+//    return Ok(session_data);
+//}
+
+pub async fn get_sessions(config: &web::Data<ProjectConfig>, session: &Session) -> Result<SessionInfoList, BackendError> {
+    let db_connection = get_db_connection!(config, false, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let session_list = db_connection.get_user_sessions(session_data.user_id, session_data.session_id.as_str()).await;
+
+    if session_list.is_ok() {
+        return Ok(session_list.ok().unwrap());
+    }
+    else {
+        let redis_error = session_list.err().unwrap();
+
+        handle_error_str!(DatabaseError, redis_error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+// Api tokens have no cookie session, so their requests use the filter which the user last set in the browser
+async fn get_request_filter(config: &web::Data<ProjectConfig>, db_connection: &DbConnection, session: &Session, session_data: &db_result::SessionData) -> Filter {
+    if session_data.is_api_token() {
+        let user_filter = db_connection.get_user_filter(session_data.user_id).await;
+
+        if user_filter.is_ok() {
+            let (show_sfw, show_nsfw) = user_filter.ok().unwrap();
+
+            return Filter::new(show_sfw, show_nsfw);
+        }
+
+        return Filter::new(true, false);
+    }
+
+    let filter_settings = get_filter(config, session).await;
+
+    filter_settings.unwrap_or(Filter::new(true, false))
+}
+
+// Returns uploads which look like the given upload (reposts which were resized or re-encoded)
+pub async fn get_similar_uploads(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<i32>) -> Result<UploadPrvList, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Read);
+    let filter_settings = get_request_filter(config, &db_connection, session, &session_data).await;
+    let upload_id = url_data.as_ref().clone();
+
+    if upload_id < 1 {
+        handle_error_str!(UserInputError, "Die Upload ID kann nicht kleiner als 1 sein", BAD_REQUEST);
+    }
+
+    let upload_phash = db_connection.get_upload_phash(upload_id).await;
+
+    if upload_phash.is_err() {
+        let error = upload_phash.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let upload_phash = upload_phash.ok().unwrap();
+
+    if upload_phash.is_none() {
+        handle_error_str!(NoResult, "Für diesen Upload können keine ähnlichen Uploads gesucht werden", NOT_FOUND);
+    }
+
+    let max_distance = config.application_config.similar_upload_distance.get_value() as i32;
+    let similar_uploads = db_connection.get_similar_uploads(upload_phash.unwrap(), max_distance, upload_id, 50,
+                                                            filter_settings.show_sfw, filter_settings.show_nsfw).await;
+
+    if similar_uploads.is_ok() {
+        let similar_uploads = similar_uploads.ok().unwrap();
+
+        return Ok(similar_uploads);
+    }
+    else {
+        let error = similar_uploads.err().unwrap();
+
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+// Returns the number of received bytes of a chunked upload (used to resume an interrupted upload)
+pub async fn get_upload_session(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<String>) -> Result<UploadSessionStatus, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Upload);
+    let upload_session = get_own_upload_session(&db_connection, url_data.as_str(), session_data.user_id).await;
+
+    if upload_session.is_err() {
+        return Err(upload_session.err().unwrap());
+    }
+
+    return Ok(UploadSessionStatus::new(&upload_session.ok().unwrap()));
+}
+
+// Returns the processing state of an upload, only the uploader can see uploads which aren't ready yet
+pub async fn get_upload_status(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<i32>) -> Result<UploadStatusData, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Upload);
+    let upload_id = url_data.as_ref().clone();
+
+    if upload_id < 1 {
+        handle_error_str!(UserInputError, "Die Upload ID kann nicht kleiner als 1 sein", BAD_REQUEST);
+    }
+
+    let upload_status = db_connection.get_upload_status(upload_id).await;
+
+    if upload_status.is_err() {
+        let error = upload_status.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let mut upload_status = upload_status.ok().unwrap();
+
+    if upload_status.upload_status != UploadStatus::Ready && upload_status.uploader_id != session_data.user_id {
+        handle_error_str!(NoResult, "Upload ist nicht vorhanden", NOT_FOUND);
+    }
+
+    if upload_status.upload_status == UploadStatus::Ready {
+        upload_status.similar_upload_ids = find_similar_uploads(&config, &db_connection, upload_id, upload_status.upload_phash).await;
+    }
+
+    return Ok(upload_status);
+}
+
+pub async fn get_uploads(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<(i32, i16)>) -> Result<UploadPrvList, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Read);
+    let filter_settings = get_request_filter(config, &db_connection, session, &session_data).await;
+
+    let (start_id, amount) = url_data.as_ref().clone();
+    let show_sfw = filter_settings.show_sfw;
+    let show_nsfw = filter_settings.show_nsfw;
+
+    if start_id < 1 {
+        handle_error_str!(UserInputError, "Die Start ID kann nicht kleiner als 1 sein", BAD_REQUEST);
+    }
+
+    if amount < 1 || amount > 500 {
+        handle_error_str!(UserInputError, "Die Anzahl der auszugebenden Uploads muss im Bereich von 1 bis 500 liegen", BAD_REQUEST);
+    }
+
+    let uploads = db_connection.get_uploads(start_id, amount, show_sfw, show_nsfw).await;
+
+    if uploads.is_ok() {
+        let uploads = uploads.ok().unwrap();
+
+        return Ok(uploads);
+    }
+    else {
+        let error = uploads.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+}
+
+pub async fn get_uploads_range(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<(i32, i32)>) -> Result<UploadPrvList, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Read);
+    let filter_settings = get_request_filter(config, &db_connection, session, &session_data).await;
+
+    let (start_id, end_id) = url_data.as_ref().clone();
+    let show_sfw = filter_settings.show_sfw;
+    let show_nsfw = filter_settings.show_nsfw;
+
+    if start_id < 1 {
+        handle_error_str!(UserInputError, "Die Start ID kann nicht kleiner als 1 sein", BAD_REQUEST);
+    }
+
+    if end_id < 1 {
+        handle_error_str!(UserInputError, "Die End ID kann nicht kleiner als 1 sein", BAD_REQUEST);
+    }
+
+    let uploads = db_connection.get_uploads_range(start_id, end_id, show_sfw, show_nsfw).await;
+
+    if uploads.is_ok() {
+        let uploads = uploads.ok().unwrap();
+
+        return Ok(uploads);
+    }
+    else {
+        let error = uploads.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+}
+
+pub async fn get_upload_data(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<i32>) -> Result<UploadData, BackendError> {
+    let target_upload_id = url_data.as_ref().clone();
+
+    // Input checks
+    if target_upload_id < 1 {
+        handle_error_str!(UserInputError, "Die Upload-ID muss 1 oder größer sein", BAD_REQUEST);
+    }
+
+    let db_connection = get_db_connection!(config, true, true);
+    let _session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Read);
+    let upload_data = db_connection.get_upload_data(target_upload_id).await;
+
+    if upload_data.is_ok() {
+        let upload_data = upload_data.ok().unwrap();
+
+        return Ok(upload_data);
+    }
+    else {
+        let error = upload_data.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+}
+
+pub async fn get_userdata_by_id(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<i32>) -> Result<UserData, BackendError> {
+    let target_user_id = url_data.as_ref().clone();
+
+    if target_user_id > 0 {
+        let db_connection = get_db_connection!(config, true, true);
+        let _session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Read);
+
+        let user_data = db_connection.get_userdata_by_id(target_user_id).await;
+
+        if user_data.is_ok() {
+            let user_data = UserData::new(&user_data.ok().unwrap());
+
+            return Ok(user_data);
+        }
+        else {
+            let error = user_data.err().unwrap();
+            let error_type = error.error_type;
+            let error_msg = error.error_msg;
+
+            if error_type == DbApiErrorType::NoResult {
+                handle_error_str!(NoResult, error_msg.as_str(), NOT_FOUND);
+            }
+            else {
+                handle_error_str!(DatabaseError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+    else {
+        handle_error_str!(UserInputError, "Benutzer ID ist ungültig", BAD_REQUEST);
+    }
+}
+
+pub async fn get_userdata_by_username(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<String>) -> Result<UserData, BackendError> {
+    let target_username = check_and_normalize_username(url_data.as_str());
+
+    if target_username.is_some() {
+        let target_username = target_username.unwrap();
+        let db_connection = get_db_connection!(config, true, true);
+        let _session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Read);
+        let user_data = db_connection.get_userdata_by_username(target_username.as_str()).await;
+
+        if user_data.is_ok() {
+            let user_data = UserData::new(&user_data.ok().unwrap());
 
             return Ok(user_data);
         }
         else {
-            let error = user_data.err().unwrap();
-            let error_type = error.error_type;
-            let error_msg = error.error_msg;
+            let error = user_data.err().unwrap();
+            let error_type = error.error_type;
+            let error_msg = error.error_msg;
+
+            if error_type == DbApiErrorType::NoResult {
+                handle_error_str!(NoResult, error_msg.as_str(), NOT_FOUND);
+            }
+            else {
+                handle_error_str!(DatabaseError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+    else {
+        handle_error_str!(UserInputError, "Benutzername ist ungültig", BAD_REQUEST);
+    }
+}
+
+pub async fn login(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, login_data: &web::Form<LoginData>) -> Result<UserData, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let user_session = get_user_session(&db_connection, &session, false).await;
+
+    if user_session.is_err() {
+        let login_username = check_and_normalize_username(login_data.username.as_str());
+        let login_password = login_data.password.as_str();
+        let keep_logged_in = login_data.keep_logged_in;
+
+        let username_is_ok = login_username.is_some();
+        let password_is_ok = check_password(login_password);
+
+        if username_is_ok && password_is_ok {
+            let login_username = login_username.unwrap();
+            let login_username = login_username.as_str();
+            let client_ip = get_client_ip(&config, request);
+            let counter_ip = format!("login.ip.{}", client_ip);
+            let counter_user = format!("login.user.{}", login_username.to_lowercase());
+            let max_attempts = config.security_config.login_max_attempts.get_value();
+
+            check_rate_limit!(db_connection, counter_ip.as_str());
+            check_rate_limit!(db_connection, counter_user.as_str());
+
+            let user_data = db_connection.get_userdata_by_username(login_username).await;
+
+            if user_data.is_ok() {
+                let user_data = user_data.ok().unwrap();
+                let password_hash = user_data.password_hash.clone();
+                let password_verification = verify_password(password_hash.as_str(),
+                                                            login_password,
+                                                            &config.security_config).unwrap_or(PasswordVerification::Incorrect);
+
+                if password_verification != PasswordVerification::Incorrect {
+                    let user_id = user_data.user_id;
+                    let reset_result = db_connection.reset_rate_limit(counter_user.as_str()).await;
+
+                    if reset_result.is_err() {
+                        warn!("login: Failed to reset the failed login counter");
+                    }
+
+                    let active_ban = db_connection.get_active_user_ban(user_id).await;
+
+                    if active_ban.is_ok() {
+                        let active_ban = active_ban.ok().unwrap();
+                        let ban_msg = format!("Benutzer ist bis {} gesperrt (Grund: {})",
+                                              active_ban.ban_end.format("%d.%m.%Y %H:%M"), active_ban.ban_reason);
+
+                        handle_error_str!(UserBanned, ban_msg.as_str(), FORBIDDEN);
+                    }
+                    else if active_ban.err().unwrap().error_type != DbApiErrorType::NoResult {
+                        handle_error_str!(DatabaseError, "Fehler beim Prüfen der Benutzersperren", INTERNAL_SERVER_ERROR);
+                    }
+
+                    // Hashes with old parameters get replaced (that's only possible while the plaintext password is known)
+                    if password_verification == PasswordVerification::CorrectOutdated {
+                        let new_password_hash = hash_password(login_password, &config.security_config);
+
+                        if new_password_hash.is_some() {
+                            let new_password_hash = new_password_hash.unwrap();
+                            let change_result = db_connection.change_user_pw_by_id(user_id, new_password_hash.as_str()).await;
+
+                            if change_result.is_err() {
+                                warn!("login: Failed to store the rehashed password");
+                            }
+                        }
+                        else {
+                            warn!("login: Failed to rehash the password");
+                        }
+                    }
+
+                    let totp_data = db_connection.get_user_totp(user_id).await;
+
+                    if totp_data.is_err() {
+                        handle_error_str!(DatabaseError, "Fehler beim Laden der 2FA Einstellungen", INTERNAL_SERVER_ERROR);
+                    }
+
+                    // With 2FA the session will only be created after the totp code was verified (see login_totp)
+                    if totp_data.ok().unwrap().totp_enabled {
+                        let pending_login_id = db_connection.create_pending_login(user_id, keep_logged_in).await;
+
+                        if pending_login_id.is_err() {
+                            handle_error_str!(DatabaseError, "Fehler beim Anlegen der Anmeldung in der Redis Datenbank", INTERNAL_SERVER_ERROR);
+                        }
+
+                        let session_set_result = session.set("pending_login_id", pending_login_id.ok().unwrap());
+
+                        if session_set_result.is_err() {
+                            handle_error_str!(CookieError, "Fehler beim Setzen des Session Cookies", INTERNAL_SERVER_ERROR);
+                        }
+
+                        handle_error_str!(TotpRequired, "Bitte den Code aus der 2FA App eingeben", FORBIDDEN);
+                    }
+
+                    return start_user_session(&config, &db_connection, session, request, &user_data, keep_logged_in).await;
+                }
+                else {
+                    add_audit_event(&db_connection, AuditAction::LoginFailed, None, Some(user_data.user_id),
+                                    Some(client_ip.as_str()), Some(login_username)).await;
+                    count_rate_limit_attempt!(config, db_connection, max_attempts, counter_ip.as_str(), counter_user.as_str());
+                    handle_error_str!(UserInputError, "Benutzername oder Passwort ist falsch", FORBIDDEN);
+                }
+            }
+            else {
+                let error = user_data.err().unwrap();
+                let error_type = error.error_type;
+
+                if error_type == DbApiErrorType::NoResult {
+                    add_audit_event(&db_connection, AuditAction::LoginFailed, None, None,
+                                    Some(client_ip.as_str()), Some(login_username)).await;
+                    count_rate_limit_attempt!(config, db_connection, max_attempts, counter_ip.as_str(), counter_user.as_str());
+                    handle_error_str!(UserInputError, "Benutzername oder Passwort ist falsch", FORBIDDEN);
+                }
+                else {
+                    handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+                }
+            }
+        }
+        else {
+            handle_error_str!(UserInputError, "Ungültige Zeichen in Benutzername oder Passwort", BAD_REQUEST);
+        }
+    }
+    else {
+        handle_error_str!(Ignored, "Es ist bereits ein Benutzer eingelogt", BAD_REQUEST);
+    }
+}
+
+// Second login step for users with 2FA, accepts a totp code or a recovery code
+pub async fn login_totp(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, totp_data: &web::Form<TotpCodeData>) -> Result<UserData, BackendError> {
+    let totp_code = totp_data.totp_code.as_str();
+    let is_totp_code = check_totp_code(totp_code);
+    let is_recovery_code = check_recovery_code(totp_code);
+
+    if !is_totp_code && !is_recovery_code {
+        handle_error_str!(UserInputError, "Ungültiger 2FA Code oder Wiederherstellungscode", BAD_REQUEST);
+    }
+
+    let pending_login_id = session.get::<String>("pending_login_id").unwrap_or(None).unwrap_or_default();
+
+    if pending_login_id.is_empty() {
+        handle_error_str!(Unauthorized, "Es gibt keine ausstehende Anmeldung", FORBIDDEN);
+    }
+
+    let db_connection = get_db_connection!(config, true, true);
+    let pending_login = db_connection.get_pending_login(pending_login_id.as_str()).await;
+
+    if pending_login.is_err() {
+        let error = pending_login.err().unwrap();
+
+        if error.error_type == DbError {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+        else {
+            session.remove("pending_login_id");
+
+            handle_error_str!(Unauthorized, error.error_msg.as_str(), FORBIDDEN);
+        }
+    }
+
+    let pending_login = pending_login.ok().unwrap();
+    let user_data = db_connection.get_userdata_by_id(pending_login.user_id).await;
+
+    if user_data.is_err() {
+        let error = user_data.err().unwrap();
+
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+
+    let user_data = user_data.ok().unwrap();
+    let counter_user = format!("login.user.{}", user_data.username.to_lowercase());
+    let max_attempts = config.security_config.login_max_attempts.get_value();
+    check_rate_limit!(db_connection, counter_user.as_str());
+
+    let code_is_correct = if is_totp_code {
+        let user_totp = db_connection.get_user_totp(user_data.user_id).await;
+
+        if user_totp.is_err() {
+            handle_error_str!(DatabaseError, "Fehler beim Laden der 2FA Einstellungen", INTERNAL_SERVER_ERROR);
+        }
+
+        let user_totp = user_totp.ok().unwrap();
+        let totp_secret = user_totp.totp_secret.unwrap_or_default();
+        let time_step = verify_totp_code(totp_secret.as_str(), totp_code, user_totp.totp_last_step);
+
+        if user_totp.totp_enabled && time_step.is_some() {
+            let use_result = db_connection.use_totp_time_step(user_data.user_id, time_step.unwrap()).await;
+
+            if use_result.is_err() && use_result.as_ref().err().unwrap().error_type != DbApiErrorType::NoResult {
+                handle_error_str!(DatabaseError, "Fehler beim Prüfen des 2FA Codes", INTERNAL_SERVER_ERROR);
+            }
+
+            use_result.is_ok()
+        }
+        else {
+            false
+        }
+    }
+    else {
+        let recovery_code_hash = hash_random_token(totp_code);
+        let use_result = db_connection.use_recovery_code(user_data.user_id, recovery_code_hash.as_str()).await;
+
+        if use_result.is_err() && use_result.as_ref().err().unwrap().error_type != DbApiErrorType::NoResult {
+            handle_error_str!(DatabaseError, "Fehler beim Prüfen des Wiederherstellungscodes", INTERNAL_SERVER_ERROR);
+        }
+
+        use_result.is_ok()
+    };
+
+    if !code_is_correct {
+        let client_ip = get_client_ip(&config, request);
+        add_audit_event(&db_connection, AuditAction::LoginFailed, None, Some(user_data.user_id),
+                        Some(client_ip.as_str()), Some("2FA Code")).await;
+
+        // The pending login is dropped after too many wrong codes, so the password has to be entered again
+        let failed_attempts = db_connection.register_pending_login_attempt(pending_login_id.as_str()).await;
+
+        if failed_attempts.is_err() || failed_attempts.ok().unwrap() >= PENDING_LOGIN_MAX_ATTEMPTS {
+            let _destroy_result = db_connection.destroy_pending_login(pending_login_id.as_str()).await;
+            session.remove("pending_login_id");
+        }
+
+        count_rate_limit_attempt!(config, db_connection, max_attempts, counter_user.as_str());
+        handle_error_str!(UserInputError, "Der 2FA Code ist falsch", FORBIDDEN);
+    }
+
+    let reset_result = db_connection.reset_rate_limit(counter_user.as_str()).await;
+
+    if reset_result.is_err() {
+        warn!("login_totp: Failed to reset the failed login counter");
+    }
+
+    let destroy_result = db_connection.destroy_pending_login(pending_login_id.as_str()).await;
+
+    if destroy_result.is_err() {
+        warn!("login_totp: Failed to remove the pending login");
+    }
+
+    session.remove("pending_login_id");
+
+    return start_user_session(&config, &db_connection, session, request, &user_data, pending_login.is_lts).await;
+}
+
+pub async fn logout(config: &web::Data<ProjectConfig>, session: &Session) -> Result<SuccessReport, BackendError> {
+    let db_connection = get_db_connection!(config, false, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let session_id = session_data.session_id;
+    let logoff_result = db_connection.destroy_session(session_id.as_str()).await;
+
+    if logoff_result.is_ok() {
+        purge_session(session);
+
+        return Ok(SuccessReport::new(true));
+    }
+    else {
+        let redis_error = logoff_result.err().unwrap();
+
+        handle_error_str!(DatabaseError, redis_error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+//noinspection ALL
+// Returns the form fields and the uploaded files (the SHA-256 hash is calculated while the file is written)
+// Files are saved under a server generated name, the client filename is only kept as metadata
+// The sizes are counted while streaming, if a limit is exceeded all saved files are deleted
+async fn parse_multipart_form_data(config: &ProjectConfig, payload: &mut Multipart, allow_multiple_file_uploads: bool) -> Result<MultipartFormData, BackendError> {
+    let max_upload_size = config.application_config.max_upload_size.get_value();
+    let max_file_size = max_upload_size as usize * 1024 * 1024;
+    let max_request_size = max_file_size + MAX_FORM_FIELDS_SIZE;
+    let mut request_size : usize = 0;
+    let mut result_data = MultipartFormData::new();
+    let mut file_saved = false;
+
+    while let Ok(Some(field)) = payload.try_next().await {
+        let mut field : Field = field; // Hack to show types in IDEA IDE
+        let content_disposition = field.content_disposition();
+
+        if content_disposition.is_some() {
+            let content_disposition = content_disposition.unwrap();
+
+            if content_disposition.is_form_data() {
+                let name = content_disposition.get_name();
+                let filename = content_disposition.get_filename();
+                let mime_type = field.content_type();
+                let mut parse_full_success = true;
+                let mut size_exceeded = false;
+                let mut data_content : Vec<u8> = Vec::new();
+
+                if name.is_some() {
+                    if filename.is_some() && (!file_saved || allow_multiple_file_uploads) {
+                        let mime_type_is_ok = check_file_mime(mime_type);
+
+                        if mime_type_is_ok {
+                            // Warning: IntelliJ cant show types or perform code completion on async fs stuff,
+                            // because tokio uses cfg attributes which the IDE can't parse (yet)
+
+                            let original_filename = sanitize_filename(filename.unwrap());
+                            let original_filename = original_filename.as_str();
+                            let storage_filename = generate_upload_filename(original_filename);
+
+                            if storage_filename.is_none() {
+                                continue;
+                            }
+
+                            let storage_filename = storage_filename.unwrap();
+                            let filepath = get_upload_path_tmp(storage_filename.as_str());
+                            let file = tokio::fs::File::create(filepath.as_str()).await;
+
+                            if file.is_ok() {
+                                let mut file : tokio::fs::File = file.unwrap();
+                                let mut file_hasher = Sha256::new();
+                                let mut file_size : usize = 0;
+
+                                // Field in turn is stream of bytes
+                                while let Some(chunk) = field.next().await {
+                                    if chunk.is_err() {
+                                        parse_full_success = false;
+                                        break;
+                                    }
+
+                                    let data = chunk.unwrap();
+                                    file_size += data.len();
+                                    request_size += data.len();
+
+                                    if file_size > max_file_size || request_size > max_request_size {
+                                        parse_full_success = false;
+                                        size_exceeded = true;
+                                        break;
+                                    }
+
+                                    file_hasher.update(&data);
+                                    let write_result = file.write_all(&data).await;
+
+                                    if write_result.is_err() {
+                                        parse_full_success = false;
+                                        break;
+                                    }
+                                }
+
+                                if parse_full_success {
+                                    let file_hash = format!("{:x}", file_hasher.finalize());
+
+                                    let multipart_file = MultipartFile::new(storage_filename.as_str(), original_filename, file_hash.as_str());
+
+                                    file_saved = true;
+                                    result_data.files.insert(name.unwrap().to_owned(), multipart_file);
+                                }
+                                else {
+                                    let remove_result : Result<_, _> = tokio::fs::remove_file(filepath.as_str()).await;
+
+                                    if remove_result.is_err() {
+                                        error!("Can't delete file: {}", filepath.as_str());
+                                    }
+                                }
+
+                                if size_exceeded {
+                                    delete_multipart_files(&result_data).await;
+
+                                    let error_msg = format!("Die Datei ist zu groß (maximal {} MB)", max_upload_size);
+
+                                    handle_error_str!(PayloadTooLarge, error_msg.as_str(), PAYLOAD_TOO_LARGE);
+                                }
+                            }
+                        }
+                    }
+                    else {
+                        let mime_type_is_ok = check_form_content_mime(mime_type);
+
+                        if mime_type_is_ok {
+                            while let Some(chunk) = field.next().await {
+                                if chunk.is_err() {
+                                    parse_full_success = false;
+                                    break;
+                                }
+
+                                let data = chunk.unwrap();
+                                request_size += data.len();
+
+                                if data_content.len() + data.len() > MAX_FORM_FIELD_SIZE || request_size > max_request_size {
+                                    size_exceeded = true;
+                                    break;
+                                }
+
+                                data_content.extend_from_slice(&data);
+                            }
+
+                            if size_exceeded {
+                                delete_multipart_files(&result_data).await;
+
+                                handle_error_str!(PayloadTooLarge, "Die übermittelten Formulardaten sind zu groß", PAYLOAD_TOO_LARGE);
+                            }
+
+                            // Multibyte characters can be split between two chunks, so the value is decoded at once
+                            let parse_result = String::from_utf8(data_content);
+
+                            if parse_full_success && parse_result.is_ok() {
+                                result_data.form_fields.insert(name.unwrap().to_owned(), parse_result.unwrap());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    return Ok(result_data);
+}
+
+// Adds an uploaded file from the tmp directory to the upload queue (the tmp file is deleted if that fails)
+// The client can poll the processing state with get_upload_status
+async fn queue_upload(db_connection: &DbConnection, uploader_id: i32, upload_file: &MultipartFile,
+                      upload_is_nsfw: bool, taglist_str: Option<&str>) -> Result<AddUploadSuccess, BackendError> {
+    let filename = upload_file.storage_filename.as_str();
+    let original_filename = upload_file.original_filename.as_str();
+    let file_hash = upload_file.file_hash.as_str();
+    let existing_upload_id = db_connection.get_upload_id_by_hash(file_hash).await;
+
+    if existing_upload_id.is_ok() {
+        delete_upload_tmp(filename).await;
+
+        let existing_upload_id = existing_upload_id.ok().unwrap();
+        let error_msg = format!("Die Datei wurde bereits hochgeladen (Upload {})", existing_upload_id);
+        let backend_error = BackendError::new_with_existing_upload(StatusCode::CONFLICT.as_u16(), DuplicateUpload,
+                                                                   error_msg.as_str(), existing_upload_id);
+
+        return Err(backend_error);
+    }
+    else if existing_upload_id.err().unwrap().error_type != DbApiErrorType::NoResult {
+        delete_upload_tmp(filename).await;
+
+        handle_error_str!(DatabaseError, "Fehler beim Prüfen auf bereits hochgeladene Dateien", INTERNAL_SERVER_ERROR);
+    }
+
+    let db_success = db_connection.add_upload(filename, original_filename, upload_is_nsfw, uploader_id, file_hash).await;
+
+    if db_success.is_ok() {
+        let upload_id = db_success.ok().unwrap();
+
+        if taglist_str.is_some() {
+            let taglist_data = TagData::from_str(taglist_str.unwrap());
+            let taglist_full_success = taglist_data.full_success;
+            let taglist_vec = taglist_data.as_str_ref_vec();
+
+            let db_success = db_connection.add_tags(taglist_vec, uploader_id, upload_id).await;
+
+            if db_success.is_ok() {
+                let ret_val = AddUploadSuccess::new(true, upload_id, true, taglist_full_success, UploadStatus::Queued);
+
+                return Ok(ret_val);
+            }
+            else {
+                let error = db_success.err().unwrap();
+                let error_type = error.error_type;
+
+                if error_type == PartFail {
+                    let ret_val = AddUploadSuccess::new(true, upload_id, true, false, UploadStatus::Queued);
+
+                    return Ok(ret_val);
+                }
+            }
+        }
+
+        let ret_val = AddUploadSuccess::new(true, upload_id, false, false, UploadStatus::Queued);
+
+        return Ok(ret_val);
+    }
+    else {
+        delete_upload_tmp(filename).await;
+
+        let error = db_success.err().unwrap();
+
+        // The same file was added by a concurrent request after the hash lookup above
+        if error.error_type == DbApiErrorType::UniqueViolation {
+            let existing_upload_id = db_connection.get_upload_id_by_hash(file_hash).await;
+
+            if existing_upload_id.is_ok() {
+                let existing_upload_id = existing_upload_id.ok().unwrap();
+                let error_msg = format!("Die Datei wurde bereits hochgeladen (Upload {})", existing_upload_id);
+                let backend_error = BackendError::new_with_existing_upload(StatusCode::CONFLICT.as_u16(), DuplicateUpload,
+                                                                           error_msg.as_str(), existing_upload_id);
+
+                return Err(backend_error);
+            }
+        }
+
+        let error_msg = error.error_msg;
+        handle_error_str!(InternalError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+pub async fn register(config: &web::Data<ProjectConfig>, request: &HttpRequest, register_data: &web::Form<RegisterData>) -> Result<SuccessReport, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let client_ip = get_client_ip(&config, request);
+    let counter_ip = format!("register.ip.{}", client_ip);
+    let max_attempts = config.security_config.register_max_attempts.get_value();
+
+    check_rate_limit!(db_connection, counter_ip.as_str());
+
+    let username = check_and_normalize_username(register_data.username.as_str());
+    let password = register_data.password.as_str();
+    let invite_key = register_data.invite_key.clone();
+
+    let username_is_ok = username.is_some();
+    let password_is_ok = check_password(password);
+    let invite_key_is_ok = check_invite_key(invite_key.as_str());
+
+    if username_is_ok && password_is_ok && invite_key_is_ok {
+        let username = username.unwrap();
+        let username_skeleton = get_username_skeleton(username.as_str());
+        let user_exists = db_connection.check_user_exists(username.as_str(), username_skeleton.as_str()).await;
+
+        if user_exists.is_err() {
+            handle_error_str!(DatabaseError, "Fehler beim Prüfen des Benutzernamens", INTERNAL_SERVER_ERROR);
+        }
+
+        // Also rejects usernames which only look like an existing username (like "Pony" written with a cyrillic "о")
+        if user_exists.ok().unwrap() {
+            handle_error_str!(UserInputError, "Der Benutzername ist bereits vergeben oder einem vorhandenen Benutzernamen zu ähnlich", CONFLICT);
+        }
+
+        let master_invite_key = config.security_config.master_invite_key.get_value();
+        let password_hash = hash_password(password, &config.security_config).unwrap_or(String::new());
+
+        if password_hash == "" {
+            handle_error_str!(DatabaseError, "Fehler beim Hashen des Passwortes", INTERNAL_SERVER_ERROR);
+        }
+
+        // The master invite key stays usable as fallback for the admins
+        let create_result = if invite_key == master_invite_key {
+            db_connection.add_user(username.as_str(), username_skeleton.as_str(), password_hash.as_str(), false, false).await
+        }
+        else {
+            db_connection.add_user_with_invite(username.as_str(), username_skeleton.as_str(), password_hash.as_str(), invite_key.as_str()).await
+        };
 
-            if error_type == DbApiErrorType::NoResult {
-                handle_error_str!(NoResult, error_msg.as_str(), NOT_FOUND);
+        if create_result.is_ok() {
+            let user_id = create_result.ok().unwrap();
+            let register_details = if invite_key == master_invite_key { Some("Master Invitecode") } else { None };
+            add_audit_event(&db_connection, AuditAction::Register, Some(user_id), Some(user_id),
+                            Some(client_ip.as_str()), register_details).await;
+
+            return Ok(SuccessReport::new(true));
+        }
+        else {
+            let error = create_result.err().unwrap();
+
+            if error.error_type == DbApiErrorType::NoResult {
+                count_rate_limit_attempt!(config, db_connection, max_attempts, counter_ip.as_str());
+                handle_error_str!(UserInputError, "Der eingegebene Invitecode ist ungültig", FORBIDDEN);
             }
             else {
-                handle_error_str!(DatabaseError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
+                handle_error_str!(DatabaseError, "Fehler beim Anlegen des Benutzers in der Datenbank", INTERNAL_SERVER_ERROR);
             }
         }
     }
     else {
-        handle_error_str!(UserInputError, "Benutzer ID ist ungültig", BAD_REQUEST);
+        handle_error_str!(UserInputError, "Die eingegebenen Daten entsprechen nicht den Richtlinien", BAD_REQUEST);
+    }
+}
+
+// Returns the lockout time in seconds (0 = not locked)
+async fn register_rate_limit_attempt(config: &ProjectConfig, db_connection: &DbConnection, counter_name: &str, max_attempts: u32) -> u32 {
+    // A limit of 0 disables the rate limit
+    if max_attempts == 0 {
+        return 0;
+    }
+
+    let attempt_window = config.security_config.attempt_window.get_value();
+    let lockout_base = config.security_config.lockout_base_duration.get_value();
+    let lockout_max = config.security_config.lockout_max_duration.get_value();
+    let lock_duration = db_connection.register_rate_limit_attempt(counter_name, max_attempts, attempt_window,
+                                                                  lockout_base, lockout_max).await;
+
+    if lock_duration.is_err() {
+        error!("register_rate_limit_attempt: Failed to count attempt for {}", counter_name);
+
+        return 0;
+    }
+
+    return lock_duration.ok().unwrap();
+}
+
+// Sets a new password with a token created by an admin and logs out all sessions of the user
+pub async fn reset_password(config: &web::Data<ProjectConfig>, request: &HttpRequest, url_data: &web::Path<String>, reset_data: &web::Form<ResetPasswordData>) -> Result<SuccessReport, BackendError> {
+    let reset_token = url_data.as_str();
+    let new_password = reset_data.new_password.as_str();
+
+    if !check_reset_token(reset_token) {
+        handle_error_str!(UserInputError, "Der Link zum Zurücksetzen des Passwortes ist ungültig", BAD_REQUEST);
+    }
+
+    if !check_password(new_password) {
+        handle_error_str!(UserInputError, "Das Passwort entspricht nicht den Richtlinien", BAD_REQUEST);
+    }
+
+    let db_connection = get_db_connection!(config, true, true);
+    let client_ip = get_client_ip(&config, request);
+    let counter_ip = format!("password_reset.ip.{}", client_ip);
+    let max_attempts = config.security_config.login_max_attempts.get_value();
+
+    check_rate_limit!(db_connection, counter_ip.as_str());
+
+    // The token is used up before the password is hashed, so two requests can't change the password with the same token
+    let reset_user_id = db_connection.consume_password_reset(reset_token).await;
+
+    if reset_user_id.is_err() {
+        let error = reset_user_id.err().unwrap();
+
+        if error.error_type == SessionErrorType::SessionInvalid {
+            count_rate_limit_attempt!(config, db_connection, max_attempts, counter_ip.as_str());
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let reset_user_id = reset_user_id.ok().unwrap();
+    let new_password_hash = hash_password(new_password, &config.security_config);
+
+    let change_success = if new_password_hash.is_some() {
+        db_connection.reset_user_pw(reset_user_id, new_password_hash.unwrap().as_str()).await.is_ok()
+    }
+    else {
+        false
+    };
+
+    // The used token stays invalid, the user gets a new link to try it again
+    if !change_success {
+        let reset_lifetime = config.security_config.password_reset_lifetime.get_value();
+        let new_reset_token = db_connection.create_password_reset(reset_user_id, reset_lifetime).await;
+
+        if new_reset_token.is_ok() {
+            let new_reset_token = new_reset_token.ok().unwrap();
+            let backend_error = BackendError::new_with_reset_url(StatusCode::INTERNAL_SERVER_ERROR.as_u16(), DatabaseError,
+                                                                 "Fehler beim Speichern des neuen Passwortes", new_reset_token.as_str());
+
+            return Err(backend_error);
+        }
+
+        handle_error_str!(DatabaseError, "Fehler beim Speichern des neuen Passwortes, bitte einen Admin um einen neuen Link bitten", INTERNAL_SERVER_ERROR);
+    }
+
+    add_audit_event(&db_connection, AuditAction::PasswordReset, None, Some(reset_user_id),
+                    Some(client_ip.as_str()), None).await;
+
+    let destroy_result = db_connection.destroy_user_sessions(reset_user_id, None).await;
+
+    if destroy_result.is_err() {
+        handle_error_str!(DatabaseError, "Das Passwort wurde geändert, aber die Sessions konnten nicht beendet werden", INTERNAL_SERVER_ERROR);
+    }
+
+    return Ok(SuccessReport::new(true));
+}
+
+pub async fn revoke_api_token(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<i32>) -> Result<SuccessReport, BackendError> {
+    let token_id = url_data.as_ref().clone();
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let remove_result = db_connection.remove_api_token(token_id, session_data.user_id).await;
+
+    if remove_result.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        let token_details = format!("Token {}", token_id);
+        add_audit_event(&db_connection, AuditAction::ApiTokenRevoke, Some(session_data.user_id), Some(session_data.user_id),
+                        Some(client_ip.as_str()), Some(token_details.as_str())).await;
+
+        return Ok(SuccessReport::new(true));
+    }
+    else {
+        let error = remove_result.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+}
+
+// Destroys all sessions of the user including the current one
+pub async fn revoke_all_sessions(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest) -> Result<SuccessReport, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let revoke_result = db_connection.destroy_user_sessions(session_data.user_id, None).await;
+
+    if revoke_result.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        add_audit_event(&db_connection, AuditAction::SessionsRevoke, Some(session_data.user_id), Some(session_data.user_id),
+                        Some(client_ip.as_str()), None).await;
+
+        purge_session(session);
+
+        return Ok(SuccessReport::new(true));
+    }
+    else {
+        let redis_error = revoke_result.err().unwrap();
+
+        handle_error_str!(DatabaseError, redis_error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
+
+pub async fn revoke_session(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<String>) -> Result<SuccessReport, BackendError> {
+    let db_connection = get_db_connection!(config, false, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let session_public_id = url_data.as_str();
+    let session_list = db_connection.get_user_sessions(session_data.user_id, session_data.session_id.as_str()).await;
+
+    if session_list.is_err() {
+        let redis_error = session_list.err().unwrap();
+
+        handle_error_str!(DatabaseError, redis_error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+
+    // The public id is only used to find the session, the real session id is never send to the client
+    let target_session = session_list.ok().unwrap().session_list.into_iter()
+        .find(|session_info| session_info.session_public_id == session_public_id);
+
+    if target_session.is_none() {
+        handle_error_str!(NoResult, "Session existiert nicht", NOT_FOUND);
+    }
+
+    let target_session = target_session.unwrap();
+    let revoke_result = db_connection.destroy_session(target_session.session_id.as_str()).await;
+
+    if revoke_result.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        add_audit_event(&db_connection, AuditAction::SessionRevoke, Some(session_data.user_id), Some(session_data.user_id),
+                        Some(client_ip.as_str()), Some(target_session.session_ip.as_str())).await;
+
+        if target_session.session_is_current {
+            purge_session(session);
+        }
+
+        return Ok(SuccessReport::new(true));
+    }
+    else {
+        let redis_error = revoke_result.err().unwrap();
+
+        handle_error_str!(DatabaseError, redis_error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
     }
 }
 
-pub async fn get_userdata_by_username(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<String>) -> Result<UserData, BackendError> {
-    let target_username = url_data.as_str();
-    let username_is_ok = check_username(target_username);
+pub async fn set_filter(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<(bool, bool)>) -> Result<SuccessReport, BackendError> {
+    let (show_sfw, show_nsfw) = url_data.as_ref().clone();
 
-    if username_is_ok {
-        let db_connection = get_db_connection!(config, true, true);
-        let _session_data = get_user_session_data!(db_connection, session, false);
-        let user_data = db_connection.get_userdata_by_username(target_username).await;
+    if show_sfw && show_nsfw {
+        handle_error_str!(UserInputError, "Es muss mindestens ein Filter aktiviert sein", BAD_REQUEST);
+    }
 
-        if user_data.is_ok() {
-            let user_data = UserData::new(&user_data.ok().unwrap());
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
 
-            return Ok(user_data);
-        }
-        else {
-            let error = user_data.err().unwrap();
-            let error_type = error.error_type;
-            let error_msg = error.error_msg;
+    let set_result_1 = session.set("show_sfw", show_sfw);
+    let set_result_2 = session.set("show_nsfw", show_nsfw);
 
-            if error_type == DbApiErrorType::NoResult {
-                handle_error_str!(NoResult, error_msg.as_str(), NOT_FOUND);
-            }
-            else {
-                handle_error_str!(DatabaseError, error_msg.as_str(), INTERNAL_SERVER_ERROR);
-            }
-        }
+    if set_result_1.is_err() || set_result_2.is_err() {
+        handle_error_str!(CookieError, "Fehler beim Speichern der Einstellung", INTERNAL_SERVER_ERROR);
     }
-    else {
-        handle_error_str!(UserInputError, "Benutzername ist ungültig", BAD_REQUEST);
+
+    // Requests with api tokens use the stored filter
+    let db_result = db_connection.set_user_filter(session_data.user_id, show_sfw, show_nsfw).await;
+
+    if db_result.is_err() {
+        handle_error_str!(DatabaseError, db_result.err().unwrap().error_msg.as_str(), INTERNAL_SERVER_ERROR);
     }
+
+    return Ok(SuccessReport::new(true));
 }
 
-pub async fn login(config: &web::Data<ProjectConfig>, session: &Session, login_data: &web::Form<LoginData>) -> Result<UserData, BackendError> {
+pub async fn set_user_role(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<(i32, String)>) -> Result<SuccessReport, BackendError> {
+    let (target_user_id, user_role_str) = url_data.as_ref().clone();
+    let target_user_role = UserRole::try_from(user_role_str.as_str());
+
+    if target_user_id < 1 {
+        handle_error_str!(UserInputError, "Benutzer ID ist ungültig", BAD_REQUEST);
+    }
+
+    if target_user_role.is_err() {
+        handle_error_str!(UserInputError, "Die Rolle muss entweder user, moderator oder admin sein", BAD_REQUEST);
+    }
+
+    let target_user_role = target_user_role.unwrap();
     let db_connection = get_db_connection!(config, true, true);
-    let user_session = get_user_session(&db_connection, &session, false).await;
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let _own_user_data = require_user_role!(db_connection, session_data, UserRole::Admin);
 
-    if user_session.is_err() {
-        let login_username = login_data.username.as_str();
-        let login_password = login_data.password.as_str();
-        let keep_logged_in = login_data.keep_logged_in;
+    // Prevents admins from locking themselves out
+    if target_user_id == session_data.user_id {
+        handle_error_str!(UserInputError, "Die eigene Rolle kann nicht geändert werden", BAD_REQUEST);
+    }
 
-        let username_is_ok = check_username(login_username);
-        let password_is_ok = check_password(login_password);
+    let db_result = db_connection.set_user_role(target_user_id, target_user_role).await;
 
-        if username_is_ok && password_is_ok {
-            let user_data = db_connection.get_userdata_by_username(login_username).await;
+    if db_result.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        let role_details = user_role_str.to_lowercase();
+        add_audit_event(&db_connection, AuditAction::UserRoleChange, Some(session_data.user_id), Some(target_user_id),
+                        Some(client_ip.as_str()), Some(role_details.as_str())).await;
 
-            if user_data.is_ok() {
-                let user_data = user_data.ok().unwrap();
-                let password_hash = user_data.password_hash.clone();
-                let secret_hash_key = config.security_config.password_hash_key.get_value();
-                let password_is_correct = verify_password(password_hash.as_str(),
-                                                          login_password,
-                                                          secret_hash_key.as_str()).unwrap_or(false);
+        return Ok(SuccessReport::new(true));
+    }
+    else {
+        let error = db_result.err().unwrap();
 
-                if password_is_correct {
-                    let user_id = user_data.user_id;
-                    let session_data = db_connection.create_session(user_id, keep_logged_in).await;
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+}
 
-                    if session_data.is_ok() {
-                        let session_data = session_data.ok().unwrap();
-                        let session_id = session_data.session_id.clone();
-                        let session_set_result_1 = session.set("session_id", session_id.as_str());
-                        let session_set_result_2 = session.set("show_sfw", true);
-                        let session_set_result_3 = session.set("show_nsfw", false);
+// Creates the session after a successful login and sets the session cookie
+async fn start_user_session(config: &ProjectConfig, db_connection: &DbConnection, session: &Session, request: &HttpRequest, user_data: &db_result::UserData, keep_logged_in: bool) -> Result<UserData, BackendError> {
+    let client_ip = get_client_ip(config, request);
+    let user_agent = get_client_user_agent(request);
+    let session_data = db_connection.create_session(user_data.user_id, keep_logged_in, client_ip.as_str(), user_agent.as_str()).await;
 
-                        if session_set_result_1.is_ok() && session_set_result_2.is_ok() && session_set_result_3.is_ok() {
-                            let response_userdata = response_result::UserData::new(&user_data);
+    if session_data.is_ok() {
+        let session_data = session_data.ok().unwrap();
+        let session_id = session_data.session_id.clone();
+        let session_set_result_1 = session.set("session_id", session_id.as_str());
+        let session_set_result_2 = session.set("show_sfw", true);
+        let session_set_result_3 = session.set("show_nsfw", false);
 
-                            return Ok(response_userdata);
-                        }
-                        else {
-                            session.purge();
+        if session_set_result_1.is_ok() && session_set_result_2.is_ok() && session_set_result_3.is_ok() {
+            add_audit_event(db_connection, AuditAction::Login, Some(user_data.user_id), Some(user_data.user_id),
+                            Some(client_ip.as_str()), None).await;
 
-                            handle_error_str!(CookieError, "Fehler beim Setzen des Session Cookies", INTERNAL_SERVER_ERROR);
-                        }
-                    }
-                    else {
-                        handle_error_str!(DatabaseError, "Fehler beim Anlegen der Session in der Redis Datenbank", INTERNAL_SERVER_ERROR);
-                    }
-                }
-                else {
-                    handle_error_str!(UserInputError, "Benutzername oder Passwort ist falsch", FORBIDDEN);
-                }
-            }
-            else {
-                let error = user_data.err().unwrap();
-                let error_type = error.error_type;
+            let response_userdata = response_result::UserData::new(user_data);
 
-                if error_type == DbApiErrorType::NoResult {
-                    handle_error_str!(UserInputError, "Benutzername oder Passwort ist falsch", FORBIDDEN);
-                }
-                else {
-                    handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
-                }
-            }
+            return Ok(response_userdata);
         }
         else {
-            handle_error_str!(UserInputError, "Ungültige Zeichen in Benutzername oder Passwort", BAD_REQUEST);
+            purge_session(session);
+
+            handle_error_str!(CookieError, "Fehler beim Setzen des Session Cookies", INTERNAL_SERVER_ERROR);
         }
     }
     else {
-        handle_error_str!(Ignored, "Es ist bereits ein Benutzer eingelogt", BAD_REQUEST);
+        handle_error_str!(DatabaseError, "Fehler beim Anlegen der Session in der Redis Datenbank", INTERNAL_SERVER_ERROR);
     }
 }
 
-pub async fn logout(config: &web::Data<ProjectConfig>, session: &Session) -> Result<SuccessReport, BackendError> {
-    let db_connection = get_db_connection!(config, false, true);
+pub async fn totp_disable(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, disable_data: &web::Form<TotpDisableData>) -> Result<SuccessReport, BackendError> {
+    let password = disable_data.password.as_str();
+
+    if !check_password(password) {
+        handle_error_str!(UserInputError, "Das Passwort entspricht nicht den Richtlinien", BAD_REQUEST);
+    }
+
+    let db_connection = get_db_connection!(config, true, true);
     let session_data = get_user_session_data!(db_connection, session, false);
-    let session_id = session_data.session_id;
-    let logoff_result = db_connection.destroy_session(session_id.as_str()).await;
+    let own_user_data = require_user_role!(db_connection, session_data, UserRole::User);
 
-    if logoff_result.is_ok() {
-        session.purge();
+    let verification_result = verify_password_rate_limited(config, &db_connection, &own_user_data, password, "Das Passwort ist falsch").await;
+
+    if verification_result.is_err() {
+        return Err(verification_result.err().unwrap());
+    }
+
+    let disable_result = db_connection.disable_user_totp(session_data.user_id).await;
+
+    if disable_result.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        add_audit_event(&db_connection, AuditAction::TotpDisable, Some(session_data.user_id), Some(session_data.user_id),
+                        Some(client_ip.as_str()), None).await;
 
         return Ok(SuccessReport::new(true));
     }
     else {
-        let redis_error = logoff_result.err().unwrap();
+        let error = disable_result.err().unwrap();
 
-        handle_error_str!(DatabaseError, redis_error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
     }
 }
 
-//noinspection ALL
-// Returns a map of name and content (in case of file: content = filename)
-async fn parse_multipart_form_data(payload: &mut Multipart, allow_multiple_file_uploads: bool) -> HashMap<String, String> {
-    let mut result_map : HashMap<String, String> = HashMap::new();
-    let mut file_saved = false;
-
-    while let Ok(Some(field)) = payload.try_next().await {
-        let mut field : Field = field; // Hack to show types in IDEA IDE
-        let content_disposition = field.content_disposition();
-
-        if content_disposition.is_some() {
-            let content_disposition = content_disposition.unwrap();
-
-            if content_disposition.is_form_data() {
-                let name = content_disposition.get_name();
-                let filename = content_disposition.get_filename();
-                let mime_type = field.content_type();
-                let mut parse_full_success = true;
-                let mut data_content = String::new();
+// Confirms the enrollment with a valid totp code, enables 2FA and returns new recovery codes
+pub async fn totp_enable(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, totp_data: &web::Form<TotpCodeData>) -> Result<TotpRecoveryCodes, BackendError> {
+    let totp_code = totp_data.totp_code.as_str();
 
-                if name.is_some() {
-                    if filename.is_some() && (!file_saved || allow_multiple_file_uploads) {
-                        let mime_type_is_ok = check_file_mime(mime_type);
+    if !check_totp_code(totp_code) {
+        handle_error_str!(UserInputError, "Der 2FA Code muss aus 6 Ziffern bestehen", BAD_REQUEST);
+    }
 
-                        if mime_type_is_ok {
-                            // Warning: IntelliJ cant show types or perform code completion on async fs stuff,
-                            // because tokio uses cfg attributes which the IDE can't parse (yet)
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let user_totp = db_connection.get_user_totp(session_data.user_id).await;
 
-                            let filename = filename.unwrap().to_owned();
-                            let filepath = get_upload_path_tmp(filename.as_str());
-                            let file = tokio::fs::File::create(filepath.as_str()).await;
+    if user_totp.is_err() {
+        let error = user_totp.err().unwrap();
 
-                            if file.is_ok() {
-                                let mut file : tokio::fs::File = file.unwrap();
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
 
-                                // Field in turn is stream of bytes
-                                while let Some(chunk) = field.next().await {
-                                    let data = chunk.unwrap();
-                                    let write_result = file.write_all(&data).await;
+    let user_totp = user_totp.ok().unwrap();
 
-                                    if write_result.is_err() {
-                                        parse_full_success = false;
-                                        let remove_result : Result<_, _> = tokio::fs::remove_file(filepath.as_str()).await;
+    if user_totp.totp_enabled {
+        handle_error_str!(Ignored, "2FA ist bereits aktiviert", BAD_REQUEST);
+    }
 
-                                        if remove_result.is_err() {
-                                            error!("Can't delete file: {}", filepath.as_str());
-                                        }
+    if user_totp.totp_secret.is_none() {
+        handle_error_str!(UserInputError, "2FA wurde noch nicht eingerichtet", BAD_REQUEST);
+    }
 
-                                        break;
-                                    }
-                                }
+    let totp_secret = user_totp.totp_secret.unwrap();
+    let time_step = verify_totp_code(totp_secret.as_str(), totp_code, user_totp.totp_last_step);
 
-                                if parse_full_success {
-                                    file_saved = true;
-                                    result_map.insert(name.unwrap().to_owned(), filename.clone());
-                                }
-                            }
-                        }
-                    }
-                    else {
-                        let mime_type_is_ok = check_form_content_mime(mime_type);
+    if time_step.is_none() {
+        handle_error_str!(UserInputError, "Der 2FA Code ist falsch", FORBIDDEN);
+    }
 
-                        if mime_type_is_ok {
-                            while let Some(chunk) = field.next().await {
-                                let data = chunk.unwrap();
-                                let parse_result = String::from_utf8(data.to_vec());
+    // The confirmation code can't be used for a login afterwards
+    let use_result = db_connection.use_totp_time_step(session_data.user_id, time_step.unwrap()).await;
 
-                                if parse_result.is_ok() {
-                                    let read_data = parse_result.unwrap();
-                                    data_content.push_str(read_data.as_str());
-                                } else {
-                                    parse_full_success = false;
-                                    break;
-                                }
-                            }
+    if use_result.is_err() {
+        let error = use_result.err().unwrap();
 
-                            if parse_full_success {
-                                result_map.insert(name.unwrap().to_owned(), data_content);
-                            }
-                        }
-                    }
-                }
-            }
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(UserInputError, error.error_msg.as_str(), FORBIDDEN);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
         }
     }
 
-    return result_map;
-}
+    let recovery_codes = generate_recovery_codes(10);
+    let recovery_code_hashes : Vec<String> = recovery_codes.iter()
+        .map(|recovery_code| hash_random_token(recovery_code.as_str()))
+        .collect();
 
-pub async fn register(config: &web::Data<ProjectConfig>, register_data: &web::Form<RegisterData>) -> Result<SuccessReport, BackendError> {
-    let db_connection = get_db_connection!(config, true, false);
+    let enable_result = db_connection.enable_user_totp(session_data.user_id, &recovery_code_hashes).await;
 
-    let username = register_data.username.as_str();
-    let password = register_data.password.as_str();
-    let invite_key = register_data.invite_key.clone();
+    if enable_result.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        add_audit_event(&db_connection, AuditAction::TotpEnable, Some(session_data.user_id), Some(session_data.user_id),
+                        Some(client_ip.as_str()), None).await;
 
-    let username_is_ok = check_username(username);
-    let password_is_ok = check_password(password);
-    let invite_key_is_ok = check_invite_key(invite_key.as_str());
+        return Ok(TotpRecoveryCodes::new(recovery_codes));
+    }
+    else {
+        let error = enable_result.err().unwrap();
 
-    if username_is_ok && password_is_ok && invite_key_is_ok {
-        let master_invite_key = config.security_config.master_invite_key.get_value();
+        handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+    }
+}
 
-        if invite_key == master_invite_key {
-            let password_hash_key = config.security_config.password_hash_key.get_value();
-            let password_hash = hash_password(password, password_hash_key.as_str()).unwrap_or(String::new());
+// Generates a new totp secret, 2FA stays disabled until the secret is confirmed with totp_enable
+pub async fn totp_enroll(config: &web::Data<ProjectConfig>, session: &Session) -> Result<TotpEnrollment, BackendError> {
+    let db_connection = get_db_connection!(config, true, true);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let own_user_data = require_user_role!(db_connection, session_data, UserRole::User);
+    let totp_secret = generate_totp_secret();
+    let set_result = db_connection.set_user_totp_secret(session_data.user_id, totp_secret.as_str()).await;
 
-            if password_hash != "" {
-                let create_result = db_connection.add_user(username,password_hash.as_str(), false).await;
+    if set_result.is_ok() {
+        let totp_uri = get_totp_uri(totp_secret.as_str(), own_user_data.username.as_str());
 
-                if create_result.is_ok() {
-                    return Ok(SuccessReport::new(true));
-                }
-                else {
-                    handle_error_str!(DatabaseError, "Fehler beim Anlegen des Benutzers in der Datenbank", INTERNAL_SERVER_ERROR);
-                }
-            }
-            else {
-                handle_error_str!(DatabaseError, "Fehler beim Hashen des Passwortes", INTERNAL_SERVER_ERROR);
-            }
+        return Ok(TotpEnrollment::new(totp_secret, totp_uri));
+    }
+    else {
+        let error = set_result.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(Ignored, error.error_msg.as_str(), BAD_REQUEST);
         }
         else {
-            handle_error_str!(UserInputError, "Der eingegebene Invitecode ist ungültig", FORBIDDEN);
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
         }
     }
-    else {
-        handle_error_str!(UserInputError, "Die eingegebenen Daten entsprechen nicht den Richtlinien", BAD_REQUEST);
-    }
 }
 
-pub async fn set_filter(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<(bool, bool)>) -> Result<SuccessReport, BackendError> {
-    let (show_sfw, show_nsfw) = url_data.as_ref().clone();
+pub async fn unban_user(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<i32>) -> Result<SuccessReport, BackendError> {
+    let target_user_id = url_data.as_ref().clone();
 
-    if show_sfw && show_nsfw {
-        handle_error_str!(UserInputError, "Es muss mindestens ein Filter aktiviert sein", BAD_REQUEST);
+    if target_user_id < 1 {
+        handle_error_str!(UserInputError, "Benutzer ID ist ungültig", BAD_REQUEST);
     }
 
     let db_connection = get_db_connection!(config, true, true);
-    let _session_data = get_user_session_data!(db_connection, session, false);
+    let session_data = get_user_session_data!(db_connection, session, false);
+    let _own_user_data = require_user_role!(db_connection, session_data, UserRole::Moderator);
 
-    let set_result_1 = session.set("show_sfw", show_sfw);
-    let set_result_2 = session.set("show_nsfw", show_nsfw);
+    let unban_result = db_connection.remove_user_bans(target_user_id).await;
 
-    if set_result_1.is_err() || set_result_2.is_err() {
-        handle_error_str!(CookieError, "Fehler beim Speichern der Einstellung", INTERNAL_SERVER_ERROR);
+    if unban_result.is_ok() {
+        let client_ip = get_client_ip(&config, request);
+        add_audit_event(&db_connection, AuditAction::UserUnban, Some(session_data.user_id), Some(target_user_id),
+                        Some(client_ip.as_str()), None).await;
+
+        return Ok(SuccessReport::new(true));
+    }
+    else {
+        handle_error_str!(DatabaseError, "Fehler beim Aufheben der Sperre", INTERNAL_SERVER_ERROR);
     }
+}
 
-    return Ok(SuccessReport::new(true));
+// Verifies the password of the own account, failed verifications count as failed logins
+// Otherwise this would allow to guess the password of a hijacked session without limit
+async fn verify_password_rate_limited(config: &ProjectConfig, db_connection: &DbConnection, own_user_data: &db_result::UserData,
+                                      password: &str, error_msg: &str) -> Result<(), BackendError> {
+    let counter_user = format!("login.user.{}", own_user_data.username.to_lowercase());
+    let max_attempts = config.security_config.login_max_attempts.get_value();
+    check_rate_limit!(db_connection, counter_user.as_str());
+
+    let password_verification = verify_password(own_user_data.password_hash.as_str(),
+                                                password,
+                                                &config.security_config).unwrap_or(PasswordVerification::Incorrect);
+
+    if password_verification == PasswordVerification::Incorrect {
+        count_rate_limit_attempt!(config, db_connection, max_attempts, counter_user.as_str());
+        handle_error_str!(UserInputError, error_msg, FORBIDDEN);
+    }
+
+    return Ok(());
 }
 
-pub async fn vote_comment(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<(i32, i32)>) -> Result<SuccessReport, BackendError> {
+pub async fn vote_comment(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<(i32, i32)>) -> Result<SuccessReport, BackendError> {
     let db_connection = get_db_connection!(config, true, true);
-    let session_data = get_user_session_data!(db_connection, session, false);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Vote);
     let (comment_id, vote_value) = url_data.as_ref().clone();
     let user_id = session_data.user_id;
 
@@ -719,13 +2353,19 @@ pub async fn vote_comment(config: &web::Data<ProjectConfig>, session: &Session,
         return Ok(SuccessReport::new(true));
     }
     else {
+        let error = db_result.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+
         handle_error_str!(DatabaseError, "Fehler beim Speichern der Bewertung", INTERNAL_SERVER_ERROR);
     }
 }
 
-pub async fn vote_tag(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<(i32, i32)>) -> Result<SuccessReport, BackendError> {
+pub async fn vote_tag(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<(i32, i32)>) -> Result<SuccessReport, BackendError> {
     let db_connection = get_db_connection!(config, true, true);
-    let session_data = get_user_session_data!(db_connection, session, false);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Vote);
     let (tum_id, vote_value) = url_data.as_ref().clone();
     let user_id = session_data.user_id;
 
@@ -743,13 +2383,19 @@ pub async fn vote_tag(config: &web::Data<ProjectConfig>, session: &Session, url_
         return Ok(SuccessReport::new(true));
     }
     else {
+        let error = db_result.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+
         handle_error_str!(DatabaseError, "Fehler beim Speichern der Bewertung", INTERNAL_SERVER_ERROR);
     }
 }
 
-pub async fn vote_upload(config: &web::Data<ProjectConfig>, session: &Session, url_data: &web::Path<(i32, i32)>) -> Result<SuccessReport, BackendError> {
+pub async fn vote_upload(config: &web::Data<ProjectConfig>, session: &Session, request: &HttpRequest, url_data: &web::Path<(i32, i32)>) -> Result<SuccessReport, BackendError> {
     let db_connection = get_db_connection!(config, true, true);
-    let session_data = get_user_session_data!(db_connection, session, false);
+    let session_data = get_user_session_data!(db_connection, session, request, false, ApiTokenScope::Vote);
     let (upload_id, vote_value) = url_data.as_ref().clone();
     let user_id = session_data.user_id;
 
@@ -767,6 +2413,54 @@ pub async fn vote_upload(config: &web::Data<ProjectConfig>, session: &Session, u
         return Ok(SuccessReport::new(true));
     }
     else {
+        let error = db_result.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+
         handle_error_str!(DatabaseError, "Fehler beim Speichern der Bewertung", INTERNAL_SERVER_ERROR);
     }
+}
+
+// Writes the payload to the tmp file of the upload session and stores the new offset (the caller has to hold the lock)
+// Returns the upload session with the new offset
+async fn write_upload_chunk(db_connection: &DbConnection, upload_session: UploadSessionData, chunk_offset: u64,
+                            payload: &mut web::Payload) -> Result<UploadSessionData, BackendError> {
+    let mut upload_session = upload_session;
+
+    if chunk_offset != upload_session.upload_offset {
+        let error_msg = format!("Der Upload muss bei Byte {} fortgesetzt werden", upload_session.upload_offset);
+
+        handle_error_str!(UserInputError, error_msg.as_str(), CONFLICT);
+    }
+
+    let filepath = get_upload_path_tmp(upload_session.storage_filename.as_str());
+    let append_result = append_upload_chunks(filepath.as_str(), upload_session.upload_offset, upload_session.upload_size, payload).await;
+
+    if append_result.is_none() {
+        handle_error_str!(UnknownError, "Es ist ein Fehler beim Speichern der Datei auf dem Server aufgetreten", INTERNAL_SERVER_ERROR);
+    }
+
+    let (upload_offset, size_exceeded) = append_result.unwrap();
+    upload_session.upload_offset = upload_offset;
+
+    let offset_result = db_connection.set_upload_session_offset(upload_session.upload_session_id.as_str(), upload_session.upload_offset).await;
+
+    if offset_result.is_err() {
+        let error = offset_result.err().unwrap();
+
+        if error.error_type == SessionErrorType::SessionInvalid {
+            handle_error_str!(NoResult, error.error_msg.as_str(), NOT_FOUND);
+        }
+        else {
+            handle_error_str!(DatabaseError, error.error_msg.as_str(), INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    if size_exceeded {
+        handle_error_str!(PayloadTooLarge, "Der Abschnitt ist größer als der Rest der angekündigten Datei", PAYLOAD_TOO_LARGE);
+    }
+
+    return Ok(upload_session);
 }
\ No newline at end of file
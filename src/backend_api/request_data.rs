@@ -1,8 +1,149 @@
-use crate::security::{check_tag, check_and_escape_comment};
+use crate::security::{check_and_normalize_tag, check_and_escape_comment};
 use log::{warn};
 use mime::Mime;
 use crate::db_api::DbConnection;
 use serde::{Deserialize};
+use crate::db_api::db_result::{ApiTokenScope, AuditAction};
+use std::convert::TryFrom;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+pub struct AuditEventFilter {
+    pub action: Option<String>,
+    pub actor_id: Option<i32>,
+    pub target_id: Option<i32>,
+}
+
+impl AuditEventFilter {
+    // Returns the parsed action, actor id and target id (None values aren't filtered)
+    pub fn validate_data(&self) -> Option<(Option<AuditAction>, Option<i32>, Option<i32>)> {
+        let mut audit_action = None;
+
+        if self.action.is_some() {
+            let parsed_action = AuditAction::try_from(self.action.as_ref().unwrap().as_str());
+
+            if parsed_action.is_err() {
+                return None;
+            }
+
+            audit_action = Some(parsed_action.unwrap());
+        }
+
+        if self.actor_id.unwrap_or(1) < 1 || self.target_id.unwrap_or(1) < 1 {
+            return None;
+        }
+
+        return Some((audit_action, self.actor_id, self.target_id));
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BanUserData {
+    pub user_id: i32,
+    pub ban_reason: String,
+    pub ban_duration: i32,
+}
+
+impl BanUserData {
+    pub fn validate_data(&self) -> Option<BanUserData> {
+        let ban_reason_length = self.ban_reason.len();
+
+        // A ban can last up to 10 years (duration is given in hours) and the reason can be 512 characters long
+        if self.user_id > 0 && self.ban_duration > 0 && self.ban_duration <= 24 * 365 * 10 &&
+            ban_reason_length > 0 && ban_reason_length <= 512 {
+            let escaped_ban_reason = check_and_escape_comment(self.ban_reason.as_str());
+
+            if escaped_ban_reason.is_some() {
+                let ban_user_data = BanUserData {
+                    user_id: self.user_id,
+                    ban_reason: escaped_ban_reason.unwrap(),
+                    ban_duration: self.ban_duration,
+                };
+
+                return Some(ban_user_data);
+            }
+        }
+
+        return None;
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordData {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiTokenData {
+    pub token_name: String,
+    pub token_scopes: Option<String>, // Comma separated list of scopes (all scopes if empty)
+}
+
+impl CreateApiTokenData {
+    // Returns the escaped token name and the parsed scopes
+    pub fn validate_data(&self) -> Option<(String, Vec<ApiTokenScope>)> {
+        let token_name_length = self.token_name.len();
+
+        // A token name can be 64 characters long
+        if token_name_length == 0 || token_name_length > 64 {
+            return None;
+        }
+
+        let escaped_token_name = check_and_escape_comment(self.token_name.as_str());
+
+        // The escaped name have to fit into the database column too
+        if escaped_token_name.is_none() || escaped_token_name.as_ref().unwrap().len() > 64 {
+            return None;
+        }
+
+        let token_scopes_str = self.token_scopes.clone().unwrap_or_default();
+        let mut token_scopes = Vec::new();
+
+        for token_scope_str in token_scopes_str.split(',').filter(|scope| !scope.trim().is_empty()) {
+            let token_scope = ApiTokenScope::try_from(token_scope_str);
+
+            if token_scope.is_err() {
+                return None;
+            }
+
+            let token_scope = token_scope.unwrap();
+
+            if !token_scopes.contains(&token_scope) {
+                token_scopes.push(token_scope);
+            }
+        }
+
+        if token_scopes.is_empty() {
+            token_scopes = ApiTokenScope::all();
+        }
+
+        return Some((escaped_token_name.unwrap(), token_scopes));
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateUploadSessionData {
+    pub filename: String,
+    pub upload_size: u64, // Size of the whole file in bytes
+    pub classification: String, // Either 'sfw' or 'nsfw'
+    pub taglist: Option<String>,
+}
+
+impl CreateUploadSessionData {
+    // Returns if the upload is nsfw (None if the data is invalid)
+    pub fn validate_data(&self) -> Option<bool> {
+        if self.upload_size == 0 || self.filename.is_empty() {
+            return None;
+        }
+
+        match self.classification.as_str() {
+            "sfw" => Some(false),
+            "nsfw" => Some(true),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Deserialize)]
 pub struct CommentData {
@@ -31,6 +172,11 @@ impl CommentData {
     }
 }
 
+#[derive(Deserialize)]
+pub struct DeleteAccountData {
+    pub password: String, // The account deletion have to be confirmed with the password
+}
+
 #[derive(Deserialize)]
 pub struct LoginData {
     pub username: String,
@@ -38,6 +184,36 @@ pub struct LoginData {
     pub keep_logged_in: bool,
 }
 
+pub struct MultipartFile {
+    pub storage_filename: String, // Server generated name of the file in the tmp directory
+    pub original_filename: String, // Filename sent by the client, only kept as metadata
+    pub file_hash: String, // Hex encoded SHA-256 hash of the file content
+}
+
+impl MultipartFile {
+    pub fn new(storage_filename: &str, original_filename: &str, file_hash: &str) -> MultipartFile {
+        MultipartFile {
+            storage_filename: storage_filename.to_owned(),
+            original_filename: original_filename.to_owned(),
+            file_hash: file_hash.to_owned(),
+        }
+    }
+}
+
+pub struct MultipartFormData {
+    pub form_fields: HashMap<String, String>, // Name and content of the text fields
+    pub files: HashMap<String, MultipartFile>, // Name and the saved file of the file fields
+}
+
+impl MultipartFormData {
+    pub fn new() -> MultipartFormData {
+        MultipartFormData {
+            form_fields: HashMap::new(),
+            files: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct RegisterData {
     pub username: String,
@@ -45,6 +221,22 @@ pub struct RegisterData {
     pub invite_key: String,
 }
 
+#[derive(Deserialize)]
+pub struct ResetPasswordData {
+    pub new_password: String,
+}
+
+// Used for the second login step and to confirm the 2FA enrollment
+#[derive(Deserialize)]
+pub struct TotpCodeData {
+    pub totp_code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpDisableData {
+    pub password: String,
+}
+
 pub struct TagData {
     pub taglist: Vec<String>,
     pub full_success: bool,
@@ -69,10 +261,10 @@ impl TagData {
                 current_tag.remove(current_tag.len() - 1);
             }
 
-            let tag_is_ok = check_tag(current_tag.as_str());
+            let normalized_tag = check_and_normalize_tag(current_tag.as_str());
 
-            if tag_is_ok {
-                result_vec.push(current_tag);
+            if normalized_tag.is_some() {
+                result_vec.push(normalized_tag.unwrap());
             }
             else {
                 full_success = false;
@@ -97,11 +289,16 @@ pub fn check_file_mime(mime_type: &Mime) -> bool {
     let type_name = mime_type.type_();
     let subtype_name = mime_type.subtype();
 
-    match (type_name, subtype_name) {
-        (mime::IMAGE, mime::PNG) => true,
-        (mime::IMAGE, mime::JPEG) => true,
-        (mime::IMAGE, mime::GIF) => true,
-        (mime::VIDEO, mime::MP4) => true,
+    // The mime crate has no constants for the newer formats
+    match (type_name, subtype_name.as_str()) {
+        (mime::IMAGE, "png") => true,
+        (mime::IMAGE, "apng") => true,
+        (mime::IMAGE, "jpeg") => true,
+        (mime::IMAGE, "gif") => true,
+        (mime::IMAGE, "webp") => true,
+        (mime::IMAGE, "avif") => true,
+        (mime::VIDEO, "mp4") => true,
+        (mime::VIDEO, "webm") => true,
         _ => false,
     }
 }
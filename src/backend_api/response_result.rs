@@ -1,5 +1,7 @@
 use crate::db_api::db_result;
 use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Duration, Local};
+use crate::frontend::get_password_reset_path;
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
 pub enum ErrorCode {
@@ -11,6 +13,13 @@ pub enum ErrorCode {
     UnknownError,
     CookieError,
     InternalError,
+    UserBanned,
+    Forbidden,
+    TooManyRequests,
+    TotpRequired,
+    CsrfTokenInvalid,
+    DuplicateUpload,
+    PayloadTooLarge,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -18,6 +27,12 @@ pub struct BackendError {
     pub http_status_code: u16,
     pub error_code: ErrorCode,
     pub error_msg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u32>, // Seconds until the request can be retried (send as Retry-After header)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub existing_upload_id: Option<i32>, // Upload which already contains the same file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_url: Option<String>, // New password reset link, if the used link was used up by a failed password reset
 }
 
 impl BackendError {
@@ -26,6 +41,42 @@ impl BackendError {
             http_status_code,
             error_code,
             error_msg: error_msg.to_owned(),
+            retry_after: None,
+            existing_upload_id: None,
+            reset_url: None,
+        }
+    }
+
+    pub fn new_with_existing_upload(http_status_code: u16, error_code: ErrorCode, error_msg: &str, existing_upload_id: i32) -> BackendError {
+        BackendError {
+            http_status_code,
+            error_code,
+            error_msg: error_msg.to_owned(),
+            retry_after: None,
+            existing_upload_id: Some(existing_upload_id),
+            reset_url: None,
+        }
+    }
+
+    pub fn new_with_reset_url(http_status_code: u16, error_code: ErrorCode, error_msg: &str, reset_token: &str) -> BackendError {
+        BackendError {
+            http_status_code,
+            error_code,
+            error_msg: error_msg.to_owned(),
+            retry_after: None,
+            existing_upload_id: None,
+            reset_url: Some(get_password_reset_path(reset_token)),
+        }
+    }
+
+    pub fn new_with_retry_after(http_status_code: u16, error_code: ErrorCode, error_msg: &str, retry_after: u32) -> BackendError {
+        BackendError {
+            http_status_code,
+            error_code,
+            error_msg: error_msg.to_owned(),
+            retry_after: Some(retry_after),
+            existing_upload_id: None,
+            reset_url: None,
         }
     }
 }
@@ -36,15 +87,32 @@ pub struct AddUploadSuccess {
     upload_id: i32,
     tags_part_success: bool,
     tags_full_success: bool,
+    upload_status: db_result::UploadStatus, // The upload is processed in the background (see get_upload_status)
 }
 
 impl AddUploadSuccess {
-    pub fn new(upload_success: bool, upload_id: i32, tags_part_success: bool, tags_full_success: bool) -> AddUploadSuccess {
+    pub fn new(upload_success: bool, upload_id: i32, tags_part_success: bool, tags_full_success: bool, upload_status: db_result::UploadStatus) -> AddUploadSuccess {
         AddUploadSuccess {
             upload_success,
             upload_id,
             tags_part_success,
             tags_full_success,
+            upload_status,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ApiTokenCreated {
+    pub api_token: String, // The plaintext token is only send once, the database only stores a hash
+    pub token_data: db_result::ApiTokenData,
+}
+
+impl ApiTokenCreated {
+    pub fn new(api_token: String, token_data: db_result::ApiTokenData) -> ApiTokenCreated {
+        ApiTokenCreated {
+            api_token,
+            token_data,
         }
     }
 }
@@ -77,6 +145,23 @@ impl Filter {
     }
 }
 
+#[derive(Clone, Serialize)]
+pub struct PasswordResetCreated {
+    pub reset_token: String,
+    pub reset_url: String, // Path of the reset page (has to be send to the user by the admin)
+    pub reset_expire: DateTime<Local>,
+}
+
+impl PasswordResetCreated {
+    pub fn new(reset_token: String, reset_lifetime: u32) -> PasswordResetCreated {
+        PasswordResetCreated {
+            reset_url: get_password_reset_path(reset_token.as_str()),
+            reset_token,
+            reset_expire: Local::now() + Duration::hours(reset_lifetime as i64),
+        }
+    }
+}
+
 #[derive(Clone, Serialize)]
 pub struct SuccessReport {
     pub success: bool,
@@ -90,11 +175,40 @@ impl SuccessReport {
     }
 }
 
+#[derive(Clone, Serialize)]
+pub struct TotpEnrollment {
+    pub totp_secret: String,
+    pub totp_uri: String, // otpauth:// provisioning uri (shown as qr code by the frontend)
+}
+
+impl TotpEnrollment {
+    pub fn new(totp_secret: String, totp_uri: String) -> TotpEnrollment {
+        TotpEnrollment {
+            totp_secret,
+            totp_uri,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct TotpRecoveryCodes {
+    pub recovery_codes: Vec<String>, // Plaintext codes are only send once, the database only stores hashes
+}
+
+impl TotpRecoveryCodes {
+    pub fn new(recovery_codes: Vec<String>) -> TotpRecoveryCodes {
+        TotpRecoveryCodes {
+            recovery_codes,
+        }
+    }
+}
+
 #[derive(Clone, Serialize)]
 pub struct UserData {
     pub user_id: i32,
     pub username: String,
     pub user_is_mod: bool,
+    pub user_is_admin: bool,
 }
 
 impl UserData {
@@ -103,6 +217,31 @@ impl UserData {
             user_id: db_userdata.user_id,
             username: db_userdata.username.clone(),
             user_is_mod: db_userdata.user_is_mod,
+            user_is_admin: db_userdata.user_is_admin,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct UploadSessionStatus {
+    upload_session_id: String,
+    upload_size: u64,
+    upload_offset: u64, // The next chunk has to start at this byte
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload_result: Option<AddUploadSuccess>, // Only set after the last chunk (the upload is queued then)
+}
+
+impl UploadSessionStatus {
+    pub fn new(upload_session_data: &db_result::UploadSessionData) -> UploadSessionStatus {
+        UploadSessionStatus {
+            upload_session_id: upload_session_data.upload_session_id.clone(),
+            upload_size: upload_session_data.upload_size,
+            upload_offset: upload_session_data.upload_offset,
+            upload_result: None,
         }
     }
+
+    pub fn set_upload_result(&mut self, upload_result: AddUploadSuccess) {
+        self.upload_result = Some(upload_result);
+    }
 }
\ No newline at end of file
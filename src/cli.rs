@@ -1,8 +1,12 @@
 use clap::ArgMatches;
 use crate::config::ProjectConfig;
 use std::io;
+use std::io::Write;
 use crate::db_api::DbConnection;
-use crate::security::hash_password;
+use crate::db_api::db_result::AuditAction;
+use crate::security::{hash_password, check_and_normalize_username, check_password, get_username_skeleton};
+use crate::frontend::get_password_reset_path;
+use crate::file_api::{delete_replaced_previews, regenerate_upload_previews};
 
 pub struct CliActionError {
     pub error_msg: String,
@@ -35,6 +39,13 @@ pub struct CliActions {
     add_admin_user: bool,
     change_user_password: bool,
     change_pw_username: String,
+    create_password_reset: bool,
+    password_reset_username: String,
+    force_logout: bool,
+    force_logout_username: String,
+    regenerate_previews: bool,
+    update_username_skeletons: bool,
+    upgrade_db_tables: bool,
     drop_db: bool,
     drop_db_user: bool,
     drop_db_tables: bool,
@@ -48,6 +59,13 @@ impl CliActions {
         let mut add_admin_user = false;
         let mut change_user_password = false;
         let mut change_pw_username = "";
+        let mut create_password_reset = false;
+        let mut password_reset_username = "";
+        let mut force_logout = false;
+        let mut force_logout_username = "";
+        let mut regenerate_previews = false;
+        let mut update_username_skeletons = false;
+        let mut upgrade_db_tables = false;
         let mut drop_db = false;
         let mut drop_db_user = false;
         let mut drop_db_tables = false;
@@ -69,7 +87,14 @@ impl CliActions {
             let subcmd_maintenance = subcmd_maintenance.unwrap();
 
             change_user_password = subcmd_maintenance.is_present("change-user-password");
-            change_pw_username = subcmd_maintenance.value_of("username").unwrap_or_default();
+            change_pw_username = subcmd_maintenance.value_of("change-user-password").unwrap_or_default();
+            create_password_reset = subcmd_maintenance.is_present("create-password-reset");
+            password_reset_username = subcmd_maintenance.value_of("create-password-reset").unwrap_or_default();
+            force_logout = subcmd_maintenance.is_present("force-logout");
+            force_logout_username = subcmd_maintenance.value_of("force-logout").unwrap_or_default();
+            regenerate_previews = subcmd_maintenance.is_present("regenerate-previews");
+            update_username_skeletons = subcmd_maintenance.is_present("update-username-skeletons");
+            upgrade_db_tables = subcmd_maintenance.is_present("upgrade-db-tables");
         }
 
         let subcmd_uninstall = args.subcommand_matches("uninstall");
@@ -89,6 +114,13 @@ impl CliActions {
             add_admin_user,
             change_user_password,
             change_pw_username: change_pw_username.to_owned(),
+            create_password_reset,
+            password_reset_username: password_reset_username.to_owned(),
+            force_logout,
+            force_logout_username: force_logout_username.to_owned(),
+            regenerate_previews,
+            update_username_skeletons,
+            upgrade_db_tables,
             drop_db,
             drop_db_user,
             drop_db_tables,
@@ -109,6 +141,15 @@ impl CliActions {
     }
 }
 
+// Cli actions have no actor and no ip address, they are marked by the details instead
+async fn add_cli_audit_event(db_connection: &DbConnection, audit_action: AuditAction, target_id: Option<i32>) {
+    let add_result = db_connection.add_audit_event(audit_action, None, target_id, None, Some("CLI")).await;
+
+    if add_result.is_err() {
+        println!("Warning: Failed to write the action to the audit log");
+    }
+}
+
 pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -> Result<CliActionSuccess, CliActionError> {
     let cli_actions = CliActions::from_args(args);
     let require_db_admin = cli_actions.require_db_admin();
@@ -119,6 +160,7 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
     if require_db_admin {
         // Get pg username from stdin
         print!("Postgres admin username: ");
+        let _flush_result = io::stdout().flush();
         let stdin_read_success = io::stdin().read_line(&mut db_admin_user);
 
         if stdin_read_success.is_err() {
@@ -130,6 +172,7 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
 
         // Get pg password from stdin
         print!("Postgres admin password: ");
+        let _flush_result = io::stdout().flush();
         let stdin_pass = rpassword::read_password();
 
         if stdin_pass.is_err() {
@@ -146,6 +189,11 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
     let create_db_user = cli_actions.create_db_user;
     let create_db_tables = cli_actions.create_db_tables;
     let change_user_pw = cli_actions.change_user_password;
+    let create_password_reset = cli_actions.create_password_reset;
+    let force_logout = cli_actions.force_logout;
+    let regenerate_previews = cli_actions.regenerate_previews;
+    let update_username_skeletons = cli_actions.update_username_skeletons;
+    let upgrade_db_tables = cli_actions.upgrade_db_tables;
     let drop_db = cli_actions.drop_db;
     let drop_db_user = cli_actions.drop_db_user;
     let drop_db_tables = cli_actions.drop_db_tables;
@@ -170,9 +218,10 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
         db_root_connection = Some(connection.ok().unwrap());
     }
 
-    if create_db_tables || change_user_pw || drop_db_tables || add_admin_user {
+    if create_db_tables || change_user_pw || drop_db_tables || add_admin_user || create_password_reset || force_logout || regenerate_previews || update_username_skeletons || upgrade_db_tables {
+        // Redis is only needed to store password reset tokens and to destroy user sessions
         let connection =
-            DbConnection::new(prj_config, true, false).await;
+            DbConnection::new(prj_config, true, create_password_reset || force_logout).await;
 
         if connection.is_err() {
             let cli_action_error =
@@ -184,7 +233,7 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
         db_connection = Some(connection.ok().unwrap());
     }
 
-    if create_db || create_db_user || create_db_tables {
+    if create_db || create_db_user || create_db_tables || add_admin_user {
         let db_username = prj_config.postgres_config.user.get_value();
 
         if create_db_user {
@@ -225,6 +274,86 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
             }
         }
 
+        if add_admin_user {
+            // Get username of the new admin from stdin
+            let mut admin_username = String::new();
+            print!("Username of the new admin: ");
+            let _flush_result = io::stdout().flush();
+            let stdin_read_success = io::stdin().read_line(&mut admin_username);
+
+            if stdin_read_success.is_err() {
+                let err =
+                    CliActionError::new(String::from("Failed to read username from stdin"));
+
+                return Err(err);
+            }
+
+            // Get password of the new admin from stdin
+            print!("Password of the new admin: ");
+            let _flush_result = io::stdout().flush();
+            let stdin_pass = rpassword::read_password();
+
+            if stdin_pass.is_err() {
+                let err =
+                    CliActionError::new(String::from("Failed to read password from stdin"));
+
+                return Err(err);
+            }
+
+            let admin_username = check_and_normalize_username(admin_username.trim());
+            let admin_password = stdin_pass.ok().unwrap();
+
+            if admin_username.is_none() || !check_password(admin_password.as_str()) {
+                let err =
+                    CliActionError::new(String::from("Username or password doesn't match the requirements"));
+
+                return Err(err);
+            }
+
+            let admin_username = admin_username.unwrap();
+            let admin_username_skeleton = get_username_skeleton(admin_username.as_str());
+
+            let hashed_pw = hash_password(admin_password.as_str(), &prj_config.security_config);
+
+            if hashed_pw.is_none() {
+                let err =
+                    CliActionError::new(String::from("Failed to hash password"));
+
+                return Err(err);
+            }
+
+            let hashed_pw = hashed_pw.unwrap();
+            let create_result =
+                db_connection.as_ref().unwrap().add_user(admin_username.as_str(), admin_username_skeleton.as_str(), hashed_pw.as_str(), true, true).await;
+
+            if create_result.is_err() {
+                let err = CliActionError::new(String::from("Failed to create admin user"));
+
+                return Err(err);
+            }
+
+            let admin_user_id = create_result.ok().unwrap();
+            add_cli_audit_event(db_connection.as_ref().unwrap(), AuditAction::AdminCreate, Some(admin_user_id)).await;
+        }
+
+        let cli_actions_success = CliActionSuccess::new(true);
+
+        return Ok(cli_actions_success);
+    }
+
+    // Runs before the other maintenance actions, they need the current schema
+    if upgrade_db_tables {
+        let upgrade_result =
+            db_connection.as_ref().unwrap().upgrade_pg_tables().await;
+
+        if upgrade_result.is_err() {
+            let err = CliActionError::new(String::from("Failed to upgrade tables"));
+
+            return Err(err);
+        }
+
+        println!("The tables have the current schema version");
+
         let cli_actions_success = CliActionSuccess::new(true);
 
         return Ok(cli_actions_success);
@@ -233,6 +362,7 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
     if change_user_pw {
         // Get new password for the user from stdin
         print!("Enter new user password: ");
+        let _flush_result = io::stdout().flush();
         let stdin_pass = rpassword::read_password();
 
         if stdin_pass.is_err() {
@@ -243,10 +373,19 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
         }
 
         let target_user = cli_actions.change_pw_username;
-        let new_user_pw = stdin_pass.ok().unwrap();
-        let secret_key = prj_config.security_config.session_private_key.get_value();
+        let user_data =
+            db_connection.as_ref().unwrap().get_userdata_by_username(target_user.as_str()).await;
+
+        if user_data.is_err() {
+            let err =
+                CliActionError::new(format!("Failed to find user {}", target_user));
+
+            return Err(err);
+        }
 
-        let hashed_pw = hash_password(new_user_pw.as_str(), secret_key.as_str());
+        let user_id = user_data.ok().unwrap().user_id;
+        let new_user_pw = stdin_pass.ok().unwrap();
+        let hashed_pw = hash_password(new_user_pw.as_str(), &prj_config.security_config);
 
         if hashed_pw.is_none() {
             let err =
@@ -266,6 +405,159 @@ pub async fn do_cli_actions(args: &ArgMatches<'_>, prj_config: &ProjectConfig) -
             return Err(err);
         }
 
+        add_cli_audit_event(db_connection.as_ref().unwrap(), AuditAction::PasswordChange, Some(user_id)).await;
+
+        let cli_actions_success = CliActionSuccess::new(true);
+
+        return Ok(cli_actions_success);
+    }
+
+    if create_password_reset {
+        let target_user = cli_actions.password_reset_username;
+        let user_data =
+            db_connection.as_ref().unwrap().get_userdata_by_username(target_user.as_str()).await;
+
+        if user_data.is_err() {
+            let err =
+                CliActionError::new(format!("Failed to find user {}", target_user));
+
+            return Err(err);
+        }
+
+        let user_data = user_data.ok().unwrap();
+
+        // Anonymized accounts have no password and mustn't be reactivated
+        if user_data.password_hash.is_empty() {
+            let err =
+                CliActionError::new(format!("The account of {} was deleted", target_user));
+
+            return Err(err);
+        }
+
+        let user_id = user_data.user_id;
+        let reset_lifetime = prj_config.security_config.password_reset_lifetime.get_value();
+        let reset_token =
+            db_connection.as_ref().unwrap().create_password_reset(user_id, reset_lifetime).await;
+
+        if reset_token.is_err() {
+            let err =
+                CliActionError::new(String::from("Failed to create the password reset token"));
+
+            return Err(err);
+        }
+
+        add_cli_audit_event(db_connection.as_ref().unwrap(), AuditAction::PasswordResetCreate, Some(user_id)).await;
+
+        let reset_path = get_password_reset_path(reset_token.ok().unwrap().as_str());
+        println!("Password reset link for {} (valid for {} hours): <server address>{}", target_user, reset_lifetime, reset_path);
+
+        let cli_actions_success = CliActionSuccess::new(true);
+
+        return Ok(cli_actions_success);
+    }
+
+    if force_logout {
+        let target_user = cli_actions.force_logout_username;
+        let user_data =
+            db_connection.as_ref().unwrap().get_userdata_by_username(target_user.as_str()).await;
+
+        if user_data.is_err() {
+            let err =
+                CliActionError::new(format!("Failed to find user {}", target_user));
+
+            return Err(err);
+        }
+
+        let user_id = user_data.ok().unwrap().user_id;
+        let logout_result =
+            db_connection.as_ref().unwrap().destroy_user_sessions(user_id, None).await;
+
+        if logout_result.is_err() {
+            let err =
+                CliActionError::new(String::from("Failed to destroy the user sessions"));
+
+            return Err(err);
+        }
+
+        add_cli_audit_event(db_connection.as_ref().unwrap(), AuditAction::SessionsRevoke, Some(user_id)).await;
+
+        let cli_actions_success = CliActionSuccess::new(true);
+
+        return Ok(cli_actions_success);
+    }
+
+    if regenerate_previews {
+        let upload_files = db_connection.as_ref().unwrap().get_ready_upload_files().await;
+
+        if upload_files.is_err() {
+            let err =
+                CliActionError::new(String::from("Failed to load the uploads"));
+
+            return Err(err);
+        }
+
+        let upload_files = upload_files.ok().unwrap();
+        let mut updated_uploads = 0;
+
+        for upload_file in upload_files.iter() {
+            let upload_prv_variants = regenerate_upload_previews(prj_config, upload_file).await;
+
+            if upload_prv_variants.is_none() {
+                println!("Warning: Failed to generate the previews of upload {}", upload_file.upload_id);
+
+                continue;
+            }
+
+            let upload_prv_variants = upload_prv_variants.unwrap();
+            let update_result =
+                db_connection.as_ref().unwrap().set_upload_prv_variants(upload_file.upload_id, upload_prv_variants.as_str()).await;
+
+            // The old previews are only deleted after the new ones are stored, so that they are never missing
+            if update_result.is_ok() {
+                delete_replaced_previews(prj_config, upload_file, upload_prv_variants.as_str()).await;
+                updated_uploads += 1;
+            }
+            else {
+                println!("Warning: Failed to store the preview variants of upload {}", upload_file.upload_id);
+            }
+        }
+
+        println!("Generated the previews of {} of {} uploads", updated_uploads, upload_files.len());
+
+        let cli_actions_success = CliActionSuccess::new(true);
+
+        return Ok(cli_actions_success);
+    }
+
+    if update_username_skeletons {
+        let user_list = db_connection.as_ref().unwrap().get_users_without_skeleton().await;
+
+        if user_list.is_err() {
+            let err =
+                CliActionError::new(String::from("Failed to load the users without username skeleton"));
+
+            return Err(err);
+        }
+
+        let user_list = user_list.ok().unwrap();
+        let mut updated_users = 0;
+
+        for (user_id, username) in user_list.iter() {
+            let username_skeleton = get_username_skeleton(username.as_str());
+            let update_result =
+                db_connection.as_ref().unwrap().set_user_name_skeleton(*user_id, username_skeleton.as_str()).await;
+
+            if update_result.is_ok() {
+                updated_users += 1;
+            }
+            else {
+                // Existing lookalike usernames can't be resolved automatically
+                println!("Warning: Failed to store the username skeleton of {} ({})", username, update_result.err().unwrap().error_msg);
+            }
+        }
+
+        println!("Stored the username skeletons of {} of {} users", updated_users, user_list.len());
+
         let cli_actions_success = CliActionSuccess::new(true);
 
         return Ok(cli_actions_success);
@@ -2,14 +2,21 @@ extern crate toml;
 
 use toml::{Value, de::Error};
 use std::convert::TryFrom;
+use crate::config::AccountDeletionMode::{Anonymize, Cascade};
 use crate::config::ConnectionMethod::{Tcp, UnixSocket};
+use crate::config::PasswordHashVariant::{Argon2d, Argon2i, Argon2id};
+use crate::config::PreviewFormat::{Jpg, Webp};
+use crate::config::SimilarUploadMode::{Off, Reject, Warn};
+use crate::config::UploadMetadataMode::{Keep, Strip};
 use std::path::Path;
 use std::fs::File;
 use std::io::Read;
+use std::net::IpAddr;
 use log::{error};
 
-macro_rules! read_toml_entry_connection_method {
-    ($self:ident, $toml_obj:ident, $main_entry:expr, $config_name:ident) => {
+// Reads a string entry and converts it with TryFrom<&str> into the given enum type
+macro_rules! read_toml_entry_enum {
+    ($self:ident, $toml_obj:ident, $main_entry:expr, $config_name:ident, $config_type:ty) => {
         let config_section_content = $toml_obj.as_table();
 
         if config_section_content.is_some() {
@@ -25,11 +32,11 @@ macro_rules! read_toml_entry_connection_method {
                         let config_value = config_value.unwrap().as_str();
 
                         if config_value.is_some() {
-                            let connection_method_str = config_value.unwrap();
-                            let connection_method_obj = ConnectionMethod::try_from(connection_method_str);
+                            let enum_str = config_value.unwrap();
+                            let enum_obj = <$config_type>::try_from(enum_str);
 
-                            if connection_method_obj.is_ok() {
-                                $self.$config_name.set_value(connection_method_obj.unwrap());
+                            if enum_obj.is_ok() {
+                                $self.$config_name.set_value(enum_obj.unwrap());
                             }
                         }
                     }
@@ -91,6 +98,29 @@ macro_rules! read_toml_entry_string {
     };
 }
 
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum AccountDeletionMode {
+    Anonymize, // Keep the content of the user under an anonymized username
+    Cascade, // Delete the content of the user together with the account
+}
+
+impl TryFrom<&str> for AccountDeletionMode {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value_lowercase = value.to_lowercase();
+
+        if value_lowercase == "anonymize" {
+            return Ok(Anonymize);
+        }
+        else if value_lowercase == "cascade" {
+            return Ok(Cascade);
+        }
+
+        return Err("Failed to parse account deletion mode");
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum ConnectionMethod {
     Tcp,
@@ -117,6 +147,260 @@ impl TryFrom<&str> for ConnectionMethod {
     }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum PasswordHashVariant {
+    Argon2d,
+    Argon2i,
+    Argon2id,
+}
+
+impl TryFrom<&str> for PasswordHashVariant {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value_lowercase = value.to_lowercase();
+
+        if value_lowercase == "argon2d" {
+            return Ok(Argon2d);
+        }
+        else if value_lowercase == "argon2i" {
+            return Ok(Argon2i);
+        }
+        else if value_lowercase == "argon2id" {
+            return Ok(Argon2id);
+        }
+
+        return Err("Failed to parse password hash variant");
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum PreviewFormat {
+    Jpg,
+    Webp,
+}
+
+impl PreviewFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Jpg => "jpg",
+            Webp => "webp",
+        }
+    }
+}
+
+impl TryFrom<&str> for PreviewFormat {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value_lowercase = value.to_lowercase();
+
+        if value_lowercase == "jpg" {
+            return Ok(Jpg);
+        }
+        else if value_lowercase == "webp" {
+            return Ok(Webp);
+        }
+
+        return Err("Failed to parse preview format");
+    }
+}
+
+#[derive(Clone)]
+pub struct PreviewVariant {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub is_cropped: bool, // Cropped previews fill exactly width x height, the others keep the aspect ratio and fit into it
+    pub format: PreviewFormat,
+}
+
+impl PreviewVariant {
+    // Uploads from before the preview variants existed have a single cropped 100x100 jpg preview (<upload name>.jpg)
+    pub fn legacy() -> PreviewVariant {
+        PreviewVariant {
+            name: String::new(),
+            width: 100,
+            height: 100,
+            is_cropped: true,
+            format: Jpg,
+        }
+    }
+
+    // Returns the variant in the form which is parsed by try_from
+    pub fn to_config_string(&self) -> String {
+        let scale_mode = if self.is_cropped { "crop" } else { "fit" };
+
+        format!("{}:{}x{}:{}:{}", self.name, self.width, self.height, scale_mode, self.format.as_str())
+    }
+}
+
+impl TryFrom<&str> for PreviewVariant {
+    type Error = &'static str;
+
+    // Parses a variant in the form name:<width>x<height>:<crop or fit>:<jpg or webp>
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let variant_parts : Vec<&str> = value.trim().split(':').collect();
+
+        if variant_parts.len() == 4 {
+            let name = variant_parts[0];
+            let resolution : Vec<&str> = variant_parts[1].split('x').collect();
+            let name_is_ok = !name.is_empty() && name.len() <= 16 && name.chars().all(|char| char.is_ascii_alphanumeric());
+
+            if name_is_ok && resolution.len() == 2 {
+                let width = resolution[0].parse::<u32>();
+                let height = resolution[1].parse::<u32>();
+                let format = PreviewFormat::try_from(variant_parts[3]);
+                let is_cropped = match variant_parts[2] {
+                    "crop" => Some(true),
+                    "fit" => Some(false),
+                    _ => None,
+                };
+
+                if width.is_ok() && height.is_ok() && format.is_ok() && is_cropped.is_some() {
+                    let width = width.unwrap();
+                    let height = height.unwrap();
+
+                    if width >= 1 && width <= 4096 && height >= 1 && height <= 4096 {
+                        let preview_variant = PreviewVariant {
+                            name: name.to_owned(),
+                            width,
+                            height,
+                            is_cropped: is_cropped.unwrap(),
+                            format: format.unwrap(),
+                        };
+
+                        return Ok(preview_variant);
+                    }
+                }
+            }
+        }
+
+        return Err("Failed to parse preview variant");
+    }
+}
+
+#[derive(Clone)]
+pub struct PreviewVariantList {
+    pub variants: Vec<PreviewVariant>, // The first variant is the default preview of an upload
+}
+
+impl PreviewVariantList {
+    // The list is stored with every upload, so that changing the config doesn't break the previews of existing uploads
+    pub fn to_config_string(&self) -> String {
+        let variant_strings : Vec<String> = self.variants.iter()
+            .map(|preview_variant| preview_variant.to_config_string())
+            .collect();
+
+        variant_strings.join(",")
+    }
+}
+
+impl TryFrom<&str> for PreviewVariantList {
+    type Error = &'static str;
+
+    // Parses a comma separated list of preview variants, every combination of name and format can only exist once
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut variants : Vec<PreviewVariant> = Vec::new();
+
+        for variant_str in value.split(',') {
+            let preview_variant = PreviewVariant::try_from(variant_str);
+
+            if preview_variant.is_err() {
+                return Err(preview_variant.err().unwrap());
+            }
+
+            let preview_variant = preview_variant.unwrap();
+            let is_duplicate = variants.iter()
+                .any(|variant| variant.name == preview_variant.name && variant.format == preview_variant.format);
+
+            if is_duplicate {
+                return Err("Duplicate preview variant");
+            }
+
+            variants.push(preview_variant);
+        }
+
+        return Ok(PreviewVariantList { variants });
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum SimilarUploadMode {
+    Off, // Similar uploads are only shown by the similar uploads endpoint
+    Warn, // The upload is saved and the similar uploads are returned with the upload status
+    Reject, // The upload is rejected if there is a similar upload
+}
+
+impl TryFrom<&str> for SimilarUploadMode {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value_lowercase = value.to_lowercase();
+
+        if value_lowercase == "off" {
+            return Ok(Off);
+        }
+        else if value_lowercase == "warn" {
+            return Ok(Warn);
+        }
+        else if value_lowercase == "reject" {
+            return Ok(Reject);
+        }
+
+        return Err("Failed to parse similar upload mode");
+    }
+}
+
+#[derive(Clone)]
+pub struct TrustedProxyList {
+    pub addresses: Vec<IpAddr>, // Reverse proxies which are allowed to set the X-Forwarded-For header
+}
+
+impl TryFrom<&str> for TrustedProxyList {
+    type Error = &'static str;
+
+    // Parses a comma separated list of ip addresses (an empty string means that there is no reverse proxy)
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut addresses : Vec<IpAddr> = Vec::new();
+
+        for address_str in value.split(',').filter(|address_str| !address_str.trim().is_empty()) {
+            let address = address_str.trim().parse::<IpAddr>();
+
+            if address.is_err() {
+                return Err("Failed to parse trusted proxy address");
+            }
+
+            addresses.push(address.unwrap());
+        }
+
+        return Ok(TrustedProxyList { addresses });
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum UploadMetadataMode {
+    Strip, // Metadata (exif, container tags) is removed from the published file, the exif orientation is applied before
+    Keep, // The uploaded file is published unchanged
+}
+
+impl TryFrom<&str> for UploadMetadataMode {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value_lowercase = value.to_lowercase();
+
+        if value_lowercase == "strip" {
+            return Ok(Strip);
+        }
+        else if value_lowercase == "keep" {
+            return Ok(Keep);
+        }
+
+        return Err("Failed to parse upload metadata mode");
+    }
+}
+
 pub struct ConfigField<T> {
     value: T,
     is_ro: bool,   //Once a field is set, it can't be changed anymore
@@ -146,17 +430,50 @@ impl<T: Clone> ConfigField<T> {
 
 pub struct ApplicationConfig {
     pub max_upload_size: ConfigField<u16>,
+    pub account_deletion_mode: ConfigField<AccountDeletionMode>,
+    pub similar_upload_mode: ConfigField<SimilarUploadMode>,
+    pub similar_upload_distance: ConfigField<u32>,
+    pub upload_metadata_mode: ConfigField<UploadMetadataMode>,
+    pub preview_variants: ConfigField<PreviewVariantList>,
+    pub preview_clip_duration: ConfigField<u32>,
+    pub video_crf: ConfigField<u32>,
+    pub video_max_resolution: ConfigField<u32>,
+    pub video_transcode_timeout: ConfigField<u32>,
+    pub upload_workers: ConfigField<u16>,
+    pub failed_upload_lifetime: ConfigField<u32>,
 }
 
 impl ApplicationConfig {
     pub fn new() -> ApplicationConfig {
         ApplicationConfig {
             max_upload_size: ConfigField::new_empty(0),
+            account_deletion_mode: ConfigField::new_empty(Anonymize),
+            similar_upload_mode: ConfigField::new_empty(Warn),
+            similar_upload_distance: ConfigField::new_empty(0),
+            upload_metadata_mode: ConfigField::new_empty(Strip),
+            preview_variants: ConfigField::new_empty(PreviewVariantList { variants: Vec::new() }),
+            preview_clip_duration: ConfigField::new_empty(0),
+            video_crf: ConfigField::new_empty(23),
+            video_max_resolution: ConfigField::new_empty(0),
+            video_transcode_timeout: ConfigField::new_empty(3600),
+            upload_workers: ConfigField::new_empty(1),
+            failed_upload_lifetime: ConfigField::new_empty(24),
         }
     }
 
     pub fn parse_toml(&mut self, toml_obj: &Value) {
         read_toml_entry_number!(self, toml_obj, "application", max_upload_size, u16);
+        read_toml_entry_enum!(self, toml_obj, "application", account_deletion_mode, AccountDeletionMode);
+        read_toml_entry_enum!(self, toml_obj, "application", similar_upload_mode, SimilarUploadMode);
+        read_toml_entry_number!(self, toml_obj, "application", similar_upload_distance, u32);
+        read_toml_entry_enum!(self, toml_obj, "application", upload_metadata_mode, UploadMetadataMode);
+        read_toml_entry_enum!(self, toml_obj, "application", preview_variants, PreviewVariantList);
+        read_toml_entry_number!(self, toml_obj, "application", preview_clip_duration, u32);
+        read_toml_entry_number!(self, toml_obj, "application", video_crf, u32);
+        read_toml_entry_number!(self, toml_obj, "application", video_max_resolution, u32);
+        read_toml_entry_number!(self, toml_obj, "application", video_transcode_timeout, u32);
+        read_toml_entry_number!(self, toml_obj, "application", upload_workers, u16);
+        read_toml_entry_number!(self, toml_obj, "application", failed_upload_lifetime, u32);
     }
 }
 
@@ -197,6 +514,7 @@ impl FilesystemConfig {
 pub struct NetworkConfig {
     pub ip_addr: ConfigField<String>,
     pub port: ConfigField<u16>,
+    pub trusted_proxies: ConfigField<TrustedProxyList>,
 }
 
 impl NetworkConfig {
@@ -204,12 +522,14 @@ impl NetworkConfig {
         NetworkConfig {
             ip_addr: ConfigField::new_empty(String::new()),
             port: ConfigField::new_empty(0),
+            trusted_proxies: ConfigField::new_empty(TrustedProxyList { addresses: Vec::new() }),
         }
     }
 
     pub fn parse_toml(&mut self, toml_obj: &Value) {
         read_toml_entry_string!(self, toml_obj, "network", ip_addr);
         read_toml_entry_number!(self, toml_obj, "network", port, u16);
+        read_toml_entry_enum!(self, toml_obj, "network", trusted_proxies, TrustedProxyList);
     }
 }
 
@@ -242,7 +562,7 @@ impl PostgresConfig {
         read_toml_entry_string!(self, toml_obj, "postgres", host);
         read_toml_entry_number!(self, toml_obj, "postgres", port, u16);
         read_toml_entry_string!(self, toml_obj, "postgres", unix_socket_dir);
-        read_toml_entry_connection_method!(self, toml_obj, "postgres", connection_method);
+        read_toml_entry_enum!(self, toml_obj, "postgres", connection_method, ConnectionMethod);
         read_toml_entry_string!(self, toml_obj, "postgres", user);
         read_toml_entry_string!(self, toml_obj, "postgres", password);
         read_toml_entry_string!(self, toml_obj, "postgres", db_name);
@@ -271,29 +591,72 @@ impl RedisConfig {
         read_toml_entry_string!(self, toml_obj, "redis", host);
         read_toml_entry_number!(self, toml_obj, "redis", port, u16);
         read_toml_entry_string!(self, toml_obj, "redis", unix_socket_file);
-        read_toml_entry_connection_method!(self, toml_obj, "redis", connection_method);
+        read_toml_entry_enum!(self, toml_obj, "redis", connection_method, ConnectionMethod);
     }
 }
 
 pub struct SecurityConfig {
     pub password_hash_key: ConfigField<String>,
+    pub password_hash_variant: ConfigField<PasswordHashVariant>,
+    pub password_hash_memory: ConfigField<u32>,
+    pub password_hash_iterations: ConfigField<u32>,
+    pub password_hash_lanes: ConfigField<u32>,
     pub session_private_key: ConfigField<String>,
     pub master_invite_key: ConfigField<String>,
+    pub invite_quota: ConfigField<u16>,
+    pub invite_lifetime: ConfigField<u32>,
+    pub password_reset_lifetime: ConfigField<u32>,
+    pub api_token_quota: ConfigField<u16>,
+    pub login_max_attempts: ConfigField<u32>,
+    pub register_max_attempts: ConfigField<u32>,
+    pub username_check_max_requests: ConfigField<u32>,
+    pub attempt_window: ConfigField<u32>,
+    pub lockout_base_duration: ConfigField<u32>,
+    pub lockout_max_duration: ConfigField<u32>,
 }
 
 impl SecurityConfig {
     pub fn new() -> SecurityConfig {
         SecurityConfig {
             password_hash_key: ConfigField::new_empty(String::new()),
+            // Defaults are the parameters which were used before they became configurable
+            password_hash_variant: ConfigField::new_empty(Argon2id),
+            password_hash_memory: ConfigField::new_empty(4096),
+            password_hash_iterations: ConfigField::new_empty(192),
+            password_hash_lanes: ConfigField::new_empty(2),
             session_private_key: ConfigField::new_empty(String::new()),
             master_invite_key: ConfigField::new_empty(String::new()),
+            invite_quota: ConfigField::new_empty(0),
+            invite_lifetime: ConfigField::new_empty(0),
+            password_reset_lifetime: ConfigField::new_empty(0),
+            api_token_quota: ConfigField::new_empty(0),
+            login_max_attempts: ConfigField::new_empty(0),
+            register_max_attempts: ConfigField::new_empty(0),
+            username_check_max_requests: ConfigField::new_empty(0),
+            attempt_window: ConfigField::new_empty(0),
+            lockout_base_duration: ConfigField::new_empty(0),
+            lockout_max_duration: ConfigField::new_empty(0),
         }
     }
 
     pub fn parse_toml(&mut self, toml_obj: &Value) {
         read_toml_entry_string!(self, toml_obj, "security", password_hash_key);
+        read_toml_entry_enum!(self, toml_obj, "security", password_hash_variant, PasswordHashVariant);
+        read_toml_entry_number!(self, toml_obj, "security", password_hash_memory, u32);
+        read_toml_entry_number!(self, toml_obj, "security", password_hash_iterations, u32);
+        read_toml_entry_number!(self, toml_obj, "security", password_hash_lanes, u32);
         read_toml_entry_string!(self, toml_obj, "security", session_private_key);
         read_toml_entry_string!(self, toml_obj, "security", master_invite_key);
+        read_toml_entry_number!(self, toml_obj, "security", invite_quota, u16);
+        read_toml_entry_number!(self, toml_obj, "security", invite_lifetime, u32);
+        read_toml_entry_number!(self, toml_obj, "security", password_reset_lifetime, u32);
+        read_toml_entry_number!(self, toml_obj, "security", api_token_quota, u16);
+        read_toml_entry_number!(self, toml_obj, "security", login_max_attempts, u32);
+        read_toml_entry_number!(self, toml_obj, "security", register_max_attempts, u32);
+        read_toml_entry_number!(self, toml_obj, "security", username_check_max_requests, u32);
+        read_toml_entry_number!(self, toml_obj, "security", attempt_window, u32);
+        read_toml_entry_number!(self, toml_obj, "security", lockout_base_duration, u32);
+        read_toml_entry_number!(self, toml_obj, "security", lockout_max_duration, u32);
     }
 }
 
@@ -367,4 +730,27 @@ impl ProjectConfig {
         self.redis_config.parse_toml(toml_obj);
         self.security_config.parse_toml(toml_obj);
     }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_variant_list_parses_config_string() {
+        let preview_variant_list = PreviewVariantList::try_from("thumb:200x200:crop:jpg, large:1280x720:fit:webp").ok().unwrap();
+
+        assert_eq!(preview_variant_list.variants.len(), 2);
+        assert_eq!(preview_variant_list.variants[0].name, "thumb");
+        assert!(preview_variant_list.variants[0].is_cropped);
+        assert!(preview_variant_list.variants[1].format == Webp);
+        assert_eq!(preview_variant_list.to_config_string(), "thumb:200x200:crop:jpg,large:1280x720:fit:webp");
+
+        assert!(PreviewVariantList::try_from("thumb:200x200:crop:jpg,thumb:100x100:fit:jpg").is_err());
+        assert!(PreviewVariant::try_from("thumb:200x200:crop").is_err());
+        assert!(PreviewVariant::try_from("thumb:0x200:crop:jpg").is_err());
+        assert!(PreviewVariant::try_from("thumb:200x200:stretch:jpg").is_err());
+        assert!(PreviewVariant::try_from("thumb:200x200:crop:png").is_err());
+        assert!(PreviewVariant::try_from("th_umb:200x200:crop:jpg").is_err());
+        assert!(PreviewVariant::try_from(":200x200:crop:jpg").is_err());
+    }
 }
\ No newline at end of file
@@ -1,8 +1,8 @@
 use crate::db_api::postgres::PostgresConnection;
 use crate::db_api::redis::RedisConnection;
 use crate::config::ProjectConfig;
-use crate::db_api::db_result::{UploadPrvList, DbApiError, SessionData, SessionError, UploadData, UserData, UploadType};
-use crate::db_api::db_result::DbApiErrorType::{ConnectionError, PartFail, QueryError, NoResult};
+use crate::db_api::db_result::{ApiTokenData, ApiTokenList, ApiTokenScope, AuditAction, AuditEventList, UploadPrvList, DbApiError, SessionData, SessionError, UploadData, UploadFileData, UploadSessionData, UploadStatusData, UserData, UploadType, BanData, UserRole, InviteData, InviteList, SessionInfoList, PendingLoginData, TotpData, UserDataExport};
+use crate::db_api::db_result::DbApiErrorType::{ConnectionError, PartFail, QueryError};
 use crate::db_api::db_result::SessionErrorType::DbError;
 use log::{trace};
 use actix_session::Session;
@@ -33,12 +33,31 @@ pub struct DbConnection {
 }
 
 impl DbConnection {
+    pub async fn add_api_token(&self, user_id: i32, token_name: &str, token_hash: &str, token_scopes: &[ApiTokenScope]) -> Result<ApiTokenData, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().add_api_token(user_id, token_name, token_hash, token_scopes).await
+    }
+
+    pub async fn add_audit_event(&self, audit_action: AuditAction, actor_id: Option<i32>, target_id: Option<i32>,
+                                 client_ip: Option<&str>, event_details: Option<&str>) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().add_audit_event(audit_action, actor_id, target_id, client_ip, event_details).await
+    }
+
     pub async fn add_comment(&self, comment_poster: i32, comment_upload: i32, comment_text: &str) -> Result<(), DbApiError> {
         check_postgres_connection!(self);
 
         self.postgres_connection.as_ref().unwrap().add_comment(comment_poster, comment_upload, comment_text).await
     }
 
+    pub async fn add_invite(&self, invite_code: &str, invite_creator: i32, invite_lifetime: i32, invite_quota: Option<i64>) -> Result<InviteData, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().add_invite(invite_code, invite_creator, invite_lifetime, invite_quota).await
+    }
+
     pub async fn add_tags(&self, tags: Vec<&str>, tag_poster: i32, upload_id: i32) -> Result<(), DbApiError> {
         check_postgres_connection!(self);
 
@@ -69,17 +88,43 @@ impl DbConnection {
     }
 
     // Returns the upload_id of the new inserted upload or error
-    pub async fn add_upload(&self, upload_filename: &str, upload_is_nsfw: bool, upload_type: UploadType, uploader: i32) -> Result<i32, DbApiError> {
+    pub async fn add_upload(&self, upload_filename: &str, upload_original_filename: &str, upload_is_nsfw: bool, uploader: i32, upload_hash: &str) -> Result<i32, DbApiError> {
         check_postgres_connection!(self);
 
-        self.postgres_connection.as_ref().unwrap().add_upload(upload_filename, upload_is_nsfw, upload_type, uploader).await
+        self.postgres_connection.as_ref().unwrap().add_upload(upload_filename, upload_original_filename, upload_is_nsfw, uploader, upload_hash).await
     }
 
     // Returns the id of the new created user
-    pub async fn add_user(&self, username: &str, pw_hash: &str, user_is_mod: bool) -> Result<i32, DbApiError> {
+    pub async fn add_user(&self, username: &str, username_skeleton: &str, pw_hash: &str, user_is_mod: bool, user_is_admin: bool) -> Result<i32, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().add_user(username, username_skeleton, pw_hash, user_is_mod, user_is_admin).await
+    }
+
+    // Returns the id of the new created ban
+    pub async fn add_user_ban(&self, user_id: i32, ban_reason: &str, ban_duration: i32) -> Result<i32, DbApiError> {
         check_postgres_connection!(self);
 
-        self.postgres_connection.as_ref().unwrap().add_user(username, pw_hash, user_is_mod).await
+        self.postgres_connection.as_ref().unwrap().add_user_ban(user_id, ban_reason, ban_duration).await
+    }
+
+    // Returns the id of the new created user or an error of type NoResult if the invite code is invalid
+    pub async fn add_user_with_invite(&self, username: &str, username_skeleton: &str, pw_hash: &str, invite_code: &str) -> Result<i32, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().add_user_with_invite(username, username_skeleton, pw_hash, invite_code).await
+    }
+
+    pub async fn anonymize_user(&self, user_id: i32, anonymized_username: &str) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().anonymize_user(user_id, anonymized_username).await
+    }
+
+    pub async fn change_user_pw_by_id(&self, user_id: i32, pw_hash: &str) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().change_user_pw_by_id(user_id, pw_hash).await
     }
 
     pub async fn change_user_pw_by_username(&self, username: &str, pw_hash: &str) -> Result<(), DbApiError> {
@@ -88,23 +133,23 @@ impl DbConnection {
         self.postgres_connection.as_ref().unwrap().change_user_pw_by_username(username, pw_hash).await
     }
 
-    pub async fn check_user_exists(&self, username: &str) -> Result<bool, DbApiError> {
+    pub async fn check_user_exists(&self, username: &str, username_skeleton: &str) -> Result<bool, DbApiError> {
         check_postgres_connection!(self);
 
-        let user_data = self.postgres_connection.as_ref().unwrap().get_userdata_by_username(username).await;
+        self.postgres_connection.as_ref().unwrap().check_user_exists(username, username_skeleton).await
+    }
 
-        if user_data.is_ok() {
-            return Ok(true);
-        }
-        else {
-            let user_data_error = user_data.err().unwrap();
+    // Returns the user id of the reset token (the token gets invalid)
+    pub async fn consume_password_reset(&self, reset_token: &str) -> Result<i32, SessionError> {
+        check_redis_connection!(self);
 
-            if user_data_error.error_type == NoResult {
-                return Ok(false);
-            }
+        self.redis_connection.as_ref().unwrap().consume_password_reset(reset_token).await
+    }
 
-            Err(user_data_error)
-        }
+    pub async fn claim_queued_upload(&self) -> Result<(i32, String), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().claim_queued_upload().await
     }
 
     pub async fn create_pg_database(&self, db_name: &str, user: &str) -> Result<(), DbApiError> {
@@ -132,10 +177,48 @@ impl DbConnection {
         self.postgres_connection.as_ref().unwrap().create_pg_user(username, password).await
     }
 
-    pub async fn create_session(&self, user_id: i32, is_lts: bool) -> Result<SessionData, SessionError> {
+    // Returns the reset token, reset_lifetime is given in hours
+    pub async fn create_password_reset(&self, user_id: i32, reset_lifetime: u32) -> Result<String, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().create_password_reset(user_id, reset_lifetime).await
+    }
+
+    pub async fn create_pending_login(&self, user_id: i32, is_lts: bool) -> Result<String, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().create_pending_login(user_id, is_lts).await
+    }
+
+    pub async fn create_session(&self, user_id: i32, is_lts: bool, client_ip: &str, user_agent: &str) -> Result<SessionData, SessionError> {
         check_redis_connection!(self);
 
-        self.redis_connection.as_ref().unwrap().create_session(user_id, is_lts).await
+        self.redis_connection.as_ref().unwrap().create_session(user_id, is_lts, client_ip, user_agent).await
+    }
+
+    pub async fn create_upload_session(&self, upload_session_data: &UploadSessionData) -> Result<String, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().create_upload_session(upload_session_data).await
+    }
+
+    pub async fn delete_failed_uploads(&self, failed_upload_lifetime: i32) -> Result<usize, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().delete_failed_uploads(failed_upload_lifetime).await
+    }
+
+    // Returns the filenames of the deleted uploads
+    pub async fn delete_user(&self, user_id: i32) -> Result<Vec<UploadFileData>, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().delete_user(user_id).await
+    }
+
+    pub async fn destroy_pending_login(&self, pending_login_id: &str) -> Result<(), SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().destroy_pending_login(pending_login_id).await
     }
 
     pub async fn destroy_session(&self, session_id: &str) -> Result<(), SessionError> {
@@ -144,6 +227,24 @@ impl DbConnection {
         self.redis_connection.as_ref().unwrap().destroy_session(session_id).await
     }
 
+    pub async fn destroy_upload_session(&self, upload_session_id: &str) -> Result<(), SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().destroy_upload_session(upload_session_id).await
+    }
+
+    pub async fn destroy_user_sessions(&self, user_id: i32, keep_session_id: Option<&str>) -> Result<(), SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().destroy_user_sessions(user_id, keep_session_id).await
+    }
+
+    pub async fn disable_user_totp(&self, user_id: i32) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().disable_user_totp(user_id).await
+    }
+
     pub async fn drop_pg_db(&self) -> Result<(), DbApiError> {
         check_postgres_connection!(self);
 
@@ -162,6 +263,69 @@ impl DbConnection {
         self.postgres_connection.as_ref().unwrap().drop_pg_db_user(username).await
     }
 
+    pub async fn enable_user_totp(&self, user_id: i32, recovery_code_hashes: &Vec<String>) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().enable_user_totp(user_id, recovery_code_hashes).await
+    }
+
+    // Returns an error of type NoResult if the user isn't banned
+    pub async fn get_active_user_ban(&self, user_id: i32) -> Result<BanData, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_active_user_ban(user_id).await
+    }
+
+    pub async fn get_api_tokens(&self, user_id: i32) -> Result<ApiTokenList, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_api_tokens(user_id).await
+    }
+
+    // The filters are ignored if they are None
+    pub async fn get_audit_events(&self, start_id: i32, max_count: i16, audit_action: Option<AuditAction>,
+                                  actor_id: Option<i32>, target_id: Option<i32>) -> Result<AuditEventList, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_audit_events(start_id, max_count, audit_action, actor_id, target_id).await
+    }
+
+    pub async fn get_password_reset(&self, reset_token: &str) -> Result<i32, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().get_password_reset(reset_token).await
+    }
+
+    pub async fn get_pending_login(&self, pending_login_id: &str) -> Result<PendingLoginData, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().get_pending_login(pending_login_id).await
+    }
+
+    pub async fn get_rate_limit_lock(&self, counter_name: &str) -> Result<u32, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().get_rate_limit_lock(counter_name).await
+    }
+
+    pub async fn get_queued_upload_filenames(&self) -> Result<Vec<String>, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_queued_upload_filenames().await
+    }
+
+    pub async fn get_ready_upload_files(&self) -> Result<Vec<UploadFileData>, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_ready_upload_files().await
+    }
+
+    pub async fn get_schema_version(&self) -> Result<u32, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_schema_version().await
+    }
+
     pub async fn get_session_data(&self, session: &Session, session_id: &str, force_session_renew: bool) -> Result<SessionData, SessionError> {
         check_redis_connection!(self);
 
@@ -177,12 +341,45 @@ impl DbConnection {
         session_data
     }
 
+    pub async fn get_similar_uploads(&self, upload_phash: i64, max_distance: i32, excluded_upload_id: i32, max_count: i16,
+                                     show_sfw: bool, show_nsfw: bool) -> Result<UploadPrvList, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_similar_uploads(upload_phash, max_distance, excluded_upload_id,
+                                                                       max_count, show_sfw, show_nsfw).await
+    }
+
     pub async fn get_upload_data(&self, upload_id: i32) -> Result<UploadData, DbApiError> {
         check_postgres_connection!(self);
 
         self.postgres_connection.as_ref().unwrap().get_upload_data(upload_id).await
     }
 
+    // Returns an error of type NoResult if no upload with this hash exists
+    pub async fn get_upload_id_by_hash(&self, upload_hash: &str) -> Result<i32, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_upload_id_by_hash(upload_hash).await
+    }
+
+    pub async fn get_upload_phash(&self, upload_id: i32) -> Result<Option<i64>, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_upload_phash(upload_id).await
+    }
+
+    pub async fn get_upload_session(&self, upload_session_id: &str) -> Result<UploadSessionData, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().get_upload_session(upload_session_id).await
+    }
+
+    pub async fn get_upload_status(&self, upload_id: i32) -> Result<UploadStatusData, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_upload_status(upload_id).await
+    }
+
     pub async fn get_uploads(&self, start_id: i32, max_count: i16, show_sfw: bool, show_nsfw: bool) -> Result<UploadPrvList, DbApiError> {
         check_postgres_connection!(self);
 
@@ -195,6 +392,42 @@ impl DbConnection {
         self.postgres_connection.as_ref().unwrap().get_uploads_range(start_id, end_id, show_sfw, show_nsfw).await
     }
 
+    pub async fn get_user_export(&self, user_data: &UserData) -> Result<UserDataExport, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_user_export(user_data).await
+    }
+
+    pub async fn get_user_filter(&self, user_id: i32) -> Result<(bool, bool), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_user_filter(user_id).await
+    }
+
+    pub async fn get_user_invites(&self, user_id: i32) -> Result<InviteList, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_user_invites(user_id).await
+    }
+
+    pub async fn get_user_sessions(&self, user_id: i32, current_session_id: &str) -> Result<SessionInfoList, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().get_user_sessions(user_id, current_session_id).await
+    }
+
+    pub async fn get_user_totp(&self, user_id: i32) -> Result<TotpData, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_user_totp(user_id).await
+    }
+
+    pub async fn get_users_without_skeleton(&self) -> Result<Vec<(i32, String)>, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().get_users_without_skeleton().await
+    }
+
     pub async fn get_userdata_by_id(&self, user_id: i32) -> Result<UserData, DbApiError> {
         check_postgres_connection!(self);
 
@@ -215,6 +448,18 @@ impl DbConnection {
         self.redis_connection.is_some()
     }
 
+    pub async fn index_user_sessions(&self) -> Result<usize, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().index_user_sessions().await
+    }
+
+    pub async fn lock_upload_session(&self, upload_session_id: &str) -> Result<Option<String>, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().lock_upload_session(upload_session_id).await
+    }
+
     pub async fn new(project_config: &ProjectConfig, require_postgres: bool, require_redis: bool) -> Result<DbConnection, DbApiError> {
         trace!("Enter DbConnection::new");
 
@@ -251,6 +496,94 @@ impl DbConnection {
         Ok(db_connection)
     }
 
+    pub async fn register_pending_login_attempt(&self, pending_login_id: &str) -> Result<u32, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().register_pending_login_attempt(pending_login_id).await
+    }
+
+    // Returns the lockout time in seconds (0 = not locked)
+    pub async fn register_rate_limit_attempt(&self, counter_name: &str, max_attempts: u32, attempt_window: u32,
+                                             lockout_base: u32, lockout_max: u32) -> Result<u32, SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().register_rate_limit_attempt(counter_name, max_attempts, attempt_window,
+                                                                            lockout_base, lockout_max).await
+    }
+
+    pub async fn remove_api_token(&self, token_id: i32, user_id: i32) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().remove_api_token(token_id, user_id).await
+    }
+
+    pub async fn remove_user_bans(&self, user_id: i32) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().remove_user_bans(user_id).await
+    }
+
+    pub async fn reset_rate_limit(&self, counter_name: &str) -> Result<(), SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().reset_rate_limit(counter_name).await
+    }
+
+    pub async fn requeue_processing_uploads(&self) -> Result<usize, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().requeue_processing_uploads().await
+    }
+
+    pub async fn reset_user_pw(&self, user_id: i32, pw_hash: &str) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().reset_user_pw(user_id, pw_hash).await
+    }
+
+    pub async fn set_upload_failed(&self, upload_id: i32, upload_error: &str) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().set_upload_failed(upload_id, upload_error).await
+    }
+
+    pub async fn set_upload_prv_variants(&self, upload_id: i32, upload_prv_variants: &str) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().set_upload_prv_variants(upload_id, upload_prv_variants).await
+    }
+
+    pub async fn set_upload_ready(&self, upload_id: i32, upload_filename: &str, upload_type: UploadType, upload_phash: Option<i64>,
+                                  upload_prv_variants: &str, upload_has_prv_clip: bool) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().set_upload_ready(upload_id, upload_filename, upload_type, upload_phash, upload_prv_variants, upload_has_prv_clip).await
+    }
+
+    pub async fn set_upload_session_offset(&self, upload_session_id: &str, upload_offset: u64) -> Result<(), SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().set_upload_session_offset(upload_session_id, upload_offset).await
+    }
+
+    pub async fn set_user_filter(&self, user_id: i32, show_sfw: bool, show_nsfw: bool) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().set_user_filter(user_id, show_sfw, show_nsfw).await
+    }
+
+    pub async fn set_user_name_skeleton(&self, user_id: i32, username_skeleton: &str) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().set_user_name_skeleton(user_id, username_skeleton).await
+    }
+
+    pub async fn set_user_role(&self, user_id: i32, user_role: UserRole) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().set_user_role(user_id, user_role).await
+    }
+
     // TODO: Implement
     //pub fn search_uploads(&self, search_string: &str, start_id: i32, amount: i16, show_nsfw: bool) -> Result<UploadPrvList, DbApiError> {
     //    trace!("Enter DbConnection::search_uploads");
@@ -258,6 +591,42 @@ impl DbConnection {
     //    Err(DbApiError::new(UnknownError, "Unbekannter Fehler"))
     //}
 
+    pub async fn set_user_totp_secret(&self, user_id: i32, totp_secret: &str) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().set_user_totp_secret(user_id, totp_secret).await
+    }
+
+    pub async fn unlock_upload_session(&self, upload_session_id: &str, lock_token: &str) -> Result<(), SessionError> {
+        check_redis_connection!(self);
+
+        self.redis_connection.as_ref().unwrap().unlock_upload_session(upload_session_id, lock_token).await
+    }
+
+    pub async fn upgrade_pg_tables(&self) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().upgrade_pg_tables().await
+    }
+
+    pub async fn use_api_token(&self, token_hash: &str) -> Result<ApiTokenData, DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().use_api_token(token_hash).await
+    }
+
+    pub async fn use_recovery_code(&self, user_id: i32, recovery_code_hash: &str) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().use_recovery_code(user_id, recovery_code_hash).await
+    }
+
+    pub async fn use_totp_time_step(&self, user_id: i32, time_step: i64) -> Result<(), DbApiError> {
+        check_postgres_connection!(self);
+
+        self.postgres_connection.as_ref().unwrap().use_totp_time_step(user_id, time_step).await
+    }
+
     pub async fn vote_comment(&self, comment_id: i32, user_id: i32, vote_value: i32) -> Result<(), DbApiError> {
         check_postgres_connection!(self);
 
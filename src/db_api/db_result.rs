@@ -1,8 +1,229 @@
-use crate::file_api::{get_preview_url_from_filename, get_url_from_filename};
-use chrono::{DateTime, Local};
+use crate::config::PreviewVariant;
+use crate::file_api::{get_preview_clip_url_from_filename, get_preview_url_from_filename, get_stored_preview_variants, get_url_from_filename};
+use chrono::{DateTime, Duration, Local};
 use serde::{Serialize};
 use postgres_types::{ToSql, FromSql};
 use crate::db_api::db_result::UploadType::{AnimatedImage, Image, Video};
+use crate::db_api::db_result::UserRole::{Admin, Moderator, User};
+use crate::db_api::db_result::ApiTokenScope::{Comment, Read, Upload, Vote};
+use std::convert::TryFrom;
+use std::collections::HashMap;
+
+#[derive(Clone, Serialize)]
+pub struct ApiTokenData {
+    pub token_id: i32,
+    #[serde(skip_serializing)]
+    pub token_user: i32,
+    pub token_name: String,
+    pub token_scopes: Vec<ApiTokenScope>,
+    pub token_created: DateTime<Local>,
+    pub token_last_used: Option<DateTime<Local>>,
+}
+
+impl ApiTokenData {
+    pub fn new(token_id: i32, token_user: i32, token_name: String, token_scopes: &[String], token_created: DateTime<Local>,
+               token_last_used: Option<DateTime<Local>>) -> ApiTokenData {
+        // Unknown scopes are ignored (they can't be created through the api)
+        let token_scopes = token_scopes.iter()
+            .filter_map(|token_scope| ApiTokenScope::try_from(token_scope.as_str()).ok())
+            .collect();
+
+        ApiTokenData {
+            token_id,
+            token_user,
+            token_name,
+            token_scopes,
+            token_created,
+            token_last_used,
+        }
+    }
+
+    pub fn has_scope(&self, token_scope: ApiTokenScope) -> bool {
+        self.token_scopes.contains(&token_scope)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ApiTokenList {
+    pub token_list: Vec<ApiTokenData>,
+}
+
+impl ApiTokenList {
+    pub fn new() -> ApiTokenList {
+        ApiTokenList {
+            token_list: Vec::new(),
+        }
+    }
+
+    pub fn add_token(&mut self, token_data: ApiTokenData) {
+        self.token_list.push(token_data);
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiTokenScope {
+    Read,
+    Upload,
+    Vote,
+    Comment,
+}
+
+impl ApiTokenScope {
+    pub fn all() -> Vec<ApiTokenScope> {
+        vec![Read, Upload, Vote, Comment]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Read => "read",
+            Upload => "upload",
+            Vote => "vote",
+            Comment => "comment",
+        }
+    }
+}
+
+impl TryFrom<&str> for ApiTokenScope {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value_lowercase = value.trim().to_lowercase();
+
+        if value_lowercase == "read" {
+            return Ok(Read);
+        }
+        else if value_lowercase == "upload" {
+            return Ok(Upload);
+        }
+        else if value_lowercase == "vote" {
+            return Ok(Vote);
+        }
+        else if value_lowercase == "comment" {
+            return Ok(Comment);
+        }
+
+        return Err("Failed to parse api token scope");
+    }
+}
+
+// Actions which are written to the audit log
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    AccountDelete,
+    AdminCreate,
+    ApiTokenCreate,
+    ApiTokenRevoke,
+    InviteCreate,
+    Login,
+    LoginFailed,
+    PasswordChange,
+    PasswordReset,
+    PasswordResetCreate,
+    Register,
+    SessionRevoke,
+    SessionsRevoke,
+    TotpDisable,
+    TotpEnable,
+    UserBan,
+    UserRoleChange,
+    UserUnban,
+}
+
+impl AuditAction {
+    pub fn all() -> Vec<AuditAction> {
+        vec![AuditAction::AccountDelete, AuditAction::AdminCreate, AuditAction::ApiTokenCreate, AuditAction::ApiTokenRevoke,
+             AuditAction::InviteCreate, AuditAction::Login, AuditAction::LoginFailed, AuditAction::PasswordChange,
+             AuditAction::PasswordReset, AuditAction::PasswordResetCreate, AuditAction::Register, AuditAction::SessionRevoke,
+             AuditAction::SessionsRevoke, AuditAction::TotpDisable, AuditAction::TotpEnable, AuditAction::UserBan,
+             AuditAction::UserRoleChange, AuditAction::UserUnban]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::AccountDelete => "account_delete",
+            AuditAction::AdminCreate => "admin_create",
+            AuditAction::ApiTokenCreate => "api_token_create",
+            AuditAction::ApiTokenRevoke => "api_token_revoke",
+            AuditAction::InviteCreate => "invite_create",
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::PasswordChange => "password_change",
+            AuditAction::PasswordReset => "password_reset",
+            AuditAction::PasswordResetCreate => "password_reset_create",
+            AuditAction::Register => "register",
+            AuditAction::SessionRevoke => "session_revoke",
+            AuditAction::SessionsRevoke => "sessions_revoke",
+            AuditAction::TotpDisable => "totp_disable",
+            AuditAction::TotpEnable => "totp_enable",
+            AuditAction::UserBan => "user_ban",
+            AuditAction::UserRoleChange => "user_role_change",
+            AuditAction::UserUnban => "user_unban",
+        }
+    }
+}
+
+impl TryFrom<&str> for AuditAction {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value_lowercase = value.trim().to_lowercase();
+        let audit_action = AuditAction::all().into_iter()
+            .find(|audit_action| audit_action.as_str() == value_lowercase.as_str());
+
+        return audit_action.ok_or("Failed to parse audit action");
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct AuditEvent {
+    pub event_id: i32,
+    pub event_action: String,
+    pub event_actor_id: Option<i32>, // None for cli actions, unknown or deleted users
+    pub event_actor_username: Option<String>,
+    pub event_target_id: Option<i32>,
+    pub event_target_username: Option<String>,
+    pub event_ip: Option<String>,
+    pub event_details: Option<String>,
+    pub event_time: DateTime<Local>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct AuditEventList {
+    pub event_list: Vec<AuditEvent>,
+}
+
+impl AuditEventList {
+    pub fn new() -> AuditEventList {
+        AuditEventList {
+            event_list: Vec::new(),
+        }
+    }
+
+    pub fn add_event(&mut self, audit_event: AuditEvent) {
+        self.event_list.push(audit_event);
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct BanData {
+    pub ban_id: i32,
+    pub ban_reason: String,
+    pub ban_start: DateTime<Local>,
+    pub ban_end: DateTime<Local>,
+}
+
+impl BanData {
+    pub fn new(ban_id: i32, ban_reason: &str, ban_start: DateTime<Local>, ban_duration: i32) -> BanData {
+        BanData {
+            ban_id,
+            ban_reason: ban_reason.to_owned(),
+            ban_start,
+            ban_end: ban_start + Duration::hours(ban_duration as i64),
+        }
+    }
+}
 
 #[derive(Clone, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct CommentData {
@@ -49,6 +270,7 @@ pub enum DbApiErrorType {
     QueryError,
     NoResult,
     PartFail,
+    UniqueViolation,
 }
 
 #[derive(Clone)]
@@ -83,6 +305,132 @@ impl SessionData {
             user_id,
         }
     }
+
+    // Api tokens have no session id and don't expire
+    pub fn new_api_token(user_id: i32) -> SessionData {
+        SessionData {
+            expire_datetime: Local::now(),
+            is_lts: false,
+            session_id: String::new(),
+            user_id,
+        }
+    }
+
+    pub fn is_api_token(&self) -> bool {
+        self.session_id.is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct PendingLoginData {
+    pub user_id: i32,
+    pub is_lts: bool,
+}
+
+impl PendingLoginData {
+    pub fn new(user_id: i32, is_lts: bool) -> PendingLoginData {
+        PendingLoginData {
+            user_id,
+            is_lts,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct SessionInfo {
+    #[serde(skip_serializing)]
+    pub session_id: String,
+    pub session_public_id: String,
+    pub session_created: Option<DateTime<Local>>,
+    pub session_expire: DateTime<Local>,
+    pub session_ip: String,
+    pub session_user_agent: String,
+    pub session_is_lts: bool,
+    pub session_is_current: bool,
+}
+
+impl SessionInfo {
+    // The info map contains the fields public_id, created, ip and user_agent (missing fields stay empty)
+    pub fn new(session_data: &SessionData, session_info_map: &HashMap<String, String>, session_is_current: bool) -> SessionInfo {
+        let get_info_field = |field_name: &str| session_info_map.get(field_name).cloned().unwrap_or_default();
+        let session_created = DateTime::parse_from_rfc3339(get_info_field("created").as_str())
+            .map(|created| created.with_timezone(&Local))
+            .ok();
+
+        SessionInfo {
+            session_id: session_data.session_id.clone(),
+            session_public_id: get_info_field("public_id"),
+            session_created,
+            session_expire: session_data.expire_datetime,
+            session_ip: get_info_field("ip"),
+            session_user_agent: get_info_field("user_agent"),
+            session_is_lts: session_data.is_lts,
+            session_is_current,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct SessionInfoList {
+    pub session_list: Vec<SessionInfo>,
+}
+
+impl SessionInfoList {
+    pub fn new() -> SessionInfoList {
+        SessionInfoList {
+            session_list: Vec::new(),
+        }
+    }
+
+    pub fn add_session(&mut self, session_info: SessionInfo) {
+        self.session_list.push(session_info);
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct InviteData {
+    pub invite_code: String,
+    pub invite_created: DateTime<Local>,
+    pub invite_expire: DateTime<Local>,
+    pub invite_used_at: Option<DateTime<Local>>,
+    pub invited_user_id: Option<i32>,
+    pub invited_username: Option<String>,
+}
+
+impl InviteData {
+    pub fn new(invite_code: &str, invite_created: DateTime<Local>, invite_expire: DateTime<Local>) -> InviteData {
+        InviteData {
+            invite_code: invite_code.to_owned(),
+            invite_created,
+            invite_expire,
+            invite_used_at: None,
+            invited_user_id: None,
+            invited_username: None,
+        }
+    }
+
+    pub fn set_usage(&mut self, invite_used_at: DateTime<Local>, invited_user_id: Option<i32>, invited_username: Option<String>) {
+        self.invite_used_at = Some(invite_used_at);
+        self.invited_user_id = invited_user_id;
+        self.invited_username = invited_username;
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct InviteList {
+    pub invite_list: Vec<InviteData>,
+}
+
+impl InviteList {
+    pub fn new() -> InviteList {
+        InviteList {
+            invite_list: Vec::new(),
+        }
+    }
+
+    pub fn add_invite(&mut self, invite_data: InviteData) {
+        self.invite_list.push(invite_data);
+    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -91,6 +439,8 @@ pub enum SessionErrorType {
     DbError,
     SessionInvalid,
     NoSession,
+    UserBanned,
+    MissingScope,
 }
 
 #[derive(Clone)]
@@ -108,6 +458,23 @@ impl SessionError {
     }
 }
 
+#[derive(Clone)]
+pub struct TotpData {
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+}
+
+impl TotpData {
+    pub fn new(totp_secret: Option<String>, totp_enabled: bool, totp_last_step: Option<i64>) -> TotpData {
+        TotpData {
+            totp_secret,
+            totp_enabled,
+            totp_last_step,
+        }
+    }
+}
+
 #[derive(Clone, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct TagData {
     pub tag_id: i32,
@@ -175,6 +542,7 @@ pub struct UploadData {
     pub upload_is_nsfw: bool,
     pub upload_type: UploadType,
     pub upload_url: String,
+    pub upload_original_filename: Option<String>, // None for uploads from before the original filename was stored
     pub uploader_id: i32,
     pub uploader_username: String,
     pub upload_timestamp: DateTime<Local>,
@@ -184,14 +552,16 @@ pub struct UploadData {
 }
 
 impl UploadData {
-    pub fn new(upload_id: i32, upload_is_nsfw: bool, upload_type: UploadType, upload_filename: &str, uploader_id: i32,
-               uploader_username: &str, upload_timestamp: DateTime<Local>, upload_upvotes: i32) -> UploadData {
+    pub fn new(upload_id: i32, upload_is_nsfw: bool, upload_type: UploadType, upload_filename: &str,
+               upload_original_filename: Option<String>, uploader_id: i32, uploader_username: &str,
+               upload_timestamp: DateTime<Local>, upload_upvotes: i32) -> UploadData {
         UploadData {
             upload_id,
             upload_is_sfw: !upload_is_nsfw,
             upload_is_nsfw,
             upload_type,
             upload_url: get_url_from_filename(upload_filename),
+            upload_original_filename,
             uploader_id,
             uploader_username: uploader_username.to_owned(),
             upload_timestamp,
@@ -216,27 +586,152 @@ impl UploadData {
     }
 }
 
+// Files of an upload on the server (needed to delete them or to generate the previews again)
+#[derive(Clone)]
+pub struct UploadFileData {
+    pub upload_id: i32,
+    pub upload_filename: String,
+    pub upload_prv_variants: Option<String>, // None for uploads from before the preview variants existed
+    pub upload_has_prv_clip: bool,
+}
+
+impl UploadFileData {
+    pub fn new(upload_id: i32, upload_filename: String, upload_prv_variants: Option<String>, upload_has_prv_clip: bool) -> UploadFileData {
+        UploadFileData {
+            upload_id,
+            upload_filename,
+            upload_prv_variants,
+            upload_has_prv_clip,
+        }
+    }
+}
+
 #[derive(Clone, Serialize)]
 pub struct UploadPreview {
     pub upload_id: i32,
     pub upload_is_nsfw: bool,
-    pub upload_prv_url: String,
+    pub upload_prv_url: String, // Url of the default (first configured) preview variant
+    pub upload_prv_variants: Vec<UploadPreviewVariant>,
+    pub upload_prv_clip_url: Option<String>, // Short muted clip of videos and animated images
     pub upload_url: String,
     pub upload_type: UploadType,
 }
 
 impl UploadPreview {
-    pub fn new(upload_id: i32, upload_is_nsfw: bool, upload_filename: String, upload_type: UploadType) -> UploadPreview {
+    // The urls point to the preview variants and the clip which were generated for the upload (not to the currently configured ones)
+    pub fn new(upload_id: i32, upload_is_nsfw: bool, upload_filename: String, upload_type: UploadType,
+               upload_prv_variants: Option<String>, upload_has_prv_clip: bool) -> UploadPreview {
+        let upload_prv_variants : Vec<UploadPreviewVariant> = get_stored_preview_variants(upload_prv_variants.as_deref()).iter()
+            .map(|preview_variant| UploadPreviewVariant::new(upload_filename.as_str(), preview_variant))
+            .collect();
+        let upload_prv_url = upload_prv_variants.first().map(|preview_variant| preview_variant.url.clone()).unwrap_or_default();
+        let upload_prv_clip_url = if upload_has_prv_clip {
+            Some(get_preview_clip_url_from_filename(upload_filename.as_str()))
+        }
+        else {
+            None
+        };
+
         UploadPreview {
             upload_id,
             upload_is_nsfw,
-            upload_prv_url: get_preview_url_from_filename(upload_filename.as_str()),
+            upload_prv_url,
+            upload_prv_variants,
+            upload_prv_clip_url,
             upload_url: get_url_from_filename(upload_filename.as_str()),
             upload_type,
         }
     }
 }
 
+#[derive(Clone, Serialize)]
+pub struct UploadPreviewVariant {
+    pub name: String,
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub is_cropped: bool,
+    pub url: String,
+}
+
+impl UploadPreviewVariant {
+    pub fn new(upload_filename: &str, preview_variant: &PreviewVariant) -> UploadPreviewVariant {
+        UploadPreviewVariant {
+            name: preview_variant.name.clone(),
+            format: preview_variant.format.as_str().to_owned(),
+            width: preview_variant.width,
+            height: preview_variant.height,
+            is_cropped: preview_variant.is_cropped,
+            url: get_preview_url_from_filename(upload_filename, preview_variant),
+        }
+    }
+}
+
+// Unfinished chunked upload, the received bytes are stored in the tmp file
+#[derive(Clone)]
+pub struct UploadSessionData {
+    pub upload_session_id: String,
+    pub user_id: i32,
+    pub storage_filename: String,
+    pub original_filename: String,
+    pub upload_size: u64,
+    pub upload_offset: u64, // Number of bytes which are already written to the tmp file
+    pub upload_is_nsfw: bool,
+    pub upload_taglist: String, // The tags are added when the upload is complete
+}
+
+impl UploadSessionData {
+    pub fn new(user_id: i32, storage_filename: &str, original_filename: &str, upload_size: u64, upload_is_nsfw: bool,
+               upload_taglist: &str) -> UploadSessionData {
+        UploadSessionData {
+            upload_session_id: String::new(),
+            user_id,
+            storage_filename: storage_filename.to_owned(),
+            original_filename: original_filename.to_owned(),
+            upload_size,
+            upload_offset: 0,
+            upload_is_nsfw,
+            upload_taglist: upload_taglist.to_owned(),
+        }
+    }
+}
+
+// Processing state of an upload (new uploads are processed by the upload queue)
+#[derive(Copy, Clone, Serialize, Eq, PartialEq, Debug, ToSql, FromSql)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadStatus {
+    Queued,
+    Processing,
+    Ready,
+    Failed,
+}
+
+#[derive(Clone, Serialize)]
+pub struct UploadStatusData {
+    pub upload_id: i32,
+    pub upload_status: UploadStatus,
+    pub upload_error: Option<String>,
+    pub similar_upload_ids: Vec<i32>, // Only set for ready uploads, if the similar upload mode is 'warn'
+    #[serde(skip)]
+    pub uploader_id: i32,
+    #[serde(skip)]
+    pub upload_phash: Option<i64>,
+}
+
+impl UploadStatusData {
+    pub fn new(upload_id: i32, upload_status: UploadStatus, upload_error: Option<String>, uploader_id: i32,
+               upload_phash: Option<i64>) -> UploadStatusData {
+        UploadStatusData {
+            upload_id,
+            upload_status,
+            upload_error,
+            similar_upload_ids: Vec::new(),
+            uploader_id,
+            upload_phash,
+        }
+    }
+}
+
 #[derive(Clone, Serialize)]
 pub struct UploadPrvList {
     pub uploads: Vec<UploadPreview>,
@@ -248,15 +743,175 @@ pub struct UserData {
     pub username: String,
     pub password_hash: String,
     pub user_is_mod: bool,
+    pub user_is_admin: bool,
 }
 
 impl UserData {
-    pub fn new(user_id: i32, username: &str, password_hash: &str, user_is_mod: bool) -> UserData {
+    pub fn new(user_id: i32, username: &str, password_hash: &str, user_is_mod: bool, user_is_admin: bool) -> UserData {
         UserData {
             user_id,
             username: username.to_owned(),
             password_hash: password_hash.to_owned(),
             user_is_mod,
+            user_is_admin,
+        }
+    }
+
+    pub fn get_user_role(&self) -> UserRole {
+        UserRole::new(self.user_is_mod, self.user_is_admin)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct UserDataExport {
+    pub user_id: i32,
+    pub username: String,
+    pub user_role: UserRole,
+    pub export_created: DateTime<Local>,
+    pub upload_list: Vec<UserExportUpload>,
+    pub comment_list: Vec<UserExportComment>,
+    pub tag_list: Vec<UserExportTag>,
+    pub vote_list: Vec<UserExportVote>,
+}
+
+impl UserDataExport {
+    pub fn new(user_data: &UserData) -> UserDataExport {
+        UserDataExport {
+            user_id: user_data.user_id,
+            username: user_data.username.clone(),
+            user_role: user_data.get_user_role(),
+            export_created: Local::now(),
+            upload_list: Vec::new(),
+            comment_list: Vec::new(),
+            tag_list: Vec::new(),
+            vote_list: Vec::new(),
         }
     }
+
+    pub fn add_comment(&mut self, comment_id: i32, comment_upload: i32, comment_timestamp: DateTime<Local>,
+                       comment_text: &str, comment_upvotes: i32) {
+        self.comment_list.push(UserExportComment {
+            comment_id,
+            comment_upload,
+            comment_timestamp,
+            comment_text: comment_text.to_owned(),
+            comment_upvotes,
+        });
+    }
+
+    pub fn add_tag(&mut self, tum_id: i32, upload_id: i32, tag_text: &str, tag_upvotes: i32) {
+        self.tag_list.push(UserExportTag {
+            tum_id,
+            upload_id,
+            tag_text: tag_text.to_owned(),
+            tag_upvotes,
+        });
+    }
+
+    pub fn add_upload(&mut self, upload_id: i32, upload_filename: &str, upload_timestamp: DateTime<Local>,
+                      upload_is_nsfw: bool, upload_type: UploadType, upload_upvotes: i32) {
+        self.upload_list.push(UserExportUpload {
+            upload_id,
+            upload_filename: upload_filename.to_owned(),
+            upload_url: get_url_from_filename(upload_filename),
+            upload_timestamp,
+            upload_is_nsfw,
+            upload_type,
+            upload_upvotes,
+        });
+    }
+
+    pub fn add_vote(&mut self, vote_target: &str, vote_target_id: i32, vote_value: i32) {
+        self.vote_list.push(UserExportVote {
+            vote_target: vote_target.to_owned(),
+            vote_target_id,
+            vote_value,
+        });
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct UserExportComment {
+    pub comment_id: i32,
+    pub comment_upload: i32,
+    pub comment_timestamp: DateTime<Local>,
+    pub comment_text: String,
+    pub comment_upvotes: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct UserExportTag {
+    pub tum_id: i32,
+    pub upload_id: i32,
+    pub tag_text: String,
+    pub tag_upvotes: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct UserExportUpload {
+    pub upload_id: i32,
+    pub upload_filename: String, // Name of the file inside the zip archive of the file export
+    pub upload_url: String,
+    pub upload_timestamp: DateTime<Local>,
+    pub upload_is_nsfw: bool,
+    pub upload_type: UploadType,
+    pub upload_upvotes: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct UserExportVote {
+    pub vote_target: String, // upload, comment or tag
+    pub vote_target_id: i32,
+    pub vote_value: i32,
+}
+
+// Roles are ordered by their permissions (every role has all permissions of the roles below)
+#[derive(Copy, Clone, Eq, Ord, PartialEq, PartialOrd, Serialize, Debug)]
+pub enum UserRole {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl UserRole {
+    pub fn new(user_is_mod: bool, user_is_admin: bool) -> UserRole {
+        if user_is_admin {
+            Admin
+        }
+        else if user_is_mod {
+            Moderator
+        }
+        else {
+            User
+        }
+    }
+
+    // Returns the values of the user_is_mod and user_is_admin database columns
+    pub fn as_db_flags(&self) -> (bool, bool) {
+        match self {
+            User => (false, false),
+            Moderator => (true, false),
+            Admin => (true, true),
+        }
+    }
+}
+
+impl TryFrom<&str> for UserRole {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value_lowercase = value.to_lowercase();
+
+        if value_lowercase == "user" {
+            return Ok(User);
+        }
+        else if value_lowercase == "moderator" {
+            return Ok(Moderator);
+        }
+        else if value_lowercase == "admin" {
+            return Ok(Admin);
+        }
+
+        return Err("Failed to parse user role");
+    }
 }
\ No newline at end of file
@@ -1,12 +1,13 @@
 use tokio_postgres::{NoTls, Error, Client, Config, Statement};
 use tokio_postgres::types::ToSql;
+use tokio_postgres::error::{DbError, SqlState};
 use crate::config::{ProjectConfig, ConnectionMethod};
 use tokio_postgres::config::SslMode::Disable;
 use crate::config::ConnectionMethod::Tcp;
 use std::path::Path;
 use std::time::Duration;
-use crate::db_api::db_result::{UploadPrvList, DbApiError, UploadPreview, UploadData, UserData, UploadType};
-use crate::db_api::db_result::DbApiErrorType::{QueryError, NoResult};
+use crate::db_api::db_result::{ApiTokenData, ApiTokenList, ApiTokenScope, AuditAction, AuditEvent, AuditEventList, UploadPrvList, DbApiError, UploadFileData, UploadPreview, UploadData, UploadStatus, UploadStatusData, UserData, UploadType, BanData, UserRole, InviteData, InviteList, TotpData, UserDataExport};
+use crate::db_api::db_result::DbApiErrorType::{QueryError, NoResult, UniqueViolation};
 use chrono::{DateTime, Local};
 use futures::future;
 use log::{trace, warn, error};
@@ -22,6 +23,57 @@ pub struct PostgresConnection {
 }
 
 impl PostgresConnection {
+    pub async fn add_api_token(&self, user_id: i32, token_name: &str, token_hash: &str, token_scopes: &[ApiTokenScope]) -> Result<ApiTokenData, DbApiError> {
+        trace!("Enter PostgresConnection::add_api_token");
+
+        let sql_cmd = include_str!(get_filepath!("add_api_token.sql"));
+        let token_scopes : Vec<String> = token_scopes.iter().map(|token_scope| token_scope.as_str().to_owned()).collect();
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, &token_name, &token_hash, &token_scopes];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let token_id : i32 = first_row.get(0);
+                let token_created : DateTime<Local> = first_row.get(1);
+                let token_data = ApiTokenData::new(token_id, user_id, token_name.to_owned(), &token_scopes,
+                                                   token_created, None);
+
+                return Ok(token_data);
+            }
+            else {
+                error!("PostgresConnection::add_api_token: Got no result from postgres");
+            }
+        }
+        else {
+            error!("PostgresConnection::add_api_token: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn add_audit_event(&self, audit_action: AuditAction, actor_id: Option<i32>, target_id: Option<i32>,
+                                 client_ip: Option<&str>, event_details: Option<&str>) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::add_audit_event");
+
+        let audit_action = audit_action.as_str();
+        let sql_cmd = include_str!(get_filepath!("add_audit_event.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&audit_action, &actor_id, &target_id, &client_ip, &event_details];
+        let result_rows = self.postgres_client.execute(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            return Ok(());
+        }
+        else {
+            error!("PostgresConnection::add_audit_event: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
     pub async fn add_comment(&self, comment_poster: i32, comment_upload: i32, comment_text: &str) -> Result<(), DbApiError> {
         trace!("Enter PostgresConnection::add_comment");
 
@@ -30,12 +82,76 @@ impl PostgresConnection {
         let result_rows = self.postgres_client.execute(sql_cmd, sql_parameters).await;
 
         if result_rows.is_ok() {
+            if result_rows.unwrap() == 0 {
+                return Err(DbApiError::new(NoResult, "Upload ist nicht vorhanden"));
+            }
+
             return Ok(());
         }
 
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
     }
 
+    // The row of the creator is locked while the quota is checked, so concurrent requests can't exceed the quota
+    // Returns an error of type NoResult if the invite quota (None = unlimited) is exhausted
+    pub async fn add_invite(&self, invite_code: &str, invite_creator: i32, invite_lifetime: i32, invite_quota: Option<i64>) -> Result<InviteData, DbApiError> {
+        trace!("Enter PostgresConnection::add_invite");
+
+        let begin_result = self.postgres_client.batch_execute("BEGIN").await;
+
+        if begin_result.is_err() {
+            error!("PostgresConnection::add_invite: Failed to start transaction");
+
+            return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+        }
+
+        let lock_sql_cmd = include_str!(get_filepath!("lock_user.sql"));
+        let lock_sql_parameters : &[&(dyn ToSql + Sync)] = &[&invite_creator];
+        let lock_result = self.postgres_client.query(lock_sql_cmd, lock_sql_parameters).await;
+
+        if lock_result.is_err() {
+            error!("PostgresConnection::add_invite: Failed to lock the invite creator");
+            let _rollback_result = self.postgres_client.batch_execute("ROLLBACK").await;
+
+            return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+        }
+
+        let sql_cmd = include_str!(get_filepath!("add_invite.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&invite_code, &invite_creator, &invite_lifetime, &invite_quota];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_err() {
+            error!("PostgresConnection::add_invite: Failed to execute sql statement");
+            let _rollback_result = self.postgres_client.batch_execute("ROLLBACK").await;
+
+            return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+        }
+
+        let result_rows = result_rows.unwrap();
+        let first_row = result_rows.get(0);
+
+        if first_row.is_none() {
+            let _rollback_result = self.postgres_client.batch_execute("ROLLBACK").await;
+
+            return Err(DbApiError::new(NoResult, "Das Kontingent an Invitecodes ist aufgebraucht"));
+        }
+
+        let commit_result = self.postgres_client.batch_execute("COMMIT").await;
+
+        if commit_result.is_err() {
+            error!("PostgresConnection::add_invite: Failed to commit transaction");
+
+            return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+        }
+
+        let first_row = first_row.unwrap();
+        let invite_created : DateTime<Local> = first_row.get(1);
+        let invite_expire : DateTime<Local> = first_row.get(2);
+        let invite_data = InviteData::new(invite_code, invite_created, invite_expire);
+
+        return Ok(invite_data);
+    }
+
     pub async fn add_tag(&self, tag_text: &str, tag_poster: i32, upload_id: i32) -> Result<(), DbApiError> {
         trace!("Enter PostgresConnection::add_tag");
 
@@ -86,12 +202,14 @@ impl PostgresConnection {
     }
 
     // Returns the upload_id of the new inserted upload or error
-    pub async fn add_upload(&self, upload_filename: &str, upload_is_nsfw: bool, upload_type: UploadType, uploader: i32) -> Result<i32, DbApiError> {
+    // The upload is queued for processing (upload type and perceptual hash are set by set_upload_ready)
+    // A concurrent upload of the same file fails with UniqueViolation on upload_hash_unique
+    pub async fn add_upload(&self, upload_filename: &str, upload_original_filename: &str, upload_is_nsfw: bool, uploader: i32, upload_hash: &str) -> Result<i32, DbApiError> {
         trace!("Enter PostgresConnection::add_upload");
 
         let sql_cmd = include_str!(get_filepath!("add_upload.sql"));
         let upload_is_sfw = !upload_is_nsfw;
-        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_filename, &upload_is_sfw, &upload_is_nsfw, &upload_type, &uploader];
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_filename, &upload_original_filename, &upload_is_sfw, &upload_is_nsfw, &uploader, &upload_hash];
         let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
 
         if result_rows.is_ok() {
@@ -109,17 +227,79 @@ impl PostgresConnection {
             }
         }
         else {
+            let error = result_rows.err().unwrap();
+
+            let db_error = std::error::Error::source(&error).and_then(|source| source.downcast_ref::<DbError>());
+            let is_hash_violation = db_error.is_some() && db_error.unwrap().constraint() == Some("upload_hash_unique");
+
+            if error.code() == Some(&SqlState::UNIQUE_VIOLATION) && is_hash_violation {
+                return Err(DbApiError::new(UniqueViolation, "Die Datei wurde bereits hochgeladen"));
+            }
+
             error!("PostgresConnection::add_upload: Failed to execute sql statement");
         }
 
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
     }
 
-    pub async fn add_user(&self, username: &str, pw_hash: &str, user_is_mod: bool) -> Result<i32, DbApiError> {
+    pub async fn add_user(&self, username: &str, username_skeleton: &str, pw_hash: &str, user_is_mod: bool, user_is_admin: bool) -> Result<i32, DbApiError> {
         trace!("Enter PostgresConnection::add_user");
 
         let sql_cmd = include_str!(get_filepath!("add_user.sql"));
-        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&username, &pw_hash, &user_is_mod];
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&username, &pw_hash, &user_is_mod, &user_is_admin, &username_skeleton];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let user_id = first_row.get(0);
+
+                return Ok(user_id);
+            }
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns the ban_id of the new inserted ban or error
+    pub async fn add_user_ban(&self, user_id: i32, ban_reason: &str, ban_duration: i32) -> Result<i32, DbApiError> {
+        trace!("Enter PostgresConnection::add_user_ban");
+
+        let sql_cmd = include_str!(get_filepath!("add_user_ban.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, &ban_reason, &ban_duration];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let ban_id = first_row.get(0);
+
+                return Ok(ban_id);
+            }
+            else {
+                error!("PostgresConnection::add_user_ban: Got no result from postgres");
+            }
+        }
+        else {
+            error!("PostgresConnection::add_user_ban: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Creates the user and consumes the invite code in one statement
+    // Returns the user_id of the new user or an error of type NoResult if the invite code is invalid
+    pub async fn add_user_with_invite(&self, username: &str, username_skeleton: &str, pw_hash: &str, invite_code: &str) -> Result<i32, DbApiError> {
+        trace!("Enter PostgresConnection::add_user_with_invite");
+
+        let sql_cmd = include_str!(get_filepath!("add_user_with_invite.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&username, &pw_hash, &invite_code, &username_skeleton];
         let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
 
         if result_rows.is_ok() {
@@ -132,6 +312,49 @@ impl PostgresConnection {
 
                 return Ok(user_id);
             }
+
+            return Err(DbApiError::new(NoResult, "Der Invitecode ist ungültig, abgelaufen oder wurde bereits verwendet"));
+        }
+        else {
+            error!("PostgresConnection::add_user_with_invite: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Removes the credentials and personal settings of the user, the content stays under the anonymized username
+    pub async fn anonymize_user(&self, user_id: i32, anonymized_username: &str) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::anonymize_user");
+
+        let sql_cmd = include_str!(get_filepath!("anonymize_user.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, &anonymized_username];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Benutzer ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::anonymize_user: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn change_user_pw_by_id(&self, user_id: i32, pw_hash: &str) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::change_user_pw_by_id");
+
+        let sql_cmd = include_str!(get_filepath!("change_user_pw_by_id.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&pw_hash, &user_id];
+        let result_rows = self.postgres_client.execute(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            return Ok(());
         }
 
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
@@ -151,6 +374,61 @@ impl PostgresConnection {
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
     }
 
+    // Returns true if the username is already taken or confusable with an existing username
+    pub async fn check_user_exists(&self, username: &str, username_skeleton: &str) -> Result<bool, DbApiError> {
+        trace!("Enter PostgresConnection::check_user_exists");
+
+        let sql_cmd = include_str!(get_filepath!("check_user_exists.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&username, &username_skeleton];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let user_exists = first_row.get(0);
+
+                return Ok(user_exists);
+            }
+        }
+        else {
+            error!("PostgresConnection::check_user_exists: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Marks the oldest queued upload as processing, returns an error of type NoResult if the queue is empty
+    pub async fn claim_queued_upload(&self) -> Result<(i32, String), DbApiError> {
+        trace!("Enter PostgresConnection::claim_queued_upload");
+
+        let sql_cmd = include_str!(get_filepath!("claim_queued_upload.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let upload_id : i32 = first_row.get(0);
+                let upload_filename : String = first_row.get(1);
+
+                return Ok((upload_id, upload_filename));
+            }
+
+            return Err(DbApiError::new(NoResult, "Keine Uploads in der Warteschlange"));
+        }
+        else {
+            error!("PostgresConnection::claim_queued_upload: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
     pub async fn create_pg_database(&self, db_name: &str, user: &str) -> Result<(), DbApiError> {
         trace!("Enter PostgresConnection::create_pg_user");
 
@@ -210,6 +488,63 @@ impl PostgresConnection {
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
     }
 
+    // Removes uploads whose processing failed before the lifetime, the uploader could read the error until then
+    // Returns the number of removed uploads
+    pub async fn delete_failed_uploads(&self, failed_upload_lifetime: i32) -> Result<usize, DbApiError> {
+        trace!("Enter PostgresConnection::delete_failed_uploads");
+
+        let sql_cmd = include_str!(get_filepath!("delete_failed_uploads.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&failed_upload_lifetime];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            return Ok(result_rows.unwrap().len());
+        }
+        else {
+            error!("PostgresConnection::delete_failed_uploads: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Deletes the user with all of his content
+    // Returns the filenames of the deleted uploads (the files have to be removed by the caller)
+    pub async fn delete_user(&self, user_id: i32) -> Result<Vec<UploadFileData>, DbApiError> {
+        trace!("Enter PostgresConnection::delete_user");
+
+        let sql_cmd = include_str!(get_filepath!("delete_user.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let upload_files = result_rows.iter()
+                .map(|row| UploadFileData::new(row.get(0), row.get(1), row.get(2), row.get(3)))
+                .collect();
+
+            return Ok(upload_files);
+        }
+        else {
+            error!("PostgresConnection::delete_user: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn disable_user_totp(&self, user_id: i32) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::disable_user_totp");
+
+        let sql_cmd = include_str!(get_filepath!("disable_user_totp.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id];
+        let result_rows = self.postgres_client.execute(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            return Ok(());
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
     pub async fn drop_pg_db(&self) -> Result<(), DbApiError> {
         trace!("Enter PostgresConnection::drop_pg_db");
 
@@ -253,51 +588,279 @@ impl PostgresConnection {
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
     }
 
-    pub async fn get_upload_data(&self, upload_id: i32) -> Result<UploadData, DbApiError> {
-        trace!("Enter PostgresConnection::get_upload_data");
+    // Enables 2FA and replaces all recovery codes of the user
+    pub async fn enable_user_totp(&self, user_id: i32, recovery_code_hashes: &Vec<String>) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::enable_user_totp");
 
-        let sql_cmd_upload_data = include_str!(get_filepath!("get_upload_data.sql"));
-        let sql_cmd_comment_data = include_str!(get_filepath!("get_comments_for_upload.sql"));
-        let sql_cmd_tag_data = include_str!(get_filepath!("get_tags_for_upload.sql"));
-        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_id];
+        let sql_cmd = include_str!(get_filepath!("enable_user_totp.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, recovery_code_hashes];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
 
-        let prepared_statement : Result<(Statement, Statement, Statement), Error> = future::try_join3(
-            self.postgres_client.prepare(sql_cmd_upload_data),
-            self.postgres_client.prepare(sql_cmd_comment_data),
-            self.postgres_client.prepare(sql_cmd_tag_data)
-        ).await;
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
 
-        if prepared_statement.is_ok() {
-            let (sql_stm_1, sql_stm_2, sql_stm_3) = prepared_statement.unwrap();
-            let result_rows_up = self.postgres_client.query(&sql_stm_1, sql_parameters).await;
-            let result_rows_cm = self.postgres_client.query(&sql_stm_2, sql_parameters).await;
-            let result_rows_ta = self.postgres_client.query(&sql_stm_3, sql_parameters).await;
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
 
-            if result_rows_up.is_ok() && result_rows_cm.is_ok() && result_rows_ta.is_ok() {
-                let result_rows_up = result_rows_up.unwrap();
-                let result_rows_cm = result_rows_cm.unwrap();
-                let result_rows_ta = result_rows_ta.unwrap();
+            return Err(DbApiError::new(NoResult, "2FA wurde noch nicht eingerichtet"));
+        }
+        else {
+            error!("PostgresConnection::enable_user_totp: Failed to execute sql statement");
+        }
 
-                if !result_rows_up.is_empty() {
-                    let first_result_row = result_rows_up.get(0).unwrap();
-                    let upload_filename : String = first_result_row.get(0);
-                    let upload_timestamp : DateTime<Local> = first_result_row.get(1);
-                    let upload_is_nsfw : bool = first_result_row.get(2);
-                    let upload_type : UploadType = first_result_row.get(3);
-                    let uploader_id : i32 = first_result_row.get(4);
-                    let uploader_username : String = first_result_row.get(5);
-                    let upload_upvotes : i32 = first_result_row.get(6);
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
 
-                    let mut upload_data = UploadData::new(upload_id, upload_is_nsfw, upload_type, upload_filename.as_str(),
-                                    uploader_id, uploader_username.as_str(), upload_timestamp, upload_upvotes);
+    // Returns the active ban of the user or an error of type NoResult if the user isn't banned
+    pub async fn get_active_user_ban(&self, user_id: i32) -> Result<BanData, DbApiError> {
+        trace!("Enter PostgresConnection::get_active_user_ban");
 
-                    // Process comments
-                    for row in result_rows_cm {
-                        let comment_timestamp : DateTime<Local> = row.get(0);
-                        let comment_text : String = row.get(1);
-                        let comment_poster_id : i32 = row.get(2);
-                        let comment_poster_username : String = row.get(3);
-                        let comment_upvotes : i32 = row.get(4);
+        let sql_cmd = include_str!(get_filepath!("get_active_user_ban.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows_vec = result_rows.unwrap();
+
+            if !result_rows_vec.is_empty() {
+                let row = result_rows_vec.get(0).unwrap();
+                let ban_id : i32 = row.get(0);
+                let ban_reason : String = row.get(1);
+                let ban_start : DateTime<Local> = row.get(2);
+                let ban_duration : i32 = row.get(3);
+                let ban_data = BanData::new(ban_id, ban_reason.as_str(), ban_start, ban_duration);
+
+                return Ok(ban_data);
+            }
+
+            return Err(DbApiError::new(NoResult, "Benutzer ist nicht gesperrt"));
+        }
+        else {
+            error!("PostgresConnection::get_active_user_ban: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_api_tokens(&self, user_id: i32) -> Result<ApiTokenList, DbApiError> {
+        trace!("Enter PostgresConnection::get_api_tokens");
+
+        let sql_cmd = include_str!(get_filepath!("get_api_tokens.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let mut token_list = ApiTokenList::new();
+
+            for row in result_rows.unwrap() {
+                let token_id : i32 = row.get(0);
+                let token_name : String = row.get(1);
+                let token_scopes : Vec<String> = row.get(2);
+                let token_created : DateTime<Local> = row.get(3);
+                let token_last_used : Option<DateTime<Local>> = row.get(4);
+                let token_data = ApiTokenData::new(token_id, user_id, token_name, &token_scopes,
+                                                   token_created, token_last_used);
+
+                token_list.add_token(token_data);
+            }
+
+            return Ok(token_list);
+        }
+        else {
+            error!("PostgresConnection::get_api_tokens: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_audit_events(&self, start_id: i32, max_count: i16, audit_action: Option<AuditAction>,
+                                  actor_id: Option<i32>, target_id: Option<i32>) -> Result<AuditEventList, DbApiError> {
+        trace!("Enter PostgresConnection::get_audit_events");
+
+        let audit_action = audit_action.map(|audit_action| audit_action.as_str());
+        let sql_cmd = include_str!(get_filepath!("get_audit_events.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&start_id, &max_count, &audit_action, &actor_id, &target_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let mut event_list = AuditEventList::new();
+
+            for row in result_rows.unwrap() {
+                let audit_event = AuditEvent {
+                    event_id: row.get(0),
+                    event_action: row.get(1),
+                    event_actor_id: row.get(2),
+                    event_actor_username: row.get(3),
+                    event_target_id: row.get(4),
+                    event_target_username: row.get(5),
+                    event_ip: row.get(6),
+                    event_details: row.get(7),
+                    event_time: row.get(8),
+                };
+
+                event_list.add_event(audit_event);
+            }
+
+            return Ok(event_list);
+        }
+        else {
+            error!("PostgresConnection::get_audit_events: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_queued_upload_filenames(&self) -> Result<Vec<String>, DbApiError> {
+        trace!("Enter PostgresConnection::get_queued_upload_filenames");
+
+        let sql_cmd = include_str!(get_filepath!("get_queued_upload_filenames.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let upload_filenames = result_rows.unwrap().iter()
+                .map(|row| row.get(0))
+                .collect();
+
+            return Ok(upload_filenames);
+        }
+        else {
+            error!("PostgresConnection::get_queued_upload_filenames: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_ready_upload_files(&self) -> Result<Vec<UploadFileData>, DbApiError> {
+        trace!("Enter PostgresConnection::get_ready_upload_files");
+
+        let sql_cmd = include_str!(get_filepath!("get_ready_upload_files.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let upload_files = result_rows.unwrap().iter()
+                .map(|row| UploadFileData::new(row.get(0), row.get(1), row.get(2), row.get(3)))
+                .collect();
+
+            return Ok(upload_files);
+        }
+        else {
+            error!("PostgresConnection::get_ready_upload_files: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Installations whose schema version was never stored have version 1
+    pub async fn get_schema_version(&self) -> Result<u32, DbApiError> {
+        trace!("Enter PostgresConnection::get_schema_version");
+
+        let sql_cmd = include_str!(get_filepath!("get_schema_version.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let schema_version : String = first_row.unwrap().get(0);
+                let schema_version = schema_version.parse::<u32>();
+
+                if schema_version.is_ok() {
+                    return Ok(schema_version.unwrap());
+                }
+            }
+
+            return Err(DbApiError::new(NoResult, "Schemaversion der Datenbank ist ungültig"));
+        }
+        else {
+            error!("PostgresConnection::get_schema_version: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns the uploads with a hamming distance of max_distance or less to the perceptual hash (can be empty)
+    pub async fn get_similar_uploads(&self, upload_phash: i64, max_distance: i32, excluded_upload_id: i32, max_count: i16,
+                                     show_sfw: bool, show_nsfw: bool) -> Result<UploadPrvList, DbApiError> {
+        trace!("Enter PostgresConnection::get_similar_uploads");
+
+        let sql_cmd = include_str!(get_filepath!("get_similar_uploads.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_phash, &max_distance, &excluded_upload_id, &max_count, &show_sfw, &show_nsfw];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let mut return_vec: Vec<UploadPreview> = Vec::new();
+
+            for row in result_rows.unwrap() {
+                let upload_id = row.get(0);
+                let upload_filename = row.get(1);
+                let upload_is_nsfw = row.get(2);
+                let upload_type : UploadType = row.get(3);
+                let upload_prv_variants = row.get(4);
+                let upload_has_prv_clip = row.get(5);
+                let upload_preview = UploadPreview::new(upload_id, upload_is_nsfw, upload_filename, upload_type, upload_prv_variants, upload_has_prv_clip);
+                return_vec.push(upload_preview);
+            }
+
+            return Ok(UploadPrvList{ uploads: return_vec });
+        }
+        else {
+            error!("PostgresConnection::get_similar_uploads: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_upload_data(&self, upload_id: i32) -> Result<UploadData, DbApiError> {
+        trace!("Enter PostgresConnection::get_upload_data");
+
+        let sql_cmd_upload_data = include_str!(get_filepath!("get_upload_data.sql"));
+        let sql_cmd_comment_data = include_str!(get_filepath!("get_comments_for_upload.sql"));
+        let sql_cmd_tag_data = include_str!(get_filepath!("get_tags_for_upload.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_id];
+
+        let prepared_statement : Result<(Statement, Statement, Statement), Error> = future::try_join3(
+            self.postgres_client.prepare(sql_cmd_upload_data),
+            self.postgres_client.prepare(sql_cmd_comment_data),
+            self.postgres_client.prepare(sql_cmd_tag_data)
+        ).await;
+
+        if prepared_statement.is_ok() {
+            let (sql_stm_1, sql_stm_2, sql_stm_3) = prepared_statement.unwrap();
+            let result_rows_up = self.postgres_client.query(&sql_stm_1, sql_parameters).await;
+            let result_rows_cm = self.postgres_client.query(&sql_stm_2, sql_parameters).await;
+            let result_rows_ta = self.postgres_client.query(&sql_stm_3, sql_parameters).await;
+
+            if result_rows_up.is_ok() && result_rows_cm.is_ok() && result_rows_ta.is_ok() {
+                let result_rows_up = result_rows_up.unwrap();
+                let result_rows_cm = result_rows_cm.unwrap();
+                let result_rows_ta = result_rows_ta.unwrap();
+
+                if !result_rows_up.is_empty() {
+                    let first_result_row = result_rows_up.get(0).unwrap();
+                    let upload_filename : String = first_result_row.get(0);
+                    let upload_timestamp : DateTime<Local> = first_result_row.get(1);
+                    let upload_is_nsfw : bool = first_result_row.get(2);
+                    let upload_type : UploadType = first_result_row.get(3);
+                    let uploader_id : i32 = first_result_row.get(4);
+                    let uploader_username : String = first_result_row.get(5);
+                    let upload_upvotes : i32 = first_result_row.get(6);
+                    let upload_original_filename : Option<String> = first_result_row.get(7);
+
+                    let mut upload_data = UploadData::new(upload_id, upload_is_nsfw, upload_type, upload_filename.as_str(),
+                                    upload_original_filename, uploader_id, uploader_username.as_str(), upload_timestamp, upload_upvotes);
+
+                    // Process comments
+                    for row in result_rows_cm {
+                        let comment_timestamp : DateTime<Local> = row.get(0);
+                        let comment_text : String = row.get(1);
+                        let comment_poster_id : i32 = row.get(2);
+                        let comment_poster_username : String = row.get(3);
+                        let comment_upvotes : i32 = row.get(4);
 
                         upload_data.add_comment(comment_timestamp, comment_text.as_str(),
                                                 comment_poster_id, comment_poster_username.as_str(),
@@ -328,6 +891,92 @@ impl PostgresConnection {
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
     }
 
+    // Returns an error of type NoResult if no upload with this hash exists
+    pub async fn get_upload_id_by_hash(&self, upload_hash: &str) -> Result<i32, DbApiError> {
+        trace!("Enter PostgresConnection::get_upload_id_by_hash");
+
+        let sql_cmd = include_str!(get_filepath!("get_upload_id_by_hash.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_hash];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let upload_id = first_row.get(0);
+
+                return Ok(upload_id);
+            }
+
+            return Err(DbApiError::new(NoResult, "Keine Datei mit diesem Hash vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::get_upload_id_by_hash: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns None if the hash couldn't be calculated for this upload or an error of type NoResult if the upload doesn't exist
+    pub async fn get_upload_phash(&self, upload_id: i32) -> Result<Option<i64>, DbApiError> {
+        trace!("Enter PostgresConnection::get_upload_phash");
+
+        let sql_cmd = include_str!(get_filepath!("get_upload_phash.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let upload_phash = first_row.get(0);
+
+                return Ok(upload_phash);
+            }
+
+            return Err(DbApiError::new(NoResult, "Upload ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::get_upload_phash: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_upload_status(&self, upload_id: i32) -> Result<UploadStatusData, DbApiError> {
+        trace!("Enter PostgresConnection::get_upload_status");
+
+        let sql_cmd = include_str!(get_filepath!("get_upload_status.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let upload_status : UploadStatus = first_row.get(0);
+                let upload_error : Option<String> = first_row.get(1);
+                let uploader_id : i32 = first_row.get(2);
+                let upload_phash : Option<i64> = first_row.get(3);
+
+                return Ok(UploadStatusData::new(upload_id, upload_status, upload_error, uploader_id, upload_phash));
+            }
+
+            return Err(DbApiError::new(NoResult, "Upload ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::get_upload_status: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
     pub async fn get_uploads(&self, start_id: i32, max_count: i16, show_sfw: bool, show_nsfw: bool) -> Result<UploadPrvList, DbApiError> {
         trace!("Enter PostgresConnection::get_uploads");
 
@@ -345,56 +994,265 @@ impl PostgresConnection {
                     let upload_filename = row.get(1);
                     let upload_is_nsfw = row.get(2);
                     let upload_type : UploadType = row.get(3);
-                    let upload_preview = UploadPreview::new(upload_id, upload_is_nsfw, upload_filename, upload_type);
+                    let upload_prv_variants = row.get(4);
+                    let upload_has_prv_clip = row.get(5);
+                    let upload_preview = UploadPreview::new(upload_id, upload_is_nsfw, upload_filename, upload_type, upload_prv_variants, upload_has_prv_clip);
+                    return_vec.push(upload_preview);
+                }
+            }
+            else {
+                warn!("PostgresConnection::get_uploads: No uploads found"); // Usually this should only happen if there is nothing uploaded yet
+
+                return Err(DbApiError::new(NoResult, "Keine Uploads vorhanden"));
+            }
+
+            return Ok(UploadPrvList{ uploads: return_vec });
+        }
+        else {
+            error!("PostgresConnection::get_uploads: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_uploads_range(&self, start_id: i32, end_id: i32, show_sfw: bool, show_nsfw: bool) -> Result<UploadPrvList, DbApiError> {
+        trace!("Enter PostgresConnection::get_uploads_range");
+
+        let sql_cmd = include_str!(get_filepath!("get_uploads_range.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&start_id, &end_id, &show_sfw, &show_nsfw];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows_vec = result_rows.unwrap();
+            let mut return_vec: Vec<UploadPreview> = Vec::new();
+
+            if !result_rows_vec.is_empty() {
+                for row in result_rows_vec {
+                    let upload_id = row.get(0);
+                    let upload_filename = row.get(1);
+                    let upload_is_nsfw = row.get(2);
+                    let upload_type : UploadType = row.get(3);
+                    let upload_prv_variants = row.get(4);
+                    let upload_has_prv_clip = row.get(5);
+                    let upload_preview = UploadPreview::new(upload_id, upload_is_nsfw, upload_filename, upload_type, upload_prv_variants, upload_has_prv_clip);
                     return_vec.push(upload_preview);
                 }
             }
             else {
                 warn!("PostgresConnection::get_uploads: No uploads found"); // Usually this should only happen if there is nothing uploaded yet
 
-                return Err(DbApiError::new(NoResult, "Keine Uploads vorhanden"));
+                return Err(DbApiError::new(NoResult, "Keine Uploads vorhanden"));
+            }
+
+            return Ok(UploadPrvList{ uploads: return_vec });
+        }
+        else {
+            error!("PostgresConnection::get_uploads: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_user_export(&self, user_data: &UserData) -> Result<UserDataExport, DbApiError> {
+        trace!("Enter PostgresConnection::get_user_export");
+
+        let sql_cmd_uploads = include_str!(get_filepath!("get_user_export_uploads.sql"));
+        let sql_cmd_comments = include_str!(get_filepath!("get_user_export_comments.sql"));
+        let sql_cmd_tags = include_str!(get_filepath!("get_user_export_tags.sql"));
+        let sql_cmd_votes = include_str!(get_filepath!("get_user_export_votes.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_data.user_id];
+
+        let prepared_statement : Result<(Statement, Statement, Statement, Statement), Error> = future::try_join4(
+            self.postgres_client.prepare(sql_cmd_uploads),
+            self.postgres_client.prepare(sql_cmd_comments),
+            self.postgres_client.prepare(sql_cmd_tags),
+            self.postgres_client.prepare(sql_cmd_votes)
+        ).await;
+
+        if prepared_statement.is_ok() {
+            let (sql_stm_1, sql_stm_2, sql_stm_3, sql_stm_4) = prepared_statement.unwrap();
+            let result_rows_up = self.postgres_client.query(&sql_stm_1, sql_parameters).await;
+            let result_rows_cm = self.postgres_client.query(&sql_stm_2, sql_parameters).await;
+            let result_rows_ta = self.postgres_client.query(&sql_stm_3, sql_parameters).await;
+            let result_rows_vo = self.postgres_client.query(&sql_stm_4, sql_parameters).await;
+
+            if result_rows_up.is_ok() && result_rows_cm.is_ok() && result_rows_ta.is_ok() && result_rows_vo.is_ok() {
+                let mut user_export = UserDataExport::new(user_data);
+
+                // Process uploads
+                for row in result_rows_up.unwrap() {
+                    let upload_id : i32 = row.get(0);
+                    let upload_filename : String = row.get(1);
+                    let upload_timestamp : DateTime<Local> = row.get(2);
+                    let upload_is_nsfw : bool = row.get(3);
+                    let upload_type : UploadType = row.get(4);
+                    let upload_upvotes : i32 = row.get(5);
+
+                    user_export.add_upload(upload_id, upload_filename.as_str(), upload_timestamp,
+                                           upload_is_nsfw, upload_type, upload_upvotes);
+                }
+
+                // Process comments
+                for row in result_rows_cm.unwrap() {
+                    let comment_id : i32 = row.get(0);
+                    let comment_upload : i32 = row.get(1);
+                    let comment_timestamp : DateTime<Local> = row.get(2);
+                    let comment_text : String = row.get(3);
+                    let comment_upvotes : i32 = row.get(4);
+
+                    user_export.add_comment(comment_id, comment_upload, comment_timestamp,
+                                            comment_text.as_str(), comment_upvotes);
+                }
+
+                // Process tags
+                for row in result_rows_ta.unwrap() {
+                    let tum_id : i32 = row.get(0);
+                    let upload_id : i32 = row.get(1);
+                    let tag_text : String = row.get(2);
+                    let tag_upvotes : i32 = row.get(3);
+
+                    user_export.add_tag(tum_id, upload_id, tag_text.as_str(), tag_upvotes);
+                }
+
+                // Process votes
+                for row in result_rows_vo.unwrap() {
+                    let vote_target : String = row.get(0);
+                    let vote_target_id : i32 = row.get(1);
+                    let vote_value : i32 = row.get(2);
+
+                    user_export.add_vote(vote_target.as_str(), vote_target_id, vote_value);
+                }
+
+                return Ok(user_export);
+            }
+            else {
+                error!("PostgresConnection::get_user_export: Failed to execute sql statement");
+            }
+        }
+        else {
+            error!("PostgresConnection::get_user_export: Failed to form prepared statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns show_sfw and show_nsfw
+    pub async fn get_user_filter(&self, user_id: i32) -> Result<(bool, bool), DbApiError> {
+        trace!("Enter PostgresConnection::get_user_filter");
+
+        let sql_cmd = include_str!(get_filepath!("get_user_filter.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let show_sfw : bool = first_row.get(0);
+                let show_nsfw : bool = first_row.get(1);
+
+                return Ok((show_sfw, show_nsfw));
+            }
+
+            return Err(DbApiError::new(NoResult, "Benutzer ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::get_user_filter: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_user_invites(&self, user_id: i32) -> Result<InviteList, DbApiError> {
+        trace!("Enter PostgresConnection::get_user_invites");
+
+        let sql_cmd = include_str!(get_filepath!("get_user_invites.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows_vec = result_rows.unwrap();
+            let mut invite_list = InviteList::new();
+
+            for row in result_rows_vec {
+                let invite_code : String = row.get(0);
+                let invite_created : DateTime<Local> = row.get(1);
+                let invite_expire : DateTime<Local> = row.get(2);
+                let invite_used_at : Option<DateTime<Local>> = row.get(3);
+                let invited_user_id : Option<i32> = row.get(4);
+                let invited_username : Option<String> = row.get(5);
+                let mut invite_data = InviteData::new(invite_code.as_str(), invite_created, invite_expire);
+
+                if invite_used_at.is_some() {
+                    invite_data.set_usage(invite_used_at.unwrap(), invited_user_id, invited_username);
+                }
+
+                invite_list.add_invite(invite_data);
+            }
+
+            return Ok(invite_list);
+        }
+        else {
+            error!("PostgresConnection::get_user_invites: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn get_user_totp(&self, user_id: i32) -> Result<TotpData, DbApiError> {
+        trace!("Enter PostgresConnection::get_user_totp");
+
+        let sql_cmd = include_str!(get_filepath!("get_user_totp.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let totp_secret : Option<String> = first_row.get(0);
+                let totp_enabled : bool = first_row.get(1);
+                let totp_last_step : Option<i64> = first_row.get(2);
+
+                return Ok(TotpData::new(totp_secret, totp_enabled, totp_last_step));
             }
 
-            return Ok(UploadPrvList{ uploads: return_vec });
+            return Err(DbApiError::new(NoResult, "Benutzer ist nicht vorhanden"));
         }
         else {
-            error!("PostgresConnection::get_uploads: Failed to execute sql statement");
+            error!("PostgresConnection::get_user_totp: Failed to execute sql statement");
         }
 
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
     }
 
-    pub async fn get_uploads_range(&self, start_id: i32, end_id: i32, show_sfw: bool, show_nsfw: bool) -> Result<UploadPrvList, DbApiError> {
-        trace!("Enter PostgresConnection::get_uploads_range");
+    // Returns the user id and the username of the users without username skeleton
+    pub async fn get_users_without_skeleton(&self) -> Result<Vec<(i32, String)>, DbApiError> {
+        trace!("Enter PostgresConnection::get_users_without_skeleton");
 
-        let sql_cmd = include_str!(get_filepath!("get_uploads_range.sql"));
-        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&start_id, &end_id, &show_sfw, &show_nsfw];
+        let sql_cmd = include_str!(get_filepath!("get_users_without_skeleton.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[];
         let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
 
         if result_rows.is_ok() {
             let result_rows_vec = result_rows.unwrap();
-            let mut return_vec: Vec<UploadPreview> = Vec::new();
+            let mut user_list : Vec<(i32, String)> = Vec::new();
 
-            if !result_rows_vec.is_empty() {
-                for row in result_rows_vec {
-                    let upload_id = row.get(0);
-                    let upload_filename = row.get(1);
-                    let upload_is_nsfw = row.get(2);
-                    let upload_type : UploadType = row.get(3);
-                    let upload_preview = UploadPreview::new(upload_id, upload_is_nsfw, upload_filename, upload_type);
-                    return_vec.push(upload_preview);
-                }
-            }
-            else {
-                warn!("PostgresConnection::get_uploads: No uploads found"); // Usually this should only happen if there is nothing uploaded yet
+            for row in result_rows_vec {
+                let user_id : i32 = row.get(0);
+                let username : String = row.get(1);
 
-                return Err(DbApiError::new(NoResult, "Keine Uploads vorhanden"));
+                user_list.push((user_id, username));
             }
 
-            return Ok(UploadPrvList{ uploads: return_vec });
+            return Ok(user_list);
         }
         else {
-            error!("PostgresConnection::get_uploads: Failed to execute sql statement");
+            error!("PostgresConnection::get_users_without_skeleton: Failed to execute sql statement");
         }
 
         return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
@@ -416,7 +1274,8 @@ impl PostgresConnection {
                 let user_name = row.get(1);
                 let user_pass = row.get(2);
                 let user_is_mod = row.get(3);
-                let user_data = UserData::new(user_id, user_name, user_pass, user_is_mod);
+                let user_is_admin = row.get(4);
+                let user_data = UserData::new(user_id, user_name, user_pass, user_is_mod, user_is_admin);
 
                 return Ok(user_data);
             }
@@ -449,7 +1308,8 @@ impl PostgresConnection {
                 let user_name = row.get(1);
                 let user_pass = row.get(2);
                 let user_is_mod = row.get(3);
-                let user_data = UserData::new(user_id, user_name, user_pass, user_is_mod);
+                let user_is_admin = row.get(4);
+                let user_data = UserData::new(user_id, user_name, user_pass, user_is_mod, user_is_admin);
 
                 return Ok(user_data);
             }
@@ -548,6 +1408,347 @@ impl PostgresConnection {
         return None;
     }
 
+    // Returns an error of type NoResult if the token doesn't exist or belongs to another user
+    pub async fn remove_api_token(&self, token_id: i32, user_id: i32) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::remove_api_token");
+
+        let sql_cmd = include_str!(get_filepath!("remove_api_token.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&token_id, &user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "API Token existiert nicht"));
+        }
+        else {
+            error!("PostgresConnection::remove_api_token: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn remove_user_bans(&self, user_id: i32) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::remove_user_bans");
+
+        let sql_cmd = include_str!(get_filepath!("remove_user_bans.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id];
+        let result_rows = self.postgres_client.execute(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            return Ok(());
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns the number of uploads which were put back into the queue
+    pub async fn requeue_processing_uploads(&self) -> Result<usize, DbApiError> {
+        trace!("Enter PostgresConnection::requeue_processing_uploads");
+
+        let sql_cmd = include_str!(get_filepath!("requeue_processing_uploads.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            return Ok(result_rows.unwrap().len());
+        }
+        else {
+            error!("PostgresConnection::requeue_processing_uploads: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Changes the password and removes all api tokens of the user
+    pub async fn reset_user_pw(&self, user_id: i32, pw_hash: &str) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::reset_user_pw");
+
+        let sql_cmd = include_str!(get_filepath!("reset_user_pw.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&pw_hash, &user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Benutzer ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::reset_user_pw: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns an error of type NoResult if the upload was deleted in the meantime
+    pub async fn set_upload_failed(&self, upload_id: i32, upload_error: &str) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::set_upload_failed");
+
+        let sql_cmd = include_str!(get_filepath!("set_upload_failed.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_id, &upload_error];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            if !result_rows.unwrap().is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Upload ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::set_upload_failed: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns an error of type NoResult if the upload was deleted in the meantime
+    pub async fn set_upload_prv_variants(&self, upload_id: i32, upload_prv_variants: &str) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::set_upload_prv_variants");
+
+        let sql_cmd = include_str!(get_filepath!("set_upload_prv_variants.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_id, &upload_prv_variants];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            if !result_rows.unwrap().is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Upload ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::set_upload_prv_variants: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn set_upload_ready(&self, upload_id: i32, upload_filename: &str, upload_type: UploadType, upload_phash: Option<i64>,
+                                  upload_prv_variants: &str, upload_has_prv_clip: bool) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::set_upload_ready");
+
+        let sql_cmd = include_str!(get_filepath!("set_upload_ready.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&upload_id, &upload_filename, &upload_type, &upload_phash, &upload_prv_variants, &upload_has_prv_clip];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            if !result_rows.unwrap().is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Upload ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::set_upload_ready: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn set_user_filter(&self, user_id: i32, show_sfw: bool, show_nsfw: bool) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::set_user_filter");
+
+        let sql_cmd = include_str!(get_filepath!("set_user_filter.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, &show_sfw, &show_nsfw];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Benutzer ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::set_user_filter: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns an error of type NoResult if another user already has the same skeleton
+    pub async fn set_user_name_skeleton(&self, user_id: i32, username_skeleton: &str) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::set_user_name_skeleton");
+
+        let sql_cmd = include_str!(get_filepath!("set_user_name_skeleton.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, &username_skeleton];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Ein anderer Benutzer hat einen zu ähnlichen Benutzernamen"));
+        }
+        else {
+            error!("PostgresConnection::set_user_name_skeleton: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    pub async fn set_user_role(&self, user_id: i32, user_role: UserRole) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::set_user_role");
+
+        let sql_cmd = include_str!(get_filepath!("set_user_role.sql"));
+        let (user_is_mod, user_is_admin) = user_role.as_db_flags();
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_is_mod, &user_is_admin, &user_id];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Benutzer ist nicht vorhanden"));
+        }
+        else {
+            error!("PostgresConnection::set_user_role: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns an error of type NoResult if 2FA is already enabled
+    pub async fn set_user_totp_secret(&self, user_id: i32, totp_secret: &str) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::set_user_totp_secret");
+
+        let sql_cmd = include_str!(get_filepath!("set_user_totp_secret.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, &totp_secret];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "2FA ist bereits aktiviert"));
+        }
+        else {
+            error!("PostgresConnection::set_user_totp_secret: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Brings the tables of an existing installation to the current schema version (see create_pg_tables for new installations)
+    pub async fn upgrade_pg_tables(&self) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::upgrade_pg_tables");
+
+        let sql_cmd = include_str!(get_filepath!("upgrade_tables.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[];
+        let result_rows = self.postgres_client.execute(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            return Ok(());
+        }
+
+        error!("PostgresConnection::upgrade_pg_tables: Failed to execute sql statement: {}", result_rows.err().unwrap());
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns an error of type NoResult if the token is unknown
+    pub async fn use_api_token(&self, token_hash: &str) -> Result<ApiTokenData, DbApiError> {
+        trace!("Enter PostgresConnection::use_api_token");
+
+        let sql_cmd = include_str!(get_filepath!("use_api_token.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&token_hash];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+            let first_row = result_rows.get(0);
+
+            if first_row.is_some() {
+                let first_row = first_row.unwrap();
+                let token_id : i32 = first_row.get(0);
+                let token_user : i32 = first_row.get(1);
+                let token_name : String = first_row.get(2);
+                let token_scopes : Vec<String> = first_row.get(3);
+                let token_created : DateTime<Local> = first_row.get(4);
+                let token_last_used : Option<DateTime<Local>> = first_row.get(5);
+                let token_data = ApiTokenData::new(token_id, token_user, token_name, &token_scopes,
+                                                   token_created, token_last_used);
+
+                return Ok(token_data);
+            }
+
+            return Err(DbApiError::new(NoResult, "API Token ist ungültig"));
+        }
+        else {
+            error!("PostgresConnection::use_api_token: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns an error of type NoResult if the recovery code is unknown or already used
+    pub async fn use_recovery_code(&self, user_id: i32, recovery_code_hash: &str) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::use_recovery_code");
+
+        let sql_cmd = include_str!(get_filepath!("use_recovery_code.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, &recovery_code_hash];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Wiederherstellungscode ist ungültig"));
+        }
+        else {
+            error!("PostgresConnection::use_recovery_code: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
+    // Returns an error of type NoResult if the time step was already used
+    pub async fn use_totp_time_step(&self, user_id: i32, time_step: i64) -> Result<(), DbApiError> {
+        trace!("Enter PostgresConnection::use_totp_time_step");
+
+        let sql_cmd = include_str!(get_filepath!("use_totp_time_step.sql"));
+        let sql_parameters : &[&(dyn ToSql + Sync)] = &[&user_id, &time_step];
+        let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
+
+        if result_rows.is_ok() {
+            let result_rows = result_rows.unwrap();
+
+            if !result_rows.is_empty() {
+                return Ok(());
+            }
+
+            return Err(DbApiError::new(NoResult, "Der 2FA Code wurde bereits verwendet"));
+        }
+        else {
+            error!("PostgresConnection::use_totp_time_step: Failed to execute sql statement");
+        }
+
+        return Err(DbApiError::new(QueryError, "Fehler beim Ausführen der SQL Anweisung"));
+    }
+
     pub async fn vote_comment(&self, comment_id: i32, user_id: i32, vote_value: i32) -> Result<(), DbApiError> {
         trace!("Enter PostgresConnection::vote_comment");
 
@@ -556,6 +1757,10 @@ impl PostgresConnection {
         let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
 
         if result_rows.is_ok() {
+            if result_rows.unwrap().is_empty() {
+                return Err(DbApiError::new(NoResult, "Kommentar ist nicht vorhanden"));
+            }
+
             return Ok(());
         }
         else {
@@ -571,6 +1776,10 @@ impl PostgresConnection {
         let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
 
         if result_rows.is_ok() {
+            if result_rows.unwrap().is_empty() {
+                return Err(DbApiError::new(NoResult, "Tag ist nicht vorhanden"));
+            }
+
             return Ok(());
         }
         else {
@@ -586,6 +1795,10 @@ impl PostgresConnection {
         let result_rows = self.postgres_client.query(sql_cmd, sql_parameters).await;
 
         if result_rows.is_ok() {
+            if result_rows.unwrap().is_empty() {
+                return Err(DbApiError::new(NoResult, "Upload ist nicht vorhanden"));
+            }
+
             return Ok(());
         }
         else {
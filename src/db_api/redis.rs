@@ -1,10 +1,11 @@
-use redis::{ConnectionInfo, ConnectionAddr, RedisResult, ErrorKind};
+use redis::{ConnectionInfo, ConnectionAddr, RedisResult, ErrorKind, AsyncCommands};
 use redis::aio::{MultiplexedConnection};
 use crate::config::ProjectConfig;
 use crate::config::ConnectionMethod::Tcp;
 use std::path::PathBuf;
-use crate::db_api::db_result::{SessionData, SessionError, SessionErrorType};
+use crate::db_api::db_result::{PendingLoginData, SessionData, SessionError, SessionErrorType, SessionInfo, SessionInfoList, UploadSessionData};
 use chrono::{Local, Duration};
+use std::collections::HashMap;
 use rand::{thread_rng, Rng};
 use rand::distributions::Alphanumeric;
 use crate::db_api::db_result::SessionErrorType::{DbError, SessionInvalid};
@@ -14,6 +15,11 @@ use actix_session::Session;
 const LTS_DURATION : u32 = 24 * 30; // Long time session are valid for 30 days without activity
 const TTL_BUFFER: u8 = 30; // If the value ttl is smaller than this value, the session counts as expired
 const STS_DURATION : u32 = 24; // Short time sessions are valid for 1 day without activity
+const SESSION_INDEX_DURATION : usize = (LTS_DURATION as usize * 3600) + TTL_BUFFER as usize; // The session index of a user have to live as long as the longest session
+const USER_AGENT_MAX_LENGTH : usize = 256;
+const PENDING_LOGIN_DURATION : usize = 300; // The second login step (2FA) have to be done within 5 minutes
+const UPLOAD_SESSION_DURATION : usize = 24 * 3600; // Unfinished chunked uploads can be resumed for 1 day after the last received chunk
+const UPLOAD_SESSION_LOCK_DURATION : usize = 600; // The lock of an upload session is released after 10 minutes, if the request which holds it got lost
 
 pub struct RedisConnection {
     redis_connection: MultiplexedConnection,
@@ -56,7 +62,86 @@ impl RedisConnection {
         return Err(SessionError::new(SessionErrorType::UnknownError, "Unbekannter Fehler"));
     }
 
-    pub async fn create_session(&self, user_id: i32, is_lts: bool) -> Result<SessionData, SessionError> {
+    // Returns the user id of the password reset token and removes the token (every token can only be used once)
+    pub async fn consume_password_reset(&self, reset_token: &str) -> Result<i32, SessionError> {
+        trace!("Enter RedisConnection::consume_password_reset");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_userid = format!("password_resets.{}.user_id", reset_token);
+
+        let query_result = redis::pipe().atomic()
+            .get(redis_key_userid.as_str())
+            .del(redis_key_userid.as_str()).ignore()
+            .query_async::<MultiplexedConnection, (Option<i32>,)>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            let (user_id,) = query_result.unwrap();
+
+            if user_id.is_some() {
+                return Ok(user_id.unwrap());
+            }
+
+            return Err(SessionError::new(SessionInvalid, "Der Link zum Zurücksetzen des Passwortes ist ungültig oder abgelaufen"));
+        }
+
+        error!("RedisConnection::consume_password_reset: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    // Stores a one-time password reset token for the user and returns the token
+    pub async fn create_password_reset(&self, user_id: i32, reset_lifetime: u32) -> Result<String, SessionError> {
+        trace!("Enter RedisConnection::create_password_reset");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let reset_token : String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .collect();
+
+        let redis_key_userid = format!("password_resets.{}.user_id", reset_token);
+        let reset_lifetime = reset_lifetime as usize * 3600;
+        let query_result : RedisResult<()> = redis_connection.set_ex(redis_key_userid, user_id, reset_lifetime).await;
+
+        if query_result.is_ok() {
+            return Ok(reset_token);
+        }
+
+        error!("RedisConnection::create_password_reset: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    // Stores a login which waits for the 2FA code and returns the id of the pending login
+    pub async fn create_pending_login(&self, user_id: i32, is_lts: bool) -> Result<String, SessionError> {
+        trace!("Enter RedisConnection::create_pending_login");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let pending_login_id : String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .collect();
+
+        let redis_key_userid = format!("pending_logins.{}.user_id", pending_login_id);
+        let redis_key_lts = format!("pending_logins.{}.lts", pending_login_id);
+
+        let query_result = redis::pipe().atomic()
+            .set_ex(redis_key_userid, user_id, PENDING_LOGIN_DURATION)
+            .set_ex(redis_key_lts, is_lts, PENDING_LOGIN_DURATION)
+            .query_async::<MultiplexedConnection, ((), ())>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            return Ok(pending_login_id);
+        }
+
+        error!("RedisConnection::create_pending_login: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    pub async fn create_session(&self, user_id: i32, is_lts: bool, client_ip: &str, user_agent: &str) -> Result<SessionData, SessionError> {
         trace!("Enter RedisConnection::create_session");
 
         let mut redis_connection = self.redis_connection.clone();
@@ -88,7 +173,23 @@ impl RedisConnection {
         if rand_session_id != "" {
             let redis_key_userid = format!("sessions.{}.user_id", rand_session_id);
             let redis_key_lts = format!("sessions.{}.lts", rand_session_id); // Is long time session (aka keep logged in)
+            let redis_key_info = format!("sessions.{}.info", rand_session_id); // Creation time, ip and user agent
+            let redis_key_index = format!("user_sessions.{}", user_id); // Set of all session ids of the user
             let current_time = Local::now();
+            let user_agent : String = user_agent.chars().take(USER_AGENT_MAX_LENGTH).collect();
+
+            // Public id of the session (the session id itself must not be exposed to the frontend)
+            let public_id : String = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(16)
+                .collect();
+
+            let session_info = [
+                ("public_id", public_id),
+                ("created", current_time.to_rfc3339()),
+                ("ip", client_ip.to_owned()),
+                ("user_agent", user_agent),
+            ];
 
             let expire_time = if is_lts {
                 current_time + Duration::hours(LTS_DURATION as i64)
@@ -102,7 +203,11 @@ impl RedisConnection {
             let query_result = redis::pipe().atomic()
                 .set_ex(redis_key_userid, user_id, new_ttl)
                 .set_ex(redis_key_lts, is_lts, new_ttl)
-                .query_async::<MultiplexedConnection, ((), ())>(&mut redis_connection)
+                .hset_multiple(redis_key_info.as_str(), &session_info)
+                .expire(redis_key_info.as_str(), new_ttl)
+                .sadd(redis_key_index.as_str(), rand_session_id.as_str())
+                .expire(redis_key_index.as_str(), SESSION_INDEX_DURATION)
+                .query_async::<MultiplexedConnection, ((), (), (), (), (), ())>(&mut redis_connection)
                 .await;
 
             if query_result.is_ok() {
@@ -121,16 +226,113 @@ impl RedisConnection {
         return Err(SessionError::new(DbError, "Erstellen der Redis Einträge fehlgeschlagen"));
     }
 
+    // Stores the data of a new chunked upload and returns the id of the upload session
+    pub async fn create_upload_session(&self, upload_session_data: &UploadSessionData) -> Result<String, SessionError> {
+        trace!("Enter RedisConnection::create_upload_session");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let upload_session_id : String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .collect();
+
+        let redis_key_upload = format!("upload_sessions.{}", upload_session_id);
+        let upload_session_fields = [
+            ("user_id", upload_session_data.user_id.to_string()),
+            ("storage_filename", upload_session_data.storage_filename.clone()),
+            ("original_filename", upload_session_data.original_filename.clone()),
+            ("upload_size", upload_session_data.upload_size.to_string()),
+            ("upload_offset", upload_session_data.upload_offset.to_string()),
+            ("upload_is_nsfw", upload_session_data.upload_is_nsfw.to_string()),
+            ("upload_taglist", upload_session_data.upload_taglist.clone()),
+        ];
+
+        let query_result = redis::pipe().atomic()
+            .hset_multiple(redis_key_upload.as_str(), &upload_session_fields)
+            .expire(redis_key_upload.as_str(), UPLOAD_SESSION_DURATION)
+            .query_async::<MultiplexedConnection, ((), ())>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            return Ok(upload_session_id);
+        }
+
+        error!("RedisConnection::create_upload_session: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    pub async fn destroy_pending_login(&self, pending_login_id: &str) -> Result<(), SessionError> {
+        trace!("Enter RedisConnection::destroy_pending_login");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_userid = format!("pending_logins.{}.user_id", pending_login_id);
+        let redis_key_lts = format!("pending_logins.{}.lts", pending_login_id);
+        let redis_key_attempts = format!("pending_logins.{}.attempts", pending_login_id);
+
+        let query_result = redis::pipe().atomic()
+            .del(redis_key_userid)
+            .del(redis_key_lts)
+            .del(redis_key_attempts)
+            .query_async::<MultiplexedConnection, (i32, i32, i32)>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            return Ok(());
+        }
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
     pub async fn destroy_session(&self, session_id: &str) -> Result<(), SessionError> {
         trace!("Enter RedisConnection::destroy_session");
 
         let mut redis_connection = self.redis_connection.clone();
         let redis_key_userid = format!("sessions.{}.user_id", session_id);
         let redis_key_lts = format!("sessions.{}.lts", session_id); // Is long time session (aka keep logged in)
+        let redis_key_info = format!("sessions.{}.info", session_id);
+
+        // The user id is needed to remove the session from the session index of the user
+        let session_user_id : RedisResult<Option<i32>> = redis_connection.get(redis_key_userid.as_str()).await;
+
+        if session_user_id.is_err() {
+            return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+        }
+
+        let session_user_id = session_user_id.unwrap();
+        let mut redis_pipe = redis::pipe();
+
+        redis_pipe.atomic()
+            .del(redis_key_userid).ignore()
+            .del(redis_key_lts).ignore()
+            .del(redis_key_info).ignore();
+
+        if session_user_id.is_some() {
+            let redis_key_index = format!("user_sessions.{}", session_user_id.unwrap());
+            redis_pipe.srem(redis_key_index, session_id).ignore();
+        }
+
+        let query_result = redis_pipe
+            .query_async::<MultiplexedConnection, ()>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            return Ok(());
+        }
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    pub async fn destroy_upload_session(&self, upload_session_id: &str) -> Result<(), SessionError> {
+        trace!("Enter RedisConnection::destroy_upload_session");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_upload = format!("upload_sessions.{}", upload_session_id);
+        let redis_key_lock = format!("upload_sessions.{}.lock", upload_session_id);
 
         let query_result = redis::pipe().atomic()
-            .del(redis_key_userid)
-            .del(redis_key_lts)
+            .del(redis_key_upload)
+            .del(redis_key_lock)
             .query_async::<MultiplexedConnection, (i32, i32)>(&mut redis_connection)
             .await;
 
@@ -141,6 +343,85 @@ impl RedisConnection {
         return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
     }
 
+    // Destroys all sessions of the given user (for example if the user gets banned)
+    // The session keep_session_id (if given) stays active
+    pub async fn destroy_user_sessions(&self, user_id: i32, keep_session_id: Option<&str>) -> Result<(), SessionError> {
+        trace!("Enter RedisConnection::destroy_user_sessions");
+
+        let session_ids = self.get_user_session_ids(user_id).await;
+
+        if session_ids.is_err() {
+            return Err(session_ids.err().unwrap());
+        }
+
+        for session_id in session_ids.ok().unwrap() {
+            if keep_session_id.is_some() && keep_session_id.unwrap() == session_id.as_str() {
+                continue;
+            }
+
+            let destroy_result = self.destroy_session(session_id.as_str()).await;
+
+            if destroy_result.is_err() {
+                return destroy_result;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Returns the user id of the password reset token without using the token
+    pub async fn get_password_reset(&self, reset_token: &str) -> Result<i32, SessionError> {
+        trace!("Enter RedisConnection::get_password_reset");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_userid = format!("password_resets.{}.user_id", reset_token);
+        let query_result : RedisResult<Option<i32>> = redis_connection.get(redis_key_userid.as_str()).await;
+
+        if query_result.is_ok() {
+            let user_id = query_result.unwrap();
+
+            if user_id.is_some() {
+                return Ok(user_id.unwrap());
+            }
+
+            return Err(SessionError::new(SessionInvalid, "Der Link zum Zurücksetzen des Passwortes ist ungültig oder abgelaufen"));
+        }
+
+        error!("RedisConnection::get_password_reset: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    pub async fn get_pending_login(&self, pending_login_id: &str) -> Result<PendingLoginData, SessionError> {
+        trace!("Enter RedisConnection::get_pending_login");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_userid = format!("pending_logins.{}.user_id", pending_login_id);
+        let redis_key_lts = format!("pending_logins.{}.lts", pending_login_id);
+
+        let query_result = redis::pipe().atomic()
+            .get(redis_key_userid.as_str())
+            .get(redis_key_lts.as_str())
+            .query_async::<MultiplexedConnection, (Option<i32>, Option<bool>)>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            let (user_id, is_lts) = query_result.unwrap();
+
+            if user_id.is_some() && is_lts.is_some() {
+                return Ok(PendingLoginData::new(user_id.unwrap(), is_lts.unwrap()));
+            }
+
+            info!("RedisConnection::get_pending_login: Expired or invalid pending login");
+
+            return Err(SessionError::new(SessionInvalid, "Anmeldung ist abgelaufen, bitte erneut einloggen"));
+        }
+
+        error!("RedisConnection::get_pending_login: Failed to execute Redis query");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
     pub async fn get_session_data(&self, session_id: &str) -> Result<SessionData, SessionError> {
         trace!("Enter RedisConnection::get_session_data");
 
@@ -187,6 +468,212 @@ impl RedisConnection {
         }
     }
 
+    // Returns the remaining lockout time (in seconds) of the given rate limit counter (0 = not locked)
+    pub async fn get_rate_limit_lock(&self, counter_name: &str) -> Result<u32, SessionError> {
+        trace!("Enter RedisConnection::get_rate_limit_lock");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_lock = format!("ratelimit.{}.lock", counter_name);
+        let query_result : RedisResult<i32> = redis_connection.ttl(redis_key_lock.as_str()).await;
+
+        if query_result.is_ok() {
+            // ttl returns -2 if the key doesn't exist and -1 if the key has no expire time
+            let lock_ttl = query_result.unwrap().max(0);
+
+            return Ok(lock_ttl as u32);
+        }
+
+        error!("RedisConnection::get_rate_limit_lock: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    pub async fn get_upload_session(&self, upload_session_id: &str) -> Result<UploadSessionData, SessionError> {
+        trace!("Enter RedisConnection::get_upload_session");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_upload = format!("upload_sessions.{}", upload_session_id);
+        let query_result : RedisResult<HashMap<String, String>> = redis_connection.hgetall(redis_key_upload.as_str()).await;
+
+        if query_result.is_err() {
+            error!("RedisConnection::get_upload_session: Failed to execute Redis command");
+
+            return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+        }
+
+        // hgetall returns an empty map if the upload session doesn't exist (anymore)
+        let upload_session_fields = query_result.unwrap();
+        let get_field = |field_name: &str| upload_session_fields.get(field_name).cloned().unwrap_or_default();
+
+        let user_id = get_field("user_id").parse::<i32>();
+        let upload_size = get_field("upload_size").parse::<u64>();
+        let upload_offset = get_field("upload_offset").parse::<u64>();
+        let upload_is_nsfw = get_field("upload_is_nsfw").parse::<bool>();
+
+        if user_id.is_ok() && upload_size.is_ok() && upload_offset.is_ok() && upload_is_nsfw.is_ok() {
+            let mut upload_session_data = UploadSessionData::new(user_id.unwrap(), get_field("storage_filename").as_str(),
+                                                                 get_field("original_filename").as_str(), upload_size.unwrap(),
+                                                                 upload_is_nsfw.unwrap(), get_field("upload_taglist").as_str());
+            upload_session_data.upload_session_id = upload_session_id.to_owned();
+            upload_session_data.upload_offset = upload_offset.unwrap();
+
+            return Ok(upload_session_data);
+        }
+
+        info!("RedisConnection::get_upload_session: Expired or invalid upload session");
+
+        return Err(SessionError::new(SessionInvalid, "Der Upload ist abgelaufen oder existiert nicht"));
+    }
+
+    async fn get_user_session_ids(&self, user_id: i32) -> Result<Vec<String>, SessionError> {
+        trace!("Enter RedisConnection::get_user_session_ids");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_index = format!("user_sessions.{}", user_id);
+        let query_result : RedisResult<Vec<String>> = redis_connection.smembers(redis_key_index.as_str()).await;
+
+        if query_result.is_ok() {
+            return Ok(query_result.unwrap());
+        }
+
+        error!("RedisConnection::get_user_session_ids: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    // Returns all active sessions of the user (expired sessions will be removed from the session index)
+    pub async fn get_user_sessions(&self, user_id: i32, current_session_id: &str) -> Result<SessionInfoList, SessionError> {
+        trace!("Enter RedisConnection::get_user_sessions");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_index = format!("user_sessions.{}", user_id);
+        let session_ids = self.get_user_session_ids(user_id).await;
+        let mut session_list = SessionInfoList::new();
+
+        if session_ids.is_err() {
+            return Err(session_ids.err().unwrap());
+        }
+
+        for session_id in session_ids.ok().unwrap() {
+            let session_data = self.get_session_data(session_id.as_str()).await;
+
+            if session_data.is_err() {
+                let session_error = session_data.err().unwrap();
+
+                if session_error.error_type == DbError {
+                    return Err(session_error);
+                }
+
+                let remove_result : RedisResult<()> = redis_connection.srem(redis_key_index.as_str(), session_id.as_str()).await;
+
+                if remove_result.is_err() {
+                    warn!("RedisConnection::get_user_sessions: Failed to remove expired session from the session index");
+                }
+
+                continue;
+            }
+
+            let session_data = session_data.ok().unwrap();
+            let redis_key_info = format!("sessions.{}.info", session_id);
+            let query_result : RedisResult<HashMap<String, String>> = redis_connection.hgetall(redis_key_info.as_str()).await;
+
+            if query_result.is_err() {
+                error!("RedisConnection::get_user_sessions: Failed to execute Redis command");
+
+                return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+            }
+
+            let session_info_map = query_result.unwrap();
+            let is_current = session_id == current_session_id;
+            let session_info = SessionInfo::new(&session_data, &session_info_map, is_current);
+
+            session_list.add_session(session_info);
+        }
+
+        return Ok(session_list);
+    }
+
+    // Adds all existing sessions to the session index of their user (sessions created before the index existed aren't in it)
+    pub async fn index_user_sessions(&self) -> Result<usize, SessionError> {
+        trace!("Enter RedisConnection::index_user_sessions");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let mut redis_keys_userid : Vec<String> = Vec::new();
+        let scan_result : RedisResult<redis::AsyncIter<String>> = redis_connection.scan_match("sessions.*.user_id").await;
+
+        if scan_result.is_err() {
+            error!("RedisConnection::index_user_sessions: Failed to execute Redis command");
+
+            return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+        }
+
+        let mut scan_iter = scan_result.unwrap();
+
+        while let Some(redis_key_userid) = scan_iter.next_item().await {
+            redis_keys_userid.push(redis_key_userid);
+        }
+
+        let mut indexed_sessions : usize = 0;
+
+        for redis_key_userid in redis_keys_userid {
+            let session_id = redis_key_userid.trim_start_matches("sessions.").trim_end_matches(".user_id");
+            let user_id : RedisResult<Option<i32>> = redis_connection.get(redis_key_userid.as_str()).await;
+
+            // The session may have expired in the meantime
+            if user_id.is_err() || user_id.as_ref().unwrap().is_none() {
+                continue;
+            }
+
+            let redis_key_index = format!("user_sessions.{}", user_id.unwrap().unwrap());
+
+            let query_result = redis::pipe().atomic()
+                .sadd(redis_key_index.as_str(), session_id)
+                .expire(redis_key_index.as_str(), SESSION_INDEX_DURATION)
+                .query_async::<MultiplexedConnection, ((), ())>(&mut redis_connection)
+                .await;
+
+            if query_result.is_err() {
+                error!("RedisConnection::index_user_sessions: Failed to execute Redis command");
+
+                return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+            }
+
+            indexed_sessions += 1;
+        }
+
+        return Ok(indexed_sessions);
+    }
+
+    // Only one request can write to the tmp file of an upload session at the same time
+    // Returns the token which is needed to release the lock or None if the upload session is already locked
+    pub async fn lock_upload_session(&self, upload_session_id: &str) -> Result<Option<String>, SessionError> {
+        trace!("Enter RedisConnection::lock_upload_session");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_lock = format!("upload_sessions.{}.lock", upload_session_id);
+        let lock_token : String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .collect();
+
+        let query_result : RedisResult<Option<String>> = redis::cmd("SET")
+            .arg(redis_key_lock.as_str())
+            .arg(lock_token.as_str())
+            .arg("NX")
+            .arg("EX")
+            .arg(UPLOAD_SESSION_LOCK_DURATION)
+            .query_async(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            return Ok(query_result.unwrap().map(|_| lock_token));
+        }
+
+        error!("RedisConnection::lock_upload_session: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
     pub async fn new(project_config: &ProjectConfig) -> Option<RedisConnection> {
         trace!("Enter RedisConnection::new");
 
@@ -238,12 +725,107 @@ impl RedisConnection {
         return None;
     }
 
+    // Counts a failed 2FA attempt of the pending login and returns the number of failed attempts
+    pub async fn register_pending_login_attempt(&self, pending_login_id: &str) -> Result<u32, SessionError> {
+        trace!("Enter RedisConnection::register_pending_login_attempt");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_attempts = format!("pending_logins.{}.attempts", pending_login_id);
+
+        let query_result = redis::pipe().atomic()
+            .incr(redis_key_attempts.as_str(), 1)
+            .expire(redis_key_attempts.as_str(), PENDING_LOGIN_DURATION)
+            .query_async::<MultiplexedConnection, (u32, ())>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            return Ok(query_result.unwrap().0);
+        }
+
+        error!("RedisConnection::register_pending_login_attempt: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    // Counts a (failed) attempt within the attempt window and locks the counter if there were too many attempts
+    // Every further attempt doubles the lockout time (up to lockout_max seconds)
+    // Returns the lockout time in seconds (0 = not locked)
+    pub async fn register_rate_limit_attempt(&self, counter_name: &str, max_attempts: u32, attempt_window: u32,
+                                             lockout_base: u32, lockout_max: u32) -> Result<u32, SessionError> {
+        trace!("Enter RedisConnection::register_rate_limit_attempt");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_attempts = format!("ratelimit.{}.attempts", counter_name);
+        let redis_key_lock = format!("ratelimit.{}.lock", counter_name);
+
+        // The attempt window starts with the first attempt (the counter is only created with its expire time, INCR keeps it)
+        let query_result = redis::pipe().atomic()
+            .cmd("SET").arg(redis_key_attempts.as_str()).arg(0).arg("EX").arg(attempt_window.max(1) as usize).arg("NX").ignore()
+            .incr(redis_key_attempts.as_str(), 1)
+            .query_async::<MultiplexedConnection, (u32,)>(&mut redis_connection)
+            .await;
+
+        if query_result.is_err() {
+            error!("RedisConnection::register_rate_limit_attempt: Failed to execute Redis command");
+
+            return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+        }
+
+        let (attempt_count,) = query_result.unwrap();
+
+        if attempt_count < max_attempts {
+            return Ok(0);
+        }
+
+        let lockout_exponent = (attempt_count - max_attempts).min(16);
+        let lockout_duration = lockout_base.saturating_mul(1 << lockout_exponent).min(lockout_max).max(1);
+
+        // Keep the attempt counter alive as long as the lock
+        let query_result = redis::pipe().atomic()
+            .set_ex(redis_key_lock.as_str(), attempt_count, lockout_duration as usize)
+            .expire(redis_key_attempts.as_str(), attempt_window.max(lockout_duration) as usize)
+            .query_async::<MultiplexedConnection, ((), ())>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            info!("RedisConnection::register_rate_limit_attempt: Locked {} for {} seconds", counter_name, lockout_duration);
+
+            return Ok(lockout_duration);
+        }
+
+        error!("RedisConnection::register_rate_limit_attempt: Failed to create lock entry");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    pub async fn reset_rate_limit(&self, counter_name: &str) -> Result<(), SessionError> {
+        trace!("Enter RedisConnection::reset_rate_limit");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_attempts = format!("ratelimit.{}.attempts", counter_name);
+        let redis_key_lock = format!("ratelimit.{}.lock", counter_name);
+
+        let query_result = redis::pipe().atomic()
+            .del(redis_key_attempts)
+            .del(redis_key_lock)
+            .query_async::<MultiplexedConnection, (i32, i32)>(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            return Ok(());
+        }
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
     pub async fn renew_session(&self, session: &Session, session_data: &SessionData, force_session_renew: bool) -> bool {
         trace!("Enter RedisConnection::renew_session");
 
         let mut redis_connection = self.redis_connection.clone();
         let redis_key_userid = format!("sessions.{}.user_id", session_data.session_id);
         let redis_key_lts = format!("sessions.{}.lts", session_data.session_id); // Is long time session (aka keep logged in)
+        let redis_key_info = format!("sessions.{}.info", session_data.session_id);
+        let redis_key_index = format!("user_sessions.{}", session_data.user_id);
 
         // Session reload break point
         let lts_break_point : u32 = LTS_DURATION / 2; // If the session have only 15 days remaining, the session will be renewed
@@ -282,10 +864,14 @@ impl RedisConnection {
 
             let new_ttl = new_expire_time.signed_duration_since(current_time).num_seconds().abs() as usize;
 
+            // The session index is extended too, otherwise it could expire before the renewed session
             let query_result = redis::pipe().atomic()
                 .expire(redis_key_userid, new_ttl)
                 .expire(redis_key_lts, new_ttl)
-                .query_async::<MultiplexedConnection, ((),())>(&mut redis_connection)
+                .expire(redis_key_info, new_ttl)
+                .sadd(redis_key_index.as_str(), session_data.session_id.as_str())
+                .expire(redis_key_index.as_str(), SESSION_INDEX_DURATION)
+                .query_async::<MultiplexedConnection, ((),(),(),(),())>(&mut redis_connection)
                 .await;
 
             if query_result.is_ok() {
@@ -305,4 +891,67 @@ impl RedisConnection {
 
         return true;
     }
+
+    // Stores the number of received bytes, every received chunk extends the lifetime of the upload session
+    // The offset isn't written if the upload session expired in the meantime (HSET would create an incomplete session)
+    pub async fn set_upload_session_offset(&self, upload_session_id: &str, upload_offset: u64) -> Result<(), SessionError> {
+        trace!("Enter RedisConnection::set_upload_session_offset");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_upload = format!("upload_sessions.{}", upload_session_id);
+        let offset_script = redis::Script::new(r"
+            if redis.call('EXISTS', KEYS[1]) == 1 then
+                redis.call('HSET', KEYS[1], 'upload_offset', ARGV[1])
+                return redis.call('EXPIRE', KEYS[1], ARGV[2])
+            end
+            return 0
+        ");
+
+        let query_result : RedisResult<i32> = offset_script
+            .key(redis_key_upload.as_str())
+            .arg(upload_offset)
+            .arg(UPLOAD_SESSION_DURATION)
+            .invoke_async(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            if query_result.unwrap() == 1 {
+                return Ok(());
+            }
+
+            return Err(SessionError::new(SessionInvalid, "Der Upload ist abgelaufen oder existiert nicht"));
+        }
+
+        error!("RedisConnection::set_upload_session_offset: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
+
+    // The lock is only released if it still belongs to the caller (it could have expired and been taken by another request)
+    pub async fn unlock_upload_session(&self, upload_session_id: &str, lock_token: &str) -> Result<(), SessionError> {
+        trace!("Enter RedisConnection::unlock_upload_session");
+
+        let mut redis_connection = self.redis_connection.clone();
+        let redis_key_lock = format!("upload_sessions.{}.lock", upload_session_id);
+        let unlock_script = redis::Script::new(r"
+            if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('DEL', KEYS[1])
+            end
+            return 0
+        ");
+
+        let query_result : RedisResult<i32> = unlock_script
+            .key(redis_key_lock.as_str())
+            .arg(lock_token)
+            .invoke_async(&mut redis_connection)
+            .await;
+
+        if query_result.is_ok() {
+            return Ok(());
+        }
+
+        error!("RedisConnection::unlock_upload_session: Failed to execute Redis command");
+
+        return Err(SessionError::new(DbError, "Fehler beim Zugriff auf die Redis Datenbank"));
+    }
 }
\ No newline at end of file
@@ -1,11 +1,33 @@
-use crate::config::ProjectConfig;
+pub mod exif;
+pub mod metadata;
+
+use crate::config::{PreviewFormat, PreviewVariant, PreviewVariantList, ProjectConfig, UploadMetadataMode};
 use tokio::process::Command;
 use std::path::Path;
+use std::convert::TryFrom;
 use std::process::Output;
-use crate::file_api::FileProcessErrorType::{FormatError, PrvGenError, CopyError};
+use std::time::Duration;
+use crate::file_api::FileProcessErrorType::{FormatError, MetadataError, PrvGenError, TranscodeError, CopyError};
+use crate::file_api::exif::{get_orientation_filter, read_exif_orientation};
+use crate::file_api::metadata::strip_file_metadata;
 use log::{warn, error};
 use serde::{Deserialize};
-use crate::db_api::db_result::UploadType;
+use crate::db_api::db_result::{UploadFileData, UploadType};
+use std::fs::File;
+use zip::{CompressionMethod, ZipWriter};
+use zip::write::FileOptions;
+use rand::{thread_rng, Rng};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use futures::{Stream, StreamExt};
+use std::io::SeekFrom;
+use sha2::{Digest, Sha256};
+
+const PHASH_WIDTH : usize = 9; // dHash compares every pixel with its right neighbour, so one column more is needed
+const PHASH_HEIGHT : usize = 8;
+const STORAGE_NAME_BYTES : usize = 16;
+const STORAGE_EXTENSION_MAX_LENGTH : usize = 8;
+const WEBP_ANIMATION_FLAG : u8 = 0x02; // Bit in the flags of the VP8X chunk
+const TMP_FILE_LIFETIME : u64 = 25 * 3600; // Upload sessions expire a day after the last chunk (every chunk changes the tmp file)
 
 #[derive(Deserialize)]
 pub struct FFprobeFormat {
@@ -20,10 +42,23 @@ pub struct FFprobeOutput {
     pub format: FFprobeFormat,
 }
 
+impl FFprobeOutput {
+    // Returns the first stream of the given codec type ("video" or "audio", images are reported as video)
+    pub fn get_stream(&self, codec_type: &str) -> Option<&FFprobeStream> {
+        self.streams.iter().find(|stream| stream.codec_type == codec_type)
+    }
+}
+
 #[derive(Deserialize)]
 pub struct FFprobeStream {
     pub codec_name: String,
-    pub width: u32,
+    #[serde(default)]
+    pub codec_type: String,
+    #[serde(default)]
+    pub pix_fmt: String,
+    #[serde(default)]
+    pub width: u32, // Audio streams have no resolution
+    #[serde(default)]
     pub height: u32,
 }
 
@@ -58,72 +93,334 @@ impl FileProcessError {
 #[derive(Clone, Eq, PartialEq)]
 pub enum FileProcessErrorType {
     FormatError,
+    MetadataError,
+    TranscodeError,
     PrvGenError,
     CopyError,
 }
 
 #[derive(Clone)]
 pub struct FileProcessResult {
+    pub upload_filename: String, // Differs from the uploaded filename if a video was transcoded into another container format
     pub upload_type: UploadType,
+    pub upload_phash: Option<i64>, // None if the perceptual hash couldn't be calculated
+    pub upload_prv_variants: String, // Preview variants which were generated (stored with the upload)
+    pub upload_has_prv_clip: bool, // False if the clip is disabled or couldn't be generated
 }
 
 impl FileProcessResult {
-    pub fn new(upload_type: UploadType) -> FileProcessResult {
+    pub fn new(upload_filename: &str, upload_type: UploadType, upload_phash: Option<i64>, upload_prv_variants: &str,
+               upload_has_prv_clip: bool) -> FileProcessResult {
         FileProcessResult {
+            upload_filename: upload_filename.to_owned(),
             upload_type,
+            upload_phash,
+            upload_prv_variants: upload_prv_variants.to_owned(),
+            upload_has_prv_clip,
         }
     }
 }
 
-pub async fn delete_upload_srv(config: &ProjectConfig, filename: &str) {
-    let srv_upload_filepath = get_upload_path_srv(config, filename);
-    let srv_upload_prv_filepath = get_upload_prv_path_srv(config, filename);
+// Truncates the tmp file to the stored offset (bytes behind it are from a request which failed before the offset was saved)
+// and appends the received chunks until the stream ends (a broken connection keeps the received part) or the upload size is exceeded
+// Returns the new offset and if the upload size was exceeded, None if the file couldn't be written
+pub async fn append_upload_chunks<S, B, E>(filepath: &str, upload_offset: u64, upload_size: u64, chunk_stream: &mut S) -> Option<(u64, bool)>
+    where S: Stream<Item = Result<B, E>> + Unpin, B: AsRef<[u8]> {
+    let file : tokio::io::Result<tokio::fs::File> = tokio::fs::OpenOptions::new().write(true).open(filepath).await;
+
+    if file.is_err() {
+        error!("Datei konnte nicht geöffnet werden: {}", filepath);
+
+        return None;
+    }
+
+    let mut file = file.unwrap();
+    let truncate_result = file.set_len(upload_offset).await;
+    let seek_result = file.seek(SeekFrom::End(0)).await;
+
+    if truncate_result.is_err() || seek_result.is_err() {
+        error!("Datei konnte nicht gekürzt werden: {}", filepath);
+
+        return None;
+    }
+
+    let mut upload_offset = upload_offset;
+    let mut size_exceeded = false;
+
+    while let Some(chunk) = chunk_stream.next().await {
+        if chunk.is_err() {
+            break;
+        }
+
+        let chunk = chunk.ok().unwrap();
+        let data = chunk.as_ref();
+
+        if upload_offset + data.len() as u64 > upload_size {
+            size_exceeded = true;
+            break;
+        }
+
+        if file.write_all(data).await.is_err() {
+            error!("Datei konnte nicht geschrieben werden: {}", filepath);
+
+            return None;
+        }
+
+        upload_offset += data.len() as u64;
+    }
+
+    // Tokio writes in the background, the data have to be in the file before the offset is stored
+    if file.flush().await.is_err() {
+        error!("Datei konnte nicht geschrieben werden: {}", filepath);
+
+        return None;
+    }
+
+    return Some((upload_offset, size_exceeded));
+}
+
+// Copies the previews from the tmp directory to the server directory (the shard directory is created on demand)
+async fn copy_previews_srv(config: &ProjectConfig, filename: &str, preview_variants: &[PreviewVariant]) -> bool {
+    let mkdir_upload_prv_success = create_parent_directory(get_upload_prv_path_srv(config, filename, &preview_variants[0]).as_str()).await;
+
+    if !mkdir_upload_prv_success {
+        return false;
+    }
+
+    for preview_variant in preview_variants.iter() {
+        let tmp_upload_prv_filepath = get_upload_prv_path_tmp(filename, preview_variant);
+        let srv_upload_prv_filepath = get_upload_prv_path_srv(config, filename, preview_variant);
+        let cpy_result : core::result::Result<u64, _> = tokio::fs::copy(tmp_upload_prv_filepath.as_str(), srv_upload_prv_filepath.as_str()).await;
+
+        if cpy_result.is_err() {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+async fn create_parent_directory(filepath: &str) -> bool {
+    let parent_directory = Path::new(filepath).parent();
+
+    if parent_directory.is_some() {
+        let mkdir_result : tokio::io::Result<()> = tokio::fs::create_dir_all(parent_directory.unwrap()).await;
+
+        if mkdir_result.is_err() {
+            error!("Verzeichnis konnte nicht erstellt werden: {}", parent_directory.unwrap().display());
+
+            return false;
+        }
+    }
+
+    return true;
+}
+
+// Packs the given uploads into a zip archive in the tmp directory
+// Returns the path of the archive (the caller have to remove it after use)
+pub async fn create_upload_archive(config: &ProjectConfig, archive_name: &str, upload_filenames: &[String]) -> Option<String> {
+    let archive_filepath = get_export_path_tmp(format!("{}.zip", archive_name).as_str());
+    let upload_files : Vec<(String, String)> = upload_filenames.iter()
+        .map(|filename| (filename.clone(), get_upload_path_srv(config, filename.as_str())))
+        .collect();
+
+    if !create_parent_directory(archive_filepath.as_str()).await {
+        return None;
+    }
+
+    // The zip crate only supports blocking io
+    let thread_archive_filepath = archive_filepath.clone();
+    let write_success = tokio::task::spawn_blocking(move || {
+        write_upload_archive(thread_archive_filepath.as_str(), &upload_files)
+    }).await;
+
+    if write_success.is_ok() && write_success.unwrap() {
+        return Some(archive_filepath);
+    }
+
+    error!("Upload Archiv konnte nicht erstellt werden: {}", archive_filepath.as_str());
+    let _rm_archive_success : tokio::io::Result<()> = tokio::fs::remove_file(archive_filepath.as_str()).await;
+
+    return None;
+}
+
+// Deletes the previews of the stored variants of an upload which aren't part of the new variants
+pub async fn delete_replaced_previews(config: &ProjectConfig, upload_file: &UploadFileData, upload_prv_variants: &str) {
+    let filename = upload_file.upload_filename.as_str();
+    let preview_variants = get_stored_preview_variants(Some(upload_prv_variants));
+
+    for stored_preview_variant in get_stored_preview_variants(upload_file.upload_prv_variants.as_deref()).iter() {
+        let stored_preview_filename = get_preview_filename(filename, stored_preview_variant);
+        let is_replaced = preview_variants.iter()
+            .all(|preview_variant| get_preview_filename(filename, preview_variant) != stored_preview_filename);
+
+        if is_replaced {
+            let srv_upload_prv_filepath = get_upload_prv_path_srv(config, filename, stored_preview_variant);
+            let rm_upload_prv_success : tokio::io::Result<()> = tokio::fs::remove_file(srv_upload_prv_filepath.as_str()).await;
+
+            if rm_upload_prv_success.is_err() {
+                warn!("Upload Datei konnte nicht gelöscht werden: {}", srv_upload_prv_filepath.as_str());
+            }
+        }
+    }
+}
+
+// Deletes tmp files which weren't changed within the tmp file lifetime, they can't belong to a live upload session anymore
+// The files of queued uploads are kept, because an upload can wait longer in the queue
+// Returns the number of deleted files
+pub async fn delete_stale_tmp_files(queued_filenames: &[String]) -> usize {
+    let tmp_directories = ["./tmp/p0nygramm/upload_files", "./tmp/p0nygramm/work_files", "./tmp/p0nygramm/preview_files"];
+    let mut deleted_count : usize = 0;
+
+    for tmp_directory in tmp_directories.iter() {
+        let read_dir_result = tokio::fs::read_dir(tmp_directory).await;
+
+        if read_dir_result.is_err() {
+            continue;
+        }
+
+        let mut read_dir : tokio::fs::ReadDir = read_dir_result.unwrap();
 
+        while let Ok(Some(dir_entry)) = read_dir.next_entry().await {
+            let filename = dir_entry.file_name().to_string_lossy().into_owned();
+            let is_stale = dir_entry.metadata().await.ok()
+                .filter(|metadata| metadata.is_file())
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|modified| modified.elapsed().ok())
+                .map_or(false, |file_age| file_age.as_secs() > TMP_FILE_LIFETIME);
+
+            if !is_stale || queued_filenames.contains(&filename) {
+                continue;
+            }
+
+            let rm_result : tokio::io::Result<()> = tokio::fs::remove_file(dir_entry.path()).await;
+
+            if rm_result.is_ok() {
+                deleted_count += 1;
+            }
+            else {
+                warn!("Temoräre Datei konnte nicht gelöscht werden: {}", dir_entry.path().to_string_lossy());
+            }
+        }
+    }
+
+    return deleted_count;
+}
+
+// The stored preview variants of the upload are deleted, not the currently configured ones
+pub async fn delete_upload_srv(config: &ProjectConfig, filename: &str, upload_prv_variants: Option<&str>, upload_has_prv_clip: bool) {
+    let srv_upload_filepath = get_upload_path_srv(config, filename);
     let rm_upload_success: tokio::io::Result<()> = tokio::fs::remove_file(srv_upload_filepath.as_str()).await;
-    let rm_upload_prv_success: tokio::io::Result<()> = tokio::fs::remove_file(srv_upload_prv_filepath.as_str()).await;
 
     if rm_upload_success.is_err() {
         error!("Upload Datei konnte nicht gelöscht werden: {}", srv_upload_filepath.as_str());
     }
 
-    if !rm_upload_prv_success.is_err() {
-        error!("Upload Datei konnte nicht gelöscht werden: {}", srv_upload_prv_filepath.as_str());
+    for preview_variant in get_stored_preview_variants(upload_prv_variants).iter() {
+        let srv_upload_prv_filepath = get_upload_prv_path_srv(config, filename, preview_variant);
+        let rm_upload_prv_success: tokio::io::Result<()> = tokio::fs::remove_file(srv_upload_prv_filepath.as_str()).await;
+
+        if rm_upload_prv_success.is_err() {
+            error!("Upload Datei konnte nicht gelöscht werden: {}", srv_upload_prv_filepath.as_str());
+        }
+    }
+
+    if upload_has_prv_clip {
+        let srv_upload_prv_clip_filepath = get_upload_prv_clip_path_srv(config, filename);
+        let rm_upload_prv_clip_success: tokio::io::Result<()> = tokio::fs::remove_file(srv_upload_prv_clip_filepath.as_str()).await;
+
+        if rm_upload_prv_clip_success.is_err() {
+            error!("Upload Datei konnte nicht gelöscht werden: {}", srv_upload_prv_clip_filepath.as_str());
+        }
     }
 }
 
 pub async fn delete_upload_tmp(filename: &str) {
     let tmp_upload_filepath = get_upload_path_tmp(filename);
-    let tmp_upload_prv_filepath = get_upload_prv_path_tmp(filename);
-
     let rm_tmp_upload_success : tokio::io::Result<()> = tokio::fs::remove_file(tmp_upload_filepath.as_str()).await;
-    let rm_tmp_upload_prv_success : tokio::io::Result<()> = tokio::fs::remove_file(tmp_upload_prv_filepath.as_str()).await;
 
     if rm_tmp_upload_success.is_err() {
         warn!("Temoräre Datei konnte nicht gelöscht werden: {}", tmp_upload_filepath.as_str());
     }
+}
+
+// Removes the working copy and the generated previews of an upload (the queued file in the tmp directory is kept)
+pub async fn delete_upload_work(config: &ProjectConfig, filename: &str) {
+    let work_upload_filepath = get_upload_path_work(filename);
+    let rm_work_upload_success : tokio::io::Result<()> = tokio::fs::remove_file(work_upload_filepath.as_str()).await;
+
+    // There is no working copy before the first processing of an upload
+    if rm_work_upload_success.is_err() && rm_work_upload_success.unwrap_err().kind() != std::io::ErrorKind::NotFound {
+        warn!("Temoräre Datei konnte nicht gelöscht werden: {}", work_upload_filepath.as_str());
+    }
+
+    // The previews don't exist if the processing failed before, so errors are ignored
+    for preview_variant in config.application_config.preview_variants.get_value().variants.iter() {
+        let tmp_upload_prv_filepath = get_upload_prv_path_tmp(filename, preview_variant);
+        let _rm_tmp_upload_prv_success : tokio::io::Result<()> = tokio::fs::remove_file(tmp_upload_prv_filepath.as_str()).await;
+    }
+
+    let tmp_upload_prv_clip_filepath = get_upload_prv_clip_path_tmp(filename);
+    let _rm_tmp_upload_prv_clip_success : tokio::io::Result<()> = tokio::fs::remove_file(tmp_upload_prv_clip_filepath.as_str()).await;
+}
+
+// Returns the hex encoded SHA-256 hash of a file in the tmp directory (the file is read in blocks)
+pub async fn generate_file_hash(filename: &str) -> Option<String> {
+    let filepath = get_upload_path_tmp(filename);
+    let file = tokio::fs::File::open(filepath.as_str()).await;
+
+    if file.is_err() {
+        warn!("Datei konnte nicht gelesen werden: {}", filepath);
+
+        return None;
+    }
+
+    let mut file = file.unwrap();
+    let mut file_hasher = Sha256::new();
+    let mut read_buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read_result = file.read(&mut read_buffer).await;
 
-    if !rm_tmp_upload_prv_success.is_err() {
-        warn!("Temoräre Datei konnte nicht gelöscht werden: {}", tmp_upload_prv_filepath.as_str());
+        if read_result.is_err() {
+            warn!("Datei konnte nicht gelesen werden: {}", filepath);
+
+            return None;
+        }
+
+        let read_length = read_result.unwrap();
+
+        if read_length == 0 {
+            break;
+        }
+
+        file_hasher.update(&read_buffer[..read_length]);
     }
+
+    return Some(format!("{:x}", file_hasher.finalize()));
 }
 
-async fn generate_preview(ffmpeg_filepath: &str, ffprobe_data: &FFprobeOutput, filename: &str) -> bool {
+async fn generate_preview(ffmpeg_filepath: &str, ffprobe_data: &FFprobeOutput, filename: &str, preview_variant: &PreviewVariant) -> bool {
     let is_image_file = is_image_file(filename);
     let is_video_file = is_video_file(filename);
 
     if is_image_file || is_video_file {
-        let upload_filepath = get_upload_path_tmp(filename);
-        let output_filepath = get_upload_prv_path_tmp(filename);
-        let width = ffprobe_data.streams.get(0).unwrap().width;
-        let height = ffprobe_data.streams.get(0).unwrap().height;
-        let crop_resolution = width.min(height);
-        let ffmpeg_filter = format!("crop={}:{},scale=100:100", crop_resolution, crop_resolution);
+        let upload_filepath = get_upload_path_work(filename);
+        let output_filepath = get_upload_prv_path_tmp(filename, preview_variant);
+        let video_stream = ffprobe_data.get_stream("video");
+
+        // ffprobe reports no resolution if the decoder doesn't support the file (e.g. animated webp in older versions)
+        if video_stream.is_none() || video_stream.unwrap().width == 0 || video_stream.unwrap().height == 0 {
+            return false;
+        }
+
+        let ffmpeg_filter = get_preview_filter(preview_variant);
         let video_duration = ffprobe_data.format.duration.unwrap_or(0.0);
 
         let mut ffmpeg_args : Vec<&str> = Vec::new();
         ffmpeg_args.push("-loglevel");
         ffmpeg_args.push("quiet");
-
+        ffmpeg_args.push("-y");
 
         // If the file is a video and it is long enough, crate the thumbnail from the frame after the first second
         // Else: The thumbnail will be created from the first frame
@@ -138,8 +435,18 @@ async fn generate_preview(ffmpeg_filepath: &str, ffprobe_data: &FFprobeOutput, f
         ffmpeg_args.push("1");
         ffmpeg_args.push("-filter:v");
         ffmpeg_args.push(ffmpeg_filter.as_str());
-        ffmpeg_args.push("-qscale:v");
-        ffmpeg_args.push("5");
+
+        match preview_variant.format {
+            PreviewFormat::Jpg => {
+                ffmpeg_args.push("-qscale:v");
+                ffmpeg_args.push("5");
+            },
+            PreviewFormat::Webp => {
+                ffmpeg_args.push("-quality");
+                ffmpeg_args.push("75");
+            },
+        }
+
         ffmpeg_args.push(output_filepath.as_str());
 
         // Let the OS take care of finding the ffmpeg binary if there is no path provided
@@ -163,97 +470,418 @@ async fn generate_preview(ffmpeg_filepath: &str, ffprobe_data: &FFprobeOutput, f
     return false;
 }
 
+// Creates a short muted mp4 clip with the size of the given preview variant (the frontend plays it looped on hover)
+async fn generate_preview_clip(config: &ProjectConfig, filename: &str, preview_variant: &PreviewVariant) -> bool {
+    let ffmpeg_filepath = config.filesystem_config.ffmpeg_path.get_value();
+    let clip_duration = config.application_config.preview_clip_duration.get_value().to_string();
+    let upload_filepath = get_upload_path_work(filename);
+    let output_filepath = get_upload_prv_clip_path_tmp(filename);
+
+    // h264 with 4:2:0 chroma subsampling needs even dimensions
+    let ffmpeg_filter = format!("{},scale=trunc(iw/2)*2:trunc(ih/2)*2", get_preview_filter(preview_variant));
+
+    let mut ffmpeg_args : Vec<&str> = Vec::new();
+    ffmpeg_args.push("-loglevel");
+    ffmpeg_args.push("quiet");
+    ffmpeg_args.push("-y");
+    ffmpeg_args.push("-i");
+    ffmpeg_args.push(upload_filepath.as_str());
+    ffmpeg_args.push("-t");
+    ffmpeg_args.push(clip_duration.as_str());
+    ffmpeg_args.push("-an");
+    ffmpeg_args.push("-filter:v");
+    ffmpeg_args.push(ffmpeg_filter.as_str());
+    ffmpeg_args.push("-c:v");
+    ffmpeg_args.push("libx264");
+    ffmpeg_args.push("-preset");
+    ffmpeg_args.push("veryfast");
+    ffmpeg_args.push("-crf");
+    ffmpeg_args.push("28");
+    ffmpeg_args.push("-pix_fmt");
+    ffmpeg_args.push("yuv420p");
+    ffmpeg_args.push("-movflags");
+    ffmpeg_args.push("+faststart");
+    ffmpeg_args.push(output_filepath.as_str());
+
+    // Let the OS take care of finding the ffmpeg binary if there is no path provided
+    let command = if !ffmpeg_filepath.is_empty() {
+        ffmpeg_filepath.as_str()
+    }
+    else {
+        "ffmpeg"
+    };
+
+    let ffmpeg_result : std::io::Result<Output> = Command::new(command)
+        .args(ffmpeg_args)
+        .output()
+        .await;
+
+    if ffmpeg_result.is_ok() && ffmpeg_result.unwrap().status.success() {
+        return true;
+    }
+
+    warn!("Vorschauclip konnte nicht erzeugt werden: {}", upload_filepath.as_str());
+    let _rm_prv_clip_success : tokio::io::Result<()> = tokio::fs::remove_file(output_filepath.as_str()).await;
+
+    return false;
+}
+
+// Creates every preview variant in the tmp directory
+async fn generate_previews(ffmpeg_filepath: &str, ffprobe_data: &FFprobeOutput, filename: &str, preview_variants: &[PreviewVariant]) -> bool {
+    if preview_variants.is_empty() {
+        return false;
+    }
+
+    for preview_variant in preview_variants.iter() {
+        let generate_preview_success = generate_preview(ffmpeg_filepath, ffprobe_data, filename, preview_variant).await;
+
+        if !generate_preview_success {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+// Calculates the dHash of the image (or of the first keyframe of a video), the bits are stored as i64 for postgres
+// Similar images have hashes with a small hamming distance, even if they were resized or re-encoded
+async fn generate_perceptual_hash(ffmpeg_filepath: &str, filename: &str) -> Option<i64> {
+    let upload_filepath = get_upload_path_work(filename);
+    let ffmpeg_filter = format!("scale={}:{},format=gray", PHASH_WIDTH, PHASH_HEIGHT);
+
+    let mut ffmpeg_args : Vec<&str> = Vec::new();
+    ffmpeg_args.push("-loglevel");
+    ffmpeg_args.push("quiet");
+
+    if is_video_file(filename) {
+        ffmpeg_args.push("-skip_frame");
+        ffmpeg_args.push("nokey");
+    }
+
+    ffmpeg_args.push("-i");
+    ffmpeg_args.push(upload_filepath.as_str());
+    ffmpeg_args.push("-frames:v");
+    ffmpeg_args.push("1");
+    ffmpeg_args.push("-filter:v");
+    ffmpeg_args.push(ffmpeg_filter.as_str());
+    ffmpeg_args.push("-f");
+    ffmpeg_args.push("rawvideo");
+    ffmpeg_args.push("pipe:1");
+
+    // Let the OS take care of finding the ffmpeg binary if there is no path provided
+    let command = if !ffmpeg_filepath.is_empty() {
+        ffmpeg_filepath
+    }
+    else {
+        "ffmpeg"
+    };
+
+    let ffmpeg_result : std::io::Result<Output> = Command::new(command)
+        .args(ffmpeg_args)
+        .output()
+        .await;
+
+    if ffmpeg_result.is_ok() {
+        let ffmpeg_result = ffmpeg_result.unwrap();
+        let pixels = ffmpeg_result.stdout;
+
+        // Raw grayscale output has exactly one byte per pixel
+        if ffmpeg_result.status.success() && pixels.len() == PHASH_WIDTH * PHASH_HEIGHT {
+            return Some(get_perceptual_hash(&pixels));
+        }
+    }
+
+    warn!("Failed to calculate the perceptual hash of {}", filename);
+
+    return None;
+}
+
+// Generates a random storage name for an upload, only the (lowercase) extension of the original filename is kept
+// Returns None if the original filename has no usable extension
+pub fn generate_upload_filename(original_filename: &str) -> Option<String> {
+    let file_extension = Path::new(original_filename).extension();
+
+    if file_extension.is_some() {
+        let mut file_extension = file_extension.unwrap().to_string_lossy().to_ascii_lowercase();
+
+        // Both extensions are common for jpeg files, the rest of the file handling only has to know one of them
+        if file_extension == "jpeg" {
+            file_extension = String::from("jpg");
+        }
+
+        let extension_is_valid = !file_extension.is_empty()
+            && file_extension.len() <= STORAGE_EXTENSION_MAX_LENGTH
+            && file_extension.chars().all(|char| char.is_ascii_alphanumeric());
+
+        if extension_is_valid {
+            let mut rng = thread_rng();
+            let storage_name : String = (0..STORAGE_NAME_BYTES)
+                .map(|_| format!("{:02x}", rng.gen::<u8>()))
+                .collect();
+
+            return Some(format!("{}.{}", storage_name, file_extension));
+        }
+    }
+
+    return None;
+}
+
 pub fn get_url_from_filename(filename: &str) -> String {
-    format!("/uploads/{}", filename)
+    format!("/uploads/{}", get_sharded_path(filename))
 }
 
-pub fn get_preview_url_from_filename(filename: &str) -> String {
-    let filename_point_pos = filename.rfind('.').unwrap();
-    let (file_name, _file_ext) = filename.split_at(filename_point_pos);
+pub fn get_preview_url_from_filename(filename: &str, preview_variant: &PreviewVariant) -> String {
+    format!("/prv/{}", get_sharded_path(get_preview_filename(filename, preview_variant).as_str()))
+}
 
-    format!("/prv/{}.{}", file_name, ".jpg")
+pub fn get_preview_clip_url_from_filename(filename: &str) -> String {
+    format!("/prv/{}", get_sharded_path(get_preview_clip_filename(filename).as_str()))
+}
+
+// Packs the dHash bits of the grayscale pixels row by row, the first pixel pair of the first row ends up in the highest bit
+// A bit is set if the pixel is darker than its right neighbour
+fn get_perceptual_hash(pixels: &[u8]) -> i64 {
+    let mut phash : u64 = 0;
+
+    for row in 0..PHASH_HEIGHT {
+        for column in 0..PHASH_WIDTH - 1 {
+            let pixel_pos = row * PHASH_WIDTH + column;
+            phash <<= 1;
+
+            if pixels[pixel_pos] < pixels[pixel_pos + 1] {
+                phash |= 1;
+            }
+        }
+    }
+
+    return phash as i64;
+}
+
+fn get_preview_clip_filename(filename: &str) -> String {
+    let file_stem = match filename.rfind('.') {
+        Some(filename_point_pos) => filename.split_at(filename_point_pos).0,
+        None => filename,
+    };
+
+    format!("{}_clip.mp4", file_stem)
+}
+
+// Every preview variant gets its own file (<upload name>_<variant name>.<preview format>)
+// The legacy preview has no variant name (<upload name>.jpg)
+fn get_preview_filename(filename: &str, preview_variant: &PreviewVariant) -> String {
+    let file_stem = match filename.rfind('.') {
+        Some(filename_point_pos) => filename.split_at(filename_point_pos).0,
+        None => filename,
+    };
+
+    if preview_variant.name.is_empty() {
+        return format!("{}.{}", file_stem, preview_variant.format.as_str());
+    }
+
+    format!("{}_{}.{}", file_stem, preview_variant.name, preview_variant.format.as_str())
+}
+
+// Cropped previews are scaled until they cover the whole area and cut in the center
+// The other previews fit into the area and are never scaled up
+fn get_preview_filter(preview_variant: &PreviewVariant) -> String {
+    let (width, height) = (preview_variant.width, preview_variant.height);
+
+    if preview_variant.is_cropped {
+        format!("scale={}:{}:force_original_aspect_ratio=increase,crop={}:{}", width, height, width, height)
+    }
+    else {
+        format!("scale='min({},iw)':'min({},ih)':force_original_aspect_ratio=decrease", width, height)
+    }
+}
+
+// Spreads the uploads over two directory levels (ab/cd/abcd...), so that no single directory gets too large
+// Uploads from before the storage names were generated by the server stay in the top level directory
+fn get_sharded_path(filename: &str) -> String {
+    let file_stem = filename.split(|char| char == '.' || char == '_').next().unwrap_or("");
+    let is_generated_name = file_stem.len() == STORAGE_NAME_BYTES * 2
+        && file_stem.chars().all(|char| char.is_ascii_digit() || ('a'..='f').contains(&char));
+
+    if is_generated_name {
+        format!("{}/{}/{}", &filename[0..2], &filename[2..4], filename)
+    }
+    else {
+        filename.to_owned()
+    }
+}
+
+// Returns the preview variants which were generated for an upload (the single legacy preview if none are stored)
+pub fn get_stored_preview_variants(upload_prv_variants: Option<&str>) -> Vec<PreviewVariant> {
+    if upload_prv_variants.is_none() {
+        return vec![PreviewVariant::legacy()];
+    }
+
+    let preview_variant_list = PreviewVariantList::try_from(upload_prv_variants.unwrap());
+
+    if preview_variant_list.is_err() {
+        warn!("Gespeicherte Vorschauvarianten sind ungültig: {}", upload_prv_variants.unwrap());
+
+        return Vec::new();
+    }
+
+    preview_variant_list.ok().unwrap().variants
+}
+
+pub fn get_export_path_tmp(filename: &str) -> String {
+    format!("./tmp/p0nygramm/export_files/{}", filename)
 }
 
 pub fn get_upload_path_srv(config: &ProjectConfig, filename: &str) -> String {
-    format!("{}/{}", config.filesystem_config.uploads_path.get_value(), filename)
+    format!("{}/{}", config.filesystem_config.uploads_path.get_value(), get_sharded_path(filename))
+}
+
+pub fn get_upload_prv_clip_path_srv(config: &ProjectConfig, filename: &str) -> String {
+    let preview_clip_filename = get_preview_clip_filename(filename);
+
+    format!("{}/{}", config.filesystem_config.uploads_prv_path.get_value(), get_sharded_path(preview_clip_filename.as_str()))
+}
+
+pub fn get_upload_prv_clip_path_tmp(filename: &str) -> String {
+    format!("./tmp/p0nygramm/preview_files/{}", get_preview_clip_filename(filename))
 }
 
-pub fn get_upload_prv_path_srv(config: &ProjectConfig, filename: &str) -> String {
-    format!("{}/{}", config.filesystem_config.uploads_prv_path.get_value(), filename)
+pub fn get_upload_prv_path_srv(config: &ProjectConfig, filename: &str, preview_variant: &PreviewVariant) -> String {
+    let preview_filename = get_preview_filename(filename, preview_variant);
+
+    format!("{}/{}", config.filesystem_config.uploads_prv_path.get_value(), get_sharded_path(preview_filename.as_str()))
 }
 
 pub fn get_upload_path_tmp(filename: &str) -> String {
     format!("./tmp/p0nygramm/upload_files/{}", filename)
 }
 
-pub fn get_upload_prv_path_tmp(filename: &str) -> String {
-    format!("./tmp/p0nygramm/preview_files/{}", filename)
+pub fn get_upload_prv_path_tmp(filename: &str, preview_variant: &PreviewVariant) -> String {
+    format!("./tmp/p0nygramm/preview_files/{}", get_preview_filename(filename, preview_variant))
 }
 
-pub fn is_animated_image_file(filename: &str) -> bool {
-    let path_obj = Path::new(filename);
-    let file_extension = path_obj.extension();
+pub fn get_upload_path_work(filename: &str) -> String {
+    format!("./tmp/p0nygramm/work_files/{}", filename)
+}
+
+// Returns the lowercase file extension (storage names always have a lowercase extension)
+fn get_file_extension(filename: &str) -> String {
+    let file_extension = Path::new(filename).extension();
 
     if file_extension.is_some() {
-        let file_extension = file_extension.unwrap();
+        file_extension.unwrap().to_string_lossy().to_ascii_lowercase()
+    }
+    else {
+        String::new()
+    }
+}
 
-        // Detect .gif files
-        if file_extension == "gif" {
-            return true;
-        }
+// Returns the resolution of a video after the shorter side was limited to max_resolution (0 = no limit)
+// Odd sides are kept, they are only rounded by the scale filter if the video has to be transcoded anyway
+fn get_video_resolution(width: u32, height: u32, max_resolution: u32) -> (u32, u32) {
+    let shorter_side = width.min(height);
+
+    if max_resolution > 0 && shorter_side > max_resolution {
+        let scaled_width = (width as u64 * max_resolution as u64 / shorter_side as u64) as u32;
+        let scaled_height = (height as u64 * max_resolution as u64 / shorter_side as u64) as u32;
+
+        return (scaled_width, scaled_height);
     }
 
-    return false;
+    (width, height)
 }
 
-pub fn is_image_file(filename: &str) -> bool {
-    let path_obj = Path::new(filename);
-    let file_extension = path_obj.extension();
+// Scales the shorter side down to the maximal resolution while keeping the aspect ratio, the expressions use the
+// dimensions after the autorotation of ffmpeg, so rotated videos are handled too (both sides are rounded down to even
+// numbers, which is required by h264 with 4:2:0 chroma subsampling)
+fn get_video_scale_filter(max_resolution: u32) -> String {
+    if max_resolution == 0 {
+        return String::from("scale=trunc(iw/2)*2:trunc(ih/2)*2");
+    }
 
-    if file_extension.is_some() {
-        let file_extension = file_extension.unwrap();
+    format!("scale='if(lte(iw,ih),trunc(min(iw,{0})/2)*2,-2)':'if(lte(iw,ih),-2,trunc(min(ih,{0})/2)*2)'", max_resolution)
+}
 
-        // Detect .jpg and .png files
-        if file_extension == "jpg" || file_extension == "png" {
-            return true;
-        }
-    }
+// Videos and animated images get a preview clip, if the clips aren't disabled
+pub fn has_preview_clip(config: &ProjectConfig, upload_type: &UploadType) -> bool {
+    let preview_clip_duration = config.application_config.preview_clip_duration.get_value();
 
-    return false;
+    preview_clip_duration > 0 && *upload_type != UploadType::Image
 }
 
-pub fn is_video_file(filename: &str) -> bool {
-    let path_obj = Path::new(filename);
-    let file_extension = path_obj.extension();
+// Checks if the moov box (the index of the file) is in front of the media data, so that browsers can start playing while loading
+async fn is_faststart_mp4_file(filepath: &str) -> bool {
+    let file = tokio::fs::File::open(filepath).await;
 
-    if file_extension.is_some() {
-        let file_extension = file_extension.unwrap();
+    if file.is_ok() {
+        let mut file : tokio::fs::File = file.unwrap();
+        let mut box_start : u64 = 0;
 
-        if file_extension == "mp4" {
-            return true;
+        // Every top level box starts with its size and type
+        loop {
+            let mut box_header = [0u8; 8];
+            let seek_result = file.seek(SeekFrom::Start(box_start)).await;
+
+            if seek_result.is_err() || file.read_exact(&mut box_header).await.is_err() {
+                break;
+            }
+
+            let box_type = &box_header[4..8];
+            let mut box_size = u32::from_be_bytes([box_header[0], box_header[1], box_header[2], box_header[3]]) as u64;
+
+            if box_type == b"moov" {
+                return true;
+            }
+            else if box_type == b"mdat" {
+                return false;
+            }
+
+            // Size 1 means that the real size follows as 64 bit value, size 0 means that the box ends with the file
+            if box_size == 1 {
+                let mut large_size = [0u8; 8];
+
+                if file.read_exact(&mut large_size).await.is_err() {
+                    break;
+                }
+
+                box_size = u64::from_be_bytes(large_size);
+            }
+
+            if box_size < 8 {
+                break;
+            }
+
+            box_start += box_size;
         }
     }
 
     return false;
 }
 
-// Returns Some(FFprobeOutput) if the file format and codex is valid (if not -> None)
-pub async fn probe_file(ffprobe_filepath: &str, upload_filename: &str) -> Option<FileProbeResult> {
-    let upload_filepath = get_upload_path_tmp(upload_filename);
+pub fn is_image_file(filename: &str) -> bool {
+    let file_extension = get_file_extension(filename);
 
-    // Get media typ from the file extension (that will be later validated by ffprobe)
-    let is_image_file = is_image_file(upload_filename);
-    let is_animated_image_file = is_animated_image_file(upload_filename);
-    let is_video_file = is_video_file(upload_filename);
-    let upload_type = UploadType::new(is_image_file, is_animated_image_file, is_video_file);
+    // Detect static and animated images, if a file is animated is decided by probe_file
+    match file_extension.as_str() {
+        "jpg" | "jpeg" | "png" | "apng" | "gif" | "webp" | "avif" => true,
+        _ => false,
+    }
+}
 
-    if upload_type.is_none() {
-        error!("Failed to get upload type!");
+pub fn is_video_file(filename: &str) -> bool {
+    let file_extension = get_file_extension(filename);
 
-        return None;
+    match file_extension.as_str() {
+        "mp4" | "webm" => true,
+        _ => false,
     }
+}
 
-    let upload_type = upload_type.unwrap();
+// Returns Some(FFprobeOutput) if the file format and codex is valid (if not -> None)
+// The upload type is taken from the ffprobe data, the file extension only selects which formats are accepted
+pub async fn probe_file(ffprobe_filepath: &str, upload_filename: &str) -> Option<FileProbeResult> {
+    let upload_filepath = get_upload_path_work(upload_filename);
+    let file_extension = get_file_extension(upload_filename);
+    let is_image_file = is_image_file(upload_filename);
+    let is_video_file = is_video_file(upload_filename);
 
     // Let the OS take care of finding the ffprobe binary if there is no path provided
     let command = if !ffprobe_filepath.is_empty() {
@@ -272,7 +900,7 @@ pub async fn probe_file(ffprobe_filepath: &str, upload_filename: &str) -> Option
             .arg("-show_streams")
             .arg("-print_format")
             .arg("json")
-            .arg(upload_filepath)
+            .arg(upload_filepath.as_str())
             .output()
             .await;
 
@@ -290,13 +918,15 @@ pub async fn probe_file(ffprobe_filepath: &str, upload_filename: &str) -> Option
 
                     if ffprobe_stdout_json.is_ok() {
                         let ffprobe_stdout_json = ffprobe_stdout_json.unwrap();
+                        let upload_type = if is_image_file {
+                            probe_image_file(&ffprobe_stdout_json, file_extension.as_str(), upload_filepath.as_str()).await
+                        }
+                        else {
+                            probe_video_file(&ffprobe_stdout_json, file_extension.as_str())
+                        };
 
-                        // If the detected upload type detected from file extension equals the test from the ffprobe data
-                        if ( is_image_file && probe_image_file(&ffprobe_stdout_json) ) ||
-                            ( is_animated_image_file && probe_animated_image_file(&ffprobe_stdout_json) ) ||
-                            ( is_video_file && probe_video_file(&ffprobe_stdout_json) ) {
-
-                            let ret_val = FileProbeResult::new(ffprobe_stdout_json, upload_type);
+                        if upload_type.is_some() {
+                            let ret_val = FileProbeResult::new(ffprobe_stdout_json, upload_type.unwrap());
                             return Some(ret_val);
                         }
                     }
@@ -308,100 +938,195 @@ pub async fn probe_file(ffprobe_filepath: &str, upload_filename: &str) -> Option
     return None;
 }
 
-fn probe_animated_image_file(ffprobe_stdout_json: &FFprobeOutput) -> bool {
-    let stream_count = ffprobe_stdout_json.streams.len();
+// ffprobe doesn't report if a webp file is animated, so the flag is read from the VP8X header chunk
+// Returns None if the file is no valid webp file
+async fn probe_animated_webp_file(upload_filepath: &str) -> Option<bool> {
+    let file = tokio::fs::File::open(upload_filepath).await;
 
-    if stream_count == 1 {
-        let image_format = ffprobe_stdout_json.format.format_name.as_str();
+    if file.is_ok() {
+        let mut file : tokio::fs::File = file.unwrap();
+        let mut header = [0u8; 21];
+        let read_result = file.read_exact(&mut header).await;
 
-        // Allow gif as container format
-        if image_format == "gif" {
-            let image_codec = ffprobe_stdout_json.streams.get(0).unwrap().codec_name.as_str();
+        if read_result.is_ok() && &header[0..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+            // Simple (lossy or lossless) webp files have no VP8X chunk and can't be animated
+            let is_animated = &header[12..16] == b"VP8X" && (header[20] & WEBP_ANIMATION_FLAG) != 0;
 
-            // Allow gif as codec
-            if image_codec == "gif" {
-                return true;
-            }
+            return Some(is_animated);
         }
     }
 
-    return false;
+    return None;
 }
 
-fn probe_image_file(ffprobe_stdout_json: &FFprobeOutput) -> bool {
+// Checks that container and codec match the file extension, returns the upload type (static or animated image)
+async fn probe_image_file(ffprobe_stdout_json: &FFprobeOutput, file_extension: &str, upload_filepath: &str) -> Option<UploadType> {
     let stream_count = ffprobe_stdout_json.streams.len();
 
-    if stream_count == 1 {
-        let image_format = ffprobe_stdout_json.format.format_name.as_str();
+    // An avif file can have additional av1 streams for the alpha channel or the tiles of a grid image
+    let streams_are_ok = if file_extension == "avif" {
+        stream_count >= 1 && ffprobe_stdout_json.streams.iter().all(|stream| stream.codec_type == "video" && stream.codec_name == "av1")
+    }
+    else {
+        stream_count == 1
+    };
 
-        // Allow gif, jpeg (image2) and png as container format
-        if image_format == "gif" || image_format == "image2" || image_format == "png_pipe" {
-            let image_codec = ffprobe_stdout_json.streams.get(0).unwrap().codec_name.as_str();
+    if streams_are_ok {
+        let image_format = ffprobe_stdout_json.format.format_name.as_str();
+        let image_codec = ffprobe_stdout_json.streams.get(0).unwrap().codec_name.as_str();
+
+        // A .png file can contain an apng, which ffprobe reports with its own format
+        let is_animated_image = match (file_extension, image_format, image_codec) {
+            ("jpg", "image2", "mjpeg") | ("jpg", "jpeg_pipe", "mjpeg") => Some(false),
+            ("png", "png_pipe", "png") => Some(false),
+            ("png", "apng", "apng") | ("apng", "apng", "apng") => Some(true),
+            ("gif", "gif", "gif") => Some(true),
+            // ffmpeg can only decode animated webp files since version 7.1, so they are rejected until that is the minimum version
+            ("webp", "webp_pipe", "webp") => probe_animated_webp_file(upload_filepath).await.filter(|is_animated| !is_animated),
+            ("avif", "mov,mp4,m4a,3gp,3g2,mj2", "av1") => Some(false),
+            _ => None,
+        };
 
-            if image_codec == "gif" ||  image_codec == "mjpeg" || image_codec == "png" {
-                return true;
-            }
+        if is_animated_image.is_some() {
+            return UploadType::new(true, is_animated_image.unwrap(), false);
         }
     }
 
-    return false;
+    return None;
 }
 
-fn probe_video_file(ffprobe_stdout_json: &FFprobeOutput) -> bool {
+// Checks that container and codecs match the file extension, every video can have up to one audio stream
+fn probe_video_file(ffprobe_stdout_json: &FFprobeOutput, file_extension: &str) -> Option<UploadType> {
     let stream_count = ffprobe_stdout_json.streams.len();
 
     if stream_count == 1 || stream_count == 2 {
         let video_format = ffprobe_stdout_json.format.format_name.as_str();
-
-        // Allow mp4 container format
-        if video_format == "mov,mp4,m4a,3gp,3g2,mj2" {
-            let video_codec = ffprobe_stdout_json.streams.get(0).unwrap().codec_name.as_str();
-
-            // Allow h264 video codec
-            if video_codec == "h264" {
-                // if the video has an audio stream: Check if it uses the aac codec
-                if stream_count == 2 {
-                    let audio_codec = ffprobe_stdout_json.streams.get(1).unwrap().codec_name.as_str();
-
-                    if audio_codec == "aac" {
-                        return true;
-                    }
-                }
-                else {
-                    return true;
-                }
+        let video_stream = ffprobe_stdout_json.get_stream("video");
+        let audio_stream = ffprobe_stdout_json.get_stream("audio");
+
+        // Both streams have to be known, an unknown second stream (e.g. subtitles) is rejected
+        let has_unknown_stream = stream_count == 2 && audio_stream.is_none();
+
+        if video_stream.is_some() && !has_unknown_stream {
+            let video_codec = video_stream.unwrap().codec_name.as_str();
+            let audio_codec = audio_stream.map(|stream| stream.codec_name.as_str());
+
+            // Videos which aren't web safe are transcoded by process_file
+            let format_is_ok = match (file_extension, video_format) {
+                // Allow h264, hevc, mpeg4, vp9 and av1 video codecs and aac, mp3, opus and ac3 audio codecs in the mp4 container format
+                ("mp4", "mov,mp4,m4a,3gp,3g2,mj2") => {
+                    (video_codec == "h264" || video_codec == "hevc" || video_codec == "mpeg4" || video_codec == "vp9" || video_codec == "av1")
+                        && (audio_codec.is_none() || audio_codec == Some("aac") || audio_codec == Some("mp3")
+                            || audio_codec == Some("opus") || audio_codec == Some("ac3"))
+                },
+                // Allow vp8, vp9 and av1 video codecs and vorbis and opus audio codecs in the webm container format
+                ("webm", "matroska,webm") => {
+                    (video_codec == "vp8" || video_codec == "vp9" || video_codec == "av1")
+                        && (audio_codec.is_none() || audio_codec == Some("vorbis") || audio_codec == Some("opus"))
+                },
+                _ => false,
+            };
+
+            if format_is_ok {
+                return UploadType::new(false, false, true);
             }
         }
     }
 
-    return false;
+    return None;
 }
 
 pub async fn process_file(config: &ProjectConfig, filename: &str) -> Result<FileProcessResult, FileProcessError> {
     let ffmpeg_filepath = config.filesystem_config.ffmpeg_path.get_value();
     let ffprobe_path = config.filesystem_config.ffprobe_path.get_value();
-    let format_data = probe_file(ffprobe_path.as_str(), filename).await;
+    let preview_variants = config.application_config.preview_variants.get_value().variants;
+    let mut upload_filename = filename.to_owned();
+
+    // The queued file stays unchanged until the result is stored, so an interrupted processing can start again
+    // (the leftovers of the interrupted processing are removed first)
+    delete_upload_work(config, filename).await;
+
     let tmp_upload_filepath = get_upload_path_tmp(filename);
-    let tmp_upload_prv_filepath = get_upload_prv_path_tmp(filename);
+    let work_upload_filepath = get_upload_path_work(filename);
+    let mkdir_work_success = create_parent_directory(work_upload_filepath.as_str()).await;
+
+    if !mkdir_work_success || tokio::fs::copy(tmp_upload_filepath.as_str(), work_upload_filepath.as_str()).await.is_err() {
+        return Err(FileProcessError::new(CopyError, "Fehler beim Kopieren der Datei ins Arbeitsverzeichnis"));
+    }
 
+    let format_data = probe_file(ffprobe_path.as_str(), filename).await;
     let return_val;
 
     if format_data.is_some() {
         let file_probe_result = format_data.unwrap();
         let ffprobe_data = &file_probe_result.ffprobe_output;
-        let generate_preview_success = generate_preview(ffmpeg_filepath.as_str(), &ffprobe_data, filename).await;
+        let upload_metadata_mode = config.application_config.upload_metadata_mode.get_value();
+
+        // The preview and the perceptual hash are created from the stripped (and upright) file
+        if upload_metadata_mode == UploadMetadataMode::Strip {
+            let strip_metadata_success = strip_metadata(ffmpeg_filepath.as_str(), filename, &file_probe_result.upload_type).await;
+
+            if !strip_metadata_success {
+                delete_upload_work(config, filename).await;
+
+                return Err(FileProcessError::new(MetadataError, "Fehler beim Entfernen der Metadaten"));
+            }
+        }
+
+        // Videos which wouldn't play in every browser are replaced by a h264/aac mp4 file (the filename can change)
+        if file_probe_result.upload_type == UploadType::Video {
+            let transcoded_filename = transcode_video(config, &ffprobe_data, filename).await;
+
+            if transcoded_filename.is_none() {
+                delete_upload_work(config, filename).await;
+
+                return Err(FileProcessError::new(TranscodeError, "Fehler beim Umwandeln des Videos"));
+            }
+
+            upload_filename = transcoded_filename.unwrap();
+        }
+
+        let filename = upload_filename.as_str();
+        let work_upload_filepath = get_upload_path_work(filename);
+
+        let generate_preview_success = generate_previews(ffmpeg_filepath.as_str(), &ffprobe_data, filename, &preview_variants).await;
+
+        // The upload is published without a clip, if it can't be generated (the preview images are enough)
+        let mut upload_has_prv_clip = generate_preview_success && has_preview_clip(config, &file_probe_result.upload_type)
+            && generate_preview_clip(config, filename, &preview_variants[0]).await;
 
         if generate_preview_success {
-            let srv_upload_directory = config.filesystem_config.uploads_path.get_value();
-            let srv_upload_prv_directory = config.filesystem_config.uploads_prv_path.get_value();
-            let srv_upload_filepath = format!("{}/{}", srv_upload_directory, filename);
-            let srv_upload_prv_filepath = format!("{}/{}", srv_upload_prv_directory, filename);
+            let srv_upload_filepath = get_upload_path_srv(config, filename);
+
+            // The shard directories are created on demand (all previews of an upload are in the same directory)
+            let mkdir_upload_success = create_parent_directory(srv_upload_filepath.as_str()).await;
+
+            if !mkdir_upload_success {
+                delete_upload_work(config, filename).await;
+
+                return Err(FileProcessError::new(CopyError, "Fehler beim Erstellen der Serververzeichnisse"));
+            }
+
+            let cpy_upload_success : core::result::Result<u64, _> = tokio::fs::copy(work_upload_filepath.as_str(), srv_upload_filepath.as_str()).await;
+            let cpy_upload_prv_success = copy_previews_srv(config, filename, &preview_variants).await;
+
+            if upload_has_prv_clip {
+                let tmp_upload_prv_clip_filepath = get_upload_prv_clip_path_tmp(filename);
+                let srv_upload_prv_clip_filepath = get_upload_prv_clip_path_srv(config, filename);
+                let cpy_result : core::result::Result<u64, _> = tokio::fs::copy(tmp_upload_prv_clip_filepath.as_str(), srv_upload_prv_clip_filepath.as_str()).await;
 
-            let cpy_upload_success : core::result::Result<u64, _> = tokio::fs::copy(tmp_upload_filepath.as_str(), srv_upload_filepath.as_str()).await;
-            let cpy_upload_prv_success : core::result::Result<u64, _> = tokio::fs::copy(tmp_upload_prv_filepath.as_str(), srv_upload_prv_filepath.as_str()).await;
+                if cpy_result.is_err() {
+                    warn!("Vorschauclip konnte nicht kopiert werden: {}", srv_upload_prv_clip_filepath.as_str());
+                    upload_has_prv_clip = false;
+                }
+            }
 
-            if cpy_upload_success.is_ok() && cpy_upload_prv_success.is_ok() {
-                let file_process_result = FileProcessResult::new(file_probe_result.upload_type);
+            if cpy_upload_success.is_ok() && cpy_upload_prv_success {
+                // A missing hash only disables the repost detection for this upload
+                let upload_phash = generate_perceptual_hash(ffmpeg_filepath.as_str(), filename).await;
+                let upload_prv_variants = PreviewVariantList { variants: preview_variants }.to_config_string();
+                let file_process_result = FileProcessResult::new(filename, file_probe_result.upload_type, upload_phash, upload_prv_variants.as_str(),
+                                                                 upload_has_prv_clip);
 
                 return_val = Ok(file_process_result);
             }
@@ -417,7 +1142,478 @@ pub async fn process_file(config: &ProjectConfig, filename: &str) -> Result<File
         return_val = Err(FileProcessError::new(FormatError, "Format der Datei wird nicht akzepziert"));
     }
 
-    delete_upload_tmp(filename).await;
+    delete_upload_work(config, upload_filename.as_str()).await;
 
     return return_val;
+}
+
+// Generates the configured preview variants of a processed upload again (the previews of the stored variants are kept)
+// Returns the new list of preview variants (has to be stored with the upload) or None if the previews couldn't be generated
+pub async fn regenerate_upload_previews(config: &ProjectConfig, upload_file: &UploadFileData) -> Option<String> {
+    let ffmpeg_filepath = config.filesystem_config.ffmpeg_path.get_value();
+    let ffprobe_path = config.filesystem_config.ffprobe_path.get_value();
+    let preview_variants = config.application_config.preview_variants.get_value().variants;
+    let filename = upload_file.upload_filename.as_str();
+
+    // The previews are generated from a working copy, like the previews of new uploads
+    let srv_upload_filepath = get_upload_path_srv(config, filename);
+    let work_upload_filepath = get_upload_path_work(filename);
+    let mkdir_work_success = create_parent_directory(work_upload_filepath.as_str()).await;
+    let cpy_upload_success : core::result::Result<u64, _> = tokio::fs::copy(srv_upload_filepath.as_str(), work_upload_filepath.as_str()).await;
+
+    if !mkdir_work_success || cpy_upload_success.is_err() {
+        error!("Upload Datei konnte nicht gelesen werden: {}", srv_upload_filepath.as_str());
+
+        return None;
+    }
+
+    let format_data = probe_file(ffprobe_path.as_str(), filename).await;
+    let mut regenerate_success = format_data.is_some();
+
+    if regenerate_success {
+        let ffprobe_data = &format_data.unwrap().ffprobe_output;
+
+        regenerate_success = generate_previews(ffmpeg_filepath.as_str(), ffprobe_data, filename, &preview_variants).await
+            && copy_previews_srv(config, filename, &preview_variants).await;
+    }
+
+    delete_upload_work(config, filename).await;
+
+    if regenerate_success {
+        return Some(PreviewVariantList { variants: preview_variants }.to_config_string());
+    }
+
+    return None;
+}
+
+// Writes the upload file without its metadata blocks to stripped_filepath and replaces the upload file with it
+// The image data is copied unchanged, so this works for every image format (including animations) without losing quality
+async fn strip_image_metadata(upload_filepath: &str, stripped_filepath: &str, file_extension: &str) -> bool {
+    let file_content = tokio::fs::read(upload_filepath).await;
+
+    if file_content.is_ok() {
+        let stripped_content = strip_file_metadata(file_content.unwrap().as_slice(), file_extension);
+
+        if stripped_content.is_some() {
+            let write_result : tokio::io::Result<()> = tokio::fs::write(stripped_filepath, stripped_content.unwrap()).await;
+
+            if write_result.is_ok() && tokio::fs::rename(stripped_filepath, upload_filepath).await.is_ok() {
+                return true;
+            }
+        }
+    }
+
+    error!("Metadaten konnten nicht entfernt werden: {}", upload_filepath);
+    let _rm_stripped_success : tokio::io::Result<()> = tokio::fs::remove_file(stripped_filepath).await;
+
+    return false;
+}
+
+// Removes the metadata of the tmp upload file (replaces the tmp file on success)
+// Images are rewritten without their metadata blocks, only static images with an exif orientation are re-encoded by ffmpeg to apply it
+// Videos are remuxed by ffmpeg without re-encoding
+async fn strip_metadata(ffmpeg_filepath: &str, filename: &str, upload_type: &UploadType) -> bool {
+    let file_extension = get_file_extension(filename);
+    let upload_filepath = get_upload_path_work(filename);
+    let stripped_filepath = get_upload_path_work(format!("stripped_{}", filename).as_str());
+    let is_video = *upload_type == UploadType::Video;
+    let mut orientation_filter = None;
+
+    if *upload_type == UploadType::Image {
+        let exif_orientation = read_exif_orientation(upload_filepath.as_str(), file_extension.as_str()).await;
+        orientation_filter = get_orientation_filter(exif_orientation);
+    }
+
+    if !is_video && orientation_filter.is_none() {
+        return strip_image_metadata(upload_filepath.as_str(), stripped_filepath.as_str(), file_extension.as_str()).await;
+    }
+
+    let mut ffmpeg_args : Vec<String> = Vec::new();
+    ffmpeg_args.push("-loglevel".to_owned());
+    ffmpeg_args.push("quiet".to_owned());
+    ffmpeg_args.push("-y".to_owned());
+
+    if !is_video {
+        // Newer ffmpeg versions rotate jpeg files on their own, so the orientation is only applied by the filter
+        ffmpeg_args.push("-noautorotate".to_owned());
+        ffmpeg_args.push("-i".to_owned());
+        ffmpeg_args.push(upload_filepath.clone());
+        ffmpeg_args.push("-map_metadata".to_owned());
+        ffmpeg_args.push("-1".to_owned());
+        ffmpeg_args.push("-frames:v".to_owned());
+        ffmpeg_args.push("1".to_owned());
+        ffmpeg_args.push("-filter:v".to_owned());
+        ffmpeg_args.push(orientation_filter.unwrap().to_owned());
+
+        if file_extension == "jpg" {
+            ffmpeg_args.push("-qscale:v".to_owned());
+            ffmpeg_args.push("2".to_owned());
+        }
+        else if file_extension == "webp" {
+            ffmpeg_args.push("-quality".to_owned());
+            ffmpeg_args.push("90".to_owned());
+        }
+    }
+    else {
+        ffmpeg_args.push("-i".to_owned());
+        ffmpeg_args.push(upload_filepath.clone());
+        ffmpeg_args.push("-map".to_owned());
+        ffmpeg_args.push("0".to_owned());
+        ffmpeg_args.push("-map_metadata".to_owned());
+        ffmpeg_args.push("-1".to_owned());
+        ffmpeg_args.push("-map_chapters".to_owned());
+        ffmpeg_args.push("-1".to_owned());
+        ffmpeg_args.push("-c".to_owned());
+        ffmpeg_args.push("copy".to_owned());
+    }
+
+    ffmpeg_args.push(stripped_filepath.clone());
+
+    // Let the OS take care of finding the ffmpeg binary if there is no path provided
+    let command = if !ffmpeg_filepath.is_empty() {
+        ffmpeg_filepath
+    }
+    else {
+        "ffmpeg"
+    };
+
+    let ffmpeg_result : std::io::Result<Output> = Command::new(command)
+        .args(ffmpeg_args)
+        .output()
+        .await;
+
+    if ffmpeg_result.is_ok() && ffmpeg_result.unwrap().status.success() {
+        let rename_result : tokio::io::Result<()> = tokio::fs::rename(stripped_filepath.as_str(), upload_filepath.as_str()).await;
+
+        if rename_result.is_ok() {
+            return true;
+        }
+    }
+
+    error!("Metadaten konnten nicht entfernt werden: {}", upload_filepath.as_str());
+    let _rm_stripped_success : tokio::io::Result<()> = tokio::fs::remove_file(stripped_filepath.as_str()).await;
+
+    return false;
+}
+
+// Returns the filename of the web safe video (the original file if it is already web safe, else a transcoded mp4 file)
+// The original tmp file is removed if it was transcoded
+async fn transcode_video(config: &ProjectConfig, ffprobe_data: &FFprobeOutput, filename: &str) -> Option<String> {
+    let ffmpeg_filepath = config.filesystem_config.ffmpeg_path.get_value();
+    let video_crf = config.application_config.video_crf.get_value().min(51).to_string();
+    let max_resolution = config.application_config.video_max_resolution.get_value();
+    let upload_filepath = get_upload_path_work(filename);
+    let video_stream = ffprobe_data.get_stream("video");
+    let audio_stream = ffprobe_data.get_stream("audio");
+
+    if video_stream.is_none() {
+        return None;
+    }
+
+    let video_stream = video_stream.unwrap();
+    let (width, height) = get_video_resolution(video_stream.width, video_stream.height, max_resolution);
+
+    // Web safe: h264 (8 bit 4:2:0) with aac audio in a faststart mp4 file, which doesn't need to be scaled down
+    // (browsers play h264 videos with odd sides, so they aren't transcoded only to round the sides)
+    let is_web_safe = get_file_extension(filename) == "mp4"
+        && video_stream.codec_name == "h264"
+        && video_stream.pix_fmt == "yuv420p"
+        && (audio_stream.is_none() || audio_stream.unwrap().codec_name == "aac")
+        && width == video_stream.width && height == video_stream.height
+        && is_faststart_mp4_file(upload_filepath.as_str()).await;
+
+    if is_web_safe {
+        return Some(filename.to_owned());
+    }
+
+    let file_stem = filename.split('.').next().unwrap_or(filename);
+    let transcoded_filename = format!("{}.mp4", file_stem);
+    let output_filepath = get_upload_path_work(format!("transcoded_{}", transcoded_filename).as_str());
+    let ffmpeg_filter = get_video_scale_filter(max_resolution);
+
+    let mut ffmpeg_args : Vec<&str> = Vec::new();
+    ffmpeg_args.push("-loglevel");
+    ffmpeg_args.push("error");
+    ffmpeg_args.push("-y");
+    ffmpeg_args.push("-i");
+    ffmpeg_args.push(upload_filepath.as_str());
+    ffmpeg_args.push("-map");
+    ffmpeg_args.push("0:v:0");
+    ffmpeg_args.push("-map");
+    ffmpeg_args.push("0:a:0?");
+    ffmpeg_args.push("-filter:v");
+    ffmpeg_args.push(ffmpeg_filter.as_str());
+    ffmpeg_args.push("-c:v");
+    ffmpeg_args.push("libx264");
+    ffmpeg_args.push("-preset");
+    ffmpeg_args.push("medium");
+    ffmpeg_args.push("-crf");
+    ffmpeg_args.push(video_crf.as_str());
+    ffmpeg_args.push("-pix_fmt");
+    ffmpeg_args.push("yuv420p");
+    ffmpeg_args.push("-c:a");
+    ffmpeg_args.push("aac");
+    ffmpeg_args.push("-b:a");
+    ffmpeg_args.push("128k");
+    ffmpeg_args.push("-movflags");
+    ffmpeg_args.push("+faststart");
+    ffmpeg_args.push(output_filepath.as_str());
+
+    // Let the OS take care of finding the ffmpeg binary if there is no path provided
+    let command = if !ffmpeg_filepath.is_empty() {
+        ffmpeg_filepath.as_str()
+    }
+    else {
+        "ffmpeg"
+    };
+
+    // The child process gets killed, if the timeout drops the future
+    let transcode_timeout = Duration::from_secs(config.application_config.video_transcode_timeout.get_value() as u64);
+    let ffmpeg_result = tokio::time::timeout(transcode_timeout, Command::new(command)
+        .args(ffmpeg_args)
+        .kill_on_drop(true)
+        .output()
+    ).await;
+
+    let is_transcoded = match ffmpeg_result {
+        Ok(Ok(ffmpeg_output)) if ffmpeg_output.status.success() => true,
+        Ok(Ok(ffmpeg_output)) => {
+            error!("ffmpeg Fehlerausgabe: {}", String::from_utf8_lossy(&ffmpeg_output.stderr).trim());
+            false
+        },
+        Ok(Err(error)) => {
+            error!("ffmpeg konnte nicht ausgeführt werden: {}", error);
+            false
+        },
+        Err(_) => {
+            error!("Zeitlimit beim Umwandeln des Videos überschritten: {}", upload_filepath.as_str());
+            false
+        },
+    };
+
+    if is_transcoded {
+        let transcoded_filepath = get_upload_path_work(transcoded_filename.as_str());
+        let _rm_upload_success : tokio::io::Result<()> = tokio::fs::remove_file(upload_filepath.as_str()).await;
+        let rename_result : tokio::io::Result<()> = tokio::fs::rename(output_filepath.as_str(), transcoded_filepath.as_str()).await;
+
+        if rename_result.is_ok() {
+            return Some(transcoded_filename);
+        }
+    }
+
+    error!("Video konnte nicht umgewandelt werden: {}", upload_filepath.as_str());
+    let _rm_transcoded_success : tokio::io::Result<()> = tokio::fs::remove_file(output_filepath.as_str()).await;
+
+    return None;
+}
+
+fn write_upload_archive(archive_filepath: &str, upload_files: &[(String, String)]) -> bool {
+    let archive_file = File::create(archive_filepath);
+
+    if archive_file.is_err() {
+        return false;
+    }
+
+    let mut zip_writer = ZipWriter::new(archive_file.unwrap());
+
+    // Uploads are already compressed media files
+    let file_options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    for (upload_filename, upload_filepath) in upload_files {
+        let upload_file = File::open(upload_filepath.as_str());
+
+        // A missing file shouldn't prevent the export of the other files
+        if upload_file.is_err() {
+            warn!("Upload Datei konnte nicht exportiert werden: {}", upload_filepath.as_str());
+
+            continue;
+        }
+
+        let mut upload_file = upload_file.unwrap();
+        let start_success = zip_writer.start_file(upload_filename.as_str(), file_options);
+
+        if start_success.is_err() || std::io::copy(&mut upload_file, &mut zip_writer).is_err() {
+            return false;
+        }
+    }
+
+    zip_writer.finish().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn append_upload_chunks_continues_at_stored_offset() {
+        let filepath = std::env::temp_dir().join(format!("crate_test_{}_chunks.bin", std::process::id()));
+        let filepath_str = filepath.to_str().unwrap();
+
+        // The last two bytes are from a request which failed before its offset was stored
+        std::fs::write(&filepath, b"ponyXX").unwrap();
+
+        let mut chunk_stream = futures::stream::iter(vec![Ok::<_, ()>(b"gra".to_vec()), Ok(b"mm".to_vec())]);
+        assert_eq!(append_upload_chunks(filepath_str, 4, 16, &mut chunk_stream).await, Some((9, false)));
+        assert_eq!(std::fs::read(&filepath).unwrap(), b"ponygramm");
+
+        // A broken connection keeps the part which was received before
+        let mut chunk_stream = futures::stream::iter(vec![Ok(b"!".to_vec()), Err(()), Ok(b"?".to_vec())]);
+        assert_eq!(append_upload_chunks(filepath_str, 9, 16, &mut chunk_stream).await, Some((10, false)));
+
+        let mut chunk_stream = futures::stream::iter(vec![Ok::<_, ()>(b"12345".to_vec()), Ok(b"67".to_vec())]);
+        assert_eq!(append_upload_chunks(filepath_str, 10, 16, &mut chunk_stream).await, Some((15, true)));
+        assert_eq!(std::fs::read(&filepath).unwrap(), b"ponygramm!12345");
+
+        std::fs::remove_file(&filepath).unwrap();
+
+        let mut chunk_stream = futures::stream::iter(vec![Ok::<_, ()>(b"pony".to_vec())]);
+        assert_eq!(append_upload_chunks(filepath_str, 0, 16, &mut chunk_stream).await, None);
+    }
+
+    #[test]
+    fn generate_upload_filename_normalizes_extension() {
+        assert!(generate_upload_filename("Pony.JPEG").unwrap().ends_with(".jpg"));
+        assert!(generate_upload_filename("Pony.avif").unwrap().ends_with(".avif"));
+        assert!(is_image_file("Pony.jpeg"));
+    }
+
+    #[test]
+    fn get_perceptual_hash_packs_rows_from_highest_bit() {
+        let mut pixels = vec![128u8; PHASH_WIDTH * PHASH_HEIGHT];
+        assert_eq!(get_perceptual_hash(&pixels), 0);
+
+        // First pixel pair of the first row
+        pixels[0] = 0;
+        assert_eq!(get_perceptual_hash(&pixels) as u64, 1 << 63);
+
+        // Last pixel pair of the last row, the last column is only compared and has no bit of its own
+        pixels[0] = 128;
+        pixels[PHASH_WIDTH * PHASH_HEIGHT - 2] = 0;
+        assert_eq!(get_perceptual_hash(&pixels), 1);
+
+        // First pixel pair of the second row
+        pixels[PHASH_WIDTH * PHASH_HEIGHT - 2] = 128;
+        pixels[PHASH_WIDTH] = 0;
+        assert_eq!(get_perceptual_hash(&pixels) as u64, 1 << 55);
+
+        let gradient : Vec<u8> = (0..PHASH_WIDTH * PHASH_HEIGHT).map(|pixel_pos| (pixel_pos % PHASH_WIDTH) as u8).collect();
+        assert_eq!(get_perceptual_hash(&gradient), -1);
+    }
+
+    #[test]
+    fn get_sharded_path_only_shards_generated_names() {
+        let storage_filename = generate_upload_filename("Pony.PNG").unwrap();
+        let sharded_path = get_sharded_path(storage_filename.as_str());
+
+        assert!(storage_filename.ends_with(".png"));
+        assert_eq!(sharded_path, format!("{}/{}/{}", &storage_filename[0..2], &storage_filename[2..4], storage_filename));
+        assert_eq!(get_sharded_path(storage_filename.replace(".png", "_prv.jpg").as_str()).len(), sharded_path.len() + 4);
+        assert_eq!(get_sharded_path("pony.png"), "pony.png");
+        assert_eq!(get_sharded_path("0123456789abcdef.png"), "0123456789abcdef.png");
+        assert!(generate_upload_filename("pony").is_none());
+    }
+
+    #[test]
+    fn get_stored_preview_variants_falls_back_to_legacy_preview() {
+        let legacy_preview_variants = get_stored_preview_variants(None);
+        let preview_variants = get_stored_preview_variants(Some("thumb:200x200:crop:webp"));
+
+        assert_eq!(legacy_preview_variants.len(), 1);
+        assert_eq!(get_preview_filename("pony.png", &legacy_preview_variants[0]), "pony.jpg");
+        assert_eq!(get_preview_filename("pony.png", &preview_variants[0]), "pony_thumb.webp");
+        assert!(get_stored_preview_variants(Some("invalid")).is_empty());
+    }
+    #[test]
+    fn get_video_resolution_scales_shorter_side() {
+        assert_eq!(get_video_resolution(3840, 2160, 1080), (1920, 1080));
+        assert_eq!(get_video_resolution(1080, 1920, 720), (720, 1280));
+        assert_eq!(get_video_resolution(1279, 721, 720), (1277, 720));
+        assert_eq!(get_video_resolution(641, 361, 0), (641, 361));
+        assert_eq!(get_video_resolution(641, 361, 720), (641, 361));
+    }
+
+    #[test]
+    fn get_video_scale_filter_keeps_aspect_ratio() {
+        assert_eq!(get_video_scale_filter(0), "scale=trunc(iw/2)*2:trunc(ih/2)*2");
+        assert_eq!(get_video_scale_filter(720), "scale='if(lte(iw,ih),trunc(min(iw,720)/2)*2,-2)':'if(lte(iw,ih),-2,trunc(min(ih,720)/2)*2)'");
+    }
+
+    #[tokio::test]
+    async fn is_faststart_mp4_file_checks_box_order() {
+        let mut faststart_content = Vec::new();
+        faststart_content.extend_from_slice(&[0, 0, 0, 16]);
+        faststart_content.extend_from_slice(b"ftypisom\0\0\0\0");
+        faststart_content.extend_from_slice(&[0, 0, 0, 1]);
+        faststart_content.extend_from_slice(b"free");
+        faststart_content.extend_from_slice(&16u64.to_be_bytes());
+        faststart_content.extend_from_slice(&[0, 0, 0, 8]);
+        faststart_content.extend_from_slice(b"moov");
+        faststart_content.extend_from_slice(&[0, 0, 0, 8]);
+        faststart_content.extend_from_slice(b"mdat");
+
+        let mut late_moov_content = faststart_content[0..32].to_vec();
+        late_moov_content.extend_from_slice(&[0, 0, 0, 8]);
+        late_moov_content.extend_from_slice(b"mdat");
+        late_moov_content.extend_from_slice(&[0, 0, 0, 8]);
+        late_moov_content.extend_from_slice(b"moov");
+
+        let faststart_filepath = std::env::temp_dir().join(format!("crate_test_{}_faststart.mp4", std::process::id()));
+        let late_moov_filepath = std::env::temp_dir().join(format!("crate_test_{}_late_moov.mp4", std::process::id()));
+        std::fs::write(&faststart_filepath, faststart_content).unwrap();
+        std::fs::write(&late_moov_filepath, late_moov_content).unwrap();
+
+        assert!(is_faststart_mp4_file(faststart_filepath.to_str().unwrap()).await);
+        assert!(!is_faststart_mp4_file(late_moov_filepath.to_str().unwrap()).await);
+        assert!(!is_faststart_mp4_file("/nonexistent/pony.mp4").await);
+
+        std::fs::remove_file(faststart_filepath).unwrap();
+        std::fs::remove_file(late_moov_filepath).unwrap();
+    }
+    #[tokio::test]
+    async fn process_file_can_run_again_on_the_same_file() {
+        // ffmpeg and ffprobe aren't installed in every build environment
+        if std::process::Command::new("ffmpeg").arg("-version").output().is_err() || std::process::Command::new("ffprobe").arg("-version").output().is_err() {
+            return;
+        }
+
+        let srv_path = std::env::temp_dir().join(format!("crate_test_{}_srv", std::process::id()));
+        let srv_path_str = srv_path.to_str().unwrap();
+        let mut config = ProjectConfig::init().unwrap();
+        config.filesystem_config.ffmpeg_path.set_value(String::new());
+        config.filesystem_config.ffprobe_path.set_value(String::new());
+        config.filesystem_config.uploads_path.set_value(format!("{}/uploads", srv_path_str));
+        config.filesystem_config.uploads_prv_path.set_value(format!("{}/uploads-prv", srv_path_str));
+
+        let filename = generate_upload_filename("pony.png").unwrap();
+        let tmp_upload_filepath = get_upload_path_tmp(filename.as_str());
+        assert!(create_parent_directory(tmp_upload_filepath.as_str()).await);
+
+        let ffmpeg_result = std::process::Command::new("ffmpeg")
+            .args(&["-loglevel", "quiet", "-f", "lavfi", "-i", "testsrc=size=64x48", "-frames:v", "1", tmp_upload_filepath.as_str()])
+            .output()
+            .unwrap();
+        assert!(ffmpeg_result.status.success());
+
+        // Leftovers of a processing which was interrupted after the previews were generated
+        let preview_variants = config.application_config.preview_variants.get_value().variants;
+        let work_upload_filepath = get_upload_path_work(filename.as_str());
+        assert!(create_parent_directory(work_upload_filepath.as_str()).await);
+        std::fs::write(work_upload_filepath.as_str(), b"pony").unwrap();
+
+        for preview_variant in preview_variants.iter() {
+            let tmp_upload_prv_filepath = get_upload_prv_path_tmp(filename.as_str(), preview_variant);
+            assert!(create_parent_directory(tmp_upload_prv_filepath.as_str()).await);
+            std::fs::write(tmp_upload_prv_filepath.as_str(), b"pony").unwrap();
+        }
+
+        assert!(process_file(&config, filename.as_str()).await.is_ok());
+        assert!(process_file(&config, filename.as_str()).await.is_ok());
+        assert!(Path::new(tmp_upload_filepath.as_str()).is_file());
+        assert!(!Path::new(work_upload_filepath.as_str()).exists());
+
+        let upload_prv_variants = PreviewVariantList { variants: preview_variants }.to_config_string();
+        delete_upload_srv(&config, filename.as_str(), Some(upload_prv_variants.as_str()), false).await;
+        delete_upload_tmp(filename.as_str()).await;
+        std::fs::remove_dir_all(srv_path).unwrap();
+    }
 }
\ No newline at end of file
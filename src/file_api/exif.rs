@@ -0,0 +1,299 @@
+use log::{warn};
+
+const EXIF_ORIENTATION_TAG : u16 = 0x0112;
+const EXIF_HEADER : &[u8] = b"Exif\0\0";
+
+// Returns the ffmpeg filter which applies the exif orientation to the pixels (None if the image is already upright)
+pub fn get_orientation_filter(orientation: u16) -> Option<&'static str> {
+    match orientation {
+        2 => Some("hflip"),
+        3 => Some("hflip,vflip"),
+        4 => Some("vflip"),
+        5 => Some("transpose=cclock_flip"),
+        6 => Some("transpose=clock"),
+        7 => Some("transpose=clock_flip"),
+        8 => Some("transpose=cclock"),
+        _ => None,
+    }
+}
+
+// Searches the APP1 segment with the exif data in a jpeg file
+fn find_jpeg_exif(file_content: &[u8]) -> Option<&[u8]> {
+    if file_content.len() < 4 || file_content[0..2] != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut pos = 2;
+
+    while pos + 4 <= file_content.len() && file_content[pos] == 0xFF {
+        let marker = file_content[pos + 1];
+
+        // The metadata segments are all in front of the image data (start of scan)
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+
+        let segment_length = read_u16(&file_content[pos + 2..pos + 4], false) as usize;
+        let segment_end = pos + 2 + segment_length;
+
+        if segment_length < 2 || segment_end > file_content.len() {
+            break;
+        }
+
+        let segment_data = &file_content[pos + 4..segment_end];
+
+        if marker == 0xE1 && segment_data.starts_with(EXIF_HEADER) {
+            return Some(&segment_data[EXIF_HEADER.len()..]);
+        }
+
+        pos = segment_end;
+    }
+
+    return None;
+}
+
+// Searches the eXIf chunk in a png file
+fn find_png_exif(file_content: &[u8]) -> Option<&[u8]> {
+    if file_content.len() < 8 || &file_content[0..8] != b"\x89PNG\r\n\x1a\n" {
+        return None;
+    }
+
+    let mut pos = 8;
+
+    // Every chunk has a length, a type, the data and a crc
+    while pos + 12 <= file_content.len() {
+        let chunk_length = read_u32(&file_content[pos..pos + 4], false) as usize;
+        let chunk_type = &file_content[pos + 4..pos + 8];
+        let chunk_end = pos + 12 + chunk_length;
+
+        if chunk_end > file_content.len() || chunk_type == b"IDAT" {
+            break;
+        }
+
+        if chunk_type == b"eXIf" {
+            return Some(&file_content[pos + 8..pos + 8 + chunk_length]);
+        }
+
+        pos = chunk_end;
+    }
+
+    return None;
+}
+
+// Searches the EXIF chunk in a webp file
+fn find_webp_exif(file_content: &[u8]) -> Option<&[u8]> {
+    if file_content.len() < 12 || &file_content[0..4] != b"RIFF" || &file_content[8..12] != b"WEBP" {
+        return None;
+    }
+
+    let mut pos = 12;
+
+    // Chunks are padded to an even size
+    while pos + 8 <= file_content.len() {
+        let chunk_type = &file_content[pos..pos + 4];
+        let chunk_length = read_u32(&file_content[pos + 4..pos + 8], true) as usize;
+        let chunk_end = pos + 8 + chunk_length;
+
+        if chunk_end > file_content.len() {
+            break;
+        }
+
+        if chunk_type == b"EXIF" {
+            let chunk_data = &file_content[pos + 8..chunk_end];
+
+            // Some encoders keep the jpeg exif header
+            if chunk_data.starts_with(EXIF_HEADER) {
+                return Some(&chunk_data[EXIF_HEADER.len()..]);
+            }
+
+            return Some(chunk_data);
+        }
+
+        pos = chunk_end + (chunk_length % 2);
+    }
+
+    return None;
+}
+
+// Reads the orientation entry from the first ifd of the exif data (tiff structure)
+fn parse_exif_orientation(exif_data: &[u8]) -> Option<u16> {
+    if exif_data.len() < 8 {
+        return None;
+    }
+
+    let is_little_endian = match &exif_data[0..2] {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+
+    if read_u16(&exif_data[2..4], is_little_endian) != 42 {
+        return None;
+    }
+
+    let ifd_offset = read_u32(&exif_data[4..8], is_little_endian) as usize;
+
+    if ifd_offset + 2 > exif_data.len() {
+        return None;
+    }
+
+    let entry_count = read_u16(&exif_data[ifd_offset..ifd_offset + 2], is_little_endian) as usize;
+
+    for entry_index in 0..entry_count {
+        let entry_pos = ifd_offset + 2 + entry_index * 12;
+
+        if entry_pos + 12 > exif_data.len() {
+            break;
+        }
+
+        let entry_tag = read_u16(&exif_data[entry_pos..entry_pos + 2], is_little_endian);
+
+        if entry_tag == EXIF_ORIENTATION_TAG {
+            // The value (a short) is stored directly in the entry
+            let orientation = read_u16(&exif_data[entry_pos + 8..entry_pos + 10], is_little_endian);
+
+            if orientation >= 1 && orientation <= 8 {
+                return Some(orientation);
+            }
+        }
+    }
+
+    return None;
+}
+
+// Returns the exif orientation (1 to 8) of a jpeg, png or webp file (1 if the file has no orientation entry)
+pub async fn read_exif_orientation(filepath: &str, file_extension: &str) -> u16 {
+    let file_content = tokio::fs::read(filepath).await;
+
+    if file_content.is_err() {
+        warn!("Datei konnte nicht gelesen werden: {}", filepath);
+
+        return 1;
+    }
+
+    let file_content = file_content.unwrap();
+    let exif_data = match file_extension {
+        "jpg" => find_jpeg_exif(&file_content),
+        "png" => find_png_exif(&file_content),
+        "webp" => find_webp_exif(&file_content),
+        _ => None,
+    };
+
+    exif_data.and_then(parse_exif_orientation).unwrap_or(1)
+}
+
+pub fn read_u16(bytes: &[u8], is_little_endian: bool) -> u16 {
+    let bytes = [bytes[0], bytes[1]];
+
+    if is_little_endian {
+        u16::from_le_bytes(bytes)
+    }
+    else {
+        u16::from_be_bytes(bytes)
+    }
+}
+
+pub fn read_u32(bytes: &[u8], is_little_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+
+    if is_little_endian {
+        u32::from_le_bytes(bytes)
+    }
+    else {
+        u32::from_be_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tiff structure with a single ifd entry for the orientation
+    fn get_test_exif_data(orientation: u16, is_little_endian: bool) -> Vec<u8> {
+        let mut exif_data = Vec::new();
+
+        if is_little_endian {
+            exif_data.extend_from_slice(b"II");
+            exif_data.extend_from_slice(&42u16.to_le_bytes());
+            exif_data.extend_from_slice(&8u32.to_le_bytes());
+            exif_data.extend_from_slice(&1u16.to_le_bytes());
+            exif_data.extend_from_slice(&EXIF_ORIENTATION_TAG.to_le_bytes());
+            exif_data.extend_from_slice(&3u16.to_le_bytes());
+            exif_data.extend_from_slice(&1u32.to_le_bytes());
+            exif_data.extend_from_slice(&orientation.to_le_bytes());
+        }
+        else {
+            exif_data.extend_from_slice(b"MM");
+            exif_data.extend_from_slice(&42u16.to_be_bytes());
+            exif_data.extend_from_slice(&8u32.to_be_bytes());
+            exif_data.extend_from_slice(&1u16.to_be_bytes());
+            exif_data.extend_from_slice(&EXIF_ORIENTATION_TAG.to_be_bytes());
+            exif_data.extend_from_slice(&3u16.to_be_bytes());
+            exif_data.extend_from_slice(&1u32.to_be_bytes());
+            exif_data.extend_from_slice(&orientation.to_be_bytes());
+        }
+
+        exif_data.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+        return exif_data;
+    }
+
+    #[test]
+    fn find_jpeg_exif_skips_other_segments() {
+        let exif_data = get_test_exif_data(6, false);
+        let mut file_content = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xE1];
+        file_content.extend_from_slice(&((exif_data.len() + EXIF_HEADER.len() + 2) as u16).to_be_bytes());
+        file_content.extend_from_slice(EXIF_HEADER);
+        file_content.extend_from_slice(&exif_data);
+        file_content.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+
+        assert_eq!(find_jpeg_exif(&file_content), Some(exif_data.as_slice()));
+        assert_eq!(find_jpeg_exif(&file_content[0..12]), None);
+        assert_eq!(find_jpeg_exif(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn find_png_exif_stops_at_image_data() {
+        let exif_data = get_test_exif_data(3, true);
+        let mut file_content = b"\x89PNG\r\n\x1a\n".to_vec();
+        file_content.extend_from_slice(&(exif_data.len() as u32).to_be_bytes());
+        file_content.extend_from_slice(b"eXIf");
+        file_content.extend_from_slice(&exif_data);
+        file_content.extend_from_slice(&[0, 0, 0, 0]);
+
+        assert_eq!(find_png_exif(&file_content), Some(exif_data.as_slice()));
+
+        let mut late_exif_content = b"\x89PNG\r\n\x1a\n".to_vec();
+        late_exif_content.extend_from_slice(&[0, 0, 0, 0]);
+        late_exif_content.extend_from_slice(b"IDAT");
+        late_exif_content.extend_from_slice(&[0, 0, 0, 0]);
+        late_exif_content.extend_from_slice(&file_content[8..]);
+
+        assert_eq!(find_png_exif(&late_exif_content), None);
+    }
+
+    #[test]
+    fn find_webp_exif_removes_jpeg_header() {
+        let exif_data = get_test_exif_data(8, true);
+        let mut chunk_data = EXIF_HEADER.to_vec();
+        chunk_data.extend_from_slice(&exif_data);
+
+        let mut file_content = b"RIFF\0\0\0\0WEBPVP8X".to_vec();
+        file_content.extend_from_slice(&10u32.to_le_bytes());
+        file_content.extend_from_slice(&[0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        file_content.extend_from_slice(b"EXIF");
+        file_content.extend_from_slice(&(chunk_data.len() as u32).to_le_bytes());
+        file_content.extend_from_slice(&chunk_data);
+
+        assert_eq!(find_webp_exif(&file_content), Some(exif_data.as_slice()));
+    }
+
+    #[test]
+    fn parse_exif_orientation_reads_both_byte_orders() {
+        assert_eq!(parse_exif_orientation(&get_test_exif_data(6, true)), Some(6));
+        assert_eq!(parse_exif_orientation(&get_test_exif_data(8, false)), Some(8));
+        assert_eq!(parse_exif_orientation(&get_test_exif_data(9, true)), None);
+        assert_eq!(parse_exif_orientation(&get_test_exif_data(6, true)[0..12]), None);
+        assert_eq!(parse_exif_orientation(b"XX*\0\x08\0\0\0"), None);
+    }
+}
@@ -0,0 +1,544 @@
+use crate::file_api::exif::read_u32;
+
+const JPEG_ICC_PROFILE_HEADER : &[u8] = b"ICC_PROFILE\0";
+const JPEG_ADOBE_HEADER : &[u8] = b"Adobe"; // Needed to decode the colors of cmyk files
+const JPEG_JFIF_HEADER : &[u8] = b"JFIF\0";
+const PNG_METADATA_CHUNKS : [&[u8]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+const WEBP_EXIF_FLAG : u8 = 0x08; // Bits in the flags of the VP8X chunk
+const WEBP_XMP_FLAG : u8 = 0x04;
+const AVIF_XMP_CONTENT_TYPE : &[u8] = b"application/rdf+xml";
+
+// Searches a box of the given type between start and end of an iso media file (avif)
+// Returns the start of the box content and the end of the box
+fn find_iso_box(file_content: &[u8], start: usize, end: usize, box_type: &[u8]) -> Option<(usize, usize)> {
+    let mut pos = start;
+
+    while pos + 8 <= end {
+        let mut box_size = read_be_value(file_content, pos, 4)?;
+        let mut header_size = 8;
+
+        // Size 1 means that the real size follows as 64 bit value, size 0 means that the box ends with the parent
+        if box_size == 1 {
+            box_size = read_be_value(file_content, pos + 8, 8)?;
+            header_size = 16;
+        }
+        else if box_size == 0 {
+            box_size = end - pos;
+        }
+
+        let box_end = pos.checked_add(box_size)?;
+
+        if box_size < header_size || box_end > end {
+            return None;
+        }
+
+        if &file_content[pos + 4..pos + 8] == box_type {
+            return Some((pos + header_size, box_end));
+        }
+
+        pos = box_end;
+    }
+
+    return None;
+}
+
+// Returns the position and length of every extent of the given items from the content of the iloc box
+// Returns None if an item isn't stored directly in the file
+fn get_avif_item_extents(iloc_content: &[u8], item_ids: &[usize]) -> Option<Vec<(usize, usize)>> {
+    let iloc_version = read_be_value(iloc_content, 0, 1)?;
+    let size_fields = read_be_value(iloc_content, 4, 2)?;
+    let offset_size = (size_fields >> 12) & 0x0F;
+    let length_size = (size_fields >> 8) & 0x0F;
+    let base_offset_size = (size_fields >> 4) & 0x0F;
+    let index_size = if iloc_version == 1 || iloc_version == 2 { size_fields & 0x0F } else { 0 };
+    let id_size = if iloc_version < 2 { 2 } else { 4 };
+    let item_count = read_be_value(iloc_content, 6, id_size)?;
+    let mut pos = 6 + id_size;
+    let mut item_extents = Vec::new();
+
+    for _ in 0..item_count {
+        let item_id = read_be_value(iloc_content, pos, id_size)?;
+        pos += id_size;
+
+        // Method 0 means that the data is stored at a position in the file (and not in the idat box or another item)
+        let mut construction_method = 0;
+
+        if iloc_version == 1 || iloc_version == 2 {
+            construction_method = read_be_value(iloc_content, pos, 2)? & 0x0F;
+            pos += 2;
+        }
+
+        let base_offset = read_be_value(iloc_content, pos + 2, base_offset_size)?;
+        let extent_count = read_be_value(iloc_content, pos + 2 + base_offset_size, 2)?;
+        pos += 4 + base_offset_size;
+
+        for _ in 0..extent_count {
+            let extent_offset = read_be_value(iloc_content, pos + index_size, offset_size)?;
+            let extent_length = read_be_value(iloc_content, pos + index_size + offset_size, length_size)?;
+            pos += index_size + offset_size + length_size;
+
+            if item_ids.contains(&item_id) {
+                // A length of 0 means the whole file, which can't be metadata
+                if construction_method != 0 || extent_length == 0 {
+                    return None;
+                }
+
+                item_extents.push((base_offset.checked_add(extent_offset)?, extent_length));
+            }
+        }
+    }
+
+    return Some(item_extents);
+}
+
+// Returns the ids of the exif and xmp items from the content of the iinf box
+fn get_avif_metadata_items(iinf_content: &[u8]) -> Option<Vec<usize>> {
+    let iinf_version = read_be_value(iinf_content, 0, 1)?;
+    let entry_count_size = if iinf_version == 0 { 2 } else { 4 };
+    let entry_count = read_be_value(iinf_content, 4, entry_count_size)?;
+    let mut pos = 4 + entry_count_size;
+    let mut metadata_items = Vec::new();
+
+    for _ in 0..entry_count {
+        let (infe_start, infe_end) = find_iso_box(iinf_content, pos, iinf_content.len(), b"infe")?;
+        let infe_content = &iinf_content[infe_start..infe_end];
+        let infe_version = read_be_value(infe_content, 0, 1)?;
+
+        // Older versions of the item info entry have no item type
+        if infe_version >= 2 {
+            let item_id_size = if infe_version == 2 { 2 } else { 4 };
+            let item_id = read_be_value(infe_content, 4, item_id_size)?;
+            let item_type = infe_content.get(6 + item_id_size..10 + item_id_size)?;
+            let mut item_strings = infe_content[10 + item_id_size..].split(|byte| *byte == 0);
+            let _item_name = item_strings.next();
+            let content_type = item_strings.next().unwrap_or_default();
+
+            if item_type == b"Exif" || (item_type == b"mime" && content_type == AVIF_XMP_CONTENT_TYPE) {
+                metadata_items.push(item_id);
+            }
+        }
+
+        pos = infe_end;
+    }
+
+    return Some(metadata_items);
+}
+
+// Returns the size of the color table which follows the given flags in a gif file
+fn get_gif_color_table_size(flags: u8) -> usize {
+    if flags & 0x80 != 0 {
+        3 * (1 << ((flags & 0x07) + 1))
+    }
+    else {
+        0
+    }
+}
+
+// The app extension with the loop count is needed for the animation, every other one only contains metadata (like xmp)
+fn is_gif_loop_extension(extension_blocks: &[u8]) -> bool {
+    let app_identifier = extension_blocks.get(0..12);
+
+    app_identifier == Some(b"\x0BNETSCAPE2.0") || app_identifier == Some(b"\x0BANIMEXTS1.0")
+}
+
+fn is_jpeg_metadata_segment(marker: u8, segment_data: &[u8]) -> bool {
+    match marker {
+        0xE0 => !segment_data.starts_with(JPEG_JFIF_HEADER),
+        0xE2 => !segment_data.starts_with(JPEG_ICC_PROFILE_HEADER),
+        0xEE => !segment_data.starts_with(JPEG_ADOBE_HEADER),
+        0xE1..=0xEF | 0xFE => true,
+        _ => false,
+    }
+}
+
+// Reads a big endian value with the given size (0 to 8 bytes) at pos
+fn read_be_value(data: &[u8], pos: usize, size: usize) -> Option<usize> {
+    if size > 8 {
+        return None;
+    }
+
+    let value_bytes = data.get(pos..pos.checked_add(size)?)?;
+    let value = value_bytes.iter().fold(0u64, |value, byte| (value << 8) | *byte as u64);
+
+    if value > usize::MAX as u64 {
+        return None;
+    }
+
+    return Some(value as usize);
+}
+
+// Skips the data sub blocks of a gif file, returns the position after the block terminator
+fn skip_gif_sub_blocks(file_content: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start;
+
+    loop {
+        let block_size = *file_content.get(pos)? as usize;
+        pos += 1 + block_size;
+
+        if block_size == 0 {
+            return Some(pos);
+        }
+    }
+}
+
+// Overwrites the exif and xmp items of an avif file with zeros, so that the structure of the file doesn't change
+fn strip_avif_metadata(file_content: &[u8]) -> Option<Vec<u8>> {
+    let (meta_start, meta_end) = find_iso_box(file_content, 0, file_content.len(), b"meta")?;
+
+    // The meta box has a version and flags in front of the child boxes
+    let iinf_box = find_iso_box(file_content, meta_start + 4, meta_end, b"iinf");
+    let mut stripped_content = file_content.to_vec();
+
+    if iinf_box.is_none() {
+        return Some(stripped_content);
+    }
+
+    let (iinf_start, iinf_end) = iinf_box.unwrap();
+    let metadata_items = get_avif_metadata_items(&file_content[iinf_start..iinf_end])?;
+
+    if metadata_items.is_empty() {
+        return Some(stripped_content);
+    }
+
+    let (iloc_start, iloc_end) = find_iso_box(file_content, meta_start + 4, meta_end, b"iloc")?;
+    let item_extents = get_avif_item_extents(&file_content[iloc_start..iloc_end], metadata_items.as_slice())?;
+
+    for (extent_offset, extent_length) in item_extents {
+        let extent_data = stripped_content.get_mut(extent_offset..extent_offset.checked_add(extent_length)?)?;
+
+        for byte in extent_data.iter_mut() {
+            *byte = 0;
+        }
+    }
+
+    return Some(stripped_content);
+}
+
+// Returns the file content without metadata (exif, xmp, comments and text chunks), None if the file can't be parsed
+// The image data isn't changed, so this doesn't lose quality and keeps animations
+pub fn strip_file_metadata(file_content: &[u8], file_extension: &str) -> Option<Vec<u8>> {
+    match file_extension {
+        "jpg" => strip_jpeg_metadata(file_content),
+        "png" | "apng" => strip_png_metadata(file_content),
+        "gif" => strip_gif_metadata(file_content),
+        "webp" => strip_webp_metadata(file_content),
+        "avif" => strip_avif_metadata(file_content),
+        _ => None,
+    }
+}
+
+// Removes the comment extensions and every app extension which isn't needed for the animation
+fn strip_gif_metadata(file_content: &[u8]) -> Option<Vec<u8>> {
+    if file_content.len() < 13 || (&file_content[0..6] != b"GIF87a" && &file_content[0..6] != b"GIF89a") {
+        return None;
+    }
+
+    // The header and the logical screen descriptor are followed by the global color table
+    let mut pos = 13 + get_gif_color_table_size(file_content[10]);
+    let mut stripped_content = file_content.get(0..pos)?.to_vec();
+
+    loop {
+        match *file_content.get(pos)? {
+            0x21 => {
+                let extension_label = *file_content.get(pos + 1)?;
+                let extension_end = skip_gif_sub_blocks(file_content, pos + 2)?;
+                let extension_blocks = file_content.get(pos + 2..extension_end)?;
+                let is_metadata = extension_label == 0xFE || (extension_label == 0xFF && !is_gif_loop_extension(extension_blocks));
+
+                if !is_metadata {
+                    stripped_content.extend_from_slice(&file_content[pos..extension_end]);
+                }
+
+                pos = extension_end;
+            },
+            0x2C => {
+                // The image descriptor is followed by the local color table, the lzw code size and the image data
+                let image_flags = *file_content.get(pos + 9)?;
+                let image_data_start = pos + 10 + get_gif_color_table_size(image_flags) + 1;
+                let image_end = skip_gif_sub_blocks(file_content, image_data_start)?;
+
+                stripped_content.extend_from_slice(file_content.get(pos..image_end)?);
+                pos = image_end;
+            },
+            0x3B => {
+                stripped_content.push(0x3B);
+
+                return Some(stripped_content);
+            },
+            _ => return None,
+        }
+    }
+}
+
+// Removes the app segments (except the ones needed to decode the colors) and comments, data after the end of the image is dropped
+fn strip_jpeg_metadata(file_content: &[u8]) -> Option<Vec<u8>> {
+    if file_content.len() < 4 || file_content[0..2] != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut stripped_content = file_content[0..2].to_vec();
+    let mut pos = 2;
+
+    loop {
+        if *file_content.get(pos)? != 0xFF {
+            return None;
+        }
+
+        let marker = *file_content.get(pos + 1)?;
+
+        // Markers can be preceded by fill bytes
+        if marker == 0xFF {
+            pos += 1;
+
+            continue;
+        }
+
+        // End of image, everything after it (e.g. additional images of the camera) is dropped
+        if marker == 0xD9 {
+            stripped_content.extend_from_slice(&[0xFF, 0xD9]);
+
+            return Some(stripped_content);
+        }
+
+        let segment_length = read_be_value(file_content, pos + 2, 2)?;
+        let segment_end = pos + 2 + segment_length;
+
+        if segment_length < 2 {
+            return None;
+        }
+
+        let segment_data = file_content.get(pos + 4..segment_end)?;
+
+        if !is_jpeg_metadata_segment(marker, segment_data) {
+            stripped_content.extend_from_slice(&file_content[pos..segment_end]);
+        }
+
+        pos = segment_end;
+
+        // The start of scan is followed by the image data, which ends with the next marker (except restart markers)
+        if marker == 0xDA {
+            let scan_start = pos;
+
+            loop {
+                let byte = *file_content.get(pos)?;
+                let next_byte = *file_content.get(pos + 1)?;
+
+                if byte == 0xFF && next_byte != 0x00 && !(0xD0..=0xD7).contains(&next_byte) {
+                    break;
+                }
+
+                pos += 1;
+            }
+
+            stripped_content.extend_from_slice(&file_content[scan_start..pos]);
+        }
+    }
+}
+
+// Removes the exif and text chunks (the chunks of apng files are kept)
+fn strip_png_metadata(file_content: &[u8]) -> Option<Vec<u8>> {
+    if file_content.len() < 8 || &file_content[0..8] != b"\x89PNG\r\n\x1a\n" {
+        return None;
+    }
+
+    let mut stripped_content = file_content[0..8].to_vec();
+    let mut pos = 8;
+
+    // Every chunk has a length, a type, the data and a crc
+    loop {
+        let chunk_length = read_be_value(file_content, pos, 4)?;
+        let chunk_type = file_content.get(pos + 4..pos + 8)?;
+        let chunk_end = pos + 12 + chunk_length;
+
+        if chunk_end > file_content.len() {
+            return None;
+        }
+
+        if !PNG_METADATA_CHUNKS.contains(&chunk_type) {
+            stripped_content.extend_from_slice(&file_content[pos..chunk_end]);
+        }
+
+        if chunk_type == b"IEND" {
+            return Some(stripped_content);
+        }
+
+        pos = chunk_end;
+    }
+}
+
+// Removes the EXIF and XMP chunks and their flags in the VP8X chunk
+fn strip_webp_metadata(file_content: &[u8]) -> Option<Vec<u8>> {
+    if file_content.len() < 12 || &file_content[0..4] != b"RIFF" || &file_content[8..12] != b"WEBP" {
+        return None;
+    }
+
+    let riff_end = (8 + read_u32(&file_content[4..8], true) as usize).min(file_content.len());
+    let mut stripped_content = file_content[0..12].to_vec();
+    let mut pos = 12;
+
+    // Chunks are padded to an even size
+    while pos + 8 <= riff_end {
+        let chunk_type = &file_content[pos..pos + 4];
+        let chunk_length = read_u32(&file_content[pos + 4..pos + 8], true) as usize;
+        let chunk_end = (pos + 8 + chunk_length + (chunk_length % 2)).min(riff_end);
+
+        if pos + 8 + chunk_length > riff_end {
+            return None;
+        }
+
+        if chunk_type == b"VP8X" && chunk_length > 0 {
+            let flags_pos = stripped_content.len() + 8;
+
+            stripped_content.extend_from_slice(&file_content[pos..chunk_end]);
+            stripped_content[flags_pos] &= !(WEBP_EXIF_FLAG | WEBP_XMP_FLAG);
+        }
+        else if chunk_type != b"EXIF" && chunk_type != b"XMP " {
+            stripped_content.extend_from_slice(&file_content[pos..chunk_end]);
+        }
+
+        pos = chunk_end;
+    }
+
+    let riff_size = (stripped_content.len() - 8) as u32;
+    stripped_content[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    return Some(stripped_content);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_iso_box(box_type: &[u8], box_content: &[u8]) -> Vec<u8> {
+        let mut iso_box = ((box_content.len() + 8) as u32).to_be_bytes().to_vec();
+        iso_box.extend_from_slice(box_type);
+        iso_box.extend_from_slice(box_content);
+
+        return iso_box;
+    }
+
+    fn get_png_chunk(chunk_type: &[u8], chunk_data: &[u8]) -> Vec<u8> {
+        let mut chunk = (chunk_data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(chunk_type);
+        chunk.extend_from_slice(chunk_data);
+        chunk.extend_from_slice(&[0, 0, 0, 0]);
+
+        return chunk;
+    }
+
+    #[test]
+    fn strip_avif_metadata_overwrites_exif_items() {
+        let mut infe_content = vec![2, 0, 0, 0, 0, 1, 0, 0];
+        infe_content.extend_from_slice(b"Exif\0");
+        let mut iinf_content = vec![0, 0, 0, 0, 0, 1];
+        iinf_content.extend_from_slice(&get_iso_box(b"infe", &infe_content));
+
+        // The exif item is stored after the meta box, its offset is patched in below
+        let iloc_content = vec![0, 0, 0, 0, 0x44, 0x00, 0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 4];
+        let mut meta_content = vec![0, 0, 0, 0];
+        meta_content.extend_from_slice(&get_iso_box(b"iinf", &iinf_content));
+        meta_content.extend_from_slice(&get_iso_box(b"iloc", &iloc_content));
+
+        let mut file_content = get_iso_box(b"ftyp", b"avif");
+        file_content.extend_from_slice(&get_iso_box(b"meta", &meta_content));
+        let exif_offset = file_content.len() + 8;
+        file_content.extend_from_slice(&get_iso_box(b"mdat", b"EXIFimage"));
+
+        let extent_offset_pos = file_content.len() - 17 - 8;
+        file_content[extent_offset_pos..extent_offset_pos + 4].copy_from_slice(&(exif_offset as u32).to_be_bytes());
+
+        let stripped_content = strip_file_metadata(&file_content, "avif").unwrap();
+
+        assert_eq!(stripped_content.len(), file_content.len());
+        assert!(stripped_content.ends_with(b"\0\0\0\0image"));
+    }
+
+    #[test]
+    fn strip_gif_metadata_keeps_loop_extension() {
+        let mut file_content = b"GIF89a\x01\x00\x01\x00\x80\x00\x00".to_vec();
+        file_content.extend_from_slice(&[0, 0, 0, 255, 255, 255]);
+        let loop_extension = b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00";
+        file_content.extend_from_slice(loop_extension);
+        file_content.extend_from_slice(b"\x21\xFF\x0BXMP DataXMP\x04<xmp\x00");
+        file_content.extend_from_slice(b"\x21\xFE\x09Kommentar\x00");
+        let image_data = b"\x2C\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00";
+        file_content.extend_from_slice(image_data);
+        file_content.push(0x3B);
+
+        let stripped_content = strip_file_metadata(&file_content, "gif").unwrap();
+        let mut expected_content = file_content[0..19].to_vec();
+        expected_content.extend_from_slice(loop_extension);
+        expected_content.extend_from_slice(image_data);
+        expected_content.push(0x3B);
+
+        assert_eq!(stripped_content, expected_content);
+        assert_eq!(strip_file_metadata(&file_content[0..file_content.len() - 1], "gif"), None);
+    }
+
+    #[test]
+    fn strip_jpeg_metadata_keeps_image_data() {
+        let mut file_content = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x07];
+        file_content.extend_from_slice(JPEG_JFIF_HEADER);
+        let jfif_segment = file_content[2..].to_vec();
+        file_content.extend_from_slice(&[0xFF, 0xE1, 0x00, 0x08]);
+        file_content.extend_from_slice(b"Exif\0\0");
+        file_content.extend_from_slice(&[0xFF, 0xFE, 0x00, 0x04, 0x41, 0x42]);
+        let image_data = [0xFF, 0xDB, 0x00, 0x03, 0x01, 0xFF, 0xDA, 0x00, 0x02, 0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56, 0xFF, 0xD9];
+        file_content.extend_from_slice(&image_data);
+        file_content.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0xE1]);
+
+        let stripped_content = strip_file_metadata(&file_content, "jpg").unwrap();
+        let mut expected_content = vec![0xFF, 0xD8];
+        expected_content.extend_from_slice(&jfif_segment);
+        expected_content.extend_from_slice(&image_data);
+
+        assert_eq!(stripped_content, expected_content);
+        assert_eq!(strip_file_metadata(&file_content[0..20], "jpg"), None);
+    }
+
+    #[test]
+    fn strip_png_metadata_removes_text_chunks() {
+        let image_header = get_png_chunk(b"IHDR", &[0; 13]);
+        let animation_control = get_png_chunk(b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]);
+        let image_data = get_png_chunk(b"IDAT", &[1, 2, 3]);
+        let image_end = get_png_chunk(b"IEND", &[]);
+
+        let mut file_content = b"\x89PNG\r\n\x1a\n".to_vec();
+        file_content.extend_from_slice(&image_header);
+        file_content.extend_from_slice(&get_png_chunk(b"eXIf", b"MM\0*"));
+        file_content.extend_from_slice(&animation_control);
+        file_content.extend_from_slice(&get_png_chunk(b"tEXt", b"Author\0Pony"));
+        file_content.extend_from_slice(&image_data);
+        file_content.extend_from_slice(&get_png_chunk(b"iTXt", b"XML:com.adobe.xmp\0"));
+        file_content.extend_from_slice(&image_end);
+
+        let expected_content = [b"\x89PNG\r\n\x1a\n".to_vec(), image_header, animation_control, image_data, image_end].concat();
+
+        assert_eq!(strip_file_metadata(&file_content, "apng").unwrap(), expected_content);
+    }
+
+    #[test]
+    fn strip_webp_metadata_clears_flags() {
+        let mut file_content = b"RIFF\0\0\0\0WEBPVP8X".to_vec();
+        file_content.extend_from_slice(&10u32.to_le_bytes());
+        file_content.extend_from_slice(&[0x0E, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        file_content.extend_from_slice(b"ANMF");
+        file_content.extend_from_slice(&3u32.to_le_bytes());
+        file_content.extend_from_slice(&[1, 2, 3, 0]);
+        file_content.extend_from_slice(b"EXIF");
+        file_content.extend_from_slice(&5u32.to_le_bytes());
+        file_content.extend_from_slice(b"MM\0*\0\0");
+        file_content.extend_from_slice(b"XMP ");
+        file_content.extend_from_slice(&4u32.to_le_bytes());
+        file_content.extend_from_slice(b"<xmp");
+        let riff_size = (file_content.len() - 8) as u32;
+        file_content[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        let stripped_content = strip_file_metadata(&file_content, "webp").unwrap();
+
+        assert_eq!(stripped_content.len(), 42);
+        assert_eq!(read_u32(&stripped_content[4..8], true), 34);
+        assert_eq!(stripped_content[20], 0x02);
+        assert_eq!(&stripped_content[30..34], b"ANMF");
+    }
+}
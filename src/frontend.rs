@@ -4,7 +4,7 @@ use actix_web::{HttpResponse, web};
 use crate::config::ProjectConfig;
 use actix_session::Session;
 use handlebars::Handlebars;
-use crate::frontend::template_data::IndexViewTemplateData;
+use crate::frontend::template_data::{IndexViewTemplateData, ResetPasswordViewTemplateData};
 use log::{trace, error};
 
 pub const HANDLEBARS_ERROR_RESP: &str = "Fehler beim Generieren der Webseite (Fehler beim Rendern mit Handlebars)";
@@ -22,5 +22,25 @@ pub async fn index(config: web::Data<ProjectConfig>, handlebars: web::Data<Handl
         return HttpResponse::InternalServerError().body(HANDLEBARS_ERROR_RESP);
     }
 
+    return HttpResponse::Ok().body(resp_body.unwrap());
+}
+
+pub fn get_password_reset_path(reset_token: &str) -> String {
+    format!("/reset/{}", reset_token)
+}
+
+pub async fn reset_password(config: web::Data<ProjectConfig>, handlebars: web::Data<Handlebars<'_>>, session: Session, url_data: web::Path<String>) -> HttpResponse {
+    trace!("Enter Frontend::reset_password()");
+
+    let reset_password_view = ResetPasswordViewTemplateData::new_reset_password(config, session, url_data.as_str()).await;
+    let resp_body = handlebars.render("reset_password", &reset_password_view);
+
+    if resp_body.is_err() {
+        let handlebars_err = resp_body.err().unwrap();
+        error!("Handlebars error: {}", handlebars_err);
+
+        return HttpResponse::InternalServerError().body(HANDLEBARS_ERROR_RESP);
+    }
+
     return HttpResponse::Ok().body(resp_body.unwrap());
 }
\ No newline at end of file
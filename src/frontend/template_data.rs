@@ -3,8 +3,9 @@ use actix_web::web;
 use actix_session::Session;
 use crate::config::ProjectConfig;
 use serde::{Serialize};
-use crate::backend_api::{get_filter, get_own_userdata};
-use crate::backend_api::response_result::ErrorCode::{Unauthorized};
+use crate::backend_api::{check_password_reset, get_filter, get_own_userdata};
+use crate::backend_api::response_result::ErrorCode::{Unauthorized, UserBanned};
+use crate::security::get_csrf_token;
 
 // Main struct
 #[derive(Clone, Serialize)]
@@ -13,42 +14,46 @@ pub struct IndexViewTemplateData {
     pub filter_settings: Option<Filter>,
     pub user_data: Option<UserData>,
     pub read_access: bool,
+    pub csrf_token: String, // Has to be send with all state changing js-api requests (X-CSRF-Token header)
 }
 
 impl IndexViewTemplateData {
     // Used if the user is not logged in
-    pub fn new_empty() -> IndexViewTemplateData {
+    pub fn new_empty(csrf_token: String) -> IndexViewTemplateData {
         IndexViewTemplateData {
             backend_error: None,
             filter_settings: None,
             user_data: None,
             read_access: false,
+            csrf_token,
         }
     }
 
     // Used for backend errors (for example if the database is offline)
-    pub fn new_error(backend_error: BackendError) -> IndexViewTemplateData {
+    pub fn new_error(backend_error: BackendError, csrf_token: String) -> IndexViewTemplateData {
         IndexViewTemplateData {
             backend_error: Some(backend_error),
             filter_settings: None,
             user_data: None,
             read_access: false,
+            csrf_token,
         }
     }
 
     // Generate template data for the index view
     pub async fn new_index(config: web::Data<ProjectConfig>, session: Session) -> IndexViewTemplateData {
+        let csrf_token = get_csrf_token(&session).unwrap_or_default();
         let filter_data = get_filter(&config, &session).await;
 
         if filter_data.is_err() {
             let backend_error = filter_data.err().unwrap();
 
-            // Return empty data object if the user isn't logged in
-            if backend_error.error_code == Unauthorized {
-                return IndexViewTemplateData::new_empty();
+            // Return empty data object if the user isn't logged in (banned users get logged out)
+            if backend_error.error_code == Unauthorized || backend_error.error_code == UserBanned {
+                return IndexViewTemplateData::new_empty(csrf_token);
             }
 
-            return IndexViewTemplateData::new_error(backend_error);
+            return IndexViewTemplateData::new_error(backend_error, csrf_token);
         }
 
         let filter_data = filter_data.ok().unwrap();
@@ -57,7 +62,7 @@ impl IndexViewTemplateData {
         if user_data.is_err() {
             let backend_error = user_data.err().unwrap();
 
-            return IndexViewTemplateData::new_error(backend_error);
+            return IndexViewTemplateData::new_error(backend_error, csrf_token);
         }
 
         let user_data = user_data.ok().unwrap();
@@ -67,6 +72,28 @@ impl IndexViewTemplateData {
             filter_settings: Some(filter_data),
             user_data: Some(user_data),
             read_access: true,
+            csrf_token,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ResetPasswordViewTemplateData {
+    pub backend_error: Option<BackendError>, // Set if the reset token is invalid or expired
+    pub reset_token: String,
+    pub csrf_token: String,
+}
+
+impl ResetPasswordViewTemplateData {
+    // Generate template data for the password reset view
+    pub async fn new_reset_password(config: web::Data<ProjectConfig>, session: Session, reset_token: &str) -> ResetPasswordViewTemplateData {
+        let csrf_token = get_csrf_token(&session).unwrap_or_default();
+        let reset_check = check_password_reset(&config, reset_token).await;
+
+        ResetPasswordViewTemplateData {
+            backend_error: reset_check.err(),
+            reset_token: reset_token.to_owned(),
+            csrf_token,
         }
     }
 }
\ No newline at end of file
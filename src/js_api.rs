@@ -1,14 +1,15 @@
-use actix_web::{HttpResponse, web};
+use actix_web::{HttpRequest, HttpResponse, web};
 use crate::config::ProjectConfig;
 use actix_session::Session;
-use crate::backend_api::request_data::{CommentData, RegisterData, LoginData};
+use crate::backend_api::request_data::{AuditEventFilter, BanUserData, ChangePasswordData, CommentData, CreateApiTokenData, CreateUploadSessionData, DeleteAccountData, RegisterData, LoginData, ResetPasswordData, TotpCodeData, TotpDisableData};
 use actix_web::dev::HttpResponseBuilder;
 use actix_web::http::StatusCode;
+use actix_web::http::header::RETRY_AFTER;
 use actix_multipart::Multipart;
 
 macro_rules! implement_jsapi_func_backend_call {
     ($func_name:ident, $( $func_arg_names:ident: $func_arg_types:ty ),+) => {
-        crate::backend_api::$func_name( $( &$func_arg_names ),+ ).await;
+        crate::backend_api::$func_name( $( &$func_arg_names ),+ ).await
     };
 }
 
@@ -21,13 +22,25 @@ macro_rules! implement_jsapi_func_body {
             return HttpResponse::Ok().body(response_txt);
         }
         else {
-            let response_obj = $response_obj.err().unwrap();
-            let response_txt = serde_json::to_string(&response_obj).unwrap_or("".to_owned());
-            let status_code = StatusCode::from_u16(response_obj.http_status_code).unwrap();
-            let http_response = HttpResponseBuilder::new(status_code).body(response_txt);
+            implement_jsapi_error_response!($response_obj);
+        }
+    };
+}
+
+macro_rules! implement_jsapi_error_response {
+    ($response_obj:ident) => {
+        let response_obj = $response_obj.err().unwrap();
+        let response_txt = serde_json::to_string(&response_obj).unwrap_or("".to_owned());
+        let status_code = StatusCode::from_u16(response_obj.http_status_code).unwrap();
+        let mut http_response_builder = HttpResponseBuilder::new(status_code);
 
-            return http_response;
+        if response_obj.retry_after.is_some() {
+            http_response_builder.header(RETRY_AFTER, response_obj.retry_after.unwrap().to_string());
         }
+
+        let http_response = http_response_builder.body(response_txt);
+
+        return http_response;
     };
 }
 
@@ -41,39 +54,113 @@ macro_rules! implement_jsapi_func {
     };
 }
 
-implement_jsapi_func!(add_comment, config: web::Data<ProjectConfig>, session: Session, comment_data: web::Form<CommentData>);
+implement_jsapi_func!(add_comment, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, comment_data: web::Form<CommentData>);
 
 // Because of the mut payload some parts of this method have to be written by hoof
-pub async fn add_upload(config: web::Data<ProjectConfig>, session: Session, mut payload: Multipart) -> HttpResponse {
-    let response_obj = crate::backend_api::add_upload(&config, &session, &mut payload).await;
+pub async fn add_upload(config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, mut payload: Multipart) -> HttpResponse {
+    let response_obj = crate::backend_api::add_upload(&config, &session, &request, &mut payload).await;
 
     implement_jsapi_func_body!(response_obj);
 }
 
-implement_jsapi_func!(check_username_exists, config: web::Data<ProjectConfig>, url_data: web::Path<String>);
+// The chunk is streamed from the mut payload, so this method have to be written by hoof too
+pub async fn append_upload_chunk(config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<String>, mut payload: web::Payload) -> HttpResponse {
+    let response_obj = crate::backend_api::append_upload_chunk(&config, &session, &request, &url_data, &mut payload).await;
+
+    implement_jsapi_func_body!(response_obj);
+}
+
+implement_jsapi_func!(ban_user, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, ban_data: web::Form<BanUserData>);
+
+implement_jsapi_func!(cancel_upload_session, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<String>);
+
+implement_jsapi_func!(change_password, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, password_data: web::Form<ChangePasswordData>);
+
+implement_jsapi_func!(check_username_exists, config: web::Data<ProjectConfig>, request: HttpRequest, url_data: web::Path<String>);
+
+implement_jsapi_func!(create_api_token, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, token_data: web::Form<CreateApiTokenData>);
+
+implement_jsapi_func!(create_password_reset, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<i32>);
+
+implement_jsapi_func!(create_invite, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest);
+
+implement_jsapi_func!(create_upload_session, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, upload_data: web::Form<CreateUploadSessionData>);
+
+implement_jsapi_func!(delete_account, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, delete_data: web::Form<DeleteAccountData>);
+
+implement_jsapi_func!(export_user_data, config: web::Data<ProjectConfig>, session: Session);
+
+// The archive is send as file download instead of json, so this method have to be written by hoof too
+pub async fn export_user_files(config: web::Data<ProjectConfig>, session: Session, request: HttpRequest) -> HttpResponse {
+    let response_obj = crate::backend_api::export_user_files(&config, &session).await;
+
+    if response_obj.is_ok() {
+        let archive_file = response_obj.ok().unwrap();
+        let http_response = archive_file.into_response(&request);
+
+        return http_response.unwrap_or(HttpResponse::InternalServerError().finish());
+    }
+    else {
+        implement_jsapi_error_response!(response_obj);
+    }
+}
+
+implement_jsapi_func!(get_api_tokens, config: web::Data<ProjectConfig>, session: Session);
+
+implement_jsapi_func!(get_audit_events, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<(i32, i16)>, filter_data: web::Query<AuditEventFilter>);
 
 implement_jsapi_func!(get_filter, config: web::Data<ProjectConfig>, session: Session);
 
-implement_jsapi_func!(get_uploads, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<(i32, i16)>);
+implement_jsapi_func!(get_invites, config: web::Data<ProjectConfig>, session: Session);
+
+implement_jsapi_func!(get_sessions, config: web::Data<ProjectConfig>, session: Session);
+
+implement_jsapi_func!(get_similar_uploads, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<i32>);
+
+implement_jsapi_func!(get_uploads, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<(i32, i16)>);
+
+implement_jsapi_func!(get_uploads_range, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<(i32, i32)>);
+
+implement_jsapi_func!(get_upload_data, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<i32>);
 
-implement_jsapi_func!(get_uploads_range, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<(i32, i32)>);
+implement_jsapi_func!(get_upload_session, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<String>);
 
-implement_jsapi_func!(get_upload_data, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<i32>);
+implement_jsapi_func!(get_upload_status, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<i32>);
 
-implement_jsapi_func!(get_userdata_by_id, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<i32>);
+implement_jsapi_func!(get_userdata_by_id, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<i32>);
 
-implement_jsapi_func!(get_userdata_by_username, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<String>);
+implement_jsapi_func!(get_userdata_by_username, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<String>);
 
-implement_jsapi_func!(login, config: web::Data<ProjectConfig>, session: Session, login_data: web::Form<LoginData>);
+implement_jsapi_func!(login, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, login_data: web::Form<LoginData>);
+
+implement_jsapi_func!(login_totp, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, totp_data: web::Form<TotpCodeData>);
 
 implement_jsapi_func!(logout, config: web::Data<ProjectConfig>, session: Session);
 
-implement_jsapi_func!(register, config: web::Data<ProjectConfig>, register_data: web::Form<RegisterData>);
+implement_jsapi_func!(register, config: web::Data<ProjectConfig>, request: HttpRequest, register_data: web::Form<RegisterData>);
+
+implement_jsapi_func!(reset_password, config: web::Data<ProjectConfig>, request: HttpRequest, url_data: web::Path<String>, reset_data: web::Form<ResetPasswordData>);
+
+implement_jsapi_func!(revoke_api_token, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<i32>);
+
+implement_jsapi_func!(revoke_all_sessions, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest);
+
+implement_jsapi_func!(revoke_session, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<String>);
 
 implement_jsapi_func!(set_filter, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<(bool, bool)>);
 
-implement_jsapi_func!(vote_comment, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<(i32, i32)>);
+implement_jsapi_func!(set_user_role, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<(i32, String)>);
+
+implement_jsapi_func!(totp_disable, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, disable_data: web::Form<TotpDisableData>);
+
+implement_jsapi_func!(totp_enable, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, totp_data: web::Form<TotpCodeData>);
+
+implement_jsapi_func!(totp_enroll, config: web::Data<ProjectConfig>, session: Session);
+
+implement_jsapi_func!(unban_user, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<i32>);
+
+implement_jsapi_func!(vote_comment, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<(i32, i32)>);
 
-implement_jsapi_func!(vote_tag, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<(i32, i32)>);
+implement_jsapi_func!(vote_tag, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<(i32, i32)>);
 
-implement_jsapi_func!(vote_upload, config: web::Data<ProjectConfig>, session: Session, url_data: web::Path<(i32, i32)>);
\ No newline at end of file
+implement_jsapi_func!(vote_upload, config: web::Data<ProjectConfig>, session: Session, request: HttpRequest, url_data: web::Path<(i32, i32)>);
\ No newline at end of file
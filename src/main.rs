@@ -6,18 +6,26 @@ mod file_api;
 mod frontend;
 mod security;
 mod backend_api;
+mod upload_queue;
 
-use actix_web::{web, middleware};
+use actix_web::{web, middleware, HttpResponse};
+use actix_web::dev::Service;
+use actix_web::http::StatusCode;
 use actix_web::{App, HttpServer};
 use actix_files as fs;
 use crate::config::ProjectConfig;
+use crate::db_api::DbConnection;
 use actix_session::CookieSession;
 use handlebars::Handlebars;
 use log::LevelFilter;
-use log::{info, trace};
+use log::{info, trace, warn};
 use clap::load_yaml;
 use crate::cli::do_cli_actions;
 use std::io::{ErrorKind, Error};
+use futures::future::{Either, ok};
+use crate::security::verify_csrf_token;
+use crate::backend_api::response_result::BackendError;
+use crate::backend_api::response_result::ErrorCode::CsrfTokenInvalid;
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
@@ -59,6 +67,45 @@ async fn main() -> std::io::Result<()> {
 
         let prj_config_data = web::Data::new(prj_config);
 
+        let db_connection = DbConnection::new(&prj_config_data, true, true).await;
+
+        if db_connection.is_ok() {
+            let db_connection = db_connection.ok().unwrap();
+
+            // The queries of this version fail on the tables of older versions
+            let schema_version = db_connection.get_schema_version().await;
+            let required_schema_version = prj_config_data.postgres_config.required_schema_version.get_value();
+
+            if schema_version.is_ok() {
+                let schema_version = schema_version.ok().unwrap();
+
+                if schema_version < required_schema_version {
+                    warn!("The tables have the schema version {} (required: {}), run \"maintenance --upgrade-db-tables\"",
+                          schema_version, required_schema_version);
+                }
+            }
+
+            // Sessions from older versions aren't in the session index yet (the logout of all sessions relies on it)
+            let index_result = db_connection.index_user_sessions().await;
+
+            if index_result.is_err() {
+                warn!("Failed to add the existing sessions to the session index");
+            }
+
+            // Without their skeleton, lookalikes of these usernames can still be registered
+            let users_without_skeleton = db_connection.get_users_without_skeleton().await;
+
+            if users_without_skeleton.is_ok() && !users_without_skeleton.as_ref().ok().unwrap().is_empty() {
+                warn!("{} users have no username skeleton, run \"maintenance --update-username-skeletons\"",
+                      users_without_skeleton.ok().unwrap().len());
+            }
+        }
+        else {
+            warn!("The startup checks couldn't be done (no connection to the database)");
+        }
+
+        upload_queue::start_upload_workers(prj_config_data.clone()).await;
+
         let mut handlebars = Handlebars::new();
 
         handlebars
@@ -78,22 +125,63 @@ async fn main() -> std::io::Result<()> {
                 )
                 .service(web::scope("/js-api")
                         .app_data(prj_config_data.clone())
+                        .wrap_fn(|request, service| {
+                            if verify_csrf_token(&request) {
+                                Either::Left(service.call(request))
+                            }
+                            else {
+                                let backend_error = BackendError::new(StatusCode::FORBIDDEN.as_u16(), CsrfTokenInvalid,
+                                                                      "CSRF Token fehlt oder ist ungültig (bitte die Seite neu laden)");
+                                let response_txt = serde_json::to_string(&backend_error).unwrap_or("".to_owned());
+                                let response = HttpResponse::Forbidden().body(response_txt);
+
+                                Either::Right(ok(request.into_response(response)))
+                            }
+                        })
                         .route("/add_comment", web::post().to(js_api::add_comment))
                         .route("/add_upload", web::post().to(js_api::add_upload))
+                        .route("/append_upload_chunk/{upload_session_id}", web::patch().to(js_api::append_upload_chunk))
+                        .route("/ban_user", web::post().to(js_api::ban_user))
+                        .route("/cancel_upload_session/{upload_session_id}", web::post().to(js_api::cancel_upload_session))
+                        .route("/change_password", web::post().to(js_api::change_password))
                         .route("/check_username_exists/{username}", web::get().to(js_api::check_username_exists))
+                        .route("/create_api_token", web::post().to(js_api::create_api_token))
+                        .route("/create_invite", web::post().to(js_api::create_invite))
+                        .route("/create_password_reset/{user_id}", web::post().to(js_api::create_password_reset))
+                        .route("/create_upload_session", web::post().to(js_api::create_upload_session))
+                        .route("/delete_account", web::post().to(js_api::delete_account))
+                        .route("/export_user_data", web::get().to(js_api::export_user_data))
+                        .route("/export_user_files", web::get().to(js_api::export_user_files))
+                        .route("/get_api_tokens", web::get().to(js_api::get_api_tokens))
+                        .route("/get_audit_events/{start_id}/{amount}", web::get().to(js_api::get_audit_events))
                         .route("/get_filter", web::get().to(js_api::get_filter))
+                        .route("/get_invites", web::get().to(js_api::get_invites))
+                        .route("/get_sessions", web::get().to(js_api::get_sessions))
+                        .route("/get_similar_uploads/{upload_id}", web::get().to(js_api::get_similar_uploads))
                         .route("/get_uploads/{start_id}/{amount}", web::get().to(js_api::get_uploads))
                         .route("/get_uploads_range/{start_id}/{end_id}", web::get().to(js_api::get_uploads_range))
                         .route("/get_upload_data/{upload_id}", web::get().to(js_api::get_upload_data))
+                        .route("/get_upload_session/{upload_session_id}", web::get().to(js_api::get_upload_session))
+                        .route("/get_upload_status/{upload_id}", web::get().to(js_api::get_upload_status))
                         .route("/get_userdata_by_username/{user_id}", web::get().to(js_api::get_userdata_by_id))
                         .route("/get_userdata_by_username/{username}", web::get().to(js_api::get_userdata_by_username))
                         .route("/login", web::post().to(js_api::login))
-                        .route("/logout", web::get().to(js_api::logout))
+                        .route("/login_totp", web::post().to(js_api::login_totp))
+                        .route("/logout", web::post().to(js_api::logout))
                         .route("/register", web::post().to(js_api::register))
-                        .route("/set_filter/{show_sfw}/{show_nsfw}", web::get().to(js_api::set_filter))
-                        .route("/vote_comment/{comment_id}/{vote_value}", web::get().to(js_api::vote_comment))
-                        .route("/vote_tag/{tum_id}/{vote_value}", web::get().to(js_api::vote_tag))
-                        .route("/vote_upload/{upload_id}/{vote_value}", web::get().to(js_api::vote_upload))
+                        .route("/reset_password/{reset_token}", web::post().to(js_api::reset_password))
+                        .route("/revoke_api_token/{token_id}", web::post().to(js_api::revoke_api_token))
+                        .route("/revoke_all_sessions", web::post().to(js_api::revoke_all_sessions))
+                        .route("/revoke_session/{session_public_id}", web::post().to(js_api::revoke_session))
+                        .route("/set_filter/{show_sfw}/{show_nsfw}", web::put().to(js_api::set_filter))
+                        .route("/set_user_role/{user_id}/{user_role}", web::post().to(js_api::set_user_role))
+                        .route("/totp_disable", web::post().to(js_api::totp_disable))
+                        .route("/totp_enable", web::post().to(js_api::totp_enable))
+                        .route("/totp_enroll", web::post().to(js_api::totp_enroll))
+                        .route("/unban_user/{user_id}", web::post().to(js_api::unban_user))
+                        .route("/vote_comment/{comment_id}/{vote_value}", web::post().to(js_api::vote_comment))
+                        .route("/vote_tag/{tum_id}/{vote_value}", web::post().to(js_api::vote_tag))
+                        .route("/vote_upload/{upload_id}/{vote_value}", web::post().to(js_api::vote_upload))
                 )
                 .service(fs::Files::new("/uploads", uploads_path.as_str()).index_file("index.html"))
                 .service(fs::Files::new("/prv", uploads_prv_path.as_str()).index_file("index.html"))
@@ -101,6 +189,7 @@ async fn main() -> std::io::Result<()> {
                 .app_data(prj_config_data.clone())
                 .app_data(handlebars_data.clone())
                 .route("/", web::get().to(frontend::index))
+                .route("/reset/{reset_token}", web::get().to(frontend::reset_password))
         })
             .bind("127.0.0.1:8080")?
             .run()
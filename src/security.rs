@@ -1,59 +1,114 @@
 use v_htmlescape::escape;
-use crate::db_api::db_result::{SessionError, SessionData};
+use crate::db_api::db_result::{ApiTokenScope, SessionError, SessionData};
 use crate::db_api::DbConnection;
 use actix_session::Session;
-use crate::db_api::db_result::SessionErrorType::NoSession;
+use crate::db_api::db_result::SessionErrorType::{NoSession, DbError, UserBanned, SessionInvalid, MissingScope};
+use crate::db_api::db_result::DbApiErrorType::NoResult;
 use argon2::{Config, ThreadMode, Variant, Version};
+use crate::config::{PasswordHashVariant, ProjectConfig, SecurityConfig};
 use rand::{thread_rng, Rng};
 use rand::distributions::Alphanumeric;
+use log::{warn};
+use actix_web::HttpRequest;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::{HeaderMap, Method};
+use actix_web::http::header::{AUTHORIZATION, USER_AGENT};
+use actix_session::UserSession;
+use std::net::IpAddr;
+use base32::Alphabet;
+use hmac::{Hmac, Mac, NewMac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use chrono::Utc;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use unicode_security::{skeleton, GeneralSecurityProfile, MixedScript};
+use unicode_segmentation::UnicodeSegmentation;
+
+const TOTP_DIGITS : u32 = 6;
+const TOTP_PERIOD : i64 = 30; // Seconds a totp code is valid
+const TOTP_ALLOWED_DRIFT : i64 = 1; // Number of periods before and after the current one which are also accepted
+const RECOVERY_CODE_LENGTH : usize = 12;
+const API_TOKEN_PREFIX : &str = "p0ny_";
+const CSRF_TOKEN_HEADER : &str = "X-CSRF-Token";
+const PASSWORD_HASH_LENGTH : u32 = 32;
+const FILENAME_MAX_LENGTH : usize = 32; // Length of uploads.upload_original_filename
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum PasswordVerification {
+    Incorrect,
+    Correct,
+    CorrectOutdated, // The password is correct, but the hash was made with other parameters than configured
+}
 
+// Returns the NFC normalized and html escaped comment (None if the comment doesn't match the requirements)
 pub fn check_and_escape_comment(comment: &str) -> Option<String> {
-    let comment_length = comment.len();
+    let comment : String = comment.nfc().collect();
+    let comment_length = comment.graphemes(true).count();
 
-    // A comment can be 8000 characters long
+    // A comment can be 8000 characters (grapheme clusters, so an emoji counts as one character) long
     if comment_length <= 8000 {
         let comment_chars = comment.chars();
 
-        // A comment can only have alphanumeric characters, ascii punctuations (like !;:% etc.) and ascii whitespaces
+        // A comment can have all visible characters (including emoji) and whitespaces
         for char in comment_chars {
-            let char_is_alphanumeric = char.is_alphanumeric();
-            let char_is_ascii_punctuation = char.is_ascii_punctuation();
-            let char_is_ascii_whitespace = char.is_ascii_whitespace();
-
-            if !char_is_alphanumeric && !char_is_ascii_punctuation && !char_is_ascii_whitespace {
+            if !is_visible_text_char(char) {
                 return None;
             }
         }
 
-        return Some(escape(comment).to_string());
+        return Some(escape(comment.as_str()).to_string());
     }
     else {
         return None;
     }
 }
 
-pub fn check_filename(filename: &str) -> bool {
-    let filename_length = filename.len();
+// Returns an error of type UserBanned if the user has an active ban
+async fn check_user_ban(db_connection: &DbConnection, user_id: i32) -> Result<(), SessionError> {
+    let active_ban = db_connection.get_active_user_ban(user_id).await;
 
-    // A filename can be up to 32 characters long (including file extension) and have to contain exactly one point (for file extension)
-    if filename_length <= 32 {
-        let filename_chars = filename.chars();
+    if active_ban.is_ok() {
+        let active_ban = active_ban.ok().unwrap();
+        let ban_msg = format!("Benutzer ist bis {} gesperrt (Grund: {})",
+                              active_ban.ban_end.format("%d.%m.%Y %H:%M"), active_ban.ban_reason);
 
-        // A tag can only have ascii alphanumeric characters and simple whitespaces
-        for char in filename_chars {
-            let char_is_ascii_alphanumeric = char.is_ascii_alphanumeric();
-            let char_is_allowed_special_character = char == '-' || char == '_' || char == '.';
+        return Err(SessionError::new(UserBanned, ban_msg.as_str()));
+    }
+    else if active_ban.err().unwrap().error_type != NoResult {
+        return Err(SessionError::new(DbError, "Fehler beim Prüfen der Benutzersperren"));
+    }
 
-            if !char_is_ascii_alphanumeric && !char_is_allowed_special_character {
-                return false;
-            }
-        }
+    return Ok(());
+}
 
-        return true;
+// Turns the filename sent by the client into a name which can be stored as metadata (the file itself is saved under a generated name)
+// Path components are dropped, unusual characters are replaced and the name is shortened to 32 characters (keeping the extension)
+pub fn sanitize_filename(filename: &str) -> String {
+    let filename = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let filename_chars : Vec<char> = filename.chars()
+        .map(|char| {
+            let char_is_alphanumeric = char.is_alphanumeric();
+            let char_is_allowed_special_character = char == '-' || char == '_' || char == '.' || char == ' ';
+
+            if char_is_alphanumeric || char_is_allowed_special_character { char } else { '_' }
+        })
+        .collect();
+
+    if filename_chars.len() <= FILENAME_MAX_LENGTH {
+        return filename_chars.into_iter().collect();
     }
-    else {
-        return false;
+
+    let extension_start = filename_chars.iter().rposition(|char| *char == '.').unwrap_or(filename_chars.len());
+    let mut extension_chars = &filename_chars[extension_start..];
+
+    if extension_chars.len() >= FILENAME_MAX_LENGTH {
+        extension_chars = &[];
     }
+
+    let stem_length = FILENAME_MAX_LENGTH - extension_chars.len();
+
+    return filename_chars[..stem_length].iter().chain(extension_chars.iter()).collect();
 }
 
 pub fn check_invite_key(invite_key: &str) -> bool {
@@ -103,51 +158,278 @@ pub fn check_password(password: &str) -> bool {
     }
 }
 
-pub fn check_tag(tag: &str) -> bool {
-    let tag_length = tag.len();
+pub fn check_recovery_code(recovery_code: &str) -> bool {
+    // A recovery code is always 12 ascii alphanumeric characters long
+    recovery_code.len() == RECOVERY_CODE_LENGTH && recovery_code.chars().all(|char| char.is_ascii_alphanumeric())
+}
 
-    // A tag can be 64 characters long
-    if tag_length <= 64 {
+pub fn check_reset_token(reset_token: &str) -> bool {
+    // A password reset token is always 32 ascii alphanumeric characters long
+    reset_token.len() == 32 && reset_token.chars().all(|char| char.is_ascii_alphanumeric())
+}
+
+// Returns the NFC normalized tag (None if the tag doesn't match the requirements)
+pub fn check_and_normalize_tag(tag: &str) -> Option<String> {
+    let tag : String = tag.nfc().collect();
+    let tag_length = tag.graphemes(true).count();
+
+    // A tag can be 64 characters (grapheme clusters) long, the database column can store 70 code points
+    if tag_length <= 64 && tag.chars().count() <= 70 {
         let tag_chars = tag.chars();
 
-        // A tag can only have ascii alphanumeric characters and simple whitespaces
+        // A tag can only have letters and numbers of all scripts (including umlauts) and simple whitespaces
         for char in tag_chars {
-            let char_is_ascii_alphanumeric = char.is_ascii_alphanumeric();
+            let char_is_alphanumeric = char.is_alphanumeric() || is_combining_mark(char);
             let char_is_space = char == ' ';
 
-            if !char_is_ascii_alphanumeric && !char_is_space {
-                return false;
+            if !char_is_alphanumeric && !char_is_space {
+                return None;
             }
         }
 
-        return true;
+        return Some(tag);
     }
     else {
-        return false;
+        return None;
     }
 }
 
-pub fn check_username(username: &str) -> bool {
-    let username_length = username.len();
+pub fn check_totp_code(totp_code: &str) -> bool {
+    // A totp code always consists of 6 digits
+    totp_code.len() == TOTP_DIGITS as usize && totp_code.chars().all(|char| char.is_ascii_digit())
+}
+
+// Returns the NFC normalized username (None if the username doesn't match the requirements)
+pub fn check_and_normalize_username(username: &str) -> Option<String> {
+    let username : String = username.nfc().collect();
+    let username_length = username.graphemes(true).count();
 
-    // A username can be 32 characters long
-    if username_length <= 32 {
+    // A username can be 32 characters (grapheme clusters) long, the database column can store 40 code points
+    if username_length >= 1 && username_length <= 32 && username.chars().count() <= 40 {
         let username_chars = username.chars();
 
-        // A username can only have ascii alphanumeric characters
+        // A username can only have letters and numbers which are recommended for identifiers (UTS #39)
         for char in username_chars {
-            let char_is_ascii_alphanumeric = char.is_ascii_alphanumeric();
+            let char_is_alphanumeric = char.is_alphanumeric() || is_combining_mark(char);
 
-            if !char_is_ascii_alphanumeric {
-                return false;
+            if !char_is_alphanumeric || !char.identifier_allowed() {
+                return None;
             }
         }
 
-        return true;
+        // Mixing scripts (like a cyrillic "а" within latin letters) is only useful to imitate other users
+        if !username.as_str().is_single_script() {
+            return None;
+        }
+
+        return Some(username);
     }
     else {
+        return None;
+    }
+}
+
+// Compares two strings in constant time (prevents timing attacks on secret tokens)
+fn constant_time_eq(value_a: &str, value_b: &str) -> bool {
+    if value_a.len() != value_b.len() {
         return false;
     }
+
+    let difference = value_a.bytes()
+        .zip(value_b.bytes())
+        .fold(0u8, |difference, (byte_a, byte_b)| difference | (byte_a ^ byte_b));
+
+    difference == 0
+}
+
+// Generates a random api token (the prefix makes leaked tokens easier to recognize)
+pub fn generate_api_token() -> String {
+    let random_part : String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .collect();
+
+    format!("{}{}", API_TOKEN_PREFIX, random_part)
+}
+
+// Generates a random invite key which passes check_invite_key
+pub fn generate_invite_key() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .collect()
+}
+
+// Generates random recovery codes which pass check_recovery_code
+pub fn generate_recovery_codes(amount: usize) -> Vec<String> {
+    let mut recovery_codes = Vec::with_capacity(amount);
+
+    for _ in 0..amount {
+        let recovery_code : String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(RECOVERY_CODE_LENGTH)
+            .collect();
+
+        recovery_codes.push(recovery_code);
+    }
+
+    recovery_codes
+}
+
+// Generates a random 160 bit totp secret (base32 encoded, as recommended by RFC 4226)
+pub fn generate_totp_secret() -> String {
+    let secret_bytes : [u8; 20] = thread_rng().gen();
+
+    base32::encode(Alphabet::RFC4648 { padding: false }, &secret_bytes)
+}
+
+// Authenticates the request with the api token from the Authorization header (Bearer scheme)
+async fn get_api_token_session(db_connection: &DbConnection, api_token: &str, required_scope: ApiTokenScope) -> Result<SessionData, SessionError> {
+    if !api_token.starts_with(API_TOKEN_PREFIX) {
+        return Err(SessionError::new(SessionInvalid, "API Token ist ungültig"));
+    }
+
+    let token_hash = hash_random_token(api_token);
+    let token_data = db_connection.use_api_token(token_hash.as_str()).await;
+
+    if token_data.is_err() {
+        let error = token_data.err().unwrap();
+
+        if error.error_type == NoResult {
+            return Err(SessionError::new(SessionInvalid, error.error_msg.as_str()));
+        }
+
+        return Err(SessionError::new(DbError, "Fehler beim Prüfen des API Tokens"));
+    }
+
+    let token_data = token_data.ok().unwrap();
+
+    if !token_data.has_scope(required_scope) {
+        let error_msg = format!("Dem API Token fehlt die Berechtigung \"{}\"", required_scope.as_str());
+
+        return Err(SessionError::new(MissingScope, error_msg.as_str()));
+    }
+
+    let ban_check_result = check_user_ban(db_connection, token_data.token_user).await;
+
+    if ban_check_result.is_err() {
+        return Err(ban_check_result.err().unwrap());
+    }
+
+    return Ok(SessionData::new_api_token(token_data.token_user));
+}
+
+// Returns the bearer token of the Authorization header (if there is one)
+fn get_bearer_token(headers: &HeaderMap) -> Option<String> {
+    let authorization = headers.get(AUTHORIZATION);
+
+    if authorization.is_some() {
+        let authorization = authorization.unwrap().to_str().unwrap_or("");
+
+        if authorization.len() > 7 && authorization[..7].eq_ignore_ascii_case("Bearer ") {
+            return Some(authorization[7..].trim().to_owned());
+        }
+    }
+
+    return None;
+}
+
+// Returns the csrf token of the session (a new one is created if the session has none yet)
+pub fn get_csrf_token(session: &Session) -> Option<String> {
+    let csrf_token = session.get::<String>("csrf_token").unwrap_or(None);
+
+    if csrf_token.is_some() {
+        return csrf_token;
+    }
+
+    let csrf_token : String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .collect();
+
+    if session.set("csrf_token", csrf_token.as_str()).is_err() {
+        warn!("get_csrf_token: Failed to store the csrf token in the session");
+
+        return None;
+    }
+
+    Some(csrf_token)
+}
+
+// Returns the ip address of the client
+// The X-Forwarded-For header is only used for requests of trusted reverse proxies, otherwise every client could fake its ip
+pub fn get_client_ip(config: &ProjectConfig, request: &HttpRequest) -> String {
+    let peer_addr = request.peer_addr();
+
+    if peer_addr.is_none() {
+        return String::from("unknown");
+    }
+
+    let trusted_proxies = config.network_config.trusted_proxies.get_value().addresses;
+    let mut client_ip = peer_addr.unwrap().ip();
+
+    if trusted_proxies.contains(&client_ip) {
+        let forwarded_for = request.headers().get("X-Forwarded-For")
+            .and_then(|header_value| header_value.to_str().ok())
+            .unwrap_or("");
+
+        // Every proxy appends the address it got the request from, so the first untrusted address from the right is the client
+        for forwarded_ip in forwarded_for.rsplit(',') {
+            let forwarded_ip = forwarded_ip.trim().parse::<IpAddr>();
+
+            if forwarded_ip.is_err() {
+                break;
+            }
+
+            client_ip = forwarded_ip.unwrap();
+
+            if !trusted_proxies.contains(&client_ip) {
+                break;
+            }
+        }
+    }
+
+    return client_ip.to_string();
+}
+
+// Returns the user agent of the client or an empty string if the client didn't send one
+pub fn get_client_user_agent(request: &HttpRequest) -> String {
+    let user_agent = request.headers().get(USER_AGENT);
+
+    if user_agent.is_some() {
+        return user_agent.unwrap().to_str().unwrap_or("").to_owned();
+    }
+
+    return "".to_owned();
+}
+
+// Returns the otpauth uri which is used by authenticator apps to add the account (usually shown as qr code)
+pub fn get_totp_uri(totp_secret: &str, username: &str) -> String {
+    format!("otpauth://totp/p0nygramm:{}?secret={}&issuer=p0nygramm&algorithm=SHA1&digits={}&period={}",
+            percent_encode(username), totp_secret, TOTP_DIGITS, TOTP_PERIOD)
+}
+
+// Calculates the totp code for the given time step (RFC 6238 with HMAC-SHA1)
+fn get_totp_code(secret_bytes: &[u8], time_step: u64) -> Option<u32> {
+    let hmac = Hmac::<Sha1>::new_varkey(secret_bytes);
+
+    if hmac.is_err() {
+        return None;
+    }
+
+    let mut hmac = hmac.unwrap();
+    hmac.update(&time_step.to_be_bytes());
+
+    let hmac_result = hmac.finalize().into_bytes();
+
+    // Dynamic truncation (RFC 4226 section 5.3)
+    let offset = (hmac_result[hmac_result.len() - 1] & 0x0f) as usize;
+    let binary_code = ((hmac_result[offset] as u32 & 0x7f) << 24)
+        | ((hmac_result[offset + 1] as u32) << 16)
+        | ((hmac_result[offset + 2] as u32) << 8)
+        | (hmac_result[offset + 3] as u32);
+
+    Some(binary_code % 10u32.pow(TOTP_DIGITS))
 }
 
 pub async fn get_user_session(db_connection: &DbConnection, session: &Session, force_session_renew: bool) -> Result<SessionData, SessionError> {
@@ -162,6 +444,26 @@ pub async fn get_user_session(db_connection: &DbConnection, session: &Session, f
             if session_data.is_ok() {
                 let session_data = session_data.ok().unwrap();
 
+                // Banned users lose their session (bans expire by themselves, so the check has to be done on every request)
+                if db_connection.have_postgres_connection() {
+                    let ban_check_result = check_user_ban(db_connection, session_data.user_id).await;
+
+                    if ban_check_result.is_err() {
+                        let ban_error = ban_check_result.err().unwrap();
+
+                        if ban_error.error_type == UserBanned {
+                            let destroy_result = db_connection.destroy_session(session_id.as_str()).await;
+                            session.purge();
+
+                            if destroy_result.is_err() {
+                                warn!("get_user_session: Failed to destroy session of banned user");
+                            }
+                        }
+
+                        return Err(ban_error);
+                    }
+                }
+
                 return Ok(session_data);
             }
             else {
@@ -182,23 +484,64 @@ pub async fn get_user_session(db_connection: &DbConnection, session: &Session, f
     }
 }
 
-pub fn hash_password(password: &str, secret_key: &str) -> Option<String> {
+// Like get_user_session, but accepts an api token with the required scope as alternative to the session cookie
+pub async fn get_user_session_or_api_token(db_connection: &DbConnection, session: &Session, request: &HttpRequest,
+                                           force_session_renew: bool, required_scope: ApiTokenScope) -> Result<SessionData, SessionError> {
+    let api_token = get_bearer_token(request.headers());
+
+    if api_token.is_some() {
+        return get_api_token_session(db_connection, api_token.unwrap().as_str(), required_scope).await;
+    }
+
+    get_user_session(db_connection, session, force_session_renew).await
+}
+
+// Returns the UTS #39 skeleton of the username, usernames with the same skeleton look alike
+// Lowercasing between two skeleton passes makes the check case insensitive (like the username_uq index)
+pub fn get_username_skeleton(username: &str) -> String {
+    let username_skeleton : String = skeleton(username).collect();
+    let username_skeleton = username_skeleton.to_lowercase();
+
+    skeleton(username_skeleton.as_str()).collect()
+}
+
+// Used for api tokens and recovery codes: They are long random strings, so a fast hash is enough
+pub fn hash_random_token(random_token: &str) -> String {
+    let hash = Sha256::digest(random_token.as_bytes());
+
+    format!("{:x}", hash)
+}
+
+// Returns the argon2 config for the configured hash parameters
+fn get_password_hash_config<'a>(security_config: &SecurityConfig, secret_key: &'a [u8]) -> Config<'a> {
+    let variant = match security_config.password_hash_variant.get_value() {
+        PasswordHashVariant::Argon2d => Variant::Argon2d,
+        PasswordHashVariant::Argon2i => Variant::Argon2i,
+        PasswordHashVariant::Argon2id => Variant::Argon2id,
+    };
+    let lanes = security_config.password_hash_lanes.get_value();
+
+    Config {
+        variant,
+        version: Version::Version13,
+        mem_cost: security_config.password_hash_memory.get_value(),
+        time_cost: security_config.password_hash_iterations.get_value(),
+        lanes,
+        thread_mode: ThreadMode::from_threads(lanes),
+        secret: secret_key,
+        ad: &[],
+        hash_length: PASSWORD_HASH_LENGTH,
+    }
+}
+
+pub fn hash_password(password: &str, security_config: &SecurityConfig) -> Option<String> {
     let salt: String = thread_rng()
         .sample_iter(&Alphanumeric)
         .take(10)
         .collect();
 
-    let config = Config {
-        variant: Variant::Argon2id,
-        version: Version::Version13,
-        mem_cost: 4096,
-        time_cost: 192,
-        lanes: 2,
-        thread_mode: ThreadMode::Parallel,
-        secret: secret_key.as_ref(),
-        ad: &[],
-        hash_length: 32
-    };
+    let secret_key = security_config.password_hash_key.get_value();
+    let config = get_password_hash_config(security_config, secret_key.as_ref());
 
     let hash_result = argon2::hash_encoded(password.as_ref(), salt.as_ref(), &config);
 
@@ -210,13 +553,247 @@ pub fn hash_password(password: &str, secret_key: &str) -> Option<String> {
     }
 }
 
-pub fn verify_password(password_hash: &str, password: &str, secret_key: &str) -> Option<bool> {
+// Returns true for all characters which can be used in texts (all visible characters, emoji and whitespaces)
+// Control characters, bidi overrides, invisible characters and private use characters are not allowed
+fn is_visible_text_char(char: char) -> bool {
+    match char {
+        '\n' | '\r' | '\t' => true,
+        '\u{061C}' | '\u{200B}' | '\u{200E}' | '\u{200F}' | '\u{FEFF}' => false,
+        '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{2066}'..='\u{2069}' | '\u{FFF9}'..='\u{FFFB}' => false,
+        '\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{10FFFF}' => false,
+        _ => !char.is_control()
+    }
+}
+
+// Checks if the encoded hash was made with other parameters than the configured ones
+// Format of the encoded hash: $argon2id$v=19$m=4096,t=192,p=2$<salt>$<hash>
+fn is_password_hash_outdated(password_hash: &str, security_config: &SecurityConfig) -> bool {
+    let config = get_password_hash_config(security_config, &[]);
+    let expected_params = format!("m={},t={},p={}", config.mem_cost, config.time_cost, config.lanes);
+    let expected_version = format!("v={}", config.version.as_u32());
+    let hash_parts : Vec<&str> = password_hash.split('$').collect();
+
+    if hash_parts.len() != 6 {
+        return true;
+    }
+
+    let expected_hash_length = (PASSWORD_HASH_LENGTH as usize * 4 + 2) / 3; // Length of the unpadded base64 string
+
+    hash_parts[1] != config.variant.as_lowercase_str() || hash_parts[2] != expected_version ||
+        hash_parts[3] != expected_params || hash_parts[5].len() != expected_hash_length
+}
+
+// Encodes everything except the unreserved characters of RFC 3986 (usernames may contain characters like '?' or '#')
+fn percent_encode(value: &str) -> String {
+    let mut encoded_value = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'.' || byte == b'_' || byte == b'~' {
+            encoded_value.push(byte as char);
+        }
+        else {
+            encoded_value.push_str(format!("%{:02X}", byte).as_str());
+        }
+    }
+
+    encoded_value
+}
+
+// Removes all data of the session cookie and issues a new csrf token (a purged cookie couldn't store one anymore)
+// The frontend gets the new token with the next page load
+pub fn purge_session(session: &Session) {
+    session.clear();
+
+    if get_csrf_token(session).is_none() {
+        session.purge();
+    }
+}
+
+pub fn verify_password(password_hash: &str, password: &str, security_config: &SecurityConfig) -> Option<PasswordVerification> {
+    let secret_key = security_config.password_hash_key.get_value();
     let verify_result = argon2::verify_encoded_ext(password_hash, password.as_ref(), secret_key.as_ref(), &[]);
 
     if verify_result.is_ok() {
-        return Some(verify_result.unwrap());
+        if !verify_result.unwrap() {
+            return Some(PasswordVerification::Incorrect);
+        }
+
+        if is_password_hash_outdated(password_hash, security_config) {
+            return Some(PasswordVerification::CorrectOutdated);
+        }
+
+        return Some(PasswordVerification::Correct);
     }
     else {
         return None;
     }
-}
\ No newline at end of file
+}
+
+// Returns the time step of the matching code, codes of the time step last_time_step or older are rejected (prevents replays)
+// The caller has to store the returned time step as the new last used one
+pub fn verify_totp_code(totp_secret: &str, totp_code: &str, last_time_step: Option<i64>) -> Option<i64> {
+    let secret_bytes = base32::decode(Alphabet::RFC4648 { padding: false }, totp_secret);
+
+    if secret_bytes.is_none() || !check_totp_code(totp_code) {
+        return None;
+    }
+
+    let secret_bytes = secret_bytes.unwrap();
+    let current_time_step = Utc::now().timestamp() / TOTP_PERIOD;
+    let mut matching_time_step : Option<i64> = None;
+
+    // All time steps are checked, so the time of the verification doesn't reveal which one matched
+    for drift in -TOTP_ALLOWED_DRIFT..=TOTP_ALLOWED_DRIFT {
+        let time_step = current_time_step + drift;
+        let expected_code = get_totp_code(secret_bytes.as_slice(), time_step as u64);
+
+        if expected_code.is_some() {
+            let expected_code = format!("{:0width$}", expected_code.unwrap(), width = TOTP_DIGITS as usize);
+            let is_unused = last_time_step.is_none() || time_step > last_time_step.unwrap();
+
+            if constant_time_eq(expected_code.as_str(), totp_code) && is_unused {
+                matching_time_step = Some(time_step);
+            }
+        }
+    }
+
+    matching_time_step
+}
+
+// Used as middleware for the js-api: State changing requests need the csrf token of the session in the X-CSRF-Token header
+// Requests with api tokens are excluded (browsers can't send an Authorization header cross-site without CORS)
+pub fn verify_csrf_token(request: &ServiceRequest) -> bool {
+    let method = request.method();
+
+    if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+        return true;
+    }
+
+    if get_bearer_token(request.headers()).is_some() {
+        return true;
+    }
+
+    let session = request.get_session();
+    let session_csrf_token = session.get::<String>("csrf_token").unwrap_or(None);
+    let request_csrf_token = request.headers().get(CSRF_TOKEN_HEADER);
+
+    if session_csrf_token.is_none() || request_csrf_token.is_none() {
+        return false;
+    }
+
+    let session_csrf_token = session_csrf_token.unwrap();
+    let request_csrf_token = request_csrf_token.unwrap().to_str().unwrap_or("");
+
+    constant_time_eq(session_csrf_token.as_str(), request_csrf_token)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_and_normalize_tag_accepts_all_scripts() {
+        // "a" with combining acute accent is normalized to the precomposed character
+        assert_eq!(check_and_normalize_tag("Ta\u{0301}g Äpfel"), Some(String::from("Tág Äpfel")));
+        assert_eq!(check_and_normalize_tag("Пони 2"), Some(String::from("Пони 2")));
+        assert_eq!(check_and_normalize_tag("Tag!"), None);
+        assert_eq!(check_and_normalize_tag("Tag\tTab"), None);
+        assert_eq!(check_and_normalize_tag("a".repeat(65).as_str()), None);
+    }
+
+    #[test]
+    fn check_and_normalize_username_rejects_mixed_scripts() {
+        assert_eq!(check_and_normalize_username("Pony\u{0308}"), Some(String::from("Ponÿ")));
+        assert_eq!(check_and_normalize_username("Пони"), Some(String::from("Пони")));
+        assert_eq!(check_and_normalize_username("P\u{043E}ny"), None); // Cyrillic "о"
+        assert_eq!(check_and_normalize_username("Pony Pony"), None);
+        assert_eq!(check_and_normalize_username(""), None);
+        assert_eq!(check_and_normalize_username("a".repeat(33).as_str()), None);
+    }
+
+    #[test]
+    fn constant_time_eq_compares_whole_strings() {
+        assert!(constant_time_eq("123456", "123456"));
+        assert!(!constant_time_eq("123456", "123457"));
+        assert!(!constant_time_eq("123456", "12345"));
+        assert!(constant_time_eq("", ""));
+    }
+
+    #[test]
+    fn get_totp_code_matches_rfc_6238() {
+        // Test vectors of RFC 6238 (SHA1), truncated to 6 digits
+        let secret_bytes = b"12345678901234567890";
+
+        assert_eq!(get_totp_code(secret_bytes, 59 / TOTP_PERIOD as u64), Some(287082));
+        assert_eq!(get_totp_code(secret_bytes, 1111111109 / TOTP_PERIOD as u64), Some(81804));
+        assert_eq!(get_totp_code(secret_bytes, 2000000000 / TOTP_PERIOD as u64), Some(279037));
+    }
+
+    #[test]
+    fn get_totp_uri_encodes_username() {
+        let totp_uri = get_totp_uri("JBSWY3DPEHPK3PXP", "Pony?&#Ä");
+
+        assert!(totp_uri.starts_with("otpauth://totp/p0nygramm:Pony%3F%26%23%C3%84?secret=JBSWY3DPEHPK3PXP&"));
+    }
+
+    // Cheap hash parameters, the tests only need valid hashes
+    fn get_test_security_config(password_hash_iterations: u32) -> SecurityConfig {
+        let mut security_config = SecurityConfig::new();
+        security_config.password_hash_key.set_value(String::from("test_key"));
+        security_config.password_hash_memory.set_value(64);
+        security_config.password_hash_iterations.set_value(password_hash_iterations);
+        security_config.password_hash_lanes.set_value(1);
+
+        security_config
+    }
+
+    #[test]
+    fn get_username_skeleton_matches_lookalikes() {
+        let skeleton = get_username_skeleton("Pony");
+
+        assert_eq!(get_username_skeleton("pony"), skeleton);
+        assert_eq!(get_username_skeleton("P\u{043E}ny"), skeleton);
+        assert_eq!(get_username_skeleton("P0ny"), skeleton);
+        assert_ne!(get_username_skeleton("Pferd"), skeleton);
+        assert_eq!(get_username_skeleton("rn"), get_username_skeleton("m"));
+    }
+
+    #[test]
+    fn is_password_hash_outdated_detects_changed_parameters() {
+        let security_config = get_test_security_config(1);
+        let changed_security_config = get_test_security_config(2);
+        let password_hash = hash_password("Passwort123", &security_config).unwrap();
+
+        assert!(!is_password_hash_outdated(password_hash.as_str(), &security_config));
+        assert!(is_password_hash_outdated(password_hash.as_str(), &changed_security_config));
+        assert!(is_password_hash_outdated("$argon2id$v=19$m=64,t=1,p=1$c2FsdA", &security_config));
+        assert!(verify_password(password_hash.as_str(), "Passwort123", &changed_security_config) == Some(PasswordVerification::CorrectOutdated));
+    }
+
+    #[test]
+    fn sanitize_filename_keeps_extension() {
+        assert_eq!(sanitize_filename("Pony Bild (1).jpg"), "Pony Bild _1_.jpg");
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename("C:\\Bilder\\Ponyß.png"), "Ponyß.png");
+        assert_eq!(sanitize_filename("a_very_long_filename_which_does_not_fit.webm"), "a_very_long_filename_which_.webm");
+        assert_eq!(sanitize_filename("no_extension_but_a_very_long_filename").chars().count(), 32);
+    }
+
+    #[test]
+    fn verify_totp_code_rejects_used_time_steps() {
+        let totp_secret = generate_totp_secret();
+        let secret_bytes = base32::decode(Alphabet::RFC4648 { padding: false }, totp_secret.as_str()).unwrap();
+        let current_time_step = Utc::now().timestamp() / TOTP_PERIOD;
+        let totp_code = format!("{:06}", get_totp_code(secret_bytes.as_slice(), current_time_step as u64).unwrap());
+
+        // The code of the next time step is checked, so this can't fail if the time step changes during the test
+        let next_totp_code = format!("{:06}", get_totp_code(secret_bytes.as_slice(), (current_time_step + 1) as u64).unwrap());
+        let time_step = verify_totp_code(totp_secret.as_str(), next_totp_code.as_str(), None);
+
+        assert!(time_step.is_some());
+        assert_eq!(verify_totp_code(totp_secret.as_str(), next_totp_code.as_str(), time_step), None);
+        assert_eq!(verify_totp_code(totp_secret.as_str(), totp_code.as_str(), Some(current_time_step + 1)), None);
+        assert_eq!(verify_totp_code(totp_secret.as_str(), "12345a", None), None);
+    }
+}
@@ -0,0 +1,194 @@
+use actix_web::web;
+use crate::config::{ProjectConfig, SimilarUploadMode};
+use crate::db_api::DbConnection;
+use crate::db_api::db_result::DbApiErrorType;
+use crate::backend_api::find_similar_uploads;
+use crate::file_api::{delete_stale_tmp_files, delete_upload_srv, delete_upload_tmp, process_file, FileProcessErrorType};
+use log::{error, info, warn};
+use std::time::Duration;
+
+const CLEANUP_INTERVAL : u64 = 3600; // Seconds between two runs of the cleanup worker
+const QUEUE_POLL_INTERVAL : u64 = 2; // Seconds a worker waits, if the queue is empty or the database isn't available
+
+enum QueueResult {
+    Processed,
+    Empty,
+    DbError,
+}
+
+// Processes the next queued upload, the result of the processing is written to the upload
+// The queued file is only deleted after the result is stored, otherwise the upload is processed again after a restart
+async fn process_next_upload(config: &ProjectConfig, db_connection: &DbConnection) -> QueueResult {
+    let queued_upload = db_connection.claim_queued_upload().await;
+
+    if queued_upload.is_err() {
+        let error = queued_upload.err().unwrap();
+
+        if error.error_type == DbApiErrorType::NoResult {
+            return QueueResult::Empty;
+        }
+
+        error!("process_next_upload: Failed to read the upload queue: {}", error.error_msg);
+
+        return QueueResult::DbError;
+    }
+
+    let (upload_id, filename) = queued_upload.ok().unwrap();
+    let file_process_result = process_file(config, filename.as_str()).await;
+
+    if file_process_result.is_err() {
+        let error = file_process_result.err().unwrap();
+
+        // Invalid files are the uploader's fault, everything else is a problem of the server
+        if error.error_code != FileProcessErrorType::FormatError {
+            warn!("process_next_upload: Processing of upload {} failed: {}", upload_id, error.error_msg);
+        }
+
+        let db_result = db_connection.set_upload_failed(upload_id, error.error_msg.as_str()).await;
+
+        if db_result.is_err() && db_result.err().unwrap().error_type != DbApiErrorType::NoResult {
+            error!("process_next_upload: Failed to set upload {} to failed", upload_id);
+        }
+        else {
+            delete_upload_tmp(filename.as_str()).await;
+        }
+
+        return QueueResult::Processed;
+    }
+
+    let file_process_result = file_process_result.ok().unwrap();
+    let upload_filename = file_process_result.upload_filename.as_str();
+    let upload_prv_variants = file_process_result.upload_prv_variants.as_str();
+    let similar_upload_mode = config.application_config.similar_upload_mode.get_value();
+
+    if similar_upload_mode == SimilarUploadMode::Reject {
+        let similar_upload_ids = find_similar_uploads(config, db_connection, upload_id, file_process_result.upload_phash).await;
+
+        if !similar_upload_ids.is_empty() {
+            let error_msg = format!("Ein sehr ähnliches Bild wurde bereits hochgeladen (Upload {})", similar_upload_ids[0]);
+
+            delete_upload_srv(config, upload_filename, Some(upload_prv_variants), file_process_result.upload_has_prv_clip).await;
+            let db_result = db_connection.set_upload_failed(upload_id, error_msg.as_str()).await;
+
+            if db_result.is_err() && db_result.err().unwrap().error_type != DbApiErrorType::NoResult {
+                error!("process_next_upload: Failed to set upload {} to failed", upload_id);
+            }
+            else {
+                delete_upload_tmp(filename.as_str()).await;
+            }
+
+            return QueueResult::Processed;
+        }
+    }
+
+    let db_result = db_connection.set_upload_ready(upload_id, upload_filename, file_process_result.upload_type,
+                                                   file_process_result.upload_phash, upload_prv_variants, file_process_result.upload_has_prv_clip).await;
+
+    if db_result.is_err() {
+        // The files of uploads which were deleted while processing wouldn't be removed otherwise
+        if db_result.err().unwrap().error_type == DbApiErrorType::NoResult {
+            delete_upload_srv(config, upload_filename, Some(upload_prv_variants), file_process_result.upload_has_prv_clip).await;
+            delete_upload_tmp(filename.as_str()).await;
+        }
+        else {
+            error!("process_next_upload: Failed to set upload {} to ready", upload_id);
+        }
+    }
+    else {
+        delete_upload_tmp(filename.as_str()).await;
+    }
+
+    return QueueResult::Processed;
+}
+
+// Removes failed uploads after their lifetime and tmp files of aborted uploads, so that they don't stay forever
+async fn run_cleanup_worker(config: web::Data<ProjectConfig>) {
+    loop {
+        let db_connection = DbConnection::new(&config, true, false).await;
+
+        if db_connection.is_ok() {
+            let db_connection = db_connection.ok().unwrap();
+            let failed_upload_lifetime = config.application_config.failed_upload_lifetime.get_value() as i32;
+            let delete_result = db_connection.delete_failed_uploads(failed_upload_lifetime).await;
+
+            if delete_result.is_ok() && delete_result.as_ref().ok().unwrap() > &0 {
+                info!("{} failed uploads were removed", delete_result.ok().unwrap());
+            }
+
+            // Without the queued uploads it's unknown which tmp files are still needed
+            let queued_filenames = db_connection.get_queued_upload_filenames().await;
+
+            if queued_filenames.is_ok() {
+                let deleted_count = delete_stale_tmp_files(queued_filenames.ok().unwrap().as_slice()).await;
+
+                if deleted_count > 0 {
+                    info!("{} stale tmp files were removed", deleted_count);
+                }
+            }
+        }
+        else {
+            warn!("Cleanup worker: No connection to the database");
+        }
+
+        tokio::time::delay_for(Duration::from_secs(CLEANUP_INTERVAL)).await;
+    }
+}
+
+async fn run_upload_worker(config: web::Data<ProjectConfig>, worker_id: u16) {
+    let mut db_connection : Option<DbConnection> = None;
+
+    loop {
+        if db_connection.is_none() {
+            let new_db_connection = DbConnection::new(&config, true, false).await;
+
+            if new_db_connection.is_ok() {
+                db_connection = Some(new_db_connection.ok().unwrap());
+            }
+            else {
+                warn!("Upload worker {}: No connection to the database", worker_id);
+                tokio::time::delay_for(Duration::from_secs(QUEUE_POLL_INTERVAL)).await;
+
+                continue;
+            }
+        }
+
+        let queue_result = process_next_upload(&config, db_connection.as_ref().unwrap()).await;
+
+        match queue_result {
+            QueueResult::Processed => {},
+            QueueResult::Empty => {
+                tokio::time::delay_for(Duration::from_secs(QUEUE_POLL_INTERVAL)).await;
+            },
+            QueueResult::DbError => {
+                // Reconnect in the next round
+                db_connection = None;
+                tokio::time::delay_for(Duration::from_secs(QUEUE_POLL_INTERVAL)).await;
+            },
+        }
+    }
+}
+
+// Puts uploads which were interrupted by a restart back into the queue and starts the upload and cleanup workers (call once at server start)
+pub async fn start_upload_workers(config: web::Data<ProjectConfig>) {
+    let worker_count = config.application_config.upload_workers.get_value().max(1);
+    let db_connection = DbConnection::new(&config, true, false).await;
+
+    if db_connection.is_ok() {
+        let requeue_result = db_connection.ok().unwrap().requeue_processing_uploads().await;
+
+        if requeue_result.is_ok() && requeue_result.as_ref().ok().unwrap() > &0 {
+            info!("{} interrupted uploads were queued again", requeue_result.ok().unwrap());
+        }
+    }
+    else {
+        warn!("start_upload_workers: Interrupted uploads couldn't be queued again (no connection to the database)");
+    }
+
+    for worker_id in 0..worker_count {
+        actix_rt::spawn(run_upload_worker(config.clone(), worker_id));
+    }
+
+    actix_rt::spawn(run_cleanup_worker(config.clone()));
+
+    info!("Started {} upload workers", worker_count);
+}